@@ -0,0 +1,37 @@
+use chrono::{TimeDelta, Utc};
+use clap::ArgMatches;
+use server::ServerConfig;
+use std::path::PathBuf;
+
+use super::{KEEP_ARG, MAX_AGE_ARG, PATH_ARG};
+
+pub fn run(cli_matches: &ArgMatches) {
+    let result = ServerConfig::from_env().and_then(|cfg| {
+        let backup_dir = cli_matches
+            .get_one::<String>(PATH_ARG)
+            .map(PathBuf::from)
+            .or(cfg.backup_dir)
+            .ok_or_else(|| {
+                std::io::Error::other(
+                    "Backup directory must be set by argument or EGOROFF_BACKUP_DIR",
+                )
+            })?;
+        let mut retention = cfg.backup_retention;
+        if let Some(keep) = cli_matches.get_one::<usize>(KEEP_ARG) {
+            retention.keep = *keep;
+        }
+        if let Some(days) = cli_matches.get_one::<i64>(MAX_AGE_ARG) {
+            retention.max_age = Some(TimeDelta::days(*days));
+        }
+        kernel::backup::backup(&cfg.data_path, &backup_dir, Utc::now(), retention)
+    });
+    match result {
+        Ok(result) => {
+            println!("Snapshot       : {}", result.snapshot.display());
+            for removed in result.removed {
+                println!("Removed        : {}", removed.display());
+            }
+        }
+        Err(e) => eprintln!("{e:?}"),
+    }
+}
@@ -0,0 +1,25 @@
+use clap::ArgMatches;
+use kernel::sqlite::{DATABASE, Mode, Sqlite};
+use server::ServerConfig;
+use std::path::Path;
+
+use super::PATH_ARG;
+
+pub fn run(cli_matches: &ArgMatches) {
+    let Some(path) = cli_matches.get_one::<String>(PATH_ARG) else {
+        return;
+    };
+    let result = ServerConfig::from_env().and_then(|cfg| {
+        let storage = Sqlite::open(cfg.data_path.join(DATABASE), Mode::ReadOnly)?;
+        kernel::export::export(&storage, Path::new(path))
+    });
+    match result {
+        Ok(summary) => {
+            println!("Posts          : {}", summary.posts);
+            println!("Downloads      : {}", summary.downloads);
+            println!("Folders        : {}", summary.folders);
+            println!("Tags           : {}", summary.tags);
+        }
+        Err(e) => eprintln!("{e:?}"),
+    }
+}
@@ -1,8 +1,37 @@
+pub mod backup;
+pub mod export;
+pub mod import;
+pub mod restore;
 pub mod server;
 pub mod version;
 
+pub const BACKUP_SUBCOMMAND: &str = "backup";
+pub const BACKUP_DESCRIPTION: &str =
+    "Make a consistent snapshot of databases while the server may be running";
+
+pub const EXPORT_SUBCOMMAND: &str = "export";
+pub const EXPORT_DESCRIPTION: &str =
+    "Export posts, downloads, folders and tags into a directory of Markdown files";
+
+pub const IMPORT_SUBCOMMAND: &str = "import";
+pub const IMPORT_DESCRIPTION: &str = "Import content previously exported into a directory";
+
+pub const RESTORE_SUBCOMMAND: &str = "restore";
+pub const RESTORE_DESCRIPTION: &str = "Validate a snapshot and replace databases with it";
+
 pub const SERVER_SUBCOMMAND: &str = "server";
 pub const SERVER_DESCRIPTION: &str = "Run the server";
 
 pub const VERSION_SUBCOMMAND: &str = "version";
 pub const VERSION_DESCRIPTION: &str = "Display the version and build information";
+
+pub const PATH_ARG: &str = "path";
+pub const PATH_DESCRIPTION: &str = "Content directory";
+pub const BACKUP_PATH_DESCRIPTION: &str = "Backup directory, EGOROFF_BACKUP_DIR by default";
+pub const SNAPSHOT_PATH_DESCRIPTION: &str = "Snapshot directory";
+
+pub const KEEP_ARG: &str = "keep";
+pub const KEEP_DESCRIPTION: &str = "Number of the newest snapshots to keep";
+
+pub const MAX_AGE_ARG: &str = "max-age";
+pub const MAX_AGE_DESCRIPTION: &str = "Remove snapshots older than this number of days";
@@ -0,0 +1,17 @@
+use clap::ArgMatches;
+use server::ServerConfig;
+use std::path::Path;
+
+use super::PATH_ARG;
+
+pub fn run(cli_matches: &ArgMatches) {
+    let Some(path) = cli_matches.get_one::<String>(PATH_ARG) else {
+        return;
+    };
+    let result = ServerConfig::from_env()
+        .and_then(|cfg| kernel::backup::restore(Path::new(path), &cfg.data_path));
+    match result {
+        Ok(version) => println!("Schema version : {version}"),
+        Err(e) => eprintln!("{e:?}"),
+    }
+}
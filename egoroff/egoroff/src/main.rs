@@ -1,4 +1,4 @@
-use clap::{Command, command, crate_name};
+use clap::{Arg, Command, command, crate_name, value_parser};
 
 mod cli;
 
@@ -16,6 +16,42 @@ async fn main() {
         .about(clap::crate_description!())
         .subcommand(Command::new(cli::VERSION_SUBCOMMAND).about(cli::VERSION_DESCRIPTION))
         .subcommand(Command::new(cli::SERVER_SUBCOMMAND).about(cli::SERVER_DESCRIPTION))
+        .subcommand(
+            Command::new(cli::BACKUP_SUBCOMMAND)
+                .about(cli::BACKUP_DESCRIPTION)
+                .arg(Arg::new(cli::PATH_ARG).help(cli::BACKUP_PATH_DESCRIPTION))
+                .arg(
+                    Arg::new(cli::KEEP_ARG)
+                        .long(cli::KEEP_ARG)
+                        .help(cli::KEEP_DESCRIPTION)
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new(cli::MAX_AGE_ARG)
+                        .long(cli::MAX_AGE_ARG)
+                        .help(cli::MAX_AGE_DESCRIPTION)
+                        .value_parser(value_parser!(i64)),
+                ),
+        )
+        .subcommand(
+            Command::new(cli::RESTORE_SUBCOMMAND)
+                .about(cli::RESTORE_DESCRIPTION)
+                .arg(
+                    Arg::new(cli::PATH_ARG)
+                        .help(cli::SNAPSHOT_PATH_DESCRIPTION)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new(cli::EXPORT_SUBCOMMAND)
+                .about(cli::EXPORT_DESCRIPTION)
+                .arg(path_arg()),
+        )
+        .subcommand(
+            Command::new(cli::IMPORT_SUBCOMMAND)
+                .about(cli::IMPORT_DESCRIPTION)
+                .arg(path_arg()),
+        )
         .arg_required_else_help(true)
         .disable_version_flag(true);
 
@@ -28,5 +64,19 @@ async fn main() {
         cli::version::run();
     } else if let Some(server_matches) = macthes.subcommand_matches(cli::SERVER_SUBCOMMAND) {
         cli::server::run(server_matches).await;
+    } else if let Some(export_matches) = macthes.subcommand_matches(cli::EXPORT_SUBCOMMAND) {
+        cli::export::run(export_matches);
+    } else if let Some(import_matches) = macthes.subcommand_matches(cli::IMPORT_SUBCOMMAND) {
+        cli::import::run(import_matches);
+    } else if let Some(backup_matches) = macthes.subcommand_matches(cli::BACKUP_SUBCOMMAND) {
+        cli::backup::run(backup_matches);
+    } else if let Some(restore_matches) = macthes.subcommand_matches(cli::RESTORE_SUBCOMMAND) {
+        cli::restore::run(restore_matches);
     }
 }
+
+fn path_arg() -> Arg {
+    Arg::new(cli::PATH_ARG)
+        .help(cli::PATH_DESCRIPTION)
+        .required(true)
+}
@@ -0,0 +1,114 @@
+//! Concurrent read throughput of the storage pool compared to a single mutex guarded connection.
+//!
+//! Run with `cargo bench -p kernel --bench pool`.
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use futures::{future::join_all, lock::Mutex};
+use kernel::{
+    domain::{Post, PostsRequest, Storage},
+    pool::{DEFAULT_READERS, Pool},
+    sqlite::{Mode, Sqlite},
+};
+
+const POSTS: i64 = 500;
+const CONCURRENCY: usize = 64;
+const REQUESTS_PER_TASK: usize = 50;
+
+fn main() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("egoroff_pool_bench_{}.db", std::process::id()));
+    fill_database(&path)?;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    let mutex = Arc::new(Mutex::new(Sqlite::open(&path, Mode::ReadWrite)?));
+    let elapsed = runtime.block_on(mutex_reads(mutex))?;
+    report("single Mutex<Sqlite>", elapsed);
+
+    let writer = Sqlite::open(&path, Mode::ReadWrite)?;
+    let pool = Arc::new(Pool::open(writer, &path, DEFAULT_READERS)?);
+    let elapsed = runtime.block_on(pool_reads(pool))?;
+    report(&format!("pool with {DEFAULT_READERS} readers"), elapsed);
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+fn fill_database(path: &PathBuf) -> Result<()> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let mut storage = Sqlite::open(path, Mode::ReadWrite)?.with_revisions_limit(0);
+    for id in 1..=POSTS {
+        storage.upsert_post(Post {
+            id,
+            title: format!("Post {id}"),
+            short_text: "Short text".repeat(10),
+            text: "Long text of the post. ".repeat(200),
+            markdown: true,
+            is_public: true,
+            tags: vec!["rust".to_owned(), format!("tag{}", id % 10)],
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+async fn mutex_reads(storage: Arc<Mutex<Sqlite>>) -> Result<Duration> {
+    let start = Instant::now();
+    let tasks = (0..CONCURRENCY).map(|task| {
+        let storage = Arc::clone(&storage);
+        tokio::spawn(async move {
+            for i in 0..REQUESTS_PER_TASK {
+                let storage = storage.lock().await;
+                read_page(&storage, task + i)?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+    });
+    for result in join_all(tasks).await {
+        result??;
+    }
+    Ok(start.elapsed())
+}
+
+async fn pool_reads(pool: Arc<Pool<Sqlite>>) -> Result<Duration> {
+    let start = Instant::now();
+    let tasks = (0..CONCURRENCY).map(|task| {
+        let pool = Arc::clone(&pool);
+        tokio::spawn(async move {
+            for i in 0..REQUESTS_PER_TASK {
+                pool.read(move |s| read_page(s, task + i)).await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+    });
+    for result in join_all(tasks).await {
+        result??;
+    }
+    Ok(start.elapsed())
+}
+
+/// Typical blog page load: posts list with count and a single post.
+fn read_page(storage: &Sqlite, n: usize) -> Result<()> {
+    let request = PostsRequest::default();
+    storage.count_posts(request.clone())?;
+    storage.get_small_posts(20, 0, request)?;
+    let id = i64::try_from(n).unwrap_or_default() % POSTS + 1;
+    storage.get_post(id)?;
+    Ok(())
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let requests = CONCURRENCY * REQUESTS_PER_TASK;
+    #[allow(clippy::cast_precision_loss)]
+    let throughput = requests as f64 / elapsed.as_secs_f64();
+    println!("{name}: {requests} page reads in {elapsed:?} ({throughput:.0} reads/s)");
+}
@@ -0,0 +1,54 @@
+-- egoroff.db as it was at schema version 1
+PRAGMA encoding = 'UTF-8';
+
+CREATE TABLE post (
+      id              INTEGER PRIMARY KEY,
+      title           TEXT NOT NULL,
+      short_text      TEXT NOT NULL,
+      text            TEXT NOT NULL,
+      markdown        INTEGER,
+      is_public       INTEGER,
+      created         INTEGER NOT NULL,
+      modified        INTEGER NOT NULL
+      );
+
+CREATE INDEX created_ix ON post(created);
+
+CREATE TABLE tag (
+       tag           TEXT PRIMARY KEY
+      );
+
+CREATE TABLE post_tag (
+      post_id              INTEGER NOT NULL,
+      tag                  TEXT NOT NULL,
+      PRIMARY KEY (post_id, tag),
+      FOREIGN KEY(post_id) REFERENCES post(id) ON UPDATE CASCADE ON DELETE CASCADE,
+      FOREIGN KEY(tag) REFERENCES tag(tag)
+      );
+
+CREATE TABLE folder (
+      bucket          TEXT PRIMARY KEY,
+      title           TEXT NOT NULL
+      );
+
+CREATE TABLE file (
+      id              INTEGER PRIMARY KEY,
+      title           TEXT NOT NULL
+      );
+
+INSERT INTO post (id, title, short_text, text, markdown, is_public, created, modified)
+    VALUES (1, 'First post', 'Short', '# Hello', 1, 1, 1420070400, 1420070400);
+INSERT INTO post (id, title, short_text, text, markdown, is_public, created, modified)
+    VALUES (2, 'Второй пост', 'Кратко', '<p>Привет</p>', 0, 0, 1451606400, 1451606400);
+
+INSERT INTO tag (tag) VALUES ('rust');
+INSERT INTO tag (tag) VALUES ('sqlite');
+
+INSERT INTO post_tag (post_id, tag) VALUES (1, 'rust');
+INSERT INTO post_tag (post_id, tag) VALUES (1, 'sqlite');
+INSERT INTO post_tag (post_id, tag) VALUES (2, 'rust');
+
+INSERT INTO folder (bucket, title) VALUES ('apps', 'Applications');
+INSERT INTO file (id, title) VALUES (1, 'hc');
+
+PRAGMA user_version = 1;
@@ -1,23 +1,27 @@
 use crate::{
     converter::markdown2html,
     domain::{
-        ApiResult, Archive, Month, Post, PostsRequest, SmallPost, Storage, Tag, TagAggregate, Year,
+        ApiResult, Archive, Month, Post, PostsRequest, SmallPost, Storage, Tag, TagAggregate,
+        TagInfo, Year,
     },
-    sqlite::Sqlite,
 };
 use anyhow::Result;
 use chrono::{DateTime, Datelike, Utc};
-use futures::lock::MutexGuard;
 use itertools::Itertools;
+use std::collections::HashMap;
 
-pub fn archive(storage: MutexGuard<Sqlite>) -> Result<Archive> {
+pub fn archive<S: Storage>(storage: &S) -> Result<Archive> {
     let aggregated_tags: Vec<TagAggregate> = storage.get_aggregate_tags()?;
     let req = PostsRequest {
         ..Default::default()
     };
     let total_posts = storage.count_posts(req)?;
     let dates: Vec<DateTime<Utc>> = storage.get_posts_create_dates()?;
-    drop(storage);
+    let mut tag_infos: HashMap<String, TagInfo> = storage
+        .get_tags()?
+        .into_iter()
+        .map(|t| (t.tag.clone(), t))
+        .collect();
 
     let years = group_to_years(&dates);
 
@@ -29,9 +33,12 @@ pub fn archive(storage: MutexGuard<Sqlite>) -> Result<Archive> {
             } else {
                 0
             };
+            let info = tag_infos.remove(&tag.title).unwrap_or_default();
             Tag {
                 title: tag.title.clone(),
                 level: ix,
+                display_title: info.title,
+                description: info.description,
             }
         })
         .collect();
@@ -61,8 +68,8 @@ fn group_to_years(dates: &[DateTime<Utc>]) -> Vec<Year> {
         .collect()
 }
 
-pub fn get_small_posts(
-    storage: &MutexGuard<Sqlite>,
+pub fn get_small_posts<S: Storage>(
+    storage: &S,
     page_size: i32,
     request: Option<PostsRequest>,
 ) -> Result<ApiResult<SmallPost>> {
@@ -86,8 +93,8 @@ pub fn get_small_posts(
     })
 }
 
-pub fn get_posts(
-    storage: &MutexGuard<Sqlite>,
+pub fn get_posts<S: Storage>(
+    storage: &S,
     page_size: i32,
     request: PostsRequest,
 ) -> Result<ApiResult<Post>> {
@@ -109,6 +116,28 @@ pub fn get_posts(
     })
 }
 
+/// Gets public posts with full text using the same query and paging as [`get_small_posts`].
+pub fn get_public_posts<S: Storage>(
+    storage: &S,
+    page_size: i32,
+    request: Option<PostsRequest>,
+) -> Result<ApiResult<Post>> {
+    let small_posts = get_small_posts(storage, page_size, request)?;
+    let posts = small_posts
+        .result
+        .iter()
+        .map(|p| storage.get_post(p.id))
+        .collect::<Result<Vec<Post>, _>>()?;
+
+    Ok(ApiResult {
+        result: posts,
+        pages: small_posts.pages,
+        page: small_posts.page,
+        count: small_posts.count,
+        status: small_posts.status,
+    })
+}
+
 /// Integer division with upper rounding (ceil)
 fn ceil_div(dividend: i32, divider: i32) -> i32 {
     dividend / divider + (dividend % divider).signum()
@@ -130,6 +159,7 @@ mod tests {
     #![allow(clippy::unwrap_in_result)]
     #![allow(clippy::unwrap_used)]
     use super::*;
+    use crate::memory::Memory;
     use chrono::NaiveDate;
     use rstest::rstest;
 
@@ -201,4 +231,43 @@ mod tests {
         assert_eq!(1, actual[1].posts);
         assert_eq!(1, actual[1].months.len());
     }
+
+    #[rstest]
+    #[case(1, 2, vec![5, 4])]
+    #[case(3, 2, vec![1])]
+    #[case(4, 2, vec![])]
+    fn get_small_posts_paging(
+        #[case] page: i32,
+        #[case] page_size: i32,
+        #[case] expected: Vec<i64>,
+    ) {
+        // arrange
+        let mut storage = Memory::new();
+        for id in 1..=5 {
+            storage
+                .upsert_post(Post {
+                    id,
+                    title: format!("Post {id}"),
+                    is_public: true,
+                    created: DateTime::from_timestamp(1_700_000_000 + id, 0).unwrap(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let request = PostsRequest {
+            page: Some(page),
+            ..Default::default()
+        };
+
+        // act
+        let actual = get_small_posts(&storage, page_size, Some(request)).unwrap();
+
+        // assert
+        assert_eq!(5, actual.count);
+        assert_eq!(3, actual.pages);
+        assert_eq!(
+            expected,
+            actual.result.iter().map(|p| p.id).collect::<Vec<_>>()
+        );
+    }
 }
@@ -0,0 +1,366 @@
+//! Consistent snapshots of site databases made with SQLite online backup API.
+//!
+//! Every snapshot is a directory named by its UTC creation time (`20240131T235959Z`)
+//! inside the backup directory. It contains copies of the main and sessions databases.
+//! Backup API copies pages under a read lock so that snapshots are consistent even
+//! while the server keeps writing.
+
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use rusqlite::{Connection, OpenFlags, backup::Backup};
+
+use crate::{migration, session, sqlite};
+
+/// Snapshot directory name format.
+pub const SNAPSHOT_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+/// Number of snapshots kept by default.
+pub const DEFAULT_KEEP: usize = 7;
+
+/// All databases included into a snapshot. Only the main database is required.
+const DATABASES: [&str; 2] = [sqlite::DATABASE, session::DATABASE];
+const PAGES_PER_STEP: i32 = 100;
+const STEP_PAUSE: Duration = Duration::from_millis(50);
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+const SESSION_TABLES: [&str; 2] = ["session", "secret"];
+
+/// Rules which snapshots survive after a new one is made.
+/// The snapshot just made is always kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// Number of the newest snapshots to keep.
+    pub keep: usize,
+    /// Snapshots older than this are removed even if they are among the newest.
+    pub max_age: Option<TimeDelta>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            keep: DEFAULT_KEEP,
+            max_age: None,
+        }
+    }
+}
+
+/// Snapshot found in backup directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub created: DateTime<Utc>,
+    pub path: PathBuf,
+}
+
+/// Result of a backup run.
+#[derive(Debug)]
+pub struct BackupResult {
+    /// Directory of the new snapshot.
+    pub snapshot: PathBuf,
+    /// Snapshots removed by retention rules.
+    pub removed: Vec<PathBuf>,
+}
+
+/// Makes snapshot of databases from `data_dir` inside `backup_dir` and removes
+/// old snapshots according to retention rules.
+pub fn backup(
+    data_dir: &Path,
+    backup_dir: &Path,
+    now: DateTime<Utc>,
+    retention: Retention,
+) -> Result<BackupResult> {
+    let main_database = data_dir.join(sqlite::DATABASE);
+    if !main_database.is_file() {
+        bail!("Database {} not found", main_database.display());
+    }
+    let snapshot = backup_dir.join(now.format(SNAPSHOT_FORMAT).to_string());
+    if snapshot.exists() {
+        bail!("Snapshot {} already exists", snapshot.display());
+    }
+    fs::create_dir_all(&snapshot)
+        .with_context(|| format!("Failed to create {}", snapshot.display()))?;
+
+    for name in DATABASES {
+        let source = data_dir.join(name);
+        if !source.is_file() {
+            continue;
+        }
+        let result = Connection::open_with_flags(&source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .and_then(|src| copy(&src, &snapshot.join(name)))
+            .with_context(|| format!("Failed to backup {}", source.display()));
+        if let Err(e) = result {
+            // Incomplete snapshot must not be mistaken for a good one
+            let _ = fs::remove_dir_all(&snapshot);
+            return Err(e);
+        }
+    }
+
+    let removed = prune(backup_dir, now, retention, &snapshot)?;
+    Ok(BackupResult { snapshot, removed })
+}
+
+/// Validates snapshot and copies its databases over databases of `data_dir`.
+/// Returns schema version of the restored main database.
+pub fn restore(snapshot: &Path, data_dir: &Path) -> Result<u32> {
+    let version = validate(snapshot)?;
+    for name in DATABASES {
+        let source = snapshot.join(name);
+        if !source.is_file() {
+            continue;
+        }
+        let target = data_dir.join(name);
+        Connection::open_with_flags(&source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .and_then(|src| copy(&src, &target))
+            .with_context(|| format!("Failed to restore {}", target.display()))?;
+    }
+    Ok(version)
+}
+
+/// Checks integrity of snapshot databases and that main database schema is complete
+/// and supported by this version of the application. Returns schema version.
+pub fn validate(snapshot: &Path) -> Result<u32> {
+    let main_database = snapshot.join(sqlite::DATABASE);
+    if !main_database.is_file() {
+        bail!("Database {} not found", main_database.display());
+    }
+    let conn = Connection::open_with_flags(&main_database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    check_integrity(&conn).with_context(|| format!("Invalid {}", main_database.display()))?;
+    let version = migration::ensure_supported(&conn)?;
+    if version == 0 {
+        bail!("Database {} has no schema", main_database.display());
+    }
+    let expected = reference_schema(version)?;
+    let missing: Vec<_> = expected
+        .difference(&schema_objects(&conn)?)
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "Database {} schema version {version} misses: {}",
+            main_database.display(),
+            missing.join(", ")
+        );
+    }
+
+    let sessions_database = snapshot.join(session::DATABASE);
+    if sessions_database.is_file() {
+        let conn =
+            Connection::open_with_flags(&sessions_database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        check_integrity(&conn)
+            .with_context(|| format!("Invalid {}", sessions_database.display()))?;
+        let objects = schema_objects(&conn)?;
+        if let Some(table) = SESSION_TABLES.iter().find(|t| !objects.contains(**t)) {
+            bail!("Database {} misses: {table}", sessions_database.display());
+        }
+    }
+    Ok(version)
+}
+
+/// Lists snapshots of backup directory from the newest to the oldest.
+/// Entries which names are not snapshot timestamps are ignored.
+pub fn snapshots(backup_dir: &Path) -> Result<Vec<Snapshot>> {
+    if !backup_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut result: Vec<Snapshot> = fs::read_dir(backup_dir)
+        .with_context(|| format!("Failed to read {}", backup_dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_dir())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            let created = NaiveDateTime::parse_from_str(name, SNAPSHOT_FORMAT)
+                .ok()?
+                .and_utc();
+            Some(Snapshot { created, path })
+        })
+        .collect();
+    result.sort_by_key(|s| Reverse(s.created));
+    Ok(result)
+}
+
+fn prune(
+    backup_dir: &Path,
+    now: DateTime<Utc>,
+    retention: Retention,
+    current: &Path,
+) -> Result<Vec<PathBuf>> {
+    let mut removed = vec![];
+    for (i, snapshot) in snapshots(backup_dir)?.into_iter().enumerate() {
+        if snapshot.path == current {
+            continue;
+        }
+        let expired = retention
+            .max_age
+            .is_some_and(|age| now - snapshot.created > age);
+        if i >= retention.keep || expired {
+            fs::remove_dir_all(&snapshot.path)
+                .with_context(|| format!("Failed to remove {}", snapshot.path.display()))?;
+            removed.push(snapshot.path);
+        }
+    }
+    Ok(removed)
+}
+
+/// Copies database page by page. Pauses between steps let writers of the source proceed.
+fn copy(src: &Connection, target: &Path) -> rusqlite::Result<()> {
+    let mut dst = Connection::open(target)?;
+    dst.busy_timeout(BUSY_TIMEOUT)?;
+    Backup::new(src, &mut dst)?.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None)
+}
+
+fn check_integrity(conn: &Connection) -> Result<()> {
+    let result: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if result == "ok" {
+        Ok(())
+    } else {
+        Err(anyhow!(result))
+    }
+}
+
+/// Names of tables, indexes and triggers of the database.
+fn schema_objects(conn: &Connection) -> Result<BTreeSet<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE name NOT LIKE 'sqlite_%'")?;
+    let names = stmt.query_map([], |row| row.get(0))?;
+    Ok(names.collect::<Result<_, _>>()?)
+}
+
+/// Schema objects a database of the version must have. Made by applying
+/// migrations up to the version to an empty in-memory database.
+fn reference_schema(version: u32) -> Result<BTreeSet<String>> {
+    let mut conn = Connection::open_in_memory()?;
+    for migration in migration::MIGRATIONS
+        .iter()
+        .filter(|m| m.version <= version)
+    {
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.commit()?;
+    }
+    schema_objects(&conn)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::domain::{Post, Storage};
+    use crate::sqlite::{Mode, Sqlite};
+    use crate::testing::temp_dir;
+    use rstest::rstest;
+
+    #[test]
+    fn backup_and_restore() {
+        // arrange
+        let root = temp_dir("backup_and_restore");
+        let data_dir = root.join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+        let mut storage = Sqlite::open(data_dir.join(sqlite::DATABASE), Mode::ReadWrite).unwrap();
+        storage.upsert_post(post(1)).unwrap();
+        let backup_dir = root.join("backup");
+        let result = backup(&data_dir, &backup_dir, Utc::now(), Retention::default()).unwrap();
+        storage.delete_post(1).unwrap();
+
+        // act
+        let version = restore(&result.snapshot, &data_dir).unwrap();
+
+        // assert
+        assert_eq!(migration::latest_version(), version);
+        assert!(result.removed.is_empty());
+        assert!(result.snapshot.join(sqlite::DATABASE).is_file());
+        assert_eq!("Post 1", storage.get_post(1).unwrap().title);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn backup_without_database() {
+        // arrange
+        let root = temp_dir("backup_without_database");
+
+        // act
+        let actual = backup(
+            &root,
+            &root.join("backup"),
+            Utc::now(),
+            Retention::default(),
+        );
+
+        // assert
+        assert!(actual.is_err());
+        assert!(!root.join("backup").exists());
+    }
+
+    #[rstest]
+    #[case(Retention { keep: 2, max_age: None }, vec!["20240105T000000Z", "20240104T000000Z"])]
+    #[case(Retention { keep: 10, max_age: Some(TimeDelta::days(2)) }, vec!["20240105T000000Z", "20240104T000000Z", "20240103T000000Z"])]
+    #[case(Retention { keep: 0, max_age: None }, vec!["20240105T000000Z"])]
+    fn backup_retention(#[case] retention: Retention, #[case] expected: Vec<&str>) {
+        // arrange
+        let root = temp_dir(&format!("backup_retention_{}", retention.keep));
+        let data_dir = root.join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+        Sqlite::open(data_dir.join(sqlite::DATABASE), Mode::ReadWrite).unwrap();
+        let backup_dir = root.join("backup");
+        for name in ["20240101T000000Z", "20240103T000000Z", "20240104T000000Z"] {
+            fs::create_dir_all(backup_dir.join(name)).unwrap();
+        }
+        fs::create_dir_all(backup_dir.join("not a snapshot")).unwrap();
+        let now = "2024-01-05T00:00:00Z".parse().unwrap();
+
+        // act
+        let result = backup(&data_dir, &backup_dir, now, retention).unwrap();
+
+        // assert
+        let actual: Vec<String> = snapshots(&backup_dir)
+            .unwrap()
+            .iter()
+            .map(|s| s.path.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(expected, actual);
+        assert_eq!(4 - expected.len(), result.removed.len());
+        assert!(backup_dir.join("not a snapshot").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[rstest]
+    #[case::no_database("")]
+    #[case::no_schema("CREATE TABLE t (id INTEGER);")]
+    #[case::incomplete_schema("CREATE TABLE post (id INTEGER); PRAGMA user_version = 1;")]
+    #[case::newer_schema("PRAGMA user_version = 1000;")]
+    fn restore_invalid_snapshot(#[case] sql: &str) {
+        // arrange
+        let root = temp_dir(&format!("restore_invalid_{}", sql.len()));
+        let snapshot = root.join("20240101T000000Z");
+        fs::create_dir_all(&snapshot).unwrap();
+        if !sql.is_empty() {
+            Connection::open(snapshot.join(sqlite::DATABASE))
+                .unwrap()
+                .execute_batch(sql)
+                .unwrap();
+        }
+        let data_dir = root.join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        // act
+        let actual = restore(&snapshot, &data_dir);
+
+        // assert
+        assert!(actual.is_err());
+        assert!(!data_dir.join(sqlite::DATABASE).exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    fn post(id: i64) -> Post {
+        Post {
+            id,
+            title: format!("Post {id}"),
+            is_public: true,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Cache of values computed from posts, like posts navigation or images.
+//!
+//! Values are dropped all at once when any post changes. Every invalidation
+//! starts a new generation, so that values computed from posts read before
+//! invalidation are not cached after it.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Values by key shared by all requests. Must be invalidated whenever data
+/// values are computed from changes.
+pub struct GenerationCache<K, V> {
+    items: Mutex<HashMap<K, V>>,
+    generation: AtomicU64,
+}
+
+impl<K, V> Default for GenerationCache<K, V> {
+    fn default() -> Self {
+        Self {
+            items: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V: Clone> GenerationCache<K, V> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.items
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .cloned()
+    }
+
+    /// Current generation that must be read before reading data from storage.
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Caches the value if cache hasn't been invalidated since `generation`.
+    pub fn insert(&self, generation: u64, key: K, value: V) {
+        let mut items = self.items.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.generation() == generation {
+            items.insert(key, value);
+        }
+    }
+
+    pub fn invalidate(&self) {
+        let mut items = self.items.lock().unwrap_or_else(PoisonError::into_inner);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        items.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalidation() {
+        // arrange
+        let cache = GenerationCache::new();
+        let generation = cache.generation();
+        cache.insert(generation, 1, "first");
+
+        // act
+        cache.invalidate();
+        cache.insert(generation, 2, "stale");
+
+        // assert
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&2).is_none());
+        cache.insert(cache.generation(), 2, "second");
+        assert_eq!(Some("second"), cache.get(&2));
+    }
+}
@@ -10,12 +10,17 @@ use lol_html::{
     ElementContentHandlers, HtmlRewriter, Selector, Settings, doc_text, element,
     html_content::TextType,
 };
-use pulldown_cmark::{Options, Parser, html};
+use pulldown_cmark::{
+    BlockQuoteKind, CodeBlockKind, CowStr, Event as MdEvent, Options, Parser, Tag, TagEnd, html,
+};
 use quick_xml::{
     Reader, Writer,
-    events::{BytesEnd, BytesStart, Event},
+    escape::{escape, unescape},
+    events::{BytesEnd, BytesStart, BytesText, Event},
 };
 
+use crate::{domain::Post, highlight};
+
 const REPLACES: &[(&[u8], &str)] = &[
     (b"example", "pre"),
     (b"quote", "blockquote"),
@@ -53,6 +58,28 @@ pub fn xml2html(input: &str) -> Result<String> {
                     continue;
                 }
 
+                if *replace == "pre"
+                    && let Some(lang) = example_language(&e)
+                {
+                    // Examples with nested markup are rendered as they are, so the reader
+                    // is advanced past the example only if it has text only.
+                    let mut example = reader.clone();
+                    if let Some(code) = read_example(&mut example)? {
+                        reader = example;
+                        if let Some(html) = highlight::highlight(&code, &lang)? {
+                            writer.write_event(Event::Text(BytesText::from_escaped(html)))?;
+                        } else {
+                            let elem = BytesStart::new(*replace).with_attributes(
+                                e.attributes().filter_map(std::result::Result::ok),
+                            );
+                            writer.write_event(Event::Start(elem))?;
+                            writer.write_event(Event::Text(BytesText::new(&code)))?;
+                            writer.write_event(Event::End(BytesEnd::new(*replace)))?;
+                        }
+                        continue;
+                    }
+                }
+
                 let mut elem = if *replace == "h" {
                     let level = parents.last().copied().unwrap_or("1");
                     BytesStart::new(format!("h{level}"))
@@ -131,15 +158,92 @@ pub fn xml2html(input: &str) -> Result<String> {
     Ok(result)
 }
 
+/// Language of code example set like syntax highlighters expect it:
+/// `class="brush: cpp;"` or `class="language-cpp"`.
+fn example_language(e: &BytesStart) -> Option<String> {
+    let class = e.try_get_attribute("class").ok()??;
+    let class = str::from_utf8(&class.value).ok()?;
+    let lang = class
+        .strip_prefix("brush:")
+        .or_else(|| class.strip_prefix("language-"))?
+        .trim()
+        .trim_end_matches(';');
+    (!lang.is_empty()).then(|| lang.to_owned())
+}
+
+/// Reads unescaped code example text up to the end of the example.
+/// Gives `None` if the example has nested elements.
+fn read_example(reader: &mut Reader<&[u8]>) -> Result<Option<String>> {
+    let mut escaped = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Text(t) => escaped.push_str(&t.decode()?),
+            Event::GeneralRef(r) => {
+                escaped.push('&');
+                escaped.push_str(&r.decode()?);
+                escaped.push(';');
+            }
+            Event::CData(t) => escaped.push_str(&escape(t.decode()?)),
+            Event::Start(_) | Event::Empty(_) => return Ok(None),
+            // Without nested elements the first end is the end of the example
+            Event::End(_) | Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(Some(unescape(&escaped)?.into_owned()))
+}
+
+/// HTML rendered from Markdown with the headings to build table of contents from.
+#[derive(Debug, Default)]
+pub struct HtmlDocument {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+}
+
+/// Heading of the document in the table of contents.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TocEntry {
+    /// Heading level from 1 to 6.
+    pub level: u8,
+    /// Anchor of the heading.
+    pub id: String,
+    pub title: String,
+}
+
 pub fn markdown2html(input: &str) -> Result<String> {
+    markdown2document(input).map(|d| d.html)
+}
+
+/// Renders Markdown with footnotes, tables, task lists, definition lists and
+/// GitHub style admonitions (`> [!NOTE]`). Headings get anchors and make the table of contents.
+pub fn markdown2document(input: &str) -> Result<HtmlDocument> {
     let options = Options::ENABLE_STRIKETHROUGH
         | Options::ENABLE_TABLES
         | Options::ENABLE_HEADING_ATTRIBUTES
-        | Options::ENABLE_TASKLISTS;
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_DEFINITION_LIST
+        | Options::ENABLE_GFM;
+
+    let mut events: Vec<MdEvent> = Parser::new_ext(input, options)
+        .map(|event| match event {
+            MdEvent::Start(Tag::BlockQuote(Some(kind))) => MdEvent::Html(
+                format!(
+                    "<div class=\"alert alert-{}\" role=\"alert\">\n",
+                    admonition_class(kind)
+                )
+                .into(),
+            ),
+            MdEvent::End(TagEnd::BlockQuote(Some(_))) => MdEvent::Html("</div>\n".into()),
+            e => e,
+        })
+        .collect();
+    let toc = make_anchors(&mut events);
+    let events = highlight_code(events);
+    let events = footnotes(events);
 
-    let parser = Parser::new_ext(input, options);
     let mut html = String::with_capacity(input.len() * 2);
-    html::push_html(&mut html, parser);
+    html::push_html(&mut html, events.into_iter());
 
     let table_handler: (Cow<Selector>, ElementContentHandlers) = element!("table", |e| {
         e.set_attribute("class", "table table-condensed table-striped")
@@ -157,8 +261,202 @@ pub fn markdown2html(input: &str) -> Result<String> {
 
     rewriter.write(html.as_bytes())?;
     rewriter.end()?;
-    let result = String::from_utf8(result)?;
-    Ok(result)
+    let html = String::from_utf8(result)?;
+    Ok(HtmlDocument { html, toc })
+}
+
+/// Replaces fenced code blocks of known languages with highlighted HTML.
+fn highlight_code(events: Vec<MdEvent>) -> Vec<MdEvent> {
+    let mut result = Vec::with_capacity(events.len());
+    let mut block: Option<(CowStr, String)> = None;
+    for event in events {
+        match (event, &mut block) {
+            (MdEvent::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))), None)
+                if !lang.is_empty() =>
+            {
+                block = Some((lang, String::new()));
+            }
+            (MdEvent::Text(text), Some((_, code))) => code.push_str(&text),
+            (MdEvent::End(TagEnd::CodeBlock), Some(_)) => {
+                let Some((lang, code)) = block.take() else {
+                    continue;
+                };
+                match highlight::highlight(&code, &lang) {
+                    Ok(Some(html)) => result.push(MdEvent::Html(html.into())),
+                    Ok(None) | Err(_) => result.extend([
+                        MdEvent::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))),
+                        MdEvent::Text(code.into()),
+                        MdEvent::End(TagEnd::CodeBlock),
+                    ]),
+                }
+            }
+            (event, _) => result.push(event),
+        }
+    }
+    result
+}
+
+/// Renders footnotes with `fn-` and `fnref-` prefixed anchors so that they
+/// don't clash with heading anchors. Footnotes are numbered by first use.
+fn footnotes<'a>(events: Vec<MdEvent<'a>>) -> Vec<MdEvent<'a>> {
+    let referenced: HashSet<CowStr> = events
+        .iter()
+        .filter_map(|e| match e {
+            MdEvent::FootnoteReference(label) => Some(label.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut numbers: HashMap<CowStr, usize> = HashMap::new();
+    let mut number_of = |label: &CowStr<'a>| {
+        let next = numbers.len() + 1;
+        *numbers.entry(label.clone()).or_insert(next)
+    };
+    let mut anchored: HashSet<CowStr> = HashSet::new();
+    let mut definition: Option<CowStr> = None;
+    let mut result = Vec::with_capacity(events.len());
+    for event in events {
+        match event {
+            MdEvent::FootnoteReference(label) => {
+                let number = number_of(&label);
+                let anchor = escape(label.as_ref());
+                let id = if anchored.insert(label.clone()) {
+                    format!(" id=\"fnref-{anchor}\"")
+                } else {
+                    String::new()
+                };
+                result.push(MdEvent::InlineHtml(
+                    format!(
+                        "<sup class=\"footnote-reference\"{id}><a href=\"#fn-{anchor}\">{number}</a></sup>"
+                    )
+                    .into(),
+                ));
+            }
+            MdEvent::Start(Tag::FootnoteDefinition(label)) => {
+                let number = number_of(&label);
+                result.push(MdEvent::Html(
+                    format!(
+                        "<div class=\"footnote-definition\" id=\"fn-{}\"><sup class=\"footnote-definition-label\">{number}</sup>\n",
+                        escape(label.as_ref())
+                    )
+                    .into(),
+                ));
+                definition = Some(label);
+            }
+            MdEvent::End(TagEnd::FootnoteDefinition) => {
+                let backref = definition
+                    .take()
+                    .filter(|label| referenced.contains(label))
+                    .map(|label| {
+                        format!(
+                            "<a href=\"#fnref-{}\" class=\"footnote-backref\">↩</a>",
+                            escape(label.as_ref())
+                        )
+                    })
+                    .unwrap_or_default();
+                result.push(MdEvent::Html(format!("{backref}</div>\n").into()));
+            }
+            event => result.push(event),
+        }
+    }
+    result
+}
+
+fn admonition_class(kind: BlockQuoteKind) -> &'static str {
+    match kind {
+        BlockQuoteKind::Note => "info",
+        BlockQuoteKind::Tip => "success",
+        BlockQuoteKind::Important => "primary",
+        BlockQuoteKind::Warning => "warning",
+        BlockQuoteKind::Caution => "danger",
+    }
+}
+
+/// Sets unique ids to headings without explicit ones and returns all headings.
+fn make_anchors(events: &mut [MdEvent]) -> Vec<TocEntry> {
+    let mut ids: HashSet<String> = events
+        .iter()
+        .filter_map(|e| match e {
+            MdEvent::Start(Tag::Heading { id: Some(id), .. }) => Some(id.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut toc = vec![];
+    for i in 0..events.len() {
+        let MdEvent::Start(Tag::Heading { level, id, .. }) = &events[i] else {
+            continue;
+        };
+        let level = *level as u8;
+        let title: String = events[i + 1..]
+            .iter()
+            .take_while(|e| !matches!(e, MdEvent::End(TagEnd::Heading(_))))
+            .filter_map(|e| match e {
+                MdEvent::Text(t) | MdEvent::Code(t) => Some(t.as_ref()),
+                _ => None,
+            })
+            .collect();
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => {
+                let id = unique_id(&slugify(&title), &ids);
+                ids.insert(id.clone());
+                if let MdEvent::Start(Tag::Heading { id: anchor, .. }) = &mut events[i] {
+                    *anchor = Some(id.clone().into());
+                }
+                id
+            }
+        };
+        toc.push(TocEntry { level, id, title });
+    }
+    toc
+}
+
+/// Makes heading anchor keeping letters and digits of any script so that
+/// Cyrillic headings get readable ids. Other characters become single dashes.
+fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        String::from("section")
+    } else {
+        slug.to_owned()
+    }
+}
+
+fn unique_id(slug: &str, ids: &HashSet<String>) -> String {
+    if !ids.contains(slug) {
+        return slug.to_owned();
+    }
+    (1..)
+        .map(|n| format!("{slug}-{n}"))
+        .find(|id| !ids.contains(id))
+        .unwrap_or_default()
+}
+
+/// Renders post text as HTML according to its format.
+pub fn post2html(post: &Post) -> Result<String> {
+    post2document(post).map(|d| d.html)
+}
+
+/// Renders post text as HTML document. Only Markdown posts have table of contents.
+pub fn post2document(post: &Post) -> Result<HtmlDocument> {
+    if post.markdown {
+        markdown2document(&post.text)
+    } else if post.text.starts_with("<?xml version=\"1.0\"?>") {
+        xml2html(&post.text).map(|html| HtmlDocument { html, toc: vec![] })
+    } else {
+        Ok(HtmlDocument {
+            html: post.text.clone(),
+            toc: vec![],
+        })
+    }
 }
 
 pub fn html2text(html: &str) -> Result<String> {
@@ -180,6 +478,36 @@ pub fn html2text(html: &str) -> Result<String> {
     Ok(parts.join(" "))
 }
 
+/// Extracts link targets in document order without duplicates.
+pub fn extract_links(html: &str) -> Result<Vec<String>> {
+    extract_attribute(html, "a[href]", "href")
+}
+
+/// Extracts image sources in document order without duplicates.
+pub fn extract_images(html: &str) -> Result<Vec<String>> {
+    extract_attribute(html, "img[src]", "src")
+}
+
+fn extract_attribute(html: &str, selector: &str, attribute: &str) -> Result<Vec<String>> {
+    let mut values: Vec<String> = Vec::new();
+    let mut rewriter = HtmlRewriter::new(
+        Settings::new().append_element_content_handler(element!(selector, |e| {
+            if let Some(value) = e.get_attribute(attribute) {
+                // Attributes come as written in the page so entities are unescaped here.
+                let value = unescape(&value).map_or(value.clone(), Cow::into_owned);
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            Ok(())
+        })),
+        |_: &[u8]| {},
+    );
+    rewriter.write(html.as_bytes())?;
+    rewriter.end()?;
+    Ok(values)
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::unwrap_in_result)]
@@ -217,6 +545,14 @@ mod tests {
         "<?xml version=\"1.0\"?><example class=\"lang-rust\">test</example>",
         "<?xml version=\"1.0\"?><pre class=\"lang-rust\">test</pre>"
     )]
+    #[case(
+        "<?xml version=\"1.0\"?><example class=\"brush: parser3;\">a &lt; b</example>",
+        "<?xml version=\"1.0\"?><pre class=\"brush: parser3;\">a &lt; b</pre>"
+    )]
+    #[case(
+        "<?xml version=\"1.0\"?><example class=\"brush: cpp;\">a <link id=\"2\">b</link> c</example><p>d</p>",
+        "<?xml version=\"1.0\"?><pre class=\"brush: cpp;\">a <a href=\"/blog/\" itemprop=\"url\">b</a> c</pre><p>d</p>"
+    )]
     #[case(
         "<?xml version=\"1.0\"?><quote>test</quote>",
         "<?xml version=\"1.0\"?><blockquote>test</blockquote>"
@@ -293,8 +629,48 @@ mod tests {
     }
 
     #[rstest]
-    #[case("# a\nb", "<h1>a</h1>\n<p>b</p>\n")]
-    #[case("## a\nb", "<h2>a</h2>\n<p>b</p>\n")]
+    #[case("# a\nb", "<h1 id=\"a\">a</h1>\n<p>b</p>\n")]
+    #[case("## a\nb", "<h2 id=\"a\">a</h2>\n<p>b</p>\n")]
+    #[case(
+        "## Глава 1: Начало\n",
+        "<h2 id=\"глава-1-начало\">Глава 1: Начало</h2>\n"
+    )]
+    #[case("## a\n## a\n", "<h2 id=\"a\">a</h2>\n<h2 id=\"a-1\">a</h2>\n")]
+    #[case("## a {#b}\n## b\n", "<h2 id=\"b\">a</h2>\n<h2 id=\"b-1\">b</h2>\n")]
+    #[case("## `code` !\n", "<h2 id=\"code\"><code>code</code> !</h2>\n")]
+    #[case("## ***\n", "<h2 id=\"section\">***</h2>\n")]
+    #[case("~~a~~", "<p><del>a</del></p>\n")]
+    #[case(
+        "- [x] a\n- [ ] b",
+        "<ul>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\na</li>\n<li><input disabled=\"\" type=\"checkbox\"/>\nb</li>\n</ul>\n"
+    )]
+    #[case(
+        "a[^1]\n\n[^1]: b",
+        "<p>a<sup class=\"footnote-reference\" id=\"fnref-1\"><a href=\"#fn-1\">1</a></sup></p>\n<div class=\"footnote-definition\" id=\"fn-1\"><sup class=\"footnote-definition-label\">1</sup>\n<p>b</p>\n<a href=\"#fnref-1\" class=\"footnote-backref\">↩</a></div>\n"
+    )]
+    #[case(
+        "## 1\na[^1] b[^1]\n\n[^1]: c",
+        "<h2 id=\"1\">1</h2>\n<p>a<sup class=\"footnote-reference\" id=\"fnref-1\"><a href=\"#fn-1\">1</a></sup> b<sup class=\"footnote-reference\"><a href=\"#fn-1\">1</a></sup></p>\n<div class=\"footnote-definition\" id=\"fn-1\"><sup class=\"footnote-definition-label\">1</sup>\n<p>c</p>\n<a href=\"#fnref-1\" class=\"footnote-backref\">↩</a></div>\n"
+    )]
+    #[case("a\n: b", "<dl>\n<dt>a</dt>\n<dd>b</dd>\n</dl>\n")]
+    #[case(
+        "> [!NOTE]\n> a",
+        "<div class=\"alert alert-info\" role=\"alert\">\n<p>a</p>\n</div>\n"
+    )]
+    #[case(
+        "> [!WARNING]\n> a",
+        "<div class=\"alert alert-warning\" role=\"alert\">\n<p>a</p>\n</div>\n"
+    )]
+    #[case("> a", "<blockquote>\n<p>a</p>\n</blockquote>\n")]
+    #[case("```\na < b\n```", "<pre><code>a &lt; b\n</code></pre>\n")]
+    #[case(
+        "```parser3\na\n```",
+        "<pre><code class=\"language-parser3\">a\n</code></pre>\n"
+    )]
+    #[case(
+        "|a|\n|-|\n|b|",
+        "<table class=\"table table-condensed table-striped\"><thead><tr><th>a</th></tr></thead><tbody>\n<tr><td>b</td></tr>\n</tbody></table>\n"
+    )]
     #[case("1. a\n2. b", "<ol>\n<li>a</li>\n<li>b</li>\n</ol>\n")]
     #[case("- a\n- b", "<ul>\n<li>a</li>\n<li>b</li>\n</ul>\n")]
     fn markdown2html_tests(#[case] test_data: &str, #[case] expected: &str) {
@@ -307,6 +683,93 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[rstest]
+    #[case("```rust\nfn main() {}\n```")]
+    #[case("~~~rust,ignore\nfn main() {}\n~~~")]
+    fn markdown2html_highlights_code(#[case] test_data: &str) {
+        // arrange
+
+        // act
+        let actual = markdown2html(test_data).unwrap();
+
+        // assert
+        assert!(actual.starts_with(r#"<pre class="highlight"><code class="language-rust">"#));
+        assert!(
+            actual.contains(r#"<span class="hl-storage hl-type hl-function hl-rust">fn</span>"#)
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "<example class=\"brush: cpp;\">if (a &lt; b) {}</example>",
+        "language-cpp"
+    )]
+    #[case(
+        "<example class=\"language-xml\"><![CDATA[<a href=\"x\"/>]]></example>",
+        "language-xml"
+    )]
+    fn xml2html_highlights_examples(#[case] test_data: &str, #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = xml2html(test_data).unwrap();
+
+        // assert
+        assert!(actual.starts_with(&format!(
+            r#"<pre class="highlight"><code class="{expected}">"#
+        )));
+        assert!(actual.contains("&lt;"));
+        assert!(!actual.contains("<example"));
+    }
+
+    #[test]
+    fn markdown2document_toc() {
+        // arrange
+        let input = "# Заголовок\n\ntext\n\n## Раздел `один`\n\n### a {#custom}\n";
+
+        // act
+        let actual = markdown2document(input).unwrap();
+
+        // assert
+        assert_eq!(
+            vec![
+                TocEntry {
+                    level: 1,
+                    id: "заголовок".to_owned(),
+                    title: "Заголовок".to_owned()
+                },
+                TocEntry {
+                    level: 2,
+                    id: "раздел-один".to_owned(),
+                    title: "Раздел один".to_owned()
+                },
+                TocEntry {
+                    level: 3,
+                    id: "custom".to_owned(),
+                    title: "a".to_owned()
+                },
+            ],
+            actual.toc
+        );
+        assert!(actual.html.contains("<h3 id=\"custom\">a</h3>"));
+    }
+
+    #[test]
+    fn post2document_without_markdown_has_no_toc() {
+        // arrange
+        let post = Post {
+            text: "<h2>a</h2>".to_owned(),
+            ..Default::default()
+        };
+
+        // act
+        let actual = post2document(&post).unwrap();
+
+        // assert
+        assert_eq!("<h2>a</h2>", actual.html);
+        assert!(actual.toc.is_empty());
+    }
+
     #[rstest]
     #[case("<h1>a</h1>\n<p>b</p>\n", "a b")]
     #[case("a<h1>b</h1>\n<p>c</p>d\n", "a b c d")]
@@ -319,4 +782,30 @@ mod tests {
         // assert
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn extract_links_test() {
+        // arrange
+        let html = r#"<p><a href="https://a.example/?x=1&amp;y=2">a</a> <a name="top">top</a>
+            <a href="/blog/1.html">b</a> <a href="https://a.example/?x=1&amp;y=2">a</a></p>"#;
+
+        // act
+        let actual = extract_links(html).unwrap();
+
+        // assert
+        assert_eq!(vec!["https://a.example/?x=1&y=2", "/blog/1.html"], actual);
+    }
+
+    #[test]
+    fn extract_images_test() {
+        // arrange
+        let html = r#"<p><img src="/p/1.png" alt="a"> <img alt="no source">
+            <a href="/p/2.png"><img src="https://a.example/2.jpg?w=1&amp;h=2"/></a> <img src="/p/1.png"></p>"#;
+
+        // act
+        let actual = extract_images(html).unwrap();
+
+        // assert
+        assert_eq!(vec!["/p/1.png", "https://a.example/2.jpg?w=1&h=2"], actual);
+    }
 }
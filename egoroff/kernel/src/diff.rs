@@ -0,0 +1,310 @@
+//! Line based unified diff used to compare post revisions.
+
+use std::fmt::Write;
+
+/// Number of unchanged lines shown around changes.
+const CONTEXT: usize = 3;
+
+/// Edit distance explored when looking for a common part of texts. Texts that differ more
+/// are shown as fully replaced, so time spent on very different revisions stays bounded.
+const MAX_COST: isize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Makes unified diff between two texts. Equal texts produce empty diff.
+#[must_use]
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let ops = diff_lines(old, new);
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Op::Equal)
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Number of old and new lines before each operation
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    positions.push((old_pos, new_pos));
+    for (op, _) in &ops {
+        match op {
+            Op::Equal => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Op::Delete => old_pos += 1,
+            Op::Insert => new_pos += 1,
+        }
+        positions.push((old_pos, new_pos));
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut i = 0;
+    while i < changes.len() {
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * CONTEXT + 1 {
+            j += 1;
+        }
+        let start = changes[i].saturating_sub(CONTEXT);
+        let end = (changes[j] + CONTEXT + 1).min(ops.len());
+        let (old_before, new_before) = positions[start];
+        let (old_after, new_after) = positions[end];
+
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(old_before, old_after - old_before),
+            hunk_range(new_before, new_after - new_before)
+        );
+        for (op, line) in &ops[start..end] {
+            let prefix = match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            };
+            let _ = writeln!(out, "{prefix}{line}");
+        }
+        i = j + 1;
+    }
+    out
+}
+
+fn hunk_range(before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{before},0"),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{count}", before + 1),
+    }
+}
+
+/// Shortest edit script of lines found by Myers' divide and conquer algorithm,
+/// so memory stays linear in the number of lines whatever texts are compared.
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<(Op, &'a str)> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    diff_slices(&old, &new, &mut ops);
+    ops
+}
+
+fn diff_slices<'a>(a: &[&'a str], b: &[&'a str], ops: &mut Vec<(Op, &'a str)>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    ops.extend(a[..prefix].iter().map(|l| (Op::Equal, *l)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (middle_a, middle_b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if middle_a.is_empty() || middle_b.is_empty() {
+        ops.extend(middle_a.iter().map(|l| (Op::Delete, *l)));
+        ops.extend(middle_b.iter().map(|l| (Op::Insert, *l)));
+    } else if let Some((x, y)) = middle_snake(middle_a, middle_b) {
+        diff_slices(&middle_a[..x], &middle_b[..y], ops);
+        diff_slices(&middle_a[x..], &middle_b[y..], ops);
+    } else {
+        // Nothing in common
+        ops.extend(middle_a.iter().map(|l| (Op::Delete, *l)));
+        ops.extend(middle_b.iter().map(|l| (Op::Insert, *l)));
+    }
+
+    ops.extend(a[a.len() - suffix..].iter().map(|l| (Op::Equal, *l)));
+}
+
+/// Finds the point where forward and reverse shortest paths overlap.
+/// Inputs must have neither common prefix nor common suffix.
+/// Returns `None` if the only path deletes everything and inserts everything
+/// or paths don't meet within [`MAX_COST`].
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn middle_snake(a: &[&str], b: &[&str]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let size = (2 * max_d + 2) as usize;
+    // Furthest x reached on every diagonal k = x - y, -1 if not reached yet
+    let mut forward = vec![-1isize; size];
+    let mut reverse = vec![-1isize; size];
+    forward[(offset + 1) as usize] = 0;
+    reverse[(offset + 1) as usize] = 0;
+    let delta = n - m;
+    // Paths meet on forward pass if delta is odd and on reverse pass otherwise
+    let front = delta % 2 != 0;
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+
+    for d in 0..max_d.min(MAX_COST) {
+        let mut k1 = -d + k1_start;
+        while k1 <= d - k1_end {
+            let k1_offset = (offset + k1) as usize;
+            let mut x1 = if k1 == -d || (k1 != d && forward[k1_offset - 1] < forward[k1_offset + 1])
+            {
+                forward[k1_offset + 1]
+            } else {
+                forward[k1_offset - 1] + 1
+            };
+            let mut y1 = x1 - k1;
+            while x1 < n && y1 < m && a[x1 as usize] == b[y1 as usize] {
+                x1 += 1;
+                y1 += 1;
+            }
+            forward[k1_offset] = x1;
+            if x1 > n {
+                k1_end += 2;
+            } else if y1 > m {
+                k1_start += 2;
+            } else if front {
+                let k2_offset = offset + delta - k1;
+                if (0..size as isize).contains(&k2_offset) && reverse[k2_offset as usize] != -1 {
+                    let x2 = n - reverse[k2_offset as usize];
+                    if x1 >= x2 {
+                        return Some((x1 as usize, y1 as usize));
+                    }
+                }
+            }
+            k1 += 2;
+        }
+
+        let mut k2 = -d + k2_start;
+        while k2 <= d - k2_end {
+            let k2_offset = (offset + k2) as usize;
+            let mut x2 = if k2 == -d || (k2 != d && reverse[k2_offset - 1] < reverse[k2_offset + 1])
+            {
+                reverse[k2_offset + 1]
+            } else {
+                reverse[k2_offset - 1] + 1
+            };
+            let mut y2 = x2 - k2;
+            while x2 < n && y2 < m && a[(n - x2 - 1) as usize] == b[(m - y2 - 1) as usize] {
+                x2 += 1;
+                y2 += 1;
+            }
+            reverse[k2_offset] = x2;
+            if x2 > n {
+                k2_end += 2;
+            } else if y2 > m {
+                k2_start += 2;
+            } else if !front {
+                let k1_offset = offset + delta - k2;
+                if (0..size as isize).contains(&k1_offset) && forward[k1_offset as usize] != -1 {
+                    let x1 = forward[k1_offset as usize];
+                    let y1 = offset + x1 - k1_offset;
+                    if x1 >= n - x2 {
+                        return Some((x1 as usize, y1 as usize));
+                    }
+                }
+            }
+            k2 += 2;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn unified_equal_texts_empty() {
+        // arrange
+        let text = "a\nb\nc";
+
+        // act
+        let actual = unified(text, text, "a", "b");
+
+        // assert
+        assert!(actual.is_empty());
+    }
+
+    #[test]
+    fn unified_single_change_with_context() {
+        // arrange
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9";
+
+        // act
+        let actual = unified(old, new, "old", "new");
+
+        // assert
+        assert_eq!(
+            "--- old\n+++ new\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n",
+            actual
+        );
+    }
+
+    #[test]
+    fn unified_distant_changes_in_separate_hunks() {
+        // arrange
+        let mut lines: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        let old = lines.join("\n");
+        lines[1] = "two".to_owned();
+        lines[18] = "nineteen".to_owned();
+        let new = lines.join("\n");
+
+        // act
+        let actual = unified(&old, &new, "old", "new");
+
+        // assert
+        assert_eq!(2, actual.matches("@@ -").count());
+        assert!(actual.contains("-2\n+two\n"));
+        assert!(actual.contains("-19\n+nineteen\n"));
+    }
+
+    #[test]
+    fn unified_large_different_texts_replaced() {
+        // arrange
+        let old: Vec<String> = (0..20_000).map(|i| format!("old {i}")).collect();
+        let new: Vec<String> = (0..20_000).map(|i| format!("new {i}")).collect();
+
+        // act
+        let actual = unified(&old.join("\n"), &new.join("\n"), "old", "new");
+
+        // assert
+        assert!(actual.starts_with("--- old\n+++ new\n@@ -1,20000 +1,20000 @@\n"));
+        assert_eq!(20_000, actual.matches("\n-old ").count());
+        assert_eq!(20_000, actual.matches("\n+new ").count());
+    }
+
+    #[test]
+    fn unified_moved_block_keeps_common_lines() {
+        // arrange
+        let old = "a\nb\nc\nd\ne\nf";
+        let new = "d\ne\nf\na\nb\nc";
+
+        // act
+        let actual = unified(old, new, "old", "new");
+
+        // assert
+        assert_eq!(
+            "--- old\n+++ new\n@@ -1,6 +1,6 @@\n-a\n-b\n-c\n d\n e\n f\n+a\n+b\n+c\n",
+            actual
+        );
+    }
+
+    #[rstest]
+    #[case("", "a\nb", "--- old\n+++ new\n@@ -0,0 +1,2 @@\n+a\n+b\n")]
+    #[case("a\nb", "", "--- old\n+++ new\n@@ -1,2 +0,0 @@\n-a\n-b\n")]
+    #[case("a", "b", "--- old\n+++ new\n@@ -1 +1 @@\n-a\n+b\n")]
+    fn unified_edge_cases(#[case] old: &str, #[case] new: &str, #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = unified(old, new, "old", "new");
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+}
@@ -2,6 +2,28 @@ use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use std::{error::Error, fmt::Debug};
 use utoipa::{IntoParams, ToSchema};
 
+/// Implements `as_str` and `parse` for enums stored in database by their lowercase names.
+macro_rules! lowercase_names {
+    ($name:ident, $what:literal, { $($variant:ident => $value:literal),+ $(,)? }) => {
+        impl $name {
+            #[must_use]
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $value,)+
+                }
+            }
+
+            #[doc = concat!("Parses ", $what, " name ignoring case. Unknown names are `None`.")]
+            #[must_use]
+            pub fn parse(name: &str) -> Option<Self> {
+                [$($name::$variant),+]
+                    .into_iter()
+                    .find(|v| v.as_str().eq_ignore_ascii_case(name))
+            }
+        }
+    };
+}
+
 /// Represents a user in the system.
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct User {
@@ -48,6 +70,42 @@ pub struct SmallPost {
     /// A boolean indicating whether the post content is in Markdown format (not serialized).
     #[serde(skip_serializing)]
     pub markdown: bool,
+    /// The human readable part of the post URL.
+    #[schema(example = "blake3", rename = "Slug")]
+    #[serde(rename(serialize = "Slug"), default)]
+    pub slug: Option<String>,
+}
+
+impl SmallPost {
+    /// Post page path relative to the site root.
+    #[must_use]
+    pub fn path(&self) -> String {
+        post_path(self.id, &self.created, self.slug.as_deref())
+    }
+}
+
+impl From<&Post> for SmallPost {
+    fn from(post: &Post) -> Self {
+        Self {
+            created: post.created,
+            id: post.id,
+            title: post.title.clone(),
+            short_text: post.short_text.clone(),
+            markdown: post.markdown,
+            slug: post.slug.clone(),
+        }
+    }
+}
+
+/// Posts to read after the post: adjacent by date and similar ones.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, ToSchema)]
+pub struct PostNavigation {
+    /// The public post published just before the post.
+    pub previous: Option<SmallPost>,
+    /// The public post published just after the post.
+    pub next: Option<SmallPost>,
+    /// Public posts similar to the post, the most similar first.
+    pub related: Vec<SmallPost>,
 }
 
 /// Represents a regular post.
@@ -74,18 +132,147 @@ pub struct Post {
     #[serde(rename(serialize = "Markdown", deserialize = "Markdown"))]
     pub markdown: bool,
     /// A boolean indicating whether the post is publicly visible.
+    /// Storage derives it from `status` so it's set only by clients that don't know statuses.
     #[serde(rename(serialize = "IsPublic", deserialize = "IsPublic"))]
     pub is_public: bool,
     /// A list of tags associated with the post.
     #[serde(rename(serialize = "Tags", deserialize = "Tags"))]
     pub tags: Vec<String>,
+    /// The human readable part of the post URL. Generated from the title if not set.
+    #[serde(rename(serialize = "Slug", deserialize = "Slug"), default)]
+    pub slug: Option<String>,
+    /// Publication status. Scheduled posts become public at `created` time.
+    #[serde(rename(serialize = "Status", deserialize = "Status"), default)]
+    pub status: PostStatus,
+}
+
+/// Post sent by the admin editor to be saved.
+#[derive(Debug, Deserialize)]
+pub struct PostEdit {
+    #[serde(flatten)]
+    pub post: Post,
+    /// Publication status. Editors that don't send it keep the stored one.
+    #[serde(rename = "Status", default)]
+    pub status: Option<PostStatus>,
+}
+
+impl PostEdit {
+    /// Makes the post to save. Status of the stored post is kept unless
+    /// the editor sent one, it's still reconciled with the public flag.
+    #[must_use]
+    pub fn into_post(self, stored: Option<&Post>) -> Post {
+        let status = self
+            .status
+            .or_else(|| stored.map(|p| p.status))
+            .unwrap_or_default();
+        Post {
+            status,
+            ..self.post
+        }
+    }
+}
+
+/// Snapshot of post content saved on every post change.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct PostRevision {
+    /// The unique ID of the revision. Greater IDs are newer revisions.
+    pub id: i64,
+    /// The ID of the post revision belongs to.
+    pub post_id: i64,
+    /// The timestamp when the revision was saved.
+    #[serde(rename(serialize = "Created", deserialize = "Created"))]
+    pub created: DateTime<Utc>,
+    /// The title of the post.
+    #[serde(rename(serialize = "Title", deserialize = "Title"))]
+    pub title: String,
+    /// A short summary or teaser text for the post.
+    #[serde(rename(serialize = "ShortText", deserialize = "ShortText"))]
+    pub short_text: String,
+    /// The full content of the post.
+    #[serde(rename(serialize = "Text", deserialize = "Text"))]
+    pub text: String,
+    /// A boolean indicating whether the post content is in Markdown format.
+    #[serde(rename(serialize = "Markdown", deserialize = "Markdown"))]
+    pub markdown: bool,
+    /// A list of tags associated with the post.
+    #[serde(rename(serialize = "Tags", deserialize = "Tags"))]
+    pub tags: Vec<String>,
 }
 
+impl PostRevision {
+    /// Revision content as a plain document used to compare revisions.
+    #[must_use]
+    pub fn document(&self) -> String {
+        format!(
+            "Title: {}\nTags: {}\n\n{}\n\n{}\n",
+            self.title,
+            self.tags.join(", "),
+            self.short_text,
+            self.text
+        )
+    }
+
+    /// Restores revision content into the post keeping post ID, dates and status.
+    pub fn restore_into(&self, post: &mut Post) {
+        post.title.clone_from(&self.title);
+        post.short_text.clone_from(&self.short_text);
+        post.text.clone_from(&self.text);
+        post.markdown = self.markdown;
+        post.tags.clone_from(&self.tags);
+    }
+}
+
+/// Publication status of a post.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    /// Not visible to readers.
+    #[default]
+    Draft,
+    /// Visible to readers.
+    Published,
+    /// Becomes published when its publication time comes.
+    Scheduled,
+}
+
+lowercase_names!(PostStatus, "status", {
+    Draft => "draft",
+    Published => "published",
+    Scheduled => "scheduled",
+});
+
 impl Post {
+    /// Status stored for the post. It's consistent with `is_public` flag changed by clients
+    /// that don't know statuses: public posts are always published and private posts are never published.
+    #[must_use]
+    pub fn effective_status(&self) -> PostStatus {
+        match (self.is_public, self.status) {
+            (true, _) => PostStatus::Published,
+            (false, PostStatus::Published) => PostStatus::Draft,
+            (false, status) => status,
+        }
+    }
+
     #[must_use]
     pub fn keywords(&self) -> String {
         self.tags.join(",")
     }
+
+    /// Post page path relative to the site root.
+    #[must_use]
+    pub fn path(&self) -> String {
+        post_path(self.id, &self.created, self.slug.as_deref())
+    }
+}
+
+/// Builds post page path relative to the site root: `blog/{year}/{slug}`
+/// or `blog/{id}.html` for posts without slug.
+#[must_use]
+pub fn post_path(id: i64, created: &DateTime<Utc>, slug: Option<&str>) -> String {
+    match slug {
+        Some(slug) if !slug.is_empty() => format!("blog/{}/{slug}", created.year()),
+        _ => format!("blog/{id}.html"),
+    }
 }
 
 /// Request for retrieving posts.
@@ -180,6 +367,12 @@ pub struct Tag {
     pub title: String,
     /// The level of importance or relevance of the tag (1-10).
     pub level: usize,
+    /// The title to show instead of the tag if it is set.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub display_title: Option<String>,
+    /// The tag description if it is set.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub description: Option<String>,
 }
 
 /// Represents an aggregate count of tags.
@@ -191,6 +384,30 @@ pub struct TagAggregate {
     pub count: i32,
 }
 
+/// Tag with its presentation settings.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TagInfo {
+    /// The tag as it is used by posts.
+    pub tag: String,
+    /// The title shown instead of the tag on tag listing pages.
+    pub title: Option<String>,
+    /// The description shown on tag listing pages and used as page meta description.
+    pub description: Option<String>,
+    /// The number of posts with the tag including not public ones.
+    pub posts: i32,
+}
+
+impl TagInfo {
+    /// The title if it is set or the tag itself.
+    #[must_use]
+    pub fn display_title(&self) -> &str {
+        self.title
+            .as_deref()
+            .filter(|t| !t.is_empty())
+            .unwrap_or(&self.tag)
+    }
+}
+
 /// Represents a single year in the archive.
 #[derive(Deserialize, Serialize, Default)]
 pub struct Year {
@@ -253,7 +470,7 @@ pub struct ApiResult<T> {
 /// Represents an OAuth provider with its authentication settings.
 ///
 /// This struct is used for serialization purposes only.
-#[derive(Serialize, Default)]
+#[derive(Serialize, Default, Clone)]
 pub struct OAuthProvider {
     /// The name of the OAuth provider (e.g. Google, Facebook).
     pub name: String,
@@ -270,7 +487,7 @@ pub struct OAuthProvider {
 /// Represents a folder with its bucket and title information.
 ///
 /// This struct is used for serialization purposes only.
-#[derive(Serialize, Default)]
+#[derive(Serialize, Default, Clone)]
 pub struct Folder {
     /// The name of the bucket containing the folder.
     pub bucket: String,
@@ -289,10 +506,209 @@ pub struct Download {
     pub title: String,
 }
 
+/// Represents an access token issued by `IndieAuth` token endpoint.
+///
+/// This struct is used for serialization purposes only.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct IndieToken {
+    /// The unique token ID that is also kept in `jti` claim of the token.
+    pub jti: String,
+    /// The client identifier of the application the token was issued to.
+    pub client_id: String,
+    /// Space separated scopes granted to the token.
+    pub scope: String,
+    /// The timestamp when the token was issued.
+    pub issued: DateTime<Utc>,
+    /// The timestamp when the token expires.
+    pub expires: DateTime<Utc>,
+    /// The timestamp when the token was revoked if it was.
+    pub revoked: Option<DateTime<Utc>>,
+}
+
+/// Moderation state of a received webmention.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WebmentionStatus {
+    /// Received but not verified yet.
+    #[default]
+    Pending,
+    /// Source links to the post. Waits for moderation.
+    Verified,
+    /// Shown under the post.
+    Approved,
+    /// Hidden by moderator. Sending it again doesn't bring it back to moderation.
+    Rejected,
+    /// Source can't be fetched or doesn't link to the post.
+    Invalid,
+}
+
+lowercase_names!(WebmentionStatus, "status", {
+    Pending => "pending",
+    Verified => "verified",
+    Approved => "approved",
+    Rejected => "rejected",
+    Invalid => "invalid",
+});
+
+impl WebmentionStatus {
+    /// Whether moderator may change this status to `status`. Only verified
+    /// webmentions are moderated, and the decision can be changed later.
+    #[must_use]
+    pub fn can_moderate_to(self, status: WebmentionStatus) -> bool {
+        matches!(
+            (self, status),
+            (
+                WebmentionStatus::Verified | WebmentionStatus::Rejected,
+                WebmentionStatus::Approved
+            ) | (
+                WebmentionStatus::Verified | WebmentionStatus::Approved,
+                WebmentionStatus::Rejected
+            )
+        )
+    }
+}
+
+/// How the source of a webmention refers to the post.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WebmentionKind {
+    /// Just links to the post.
+    #[default]
+    Mention,
+    /// `u-in-reply-to` the post.
+    Reply,
+    /// `u-like-of` the post.
+    Like,
+    /// `u-repost-of` the post.
+    Repost,
+    /// `u-bookmark-of` the post.
+    Bookmark,
+}
+
+lowercase_names!(WebmentionKind, "kind", {
+    Mention => "mention",
+    Reply => "reply",
+    Like => "like",
+    Repost => "repost",
+    Bookmark => "bookmark",
+});
+
+impl WebmentionKind {
+    /// Reactions are shown as author avatars without content.
+    #[must_use]
+    pub fn is_reaction(&self) -> bool {
+        matches!(
+            self,
+            WebmentionKind::Like | WebmentionKind::Repost | WebmentionKind::Bookmark
+        )
+    }
+}
+
+/// Webmention received from another site.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Webmention {
+    /// The unique ID of the webmention.
+    pub id: i64,
+    /// The URL of the page that mentions the post.
+    pub source: String,
+    /// The post URL as it was sent.
+    pub target: String,
+    /// The ID of the post mentioned.
+    pub post_id: i64,
+    /// Verification and moderation state.
+    pub status: WebmentionStatus,
+    /// How the source refers to the post. Known after verification.
+    pub kind: WebmentionKind,
+    /// The source `h-entry` author name.
+    pub author_name: Option<String>,
+    /// The source `h-entry` author home page.
+    pub author_url: Option<String>,
+    /// The source `h-entry` author avatar.
+    pub author_photo: Option<String>,
+    /// The source `h-entry` plain text content.
+    pub content: Option<String>,
+    /// The source `h-entry` publication time.
+    pub published: Option<DateTime<Utc>>,
+    /// The timestamp when the webmention was last received.
+    pub received: DateTime<Utc>,
+    /// The timestamp when the source was last verified.
+    pub verified: Option<DateTime<Utc>>,
+    /// Why the source is invalid.
+    pub error: Option<String>,
+}
+
+/// Delivery state of a webmention sent to a site the post links to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Waits for the next attempt.
+    #[default]
+    Pending,
+    /// Accepted by the target endpoint.
+    Sent,
+    /// Target doesn't support webmentions.
+    Unsupported,
+    /// All attempts failed.
+    Failed,
+}
+
+lowercase_names!(DeliveryStatus, "status", {
+    Pending => "pending",
+    Sent => "sent",
+    Unsupported => "unsupported",
+    Failed => "failed",
+});
+
+/// Webmention sent to a site the post links to.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct OutgoingWebmention {
+    /// The unique ID of the webmention.
+    pub id: i64,
+    /// The ID of the post that links to the target.
+    pub post_id: i64,
+    /// The post URL.
+    pub source: String,
+    /// The URL the post links to.
+    pub target: String,
+    /// Delivery state.
+    pub status: DeliveryStatus,
+    /// The webmention endpoint of the target once discovered.
+    pub endpoint: Option<String>,
+    /// The number of failed attempts.
+    pub attempts: i32,
+    /// The time of the next attempt of pending webmention.
+    pub next_attempt: DateTime<Utc>,
+    /// The timestamp of the last attempt.
+    pub last_attempt: Option<DateTime<Utc>>,
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+}
+
+/// Verified subscription of the built-in `WebSub` hub to a site feed.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct WebSubSubscription {
+    /// The unique ID of the subscription.
+    pub id: i64,
+    /// The feed URL subscribed to.
+    pub topic: String,
+    /// The subscriber URL new feed content is delivered to.
+    pub callback: String,
+    /// The secret content signatures are made with.
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    /// When subscription was verified last time.
+    pub created: DateTime<Utc>,
+    /// When subscription lease ends.
+    pub expires: DateTime<Utc>,
+    /// Digest of the feed content delivered to the subscriber last time.
+    #[serde(skip_serializing)]
+    pub delivered: Option<String>,
+}
+
 pub trait Storage {
     type Err: Sync + Send + Error + 'static;
 
-    fn new_database(&self) -> Result<(), Self::Err>;
+    fn new_database(&mut self) -> Result<(), Self::Err>;
     fn get_small_posts(
         &self,
         limit: i32,
@@ -301,12 +717,46 @@ pub trait Storage {
     ) -> Result<Vec<SmallPost>, Self::Err>;
     fn get_posts(&self, limit: i32, offset: i32) -> Result<Vec<Post>, Self::Err>;
     fn get_post(&self, id: i64) -> Result<Post, Self::Err>;
+    /// Finds post ID by slug. Live posts are looked up first, then deleted ones,
+    /// then slugs posts had before they were changed.
+    fn get_post_id(&self, slug: &str) -> Result<i64, Self::Err>;
     fn get_new_post_id(&self, id: i64) -> Result<i64, Self::Err>;
+    /// Gets the public post created just before the post if any.
+    fn get_previous_post(&self, id: i64) -> Result<Option<SmallPost>, Self::Err>;
+    /// Gets the public post created just after the post if any.
+    fn get_next_post(&self, id: i64) -> Result<Option<SmallPost>, Self::Err>;
+    /// Gets public posts similar to the post ranked by shared tags and then by text similarity.
+    /// Posts having neither common tags nor common words are not related.
+    fn get_related_posts(&self, id: i64, limit: usize) -> Result<Vec<SmallPost>, Self::Err>;
+    /// Inserts or updates post. Slug is made unique, generated from the title
+    /// if post has none and kept unchanged if post is updated without slug.
     fn upsert_post(&mut self, post: Post) -> Result<(), Self::Err>;
     fn next_post_id(&mut self) -> Result<i64, Self::Err>;
+    /// Deletes post keeping its copy so that it can be restored by `upsert_post`.
     fn delete_post(&mut self, id: i64) -> Result<usize, Self::Err>;
+    /// Gets the last deleted copy of the post.
+    fn get_deleted_post(&self, id: i64) -> Result<Post, Self::Err>;
+    /// Gets revisions of the post newest first.
+    fn get_post_revisions(&self, post_id: i64) -> Result<Vec<PostRevision>, Self::Err>;
+    fn get_post_revision(&self, post_id: i64, id: i64) -> Result<PostRevision, Self::Err>;
+    /// Gets not yet public posts with the status specified ordered by publication time.
+    fn get_posts_by_status(&self, status: PostStatus) -> Result<Vec<Post>, Self::Err>;
+    /// Makes public scheduled posts which publication time is not after `now`.
+    /// Returns IDs of the posts published.
+    fn publish_scheduled_posts(&mut self, now: DateTime<Utc>) -> Result<Vec<i64>, Self::Err>;
     fn count_posts(&self, request: PostsRequest) -> Result<i32, Self::Err>;
     fn get_aggregate_tags(&self) -> Result<Vec<TagAggregate>, Self::Err>;
+    /// Gets all known tags ordered by tag including ones no post has.
+    fn get_tags(&self) -> Result<Vec<TagInfo>, Self::Err>;
+    fn get_tag(&self, tag: &str) -> Result<TagInfo, Self::Err>;
+    /// Updates title and description of the tag. Returns the number of tags updated.
+    fn update_tag(&mut self, tag: &TagInfo) -> Result<usize, Self::Err>;
+    /// Replaces tags with `into` in all posts. Renaming is merging of a single tag.
+    /// Merged tags are removed, `into` gets their title and description if it has none.
+    /// Returns the number of posts changed.
+    fn merge_tags(&mut self, tags: &[String], into: &str) -> Result<usize, Self::Err>;
+    /// Deletes tags no post has. Returns tags deleted.
+    fn delete_orphan_tags(&mut self) -> Result<Vec<String>, Self::Err>;
     fn get_posts_create_dates(&self) -> Result<Vec<DateTime<Utc>>, Self::Err>;
     fn get_posts_ids(&self) -> Result<Vec<i64>, Self::Err>;
     fn get_oauth_provider(&self, name: &str) -> Result<OAuthProvider, Self::Err>;
@@ -315,11 +765,77 @@ pub trait Storage {
     fn get_folders(&self) -> Result<Vec<Folder>, Self::Err>;
     fn get_download(&self, id: i64) -> Result<Download, Self::Err>;
     fn upsert_download(&mut self, download: Download) -> Result<(), Self::Err>;
+    fn upsert_folder(&mut self, folder: Folder) -> Result<(), Self::Err>;
     fn delete_download(&mut self, id: i64) -> Result<usize, Self::Err>;
     fn get_downloads(&self, limit: i32, offset: i32) -> Result<Vec<Download>, Self::Err>;
     fn count_downloads(&self) -> Result<i32, Self::Err>;
     fn get_users(&self) -> Result<Vec<User>, Self::Err>;
     fn count_users(&self) -> Result<i32, Self::Err>;
+    /// Full-text search over posts ranked by relevance. `short_text` of every
+    /// found post contains HTML snippet with highlighted matches.
+    fn search_posts(
+        &self,
+        query: &str,
+        limit: i32,
+        offset: i32,
+        include_private: bool,
+    ) -> Result<Vec<SmallPost>, Self::Err>;
+    fn count_search_results(&self, query: &str, include_private: bool) -> Result<i32, Self::Err>;
+    fn insert_token(&mut self, token: &IndieToken) -> Result<(), Self::Err>;
+    fn get_token(&self, jti: &str) -> Result<IndieToken, Self::Err>;
+    /// Marks token revoked. Returns the number of tokens revoked that is zero
+    /// if token is unknown or has already been revoked.
+    fn revoke_token(&mut self, jti: &str) -> Result<usize, Self::Err>;
+    /// Gets tokens that are neither expired nor revoked, optionally only of the client.
+    fn get_active_tokens(&self, client_id: Option<&str>) -> Result<Vec<IndieToken>, Self::Err>;
+    /// Queues received webmention for verification. Webmention with the same source
+    /// and target is queued again unless it has been rejected. Returns webmention ID.
+    fn queue_webmention(&mut self, mention: &Webmention) -> Result<i64, Self::Err>;
+    /// Gets webmentions waiting for verification oldest first.
+    fn get_pending_webmentions(&self, limit: i32) -> Result<Vec<Webmention>, Self::Err>;
+    fn get_webmention(&self, id: i64) -> Result<Webmention, Self::Err>;
+    /// Gets webmentions with the status newest first or all of them if status is not set.
+    fn get_webmentions(
+        &self,
+        status: Option<WebmentionStatus>,
+    ) -> Result<Vec<Webmention>, Self::Err>;
+    /// Gets approved webmentions of the post oldest first.
+    fn get_post_webmentions(&self, post_id: i64) -> Result<Vec<Webmention>, Self::Err>;
+    /// Saves verification result or moderation decision. Returns the number of webmentions updated.
+    fn update_webmention(&mut self, mention: &Webmention) -> Result<usize, Self::Err>;
+    fn delete_webmention(&mut self, id: i64) -> Result<usize, Self::Err>;
+    /// Queues webmentions from the post to the targets. Already known targets are
+    /// queued again to be sent at `now`. Returns the number of webmentions queued.
+    fn queue_outgoing_webmentions(
+        &mut self,
+        post_id: i64,
+        source: &str,
+        targets: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<usize, Self::Err>;
+    /// Gets pending webmentions which next attempt is not later than `now` most overdue first.
+    fn get_due_outgoing_webmentions(
+        &self,
+        now: DateTime<Utc>,
+        limit: i32,
+    ) -> Result<Vec<OutgoingWebmention>, Self::Err>;
+    /// Gets webmentions sent from the post ordered by target.
+    fn get_outgoing_webmentions(&self, post_id: i64) -> Result<Vec<OutgoingWebmention>, Self::Err>;
+    /// Saves delivery attempt result. Returns the number of webmentions updated.
+    fn update_outgoing_webmention(
+        &mut self,
+        mention: &OutgoingWebmention,
+    ) -> Result<usize, Self::Err>;
+    /// Adds the subscription or renews existing one of the same topic and callback.
+    /// Returns subscription ID.
+    fn upsert_subscription(&mut self, subscription: &WebSubSubscription) -> Result<i64, Self::Err>;
+    /// Returns the number of subscriptions deleted.
+    fn delete_subscription(&mut self, topic: &str, callback: &str) -> Result<usize, Self::Err>;
+    /// Gets subscriptions which lease isn't over at `now` ordered by topic.
+    fn get_subscriptions(&self, now: DateTime<Utc>) -> Result<Vec<WebSubSubscription>, Self::Err>;
+    /// Saves digest of the feed content delivered to the subscriber.
+    /// Returns the number of subscriptions updated.
+    fn set_subscription_delivered(&mut self, id: i64, digest: &str) -> Result<usize, Self::Err>;
 }
 
 #[cfg(test)]
@@ -434,6 +950,90 @@ mod tests {
         assert!(actual.is_empty());
     }
 
+    #[rstest]
+    #[case(Some("blake3"), "blog/2024/blake3")]
+    #[case(Some(""), "blog/42.html")]
+    #[case(None, "blog/42.html")]
+    fn post_path_tests(#[case] slug: Option<&str>, #[case] expected: &str) {
+        // arrange
+        let created = DateTime::from_timestamp(1_717_200_000, 0).unwrap();
+
+        // act
+        let actual = post_path(42, &created, slug);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case(true, PostStatus::Draft, PostStatus::Published)]
+    #[case(true, PostStatus::Scheduled, PostStatus::Published)]
+    #[case(false, PostStatus::Published, PostStatus::Draft)]
+    #[case(false, PostStatus::Draft, PostStatus::Draft)]
+    #[case(false, PostStatus::Scheduled, PostStatus::Scheduled)]
+    fn effective_status_tests(
+        mut post: Post,
+        #[case] is_public: bool,
+        #[case] status: PostStatus,
+        #[case] expected: PostStatus,
+    ) {
+        // arrange
+        post.is_public = is_public;
+        post.status = status;
+
+        // act
+        let actual = post.effective_status();
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case("draft", Some(PostStatus::Draft))]
+    #[case("Published", Some(PostStatus::Published))]
+    #[case("SCHEDULED", Some(PostStatus::Scheduled))]
+    #[case("deleted", None)]
+    fn post_status_parse_tests(#[case] status: &str, #[case] expected: Option<PostStatus>) {
+        // arrange
+
+        // act
+        let actual = PostStatus::parse(status);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case(false, None, PostStatus::Scheduled)]
+    #[case(true, None, PostStatus::Published)]
+    #[case(false, Some("draft"), PostStatus::Draft)]
+    fn post_edit_keeps_stored_status_tests(
+        #[case] is_public: bool,
+        #[case] status: Option<&str>,
+        #[case] expected: PostStatus,
+    ) {
+        // arrange
+        let stored = Post {
+            id: 1,
+            status: PostStatus::Scheduled,
+            ..Default::default()
+        };
+        let mut json = serde_json::to_value(&stored).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("Status");
+        fields.insert("IsPublic".to_owned(), is_public.into());
+        if let Some(status) = status {
+            fields.insert("Status".to_owned(), status.into());
+        }
+        let edit: PostEdit = serde_json::from_value(json).unwrap();
+
+        // act
+        let actual = edit.into_post(Some(&stored));
+
+        // assert
+        assert_eq!(expected, actual.effective_status());
+    }
+
     #[fixture]
     fn post() -> Post {
         Post {
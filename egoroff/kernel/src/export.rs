@@ -0,0 +1,453 @@
+//! Content export into Markdown files with YAML front matter and import back.
+//!
+//! Layout of the export directory:
+//! - `posts/{id}.md` front matter with post metadata, post text is the file body as is
+//! - `downloads/{id}.md` front matter only
+//! - `folders/{bucket}.md` front matter only
+//! - `tags.md` all tags used by posts, informational only since tags are imported with posts
+//!
+//! Front matter is a restricted YAML subset: one `key: value` per line where strings
+//! and lists are written as double quoted (JSON compatible) scalars and flow sequences.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::domain::{Download, Folder, Post, PostStatus, Storage};
+
+const POSTS_DIR: &str = "posts";
+const DOWNLOADS_DIR: &str = "downloads";
+const FOLDERS_DIR: &str = "folders";
+const TAGS_FILE: &str = "tags.md";
+const EXTENSION: &str = "md";
+const DELIMITER: &str = "---";
+
+/// Number of items exported or imported.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub posts: usize,
+    pub downloads: usize,
+    pub folders: usize,
+    pub tags: usize,
+}
+
+/// Writes all posts, downloads, folders and tags into the directory.
+pub fn export<S: Storage>(storage: &S, dir: &Path) -> Result<Summary> {
+    let posts = storage.get_posts(-1, 0)?;
+    let downloads = storage.get_downloads(-1, 0)?;
+    let folders = storage.get_folders()?;
+    let tags: BTreeSet<&str> = posts
+        .iter()
+        .flat_map(|p| p.tags.iter().map(String::as_str))
+        .collect();
+
+    for (name, items) in [
+        (
+            POSTS_DIR,
+            posts
+                .iter()
+                .map(|p| (p.id.to_string(), post_to_markdown(p)))
+                .collect::<Vec<_>>(),
+        ),
+        (
+            DOWNLOADS_DIR,
+            downloads
+                .iter()
+                .map(|d| (d.id.to_string(), download_to_markdown(d)))
+                .collect(),
+        ),
+        (
+            FOLDERS_DIR,
+            folders
+                .iter()
+                .map(|f| (f.bucket.clone(), folder_to_markdown(f)))
+                .collect(),
+        ),
+    ] {
+        let sub_dir = dir.join(name);
+        fs::create_dir_all(&sub_dir)
+            .with_context(|| format!("Failed to create {}", sub_dir.display()))?;
+        for (file_name, content) in items {
+            let path = sub_dir.join(file_name).with_extension(EXTENSION);
+            fs::write(&path, content)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+
+    let mut tags_content = front_matter(&[("tags", list(tags.iter().copied()))]);
+    tags_content.push('\n');
+    fs::write(dir.join(TAGS_FILE), tags_content)?;
+
+    Ok(Summary {
+        posts: posts.len(),
+        downloads: downloads.len(),
+        folders: folders.len(),
+        tags: tags.len(),
+    })
+}
+
+/// Loads posts, downloads and folders previously exported into the directory.
+/// Storage should preserve `modified` time of posts to get exactly the same export back.
+pub fn import<S: Storage>(storage: &mut S, dir: &Path) -> Result<Summary> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()));
+    }
+    let mut summary = Summary::default();
+    let mut tags = BTreeSet::new();
+
+    for content in read_documents(&dir.join(POSTS_DIR))? {
+        let post = post_from_markdown(&content)?;
+        tags.extend(post.tags.iter().cloned());
+        storage.upsert_post(post)?;
+        summary.posts += 1;
+    }
+    for content in read_documents(&dir.join(DOWNLOADS_DIR))? {
+        storage.upsert_download(download_from_markdown(&content)?)?;
+        summary.downloads += 1;
+    }
+    for content in read_documents(&dir.join(FOLDERS_DIR))? {
+        storage.upsert_folder(folder_from_markdown(&content)?)?;
+        summary.folders += 1;
+    }
+    summary.tags = tags.len();
+    Ok(summary)
+}
+
+/// Makes Markdown document with post metadata in front matter and post text as body.
+#[must_use]
+pub fn post_to_markdown(post: &Post) -> String {
+    let mut document = front_matter(&[
+        ("id", post.id.to_string()),
+        ("title", string(&post.title)),
+        ("slug", post.slug.as_deref().map(string).unwrap_or_default()),
+        ("created", datetime(&post.created)),
+        ("modified", datetime(&post.modified)),
+        ("status", post.status.as_str().to_owned()),
+        ("is_public", post.is_public.to_string()),
+        ("markdown", post.markdown.to_string()),
+        ("tags", list(post.tags.iter().map(String::as_str))),
+        ("short_text", string(&post.short_text)),
+    ]);
+    document.push_str(&post.text);
+    document
+}
+
+/// Parses document made by `post_to_markdown`.
+pub fn post_from_markdown(document: &str) -> Result<Post> {
+    let (fields, text) = parse_front_matter(document)?;
+    let slug = fields.optional("slug");
+    Ok(Post {
+        id: fields.parse("id")?,
+        title: fields.string("title")?,
+        slug: slug.map(parse_string).transpose()?,
+        created: fields.datetime("created")?,
+        modified: fields.datetime("modified")?,
+        status: PostStatus::parse(fields.get("status")?)
+            .ok_or_else(|| anyhow!("Invalid post status"))?,
+        is_public: fields.parse("is_public")?,
+        markdown: fields.parse("markdown")?,
+        tags: fields.list("tags")?,
+        short_text: fields.string("short_text")?,
+        text: text.to_owned(),
+    })
+}
+
+#[must_use]
+pub fn download_to_markdown(download: &Download) -> String {
+    front_matter(&[
+        ("id", download.id.to_string()),
+        ("title", string(&download.title)),
+    ])
+}
+
+pub fn download_from_markdown(document: &str) -> Result<Download> {
+    let (fields, _) = parse_front_matter(document)?;
+    Ok(Download {
+        id: fields.parse("id")?,
+        title: fields.string("title")?,
+    })
+}
+
+#[must_use]
+pub fn folder_to_markdown(folder: &Folder) -> String {
+    front_matter(&[
+        ("bucket", string(&folder.bucket)),
+        ("title", string(&folder.title)),
+    ])
+}
+
+pub fn folder_from_markdown(document: &str) -> Result<Folder> {
+    let (fields, _) = parse_front_matter(document)?;
+    Ok(Folder {
+        bucket: fields.string("bucket")?,
+        title: fields.string("title")?,
+    })
+}
+
+/// Reads all documents of the directory ordered by file name. Missing directory has no documents.
+fn read_documents(dir: &Path) -> Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == EXTENSION))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|p| fs::read_to_string(p).with_context(|| format!("Failed to read {}", p.display())))
+        .collect()
+}
+
+fn front_matter(fields: &[(&str, String)]) -> String {
+    let mut result = format!("{DELIMITER}\n");
+    for (key, value) in fields {
+        if value.is_empty() {
+            continue;
+        }
+        result.push_str(key);
+        result.push_str(": ");
+        result.push_str(value);
+        result.push('\n');
+    }
+    result.push_str(DELIMITER);
+    result.push('\n');
+    result
+}
+
+/// Splits document into front matter fields and body.
+fn parse_front_matter(document: &str) -> Result<(Fields<'_>, &str)> {
+    let rest = document
+        .strip_prefix(DELIMITER)
+        .and_then(|r| r.strip_prefix('\n'))
+        .ok_or_else(|| anyhow!("Document must start with front matter"))?;
+
+    let mut fields = HashMap::new();
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        if line == DELIMITER {
+            return Ok((Fields(fields), &rest[offset..]));
+        }
+        let (key, value) = line
+            .split_once(": ")
+            .ok_or_else(|| anyhow!("Invalid front matter line: {line}"))?;
+        fields.insert(key, value);
+    }
+    Err(anyhow!("Front matter is not closed"))
+}
+
+struct Fields<'a>(HashMap<&'a str, &'a str>);
+
+impl<'a> Fields<'a> {
+    fn optional(&self, key: &str) -> Option<&'a str> {
+        self.0.get(key).copied()
+    }
+
+    fn get(&self, key: &str) -> Result<&'a str> {
+        self.0
+            .get(key)
+            .copied()
+            .ok_or_else(|| anyhow!("Front matter field '{key}' is missing"))
+    }
+
+    fn parse<T: std::str::FromStr>(&self, key: &str) -> Result<T> {
+        self.get(key)?
+            .parse()
+            .map_err(|_| anyhow!("Front matter field '{key}' is invalid"))
+    }
+
+    fn string(&self, key: &str) -> Result<String> {
+        self.optional(key)
+            .map_or_else(|| Ok(String::new()), parse_string)
+    }
+
+    fn datetime(&self, key: &str) -> Result<DateTime<Utc>> {
+        let value = self.get(key)?;
+        Ok(DateTime::parse_from_rfc3339(value)
+            .with_context(|| format!("Front matter field '{key}' is invalid"))?
+            .with_timezone(&Utc))
+    }
+
+    fn list(&self, key: &str) -> Result<Vec<String>> {
+        self.optional(key).map_or_else(
+            || Ok(vec![]),
+            |v| {
+                serde_json::from_str(v)
+                    .with_context(|| format!("Front matter field '{key}' is invalid"))
+            },
+        )
+    }
+}
+
+/// Double quoted scalar. JSON escapes are valid YAML escapes.
+fn string(value: &str) -> String {
+    if value.is_empty() {
+        return String::new();
+    }
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn parse_string(value: &str) -> Result<String> {
+    serde_json::from_str(value).with_context(|| format!("Invalid string {value}"))
+}
+
+fn list<'a>(items: impl Iterator<Item = &'a str>) -> String {
+    let items: Vec<&str> = items.collect();
+    if items.is_empty() {
+        return String::new();
+    }
+    serde_json::to_string(&items).unwrap_or_default()
+}
+
+fn datetime(value: &DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::memory::Memory;
+    use crate::testing::temp_dir;
+    use rstest::rstest;
+
+    #[test]
+    fn post_to_markdown_test() {
+        // arrange
+        let post = post(
+            1,
+            "Заголовок \"в кавычках\"",
+            "Текст\n---\nпосле разделителя",
+        );
+
+        // act
+        let actual = post_to_markdown(&post);
+
+        // assert
+        assert_eq!(
+            "---\nid: 1\ntitle: \"Заголовок \\\"в кавычках\\\"\"\nslug: \"zagolovok\"\n\
+             created: 2023-05-01T10:00:00Z\nmodified: 2023-05-02T10:00:00Z\nstatus: published\n\
+             is_public: true\nmarkdown: true\ntags: [\"rust\",\"веб\"]\nshort_text: \"Кратко\\nи ясно\"\n\
+             ---\nТекст\n---\nпосле разделителя",
+            actual
+        );
+    }
+
+    #[rstest]
+    #[case("Текст\n---\nпосле разделителя")]
+    #[case("")]
+    #[case("\n\nпустые строки вокруг\n\n")]
+    fn post_markdown_round_trip(#[case] text: &str) {
+        // arrange
+        let post = post(2, "Заголовок", text);
+
+        // act
+        let actual = post_from_markdown(&post_to_markdown(&post)).unwrap();
+
+        // assert
+        assert_eq!(post_to_markdown(&post), post_to_markdown(&actual));
+        assert_eq!(text, actual.text);
+        assert_eq!(post.short_text, actual.short_text);
+        assert_eq!(post.tags, actual.tags);
+        assert_eq!(post.modified, actual.modified);
+    }
+
+    #[rstest]
+    #[case("no front matter")]
+    #[case("---\nid: 1\n")]
+    #[case("---\nid 1\n---\n")]
+    #[case("---\ntitle: \"no id\"\n---\n")]
+    fn post_from_markdown_invalid(#[case] document: &str) {
+        // arrange
+
+        // act
+        let actual = post_from_markdown(document);
+
+        // assert
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        // arrange
+        let mut storage = Memory::new().preserve_modified();
+        storage.upsert_post(post(1, "Первый", "Текст\n")).unwrap();
+        let mut draft = post(2, "Черновик", "Без перевода строки");
+        draft.is_public = false;
+        draft.status = PostStatus::Draft;
+        draft.slug = None;
+        draft.tags = vec![];
+        storage.upsert_post(draft).unwrap();
+        storage
+            .upsert_download(Download {
+                id: 5,
+                title: "Утилита".to_owned(),
+            })
+            .unwrap();
+        storage
+            .upsert_folder(Folder {
+                bucket: "apps".to_owned(),
+                title: "Программы".to_owned(),
+            })
+            .unwrap();
+        let first = temp_dir("export_first");
+        let second = temp_dir("export_second");
+
+        // act
+        let exported = export(&storage, &first).unwrap();
+        let mut imported_storage = Memory::new().preserve_modified();
+        let imported = import(&mut imported_storage, &first).unwrap();
+        export(&imported_storage, &second).unwrap();
+
+        // assert
+        let expected = Summary {
+            posts: 2,
+            downloads: 1,
+            folders: 1,
+            tags: 2,
+        };
+        assert_eq!(expected, exported);
+        assert_eq!(expected, imported);
+        for file in [
+            "posts/1.md",
+            "posts/2.md",
+            "downloads/5.md",
+            "folders/apps.md",
+            "tags.md",
+        ] {
+            assert_eq!(
+                fs::read(first.join(file)).unwrap(),
+                fs::read(second.join(file)).unwrap(),
+                "{file}"
+            );
+        }
+        fs::remove_dir_all(first).unwrap();
+        fs::remove_dir_all(second).unwrap();
+    }
+
+    fn post(id: i64, title: &str, text: &str) -> Post {
+        Post {
+            id,
+            title: title.to_owned(),
+            short_text: "Кратко\nи ясно".to_owned(),
+            text: text.to_owned(),
+            markdown: true,
+            is_public: true,
+            tags: vec!["rust".to_owned(), "веб".to_owned()],
+            slug: Some("zagolovok".to_owned()),
+            status: PostStatus::Published,
+            created: "2023-05-01T10:00:00Z".parse().unwrap(),
+            modified: "2023-05-02T10:00:00Z".parse().unwrap(),
+        }
+    }
+}
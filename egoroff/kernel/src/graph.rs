@@ -104,6 +104,23 @@ impl<'a> SiteGraph<'a> {
             .join(" | ")
     }
 
+    /// Paths of all sections like `/` and `/blog/` parents first.
+    #[must_use]
+    pub fn uris(&self) -> Vec<String> {
+        let mut uris = vec![];
+        if let Some(root) = self.get_section(SEP) {
+            Self::collect_uris(&mut uris, root, SEP);
+        }
+        uris
+    }
+
+    fn collect_uris(uris: &mut Vec<String>, section: &SiteSection, uri: &str) {
+        uris.push(uri.to_owned());
+        for child in section.children.iter().flatten() {
+            Self::collect_uris(uris, child, &format!("{uri}{}{SEP}", child.id));
+        }
+    }
+
     fn to_path<'b>(&'b self, uri: &'b str) -> Option<impl Iterator<Item = &'b SiteSection>> {
         let root = self.get_section(SEP)?;
 
@@ -194,6 +211,18 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[rstest]
+    fn uris_test(root: SiteSection) {
+        // arrange
+        let graph = SiteGraph::new(&root);
+
+        // act
+        let actual = graph.uris();
+
+        // assert
+        assert_eq!(vec!["/", "/a/", "/a/aa/", "/b/", "/b/bb/"], actual);
+    }
+
     #[fixture]
     fn root() -> SiteSection {
         let aa = SiteSection {
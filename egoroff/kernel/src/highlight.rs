@@ -0,0 +1,168 @@
+//! Server side syntax highlighting of code blocks. Highlighted code has `hl-` prefixed
+//! classes so that the stylesheet made by [`css`] styles it.
+
+use std::sync::LazyLock;
+
+use anyhow::Result;
+use syntect::{
+    html::{ClassStyle, ClassedHTMLGenerator, css_for_theme_with_class_style},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+use two_face::theme::EmbeddedThemeName;
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Language names used in posts that differ from syntax names and extensions.
+const ALIASES: &[(&str, &str)] = &[
+    ("apache", "Apache Conf"),
+    ("apacheconf", "Apache Conf"),
+    ("htaccess", "Apache Conf"),
+    ("c#", "C#"),
+    ("csharp", "C#"),
+    ("hq", "C#"),
+    ("c++", "C++"),
+    ("golang", "Go"),
+];
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(two_face::syntax::extra_newlines);
+
+/// Highlights code as `pre` block or returns `None` if the language isn't known.
+/// Language may be followed by other info like in `rust,ignore`.
+pub fn highlight(code: &str, lang: &str) -> Result<Option<String>> {
+    let lang = lang
+        .split([',', ' ', '{', ';'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let Some(syntax) = find_syntax(&lang) else {
+        return Ok(None);
+    };
+
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line)?;
+    }
+    let html = generator.finalize();
+
+    let lang: String = lang
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '+' | '#' | '-' | '_'))
+        .collect();
+    Ok(Some(format!(
+        "<pre class=\"highlight\"><code class=\"language-{lang}\">{html}</code></pre>\n"
+    )))
+}
+
+/// Stylesheet of highlighted code.
+pub fn css() -> Result<String> {
+    let themes = two_face::theme::extra();
+    let theme = themes.get(EmbeddedThemeName::Github);
+    let mut css = css_for_theme_with_class_style(theme, CLASS_STYLE)?;
+    if let Some(bg) = theme.settings.background {
+        css.push_str(&format!(
+            "pre.highlight {{\n background-color: #{:02x}{:02x}{:02x};\n padding: 0.5rem;\n}}\n",
+            bg.r, bg.g, bg.b
+        ));
+    }
+    Ok(css)
+}
+
+fn find_syntax(lang: &str) -> Option<&'static SyntaxReference> {
+    if lang.is_empty() {
+        return None;
+    }
+    match ALIASES.iter().find(|(alias, _)| *alias == lang) {
+        Some((_, name)) => SYNTAXES.find_syntax_by_name(name),
+        None => SYNTAXES.find_syntax_by_token(lang),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("rust", "Rust")]
+    #[case("rs", "Rust")]
+    #[case("c", "C")]
+    #[case("cpp", "C++")]
+    #[case("c++", "C++")]
+    #[case("cs", "C#")]
+    #[case("csharp", "C#")]
+    #[case("go", "Go")]
+    #[case("zig", "Zig")]
+    #[case("python", "Python")]
+    #[case("py", "Python")]
+    #[case("xml", "XML")]
+    #[case("apache", "Apache Conf")]
+    fn find_syntax_tests(#[case] lang: &str, #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = find_syntax(lang).unwrap();
+
+        // assert
+        assert_eq!(expected, actual.name);
+    }
+
+    #[rstest]
+    #[case("rust")]
+    #[case("Rust,ignore")]
+    #[case("rust {.numbers}")]
+    fn highlight_known_language(#[case] lang: &str) {
+        // arrange
+        let code = "fn main() {}\n";
+
+        // act
+        let actual = highlight(code, lang).unwrap().unwrap();
+
+        // assert
+        assert!(actual.starts_with(r#"<pre class="highlight"><code class="language-rust">"#));
+        assert!(
+            actual.contains(r#"<span class="hl-storage hl-type hl-function hl-rust">fn</span>"#)
+        );
+        assert!(actual.ends_with("</code></pre>\n"));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("parser3")]
+    fn highlight_unknown_language(#[case] lang: &str) {
+        // arrange
+
+        // act
+        let actual = highlight("a", lang).unwrap();
+
+        // assert
+        assert!(actual.is_none());
+    }
+
+    #[test]
+    fn highlight_escapes_code() {
+        // arrange
+
+        // act
+        let actual = highlight("<a href=\"x\">&</a>\n", "xml").unwrap().unwrap();
+
+        // assert
+        assert!(!actual.contains("<a href"));
+        assert!(actual.contains("&lt;"));
+        assert!(actual.contains("&amp;"));
+    }
+
+    #[test]
+    fn css_has_prefixed_classes() {
+        // arrange
+
+        // act
+        let actual = css().unwrap();
+
+        // assert
+        assert!(actual.contains(".hl-"));
+        assert!(actual.contains("pre.highlight"));
+    }
+}
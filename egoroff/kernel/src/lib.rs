@@ -4,12 +4,26 @@
 #![allow(clippy::missing_errors_doc)]
 
 pub mod archive;
+pub mod backup;
+pub mod cache;
 pub mod converter;
+pub mod diff;
 pub mod domain;
+pub mod export;
 pub mod graph;
+pub mod highlight;
+pub mod memory;
+pub mod microformats;
+pub mod migration;
+pub mod pool;
+pub mod related;
 pub mod resource;
+pub mod search;
 pub mod session;
+pub mod slug;
 pub mod sqlite;
+#[cfg(test)]
+mod testing;
 pub mod typograph;
 pub mod xml;
 
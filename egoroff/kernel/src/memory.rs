@@ -0,0 +1,1126 @@
+//! In-memory storage that behaves like `Sqlite` but keeps everything in process memory.
+//! Used to test code that depends on `Storage` without database files.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    domain::{
+        DeliveryStatus, Download, Folder, IndieToken, OAuthProvider, OutgoingWebmention, Post,
+        PostRevision, PostStatus, PostsRequest, SmallPost, Storage, TagAggregate, TagInfo, User,
+        WebSubSubscription, Webmention, WebmentionStatus,
+    },
+    related, search, slug,
+    sqlite::DEFAULT_REVISIONS_LIMIT,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Requested item doesn't exist.
+    NotFound,
+    /// Item with the same key already exists.
+    AlreadyExists,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotFound => write!(f, "item not found"),
+            Error::AlreadyExists => write!(f, "item already exists"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub struct Memory {
+    posts: BTreeMap<i64, Post>,
+    deleted_posts: BTreeMap<i64, (Post, DateTime<Utc>)>,
+    revisions: Vec<PostRevision>,
+    revisions_limit: usize,
+    preserve_modified: bool,
+    /// Known tags with their title and description.
+    tags: BTreeMap<String, (Option<String>, Option<String>)>,
+    post_remap: HashMap<i64, i64>,
+    /// Post IDs by slugs posts had before they were changed.
+    previous_slugs: HashMap<String, i64>,
+    users: Vec<User>,
+    oauth_providers: HashMap<String, OAuthProvider>,
+    folders: Vec<Folder>,
+    downloads: BTreeMap<i64, Download>,
+    tokens: Vec<IndieToken>,
+    webmentions: BTreeMap<i64, Webmention>,
+    outgoing_webmentions: BTreeMap<i64, OutgoingWebmention>,
+    subscriptions: BTreeMap<i64, WebSubSubscription>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self {
+            posts: BTreeMap::new(),
+            deleted_posts: BTreeMap::new(),
+            revisions: vec![],
+            revisions_limit: DEFAULT_REVISIONS_LIMIT,
+            preserve_modified: false,
+            tags: BTreeMap::new(),
+            post_remap: HashMap::new(),
+            previous_slugs: HashMap::new(),
+            users: vec![],
+            oauth_providers: HashMap::new(),
+            folders: vec![],
+            downloads: BTreeMap::new(),
+            tokens: vec![],
+            webmentions: BTreeMap::new(),
+            outgoing_webmentions: BTreeMap::new(),
+            subscriptions: BTreeMap::new(),
+        }
+    }
+}
+
+impl Memory {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many revisions are kept per post. Zero disables revisions.
+    #[must_use]
+    pub fn with_revisions_limit(mut self, limit: usize) -> Self {
+        self.revisions_limit = limit;
+        self
+    }
+
+    /// Keeps `modified` time of upserted posts instead of setting the current time.
+    #[must_use]
+    pub fn preserve_modified(mut self) -> Self {
+        self.preserve_modified = true;
+        self
+    }
+
+    /// Adds OAuth provider settings that have no storage method to write them.
+    #[must_use]
+    pub fn with_oauth_provider(mut self, provider: OAuthProvider) -> Self {
+        self.oauth_providers.insert(provider.name.clone(), provider);
+        self
+    }
+
+    /// Maps old post ID into the new one like `post_remap` table does.
+    #[must_use]
+    pub fn with_post_remap(mut self, old_id: i64, post_id: i64) -> Self {
+        self.post_remap.insert(old_id, post_id);
+        self
+    }
+
+    /// Number of posts of every tag used.
+    fn used_tags(&self) -> HashMap<String, i32> {
+        let mut used = HashMap::new();
+        for tag in self.posts.values().flat_map(|p| &p.tags) {
+            *used.entry(tag.clone()).or_default() += 1;
+        }
+        used
+    }
+
+    fn tag_info(&self, tag: &str, used: &HashMap<String, i32>) -> Option<TagInfo> {
+        self.tags.get(tag).map(|(title, description)| TagInfo {
+            tag: tag.to_owned(),
+            title: title.clone(),
+            description: description.clone(),
+            posts: used.get(tag).copied().unwrap_or_default(),
+        })
+    }
+
+    /// Posts ordered from newest to oldest.
+    fn newest_posts(&self) -> impl Iterator<Item = &Post> {
+        let mut posts: Vec<&Post> = self.posts.values().collect();
+        posts.sort_by_key(|p| Reverse(p.created));
+        posts.into_iter()
+    }
+
+    fn public_posts(&self, request: &PostsRequest) -> impl Iterator<Item = &Post> {
+        let include_private = request.include_private.unwrap_or(false);
+        let period = request.as_query_period();
+        let tag = request.tag.clone();
+        self.newest_posts().filter(move |p| {
+            (p.is_public || include_private)
+                && tag.as_ref().is_none_or(|t| p.tags.contains(t))
+                && period
+                    .as_ref()
+                    .is_none_or(|period| p.created > period.from && p.created < period.to)
+        })
+    }
+
+    fn post_slug(&self, p: &Post) -> String {
+        let requested = p
+            .slug
+            .as_deref()
+            .map(slug::slugify)
+            .filter(|s| !s.is_empty());
+        let base = match requested {
+            Some(s) => s,
+            None => {
+                if let Some(current) = self.posts.get(&p.id).and_then(|c| c.slug.clone()) {
+                    return current;
+                }
+                let generated = slug::slugify(&p.title);
+                if generated.is_empty() {
+                    format!("post-{}", p.id)
+                } else {
+                    generated
+                }
+            }
+        };
+        let exists = |s: &str| {
+            Ok::<_, Error>(
+                self.posts
+                    .values()
+                    .any(|o| o.id != p.id && o.slug.as_deref() == Some(s)),
+            )
+        };
+        slug::unique(&base, exists).unwrap_or(base)
+    }
+
+    /// Saves post content as a new revision unless it equals the latest one
+    /// and removes revisions beyond the limit.
+    fn insert_revision(&mut self, p: &Post) {
+        if self.revisions_limit == 0 {
+            return;
+        }
+        let latest = self.revisions.iter().rev().find(|r| r.post_id == p.id);
+        if let Some(latest) = latest
+            && latest.title == p.title
+            && latest.short_text == p.short_text
+            && latest.text == p.text
+            && latest.markdown == p.markdown
+            && latest.tags == p.tags
+        {
+            return;
+        }
+
+        let id = self.revisions.last().map_or(1, |r| r.id + 1);
+        self.revisions.push(PostRevision {
+            id,
+            post_id: p.id,
+            created: Utc::now(),
+            title: p.title.clone(),
+            short_text: p.short_text.clone(),
+            text: p.text.clone(),
+            markdown: p.markdown,
+            tags: p.tags.clone(),
+        });
+
+        let kept = self.revisions.iter().filter(|r| r.post_id == p.id).count();
+        let mut excess = kept.saturating_sub(self.revisions_limit);
+        self.revisions.retain(|r| {
+            if r.post_id == p.id && excess > 0 {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Matches post if every query word is a prefix of some title or text word.
+    /// Returns number of words matched in title to rank results.
+    fn search_rank(post: &Post, stems: &[String]) -> Option<usize> {
+        let title = search::normalize(&post.title);
+        let body = search::normalize(&search::plain_text(post));
+        let title: Vec<&str> = title.split(' ').collect();
+        let body: Vec<&str> = body.split(' ').collect();
+
+        let mut rank = 0;
+        for stem in stems {
+            if title.iter().any(|w| w.starts_with(stem.as_str())) {
+                rank += 1;
+            } else if !body.iter().any(|w| w.starts_with(stem.as_str())) {
+                return None;
+            }
+        }
+        Some(rank)
+    }
+
+    fn search(&self, query: &str, include_private: bool) -> Vec<&Post> {
+        let stems: Vec<String> = search::words(query).map(|w| search::stem(&w)).collect();
+        if stems.is_empty() {
+            return vec![];
+        }
+        let mut found: Vec<(usize, &Post)> = self
+            .newest_posts()
+            .filter(|p| p.is_public || include_private)
+            .filter_map(|p| Memory::search_rank(p, &stems).map(|rank| (rank, p)))
+            .collect();
+        found.sort_by_key(|(rank, _)| Reverse(*rank));
+        found.into_iter().map(|(_, p)| p).collect()
+    }
+}
+
+impl Storage for Memory {
+    type Err = Error;
+
+    fn new_database(&mut self) -> Result<(), Self::Err> {
+        Ok(())
+    }
+
+    fn get_small_posts(
+        &self,
+        limit: i32,
+        offset: i32,
+        request: PostsRequest,
+    ) -> Result<Vec<SmallPost>, Self::Err> {
+        let request = PostsRequest {
+            include_private: None,
+            ..request
+        };
+        Ok(page(self.public_posts(&request), limit, offset)
+            .map(SmallPost::from)
+            .collect())
+    }
+
+    fn get_posts(&self, limit: i32, offset: i32) -> Result<Vec<Post>, Self::Err> {
+        Ok(page(self.newest_posts(), limit, offset).cloned().collect())
+    }
+
+    fn get_post(&self, id: i64) -> Result<Post, Self::Err> {
+        self.posts.get(&id).cloned().ok_or(Error::NotFound)
+    }
+
+    fn get_post_id(&self, slug: &str) -> Result<i64, Self::Err> {
+        let slug = Some(slug);
+        self.posts
+            .values()
+            .chain(self.deleted_posts.values().map(|(p, _)| p))
+            .find(|p| p.slug.as_deref() == slug)
+            .map(|p| p.id)
+            .or_else(|| slug.and_then(|s| self.previous_slugs.get(s)).copied())
+            .ok_or(Error::NotFound)
+    }
+
+    fn get_new_post_id(&self, id: i64) -> Result<i64, Self::Err> {
+        self.post_remap.get(&id).copied().ok_or(Error::NotFound)
+    }
+
+    fn get_previous_post(&self, id: i64) -> Result<Option<SmallPost>, Self::Err> {
+        let post = self.posts.get(&id).ok_or(Error::NotFound)?;
+        let key = (post.created, post.id);
+        Ok(self
+            .posts
+            .values()
+            .filter(|p| p.is_public && (p.created, p.id) < key)
+            .max_by_key(|p| (p.created, p.id))
+            .map(SmallPost::from))
+    }
+
+    fn get_next_post(&self, id: i64) -> Result<Option<SmallPost>, Self::Err> {
+        let post = self.posts.get(&id).ok_or(Error::NotFound)?;
+        let key = (post.created, post.id);
+        Ok(self
+            .posts
+            .values()
+            .filter(|p| p.is_public && (p.created, p.id) > key)
+            .min_by_key(|p| (p.created, p.id))
+            .map(SmallPost::from))
+    }
+
+    fn get_related_posts(&self, id: i64, limit: usize) -> Result<Vec<SmallPost>, Self::Err> {
+        let post = self.posts.get(&id).ok_or(Error::NotFound)?;
+        let post = related::Candidate::rendered(post.clone());
+        let candidates: Vec<related::Candidate> = self
+            .posts
+            .values()
+            .filter(|p| p.is_public)
+            .cloned()
+            .map(related::Candidate::rendered)
+            .collect();
+        Ok(related::rank(&post, &candidates, limit))
+    }
+
+    fn upsert_post(&mut self, mut post: Post) -> Result<(), Self::Err> {
+        let slug = self.post_slug(&post);
+        self.previous_slugs.remove(&slug);
+        if let Some(current) = self.posts.get(&post.id).and_then(|p| p.slug.clone())
+            && current != slug
+        {
+            self.previous_slugs.insert(current, post.id);
+        }
+        post.slug = Some(slug);
+        post.status = post.effective_status();
+        if !self.preserve_modified {
+            post.modified = Utc::now();
+        }
+        let mut tags: Vec<String> = Vec::with_capacity(post.tags.len());
+        for tag in post.tags {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        post.tags = tags;
+        for tag in &post.tags {
+            self.tags.entry(tag.clone()).or_default();
+        }
+
+        self.insert_revision(&post);
+        self.deleted_posts.remove(&post.id);
+        self.posts.insert(post.id, post);
+        // Tags with presentation settings survive until deleted explicitly
+        let used = self.used_tags();
+        self.tags.retain(|tag, (title, description)| {
+            used.contains_key(tag.as_str()) || title.is_some() || description.is_some()
+        });
+        Ok(())
+    }
+
+    fn next_post_id(&mut self) -> Result<i64, Self::Err> {
+        Ok(self.posts.keys().next_back().map_or(1, |id| id + 1))
+    }
+
+    fn delete_post(&mut self, id: i64) -> Result<usize, Self::Err> {
+        match self.posts.remove(&id) {
+            Some(post) => {
+                self.deleted_posts.insert(id, (post, Utc::now()));
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn get_deleted_post(&self, id: i64) -> Result<Post, Self::Err> {
+        self.deleted_posts
+            .get(&id)
+            .map(|(p, _)| p.clone())
+            .ok_or(Error::NotFound)
+    }
+
+    fn get_post_revisions(&self, post_id: i64) -> Result<Vec<PostRevision>, Self::Err> {
+        Ok(self
+            .revisions
+            .iter()
+            .rev()
+            .filter(|r| r.post_id == post_id)
+            .cloned()
+            .collect())
+    }
+
+    fn get_post_revision(&self, post_id: i64, id: i64) -> Result<PostRevision, Self::Err> {
+        self.revisions
+            .iter()
+            .find(|r| r.post_id == post_id && r.id == id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    fn get_posts_by_status(&self, status: PostStatus) -> Result<Vec<Post>, Self::Err> {
+        let mut posts: Vec<Post> = self
+            .posts
+            .values()
+            .filter(|p| p.status == status && !p.is_public)
+            .cloned()
+            .collect();
+        posts.sort_by_key(|p| p.created);
+        Ok(posts)
+    }
+
+    fn publish_scheduled_posts(&mut self, now: DateTime<Utc>) -> Result<Vec<i64>, Self::Err> {
+        let mut ids = vec![];
+        for post in self.posts.values_mut() {
+            if post.status == PostStatus::Scheduled && post.created <= now {
+                post.status = PostStatus::Published;
+                post.is_public = true;
+                ids.push(post.id);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn count_posts(&self, request: PostsRequest) -> Result<i32, Self::Err> {
+        Ok(count(self.public_posts(&request)))
+    }
+
+    fn get_aggregate_tags(&self) -> Result<Vec<TagAggregate>, Self::Err> {
+        let mut tags: BTreeMap<&str, i32> = BTreeMap::new();
+        for tag in self
+            .posts
+            .values()
+            .filter(|p| p.is_public)
+            .flat_map(|p| &p.tags)
+        {
+            *tags.entry(tag).or_default() += 1;
+        }
+        Ok(tags
+            .into_iter()
+            .map(|(title, count)| TagAggregate {
+                title: title.to_owned(),
+                count,
+            })
+            .collect())
+    }
+
+    fn get_tags(&self) -> Result<Vec<TagInfo>, Self::Err> {
+        let used = self.used_tags();
+        Ok(self
+            .tags
+            .keys()
+            .filter_map(|tag| self.tag_info(tag, &used))
+            .collect())
+    }
+
+    fn get_tag(&self, tag: &str) -> Result<TagInfo, Self::Err> {
+        self.tag_info(tag, &self.used_tags()).ok_or(Error::NotFound)
+    }
+
+    fn update_tag(&mut self, tag: &TagInfo) -> Result<usize, Self::Err> {
+        let not_empty = |s: &Option<String>| s.clone().filter(|s| !s.is_empty());
+        match self.tags.get_mut(&tag.tag) {
+            Some(settings) => {
+                *settings = (not_empty(&tag.title), not_empty(&tag.description));
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn merge_tags(&mut self, tags: &[String], into: &str) -> Result<usize, Self::Err> {
+        let mut settings = self.tags.remove(into).unwrap_or_default();
+        let mut changed = HashSet::new();
+        for tag in tags.iter().filter(|t| *t != into) {
+            if let Some((title, description)) = self.tags.remove(tag) {
+                settings.0 = settings.0.or(title);
+                settings.1 = settings.1.or(description);
+            }
+            for post in self.posts.values_mut().filter(|p| p.tags.contains(tag)) {
+                changed.insert(post.id);
+                replace_tag(&mut post.tags, tag, into);
+                post.modified = Utc::now();
+            }
+            for (post, _) in self.deleted_posts.values_mut() {
+                replace_tag(&mut post.tags, tag, into);
+            }
+        }
+        self.tags.insert(into.to_owned(), settings);
+        Ok(changed.len())
+    }
+
+    fn delete_orphan_tags(&mut self) -> Result<Vec<String>, Self::Err> {
+        let used = self.used_tags();
+        let orphans: Vec<String> = self
+            .tags
+            .keys()
+            .filter(|t| !used.contains_key(t.as_str()))
+            .cloned()
+            .collect();
+        for tag in &orphans {
+            self.tags.remove(tag);
+        }
+        Ok(orphans)
+    }
+
+    fn get_posts_create_dates(&self) -> Result<Vec<DateTime<Utc>>, Self::Err> {
+        Ok(self
+            .newest_posts()
+            .filter(|p| p.is_public)
+            .map(|p| p.created)
+            .collect())
+    }
+
+    fn get_posts_ids(&self) -> Result<Vec<i64>, Self::Err> {
+        Ok(self
+            .newest_posts()
+            .filter(|p| p.is_public)
+            .map(|p| p.id)
+            .collect())
+    }
+
+    fn get_oauth_provider(&self, name: &str) -> Result<OAuthProvider, Self::Err> {
+        self.oauth_providers
+            .get(name)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    fn get_user(&self, federated_id: &str, provider: &str) -> Result<User, Self::Err> {
+        self.users
+            .iter()
+            .find(|u| u.federated_id == federated_id && u.provider == provider)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    fn upsert_user(&mut self, user: &User) -> Result<(), Self::Err> {
+        let existing = self
+            .users
+            .iter_mut()
+            .find(|u| u.federated_id == user.federated_id && u.provider == user.provider);
+        match existing {
+            Some(u) => {
+                u.email.clone_from(&user.email);
+                u.name.clone_from(&user.name);
+                u.login.clone_from(&user.login);
+                u.avatar_url.clone_from(&user.avatar_url);
+            }
+            None => self.users.push(user.clone()),
+        }
+        Ok(())
+    }
+
+    fn get_folders(&self) -> Result<Vec<Folder>, Self::Err> {
+        Ok(self.folders.clone())
+    }
+
+    fn get_download(&self, id: i64) -> Result<Download, Self::Err> {
+        self.downloads.get(&id).cloned().ok_or(Error::NotFound)
+    }
+
+    fn upsert_download(&mut self, download: Download) -> Result<(), Self::Err> {
+        self.downloads.insert(download.id, download);
+        Ok(())
+    }
+
+    fn upsert_folder(&mut self, folder: Folder) -> Result<(), Self::Err> {
+        match self.folders.iter_mut().find(|f| f.bucket == folder.bucket) {
+            Some(f) => f.title = folder.title,
+            None => self.folders.push(folder),
+        }
+        Ok(())
+    }
+
+    fn delete_download(&mut self, id: i64) -> Result<usize, Self::Err> {
+        Ok(usize::from(self.downloads.remove(&id).is_some()))
+    }
+
+    fn get_downloads(&self, limit: i32, offset: i32) -> Result<Vec<Download>, Self::Err> {
+        Ok(page(self.downloads.values().rev(), limit, offset)
+            .cloned()
+            .collect())
+    }
+
+    fn count_downloads(&self) -> Result<i32, Self::Err> {
+        Ok(count(self.downloads.values()))
+    }
+
+    fn get_users(&self) -> Result<Vec<User>, Self::Err> {
+        let mut users = self.users.clone();
+        users.sort_by_key(|p| Reverse(p.created));
+        Ok(users)
+    }
+
+    fn count_users(&self) -> Result<i32, Self::Err> {
+        Ok(count(self.users.iter()))
+    }
+
+    fn search_posts(
+        &self,
+        query: &str,
+        limit: i32,
+        offset: i32,
+        include_private: bool,
+    ) -> Result<Vec<SmallPost>, Self::Err> {
+        Ok(page(
+            self.search(query, include_private).into_iter(),
+            limit,
+            offset,
+        )
+        .map(|p| SmallPost {
+            short_text: search::snippet(&search::plain_text(p), query),
+            markdown: false,
+            ..SmallPost::from(p)
+        })
+        .collect())
+    }
+
+    fn count_search_results(&self, query: &str, include_private: bool) -> Result<i32, Self::Err> {
+        Ok(count(self.search(query, include_private).into_iter()))
+    }
+
+    fn insert_token(&mut self, token: &IndieToken) -> Result<(), Self::Err> {
+        if self.tokens.iter().any(|t| t.jti == token.jti) {
+            return Err(Error::AlreadyExists);
+        }
+        self.tokens.push(token.clone());
+        Ok(())
+    }
+
+    fn get_token(&self, jti: &str) -> Result<IndieToken, Self::Err> {
+        self.tokens
+            .iter()
+            .find(|t| t.jti == jti)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    fn revoke_token(&mut self, jti: &str) -> Result<usize, Self::Err> {
+        match self
+            .tokens
+            .iter_mut()
+            .find(|t| t.jti == jti && t.revoked.is_none())
+        {
+            Some(token) => {
+                token.revoked = Some(Utc::now());
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn get_active_tokens(&self, client_id: Option<&str>) -> Result<Vec<IndieToken>, Self::Err> {
+        let now = Utc::now();
+        let mut tokens: Vec<IndieToken> = self
+            .tokens
+            .iter()
+            .filter(|t| t.revoked.is_none() && t.expires > now)
+            .filter(|t| client_id.is_none_or(|c| t.client_id == c))
+            .cloned()
+            .collect();
+        tokens.sort_by_key(|p| Reverse(p.issued));
+        Ok(tokens)
+    }
+
+    fn queue_webmention(&mut self, mention: &Webmention) -> Result<i64, Self::Err> {
+        if let Some(existing) = self
+            .webmentions
+            .values_mut()
+            .find(|m| m.source == mention.source && m.target == mention.target)
+        {
+            existing.post_id = mention.post_id;
+            existing.received = mention.received;
+            if existing.status != WebmentionStatus::Rejected {
+                existing.status = WebmentionStatus::Pending;
+            }
+            return Ok(existing.id);
+        }
+        let id = self.webmentions.keys().next_back().map_or(1, |id| id + 1);
+        self.webmentions.insert(
+            id,
+            Webmention {
+                id,
+                status: WebmentionStatus::Pending,
+                ..mention.clone()
+            },
+        );
+        Ok(id)
+    }
+
+    fn get_pending_webmentions(&self, limit: i32) -> Result<Vec<Webmention>, Self::Err> {
+        let mut mentions: Vec<&Webmention> = self
+            .webmentions
+            .values()
+            .filter(|m| m.status == WebmentionStatus::Pending)
+            .collect();
+        mentions.sort_by_key(|m| (m.received, m.id));
+        Ok(page(mentions.into_iter(), limit, 0).cloned().collect())
+    }
+
+    fn get_webmention(&self, id: i64) -> Result<Webmention, Self::Err> {
+        self.webmentions.get(&id).cloned().ok_or(Error::NotFound)
+    }
+
+    fn get_webmentions(
+        &self,
+        status: Option<WebmentionStatus>,
+    ) -> Result<Vec<Webmention>, Self::Err> {
+        let mut mentions: Vec<Webmention> = self
+            .webmentions
+            .values()
+            .filter(|m| status.is_none_or(|s| m.status == s))
+            .cloned()
+            .collect();
+        mentions.sort_by_key(|m| Reverse((m.received, m.id)));
+        Ok(mentions)
+    }
+
+    fn get_post_webmentions(&self, post_id: i64) -> Result<Vec<Webmention>, Self::Err> {
+        let mut mentions: Vec<Webmention> = self
+            .webmentions
+            .values()
+            .filter(|m| m.post_id == post_id && m.status == WebmentionStatus::Approved)
+            .cloned()
+            .collect();
+        mentions.sort_by_key(|m| (m.published.unwrap_or(m.received), m.id));
+        Ok(mentions)
+    }
+
+    fn update_webmention(&mut self, mention: &Webmention) -> Result<usize, Self::Err> {
+        match self.webmentions.get_mut(&mention.id) {
+            Some(existing) => {
+                *existing = Webmention {
+                    source: existing.source.clone(),
+                    target: existing.target.clone(),
+                    post_id: existing.post_id,
+                    received: existing.received,
+                    ..mention.clone()
+                };
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn delete_webmention(&mut self, id: i64) -> Result<usize, Self::Err> {
+        Ok(usize::from(self.webmentions.remove(&id).is_some()))
+    }
+
+    fn queue_outgoing_webmentions(
+        &mut self,
+        post_id: i64,
+        source: &str,
+        targets: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<usize, Self::Err> {
+        for target in targets {
+            let existing = self
+                .outgoing_webmentions
+                .values_mut()
+                .find(|m| m.post_id == post_id && &m.target == target);
+            let mention = if let Some(existing) = existing {
+                existing
+            } else {
+                let id = self
+                    .outgoing_webmentions
+                    .keys()
+                    .next_back()
+                    .map_or(1, |id| id + 1);
+                self.outgoing_webmentions
+                    .entry(id)
+                    .or_insert(OutgoingWebmention {
+                        id,
+                        post_id,
+                        target: target.clone(),
+                        ..Default::default()
+                    })
+            };
+            mention.source = source.to_owned();
+            mention.status = DeliveryStatus::Pending;
+            mention.attempts = 0;
+            mention.next_attempt = now;
+            mention.error = None;
+        }
+        Ok(targets.len())
+    }
+
+    fn get_due_outgoing_webmentions(
+        &self,
+        now: DateTime<Utc>,
+        limit: i32,
+    ) -> Result<Vec<OutgoingWebmention>, Self::Err> {
+        let mut mentions: Vec<&OutgoingWebmention> = self
+            .outgoing_webmentions
+            .values()
+            .filter(|m| m.status == DeliveryStatus::Pending && m.next_attempt <= now)
+            .collect();
+        mentions.sort_by_key(|m| (m.next_attempt, m.id));
+        Ok(page(mentions.into_iter(), limit, 0).cloned().collect())
+    }
+
+    fn get_outgoing_webmentions(&self, post_id: i64) -> Result<Vec<OutgoingWebmention>, Self::Err> {
+        let mut mentions: Vec<OutgoingWebmention> = self
+            .outgoing_webmentions
+            .values()
+            .filter(|m| m.post_id == post_id)
+            .cloned()
+            .collect();
+        mentions.sort_by(|a, b| a.target.cmp(&b.target));
+        Ok(mentions)
+    }
+
+    fn update_outgoing_webmention(
+        &mut self,
+        mention: &OutgoingWebmention,
+    ) -> Result<usize, Self::Err> {
+        match self.outgoing_webmentions.get_mut(&mention.id) {
+            Some(existing) => {
+                *existing = OutgoingWebmention {
+                    post_id: existing.post_id,
+                    source: existing.source.clone(),
+                    target: existing.target.clone(),
+                    ..mention.clone()
+                };
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn upsert_subscription(&mut self, subscription: &WebSubSubscription) -> Result<i64, Self::Err> {
+        let existing = self
+            .subscriptions
+            .values()
+            .find(|s| s.topic == subscription.topic && s.callback == subscription.callback);
+        let delivered = existing.and_then(|s| s.delivered.clone());
+        let id = existing.map_or_else(
+            || self.subscriptions.keys().next_back().map_or(1, |id| id + 1),
+            |s| s.id,
+        );
+        self.subscriptions.insert(
+            id,
+            WebSubSubscription {
+                id,
+                delivered,
+                ..subscription.clone()
+            },
+        );
+        Ok(id)
+    }
+
+    fn delete_subscription(&mut self, topic: &str, callback: &str) -> Result<usize, Self::Err> {
+        let before = self.subscriptions.len();
+        self.subscriptions
+            .retain(|_, s| s.topic != topic || s.callback != callback);
+        Ok(before - self.subscriptions.len())
+    }
+
+    fn get_subscriptions(&self, now: DateTime<Utc>) -> Result<Vec<WebSubSubscription>, Self::Err> {
+        let mut subscriptions: Vec<WebSubSubscription> = self
+            .subscriptions
+            .values()
+            .filter(|s| s.expires > now)
+            .cloned()
+            .collect();
+        subscriptions.sort_by(|a, b| a.topic.cmp(&b.topic));
+        Ok(subscriptions)
+    }
+
+    fn set_subscription_delivered(&mut self, id: i64, digest: &str) -> Result<usize, Self::Err> {
+        match self.subscriptions.get_mut(&id) {
+            Some(subscription) => {
+                subscription.delivered = Some(digest.to_owned());
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// Replaces the tag keeping its position unless `into` is already there.
+fn replace_tag(tags: &mut Vec<String>, tag: &str, into: &str) {
+    if let Some(ix) = tags.iter().position(|t| t == tag) {
+        tags.remove(ix);
+        if !tags.iter().any(|t| t == into) {
+            tags.insert(ix, into.to_owned());
+        }
+    }
+}
+
+/// Applies SQL like limit and offset, negative limit means no limit.
+fn page<'a, T: 'a>(
+    items: impl Iterator<Item = &'a T>,
+    limit: i32,
+    offset: i32,
+) -> impl Iterator<Item = &'a T> {
+    let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+    let offset = usize::try_from(offset).unwrap_or_default();
+    items.skip(offset).take(limit)
+}
+
+fn count<T>(items: impl Iterator<Item = T>) -> i32 {
+    i32::try_from(items.count()).unwrap_or(i32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use chrono::TimeDelta;
+    use rstest::{fixture, rstest};
+
+    #[rstest]
+    fn upsert_post_generates_unique_slugs(mut storage: Memory) {
+        // arrange
+        storage.upsert_post(post(1, "Привет мир", true)).unwrap();
+
+        // act
+        storage.upsert_post(post(2, "Привет мир", true)).unwrap();
+
+        // assert
+        assert_eq!(
+            Some("privet-mir"),
+            storage.get_post(1).unwrap().slug.as_deref()
+        );
+        assert_eq!(
+            Some("privet-mir-2"),
+            storage.get_post(2).unwrap().slug.as_deref()
+        );
+        assert_eq!(2, storage.get_post_id("privet-mir-2").unwrap());
+    }
+
+    #[rstest]
+    fn get_small_posts_only_public_newest_first(mut storage: Memory) {
+        // arrange
+        storage.upsert_post(post(1, "first", true)).unwrap();
+        storage.upsert_post(post(2, "second", true)).unwrap();
+        storage.upsert_post(post(3, "private", false)).unwrap();
+
+        // act
+        let posts = storage
+            .get_small_posts(10, 0, PostsRequest::default())
+            .unwrap();
+        let count = storage.count_posts(PostsRequest::default()).unwrap();
+
+        // assert
+        assert_eq!(vec![2, 1], posts.iter().map(|p| p.id).collect::<Vec<_>>());
+        assert_eq!(2, count);
+    }
+
+    #[rstest]
+    fn delete_and_restore_post(mut storage: Memory) {
+        // arrange
+        storage.upsert_post(post(1, "first", true)).unwrap();
+
+        // act
+        let deleted = storage.delete_post(1).unwrap();
+        let restored = storage.get_deleted_post(1).unwrap();
+        storage.upsert_post(restored).unwrap();
+
+        // assert
+        assert_eq!(1, deleted);
+        assert!(storage.get_post(1).is_ok());
+        assert_eq!(
+            Err(Error::NotFound),
+            storage.get_deleted_post(1).map(|p| p.id)
+        );
+    }
+
+    #[rstest]
+    fn upsert_post_keeps_limited_revisions(storage: Memory) {
+        // arrange
+        let mut storage = storage.with_revisions_limit(2);
+        let mut p = post(1, "first", true);
+
+        // act
+        for text in ["a", "b", "b", "c"] {
+            p.text = text.to_owned();
+            storage.upsert_post(p.clone()).unwrap();
+        }
+
+        // assert
+        let revisions = storage.get_post_revisions(1).unwrap();
+        assert_eq!(
+            vec!["c", "b"],
+            revisions
+                .iter()
+                .map(|r| r.text.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[rstest]
+    fn publish_scheduled_posts_only_due(mut storage: Memory) {
+        // arrange
+        let now = Utc::now();
+        let mut due = post(1, "due", false);
+        due.status = PostStatus::Scheduled;
+        due.created = now - TimeDelta::minutes(1);
+        let mut later = post(2, "later", false);
+        later.status = PostStatus::Scheduled;
+        later.created = now + TimeDelta::hours(1);
+        storage.upsert_post(due).unwrap();
+        storage.upsert_post(later).unwrap();
+
+        // act
+        let published = storage.publish_scheduled_posts(now).unwrap();
+
+        // assert
+        assert_eq!(vec![1], published);
+        assert!(storage.get_post(1).unwrap().is_public);
+        assert_eq!(
+            vec![2],
+            storage
+                .get_posts_by_status(PostStatus::Scheduled)
+                .unwrap()
+                .iter()
+                .map(|p| p.id)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[rstest]
+    #[case("программы", false, vec![1])]
+    #[case("черновики", true, vec![2])]
+    #[case("программа", true, vec![2, 1])]
+    #[case("", true, vec![])]
+    fn search_posts_tests(
+        mut storage: Memory,
+        #[case] query: &str,
+        #[case] include_private: bool,
+        #[case] expected: Vec<i64>,
+    ) {
+        // arrange
+        let mut first = post(1, "Программирование на Rust", true);
+        first.text = "Пишем программу".to_owned();
+        storage.upsert_post(first).unwrap();
+        storage
+            .upsert_post(post(2, "Черновик программы", false))
+            .unwrap();
+
+        // act
+        let actual = storage.search_posts(query, 10, 0, include_private).unwrap();
+        let count = storage
+            .count_search_results(query, include_private)
+            .unwrap();
+
+        // assert
+        assert_eq!(expected, actual.iter().map(|p| p.id).collect::<Vec<_>>());
+        assert_eq!(i32::try_from(expected.len()).unwrap(), count);
+    }
+
+    #[rstest]
+    fn revoke_token_deactivates_token(mut storage: Memory) {
+        // arrange
+        let token = IndieToken {
+            jti: "jti".to_owned(),
+            client_id: "https://app.example.com/".to_owned(),
+            expires: Utc::now() + TimeDelta::days(1),
+            ..Default::default()
+        };
+        storage.insert_token(&token).unwrap();
+
+        // act
+        let revoked = storage.revoke_token("jti").unwrap();
+        let revoked_again = storage.revoke_token("jti").unwrap();
+
+        // assert
+        assert_eq!(1, revoked);
+        assert_eq!(0, revoked_again);
+        assert!(storage.get_active_tokens(None).unwrap().is_empty());
+        assert_eq!(Err(Error::AlreadyExists), storage.insert_token(&token));
+    }
+
+    #[rstest]
+    fn merge_tags_keeps_single_tag(mut storage: Memory) {
+        // arrange
+        let mut first = post(1, "first", true);
+        first.tags = vec!["rust".to_owned(), "раст".to_owned(), "web".to_owned()];
+        storage.upsert_post(first).unwrap();
+        let mut second = post(2, "second", true);
+        second.tags = vec!["раст".to_owned()];
+        storage.upsert_post(second).unwrap();
+
+        // act
+        let changed = storage.merge_tags(&["раст".to_owned()], "rust").unwrap();
+
+        // assert
+        assert_eq!(2, changed);
+        assert_eq!(vec!["rust", "web"], storage.get_post(1).unwrap().tags);
+        assert_eq!(vec!["rust"], storage.get_post(2).unwrap().tags);
+        assert!(storage.get_tag("раст").is_err());
+        assert_eq!(2, storage.get_tag("rust").unwrap().posts);
+    }
+
+    #[fixture]
+    fn storage() -> Memory {
+        Memory::new()
+    }
+
+    fn post(id: i64, title: &str, is_public: bool) -> Post {
+        Post {
+            id,
+            title: title.to_owned(),
+            text: title.to_owned(),
+            markdown: true,
+            is_public,
+            created: DateTime::from_timestamp(1_700_000_000 + id * 60, 0).unwrap(),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Minimal microformats2 parsing needed to discover `IndieAuth` clients,
+//! to verify received webmentions and to discover webmention endpoints.
+
+use std::{borrow::Cow, cell::RefCell};
+
+use anyhow::Result;
+use lol_html::{HtmlRewriter, Settings, element, text};
+use quick_xml::escape::unescape;
+
+const H_APP: &[&str] = &[".h-app", ".h-x-app"];
+const CONTENT: &[&str] = &[".h-entry .e-content", ".h-entry .p-content"];
+
+/// Application information published using `h-app` microformat
+/// together with `redirect_uri` links of the page.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HApp {
+    /// The application name (`p-name`).
+    pub name: Option<String>,
+    /// The application logo URL as written in the page (`u-logo`).
+    pub logo: Option<String>,
+    /// The application home page URL as written in the page (`u-url`).
+    pub url: Option<String>,
+    /// URLs from `<link rel="redirect_uri">` elements.
+    pub redirect_uris: Vec<String>,
+}
+
+/// Parses the first `h-app` (or legacy `h-x-app`) of the page.
+pub fn parse_h_app(html: &str) -> Result<HApp> {
+    // Handlers of both h-app flavours write into the same state
+    let app = RefCell::new(HApp::default());
+    let name = RefCell::new(String::new());
+
+    let mut settings = Settings::new();
+    for root in H_APP {
+        settings = settings
+            .append_element_content_handler(text!(&format!("{root} .p-name"), |t| {
+                if app.borrow().name.is_none() {
+                    let mut name = name.borrow_mut();
+                    name.push_str(t.as_str());
+                    if t.last_in_text_node() && !name.trim().is_empty() {
+                        app.borrow_mut().name = Some(name.trim().to_owned());
+                    }
+                }
+                Ok(())
+            }))
+            .append_element_content_handler(element!(&format!("{root} .u-logo"), |e| {
+                let mut app = app.borrow_mut();
+                if app.logo.is_none() {
+                    app.logo = e.get_attribute("src").or_else(|| e.get_attribute("href"));
+                }
+                Ok(())
+            }))
+            .append_element_content_handler(element!(&format!("{root} .u-url"), |e| {
+                let mut app = app.borrow_mut();
+                if app.url.is_none() {
+                    app.url = e.get_attribute("href");
+                }
+                Ok(())
+            }));
+    }
+    settings =
+        settings.append_element_content_handler(element!("link[rel~=\"redirect_uri\"]", |e| {
+            if let Some(href) = e.get_attribute("href") {
+                app.borrow_mut().redirect_uris.push(href);
+            }
+            Ok(())
+        }));
+
+    let mut rewriter = HtmlRewriter::new(settings, |_: &[u8]| {});
+    rewriter.write(html.as_bytes())?;
+    rewriter.end()?;
+
+    Ok(app.into_inner())
+}
+
+/// Finds target of the first `<link>` or `<a>` element having the rel value.
+/// The target is kept as written in the page.
+pub fn find_rel_link(html: &str, rel: &str) -> Result<Option<String>> {
+    let mut found = None;
+    let mut rewriter = HtmlRewriter::new(
+        Settings::new().append_element_content_handler(element!("[rel][href]", |e| {
+            let tag = e.tag_name();
+            let has_rel = e
+                .get_attribute("rel")
+                .is_some_and(|r| r.split_whitespace().any(|r| r.eq_ignore_ascii_case(rel)));
+            if found.is_none() && (tag == "link" || tag == "a") && has_rel {
+                found = e.get_attribute("href");
+            }
+            Ok(())
+        })),
+        |_: &[u8]| {},
+    );
+    rewriter.write(html.as_bytes())?;
+    rewriter.end()?;
+    Ok(found)
+}
+
+/// Post published using `h-entry` microformat together with links of the page.
+/// URLs are kept as written in the page.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HEntry {
+    /// The author name (`p-author` or its `p-name`).
+    pub author_name: Option<String>,
+    /// The author home page (`p-author` link or its `u-url`).
+    pub author_url: Option<String>,
+    /// The author avatar (`u-photo` of `p-author`).
+    pub author_photo: Option<String>,
+    /// Plain text of `e-content` or `p-content`.
+    pub content: Option<String>,
+    /// Publication time as written in the page (`dt-published`).
+    pub published: Option<String>,
+    /// `u-in-reply-to` URLs.
+    pub in_reply_to: Vec<String>,
+    /// `u-like-of` URLs.
+    pub like_of: Vec<String>,
+    /// `u-repost-of` URLs.
+    pub repost_of: Vec<String>,
+    /// `u-bookmark-of` URLs.
+    pub bookmark_of: Vec<String>,
+    /// Targets of all `<a>` elements of the page.
+    pub links: Vec<String>,
+}
+
+/// Parses the first `h-entry` of the page. Page without `h-entry` gives entry
+/// that has only links set.
+pub fn parse_h_entry(html: &str) -> Result<HEntry> {
+    let entry = RefCell::new(HEntry::default());
+    // Number of h-entry elements started so far, only the first one is parsed
+    let entries = RefCell::new(0);
+    let author = RefCell::new(String::new());
+    let author_name = RefCell::new(String::new());
+    let content = RefCell::new(String::new());
+    let published = RefCell::new(String::new());
+    let is_first = || *entries.borrow() == 1;
+
+    let mut settings = Settings::new()
+        .append_element_content_handler(element!(".h-entry", |_| {
+            *entries.borrow_mut() += 1;
+            Ok(())
+        }))
+        .append_element_content_handler(text!(".h-entry .p-author", |t| {
+            if is_first() {
+                author.borrow_mut().push_str(t.as_str());
+            }
+            Ok(())
+        }))
+        .append_element_content_handler(text!(".h-entry .p-author .p-name", |t| {
+            if is_first() {
+                author_name.borrow_mut().push_str(t.as_str());
+            }
+            Ok(())
+        }))
+        .append_element_content_handler(element!(
+            ".h-entry a.p-author, .h-entry .p-author .u-url",
+            |e| {
+                let mut entry = entry.borrow_mut();
+                if is_first() && entry.author_url.is_none() {
+                    entry.author_url = e.get_attribute("href");
+                }
+                Ok(())
+            }
+        ))
+        .append_element_content_handler(element!(".h-entry .p-author .u-photo", |e| {
+            let mut entry = entry.borrow_mut();
+            if is_first() && entry.author_photo.is_none() {
+                entry.author_photo = e.get_attribute("src").or_else(|| e.get_attribute("href"));
+            }
+            Ok(())
+        }))
+        .append_element_content_handler(element!(".h-entry .dt-published", |e| {
+            if is_first()
+                && let Some(datetime) = e.get_attribute("datetime")
+            {
+                entry.borrow_mut().published.get_or_insert(datetime);
+            }
+            Ok(())
+        }))
+        .append_element_content_handler(text!(".h-entry .dt-published", |t| {
+            if is_first() {
+                published.borrow_mut().push_str(t.as_str());
+            }
+            Ok(())
+        }))
+        .append_element_content_handler(element!("a[href]", |e| {
+            let Some(href) = e.get_attribute("href") else {
+                return Ok(());
+            };
+            let mut entry = entry.borrow_mut();
+            if is_first() {
+                let class = e.get_attribute("class").unwrap_or_default();
+                for name in class.split_whitespace() {
+                    let urls = match name {
+                        "u-in-reply-to" => &mut entry.in_reply_to,
+                        "u-like-of" => &mut entry.like_of,
+                        "u-repost-of" => &mut entry.repost_of,
+                        "u-bookmark-of" => &mut entry.bookmark_of,
+                        _ => continue,
+                    };
+                    urls.push(href.clone());
+                }
+            }
+            entry.links.push(href);
+            Ok(())
+        }));
+    for selector in CONTENT {
+        settings = settings.append_element_content_handler(text!(selector, |t| {
+            if is_first() {
+                content.borrow_mut().push_str(t.as_str());
+            }
+            Ok(())
+        }));
+    }
+
+    let mut rewriter = HtmlRewriter::new(settings, |_: &[u8]| {});
+    rewriter.write(html.as_bytes())?;
+    rewriter.end()?;
+
+    let mut entry = entry.into_inner();
+    entry.author_name = collapse_whitespace(&author_name.into_inner())
+        .or_else(|| collapse_whitespace(&author.into_inner()));
+    entry.content = collapse_whitespace(&content.into_inner());
+    if entry.published.is_none() {
+        entry.published = collapse_whitespace(&published.into_inner());
+    }
+    Ok(entry)
+}
+
+/// Text chunks come as written in the page so XML entities are unescaped here.
+fn collapse_whitespace(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text = unescape(&text).map_or(text.clone(), Cow::into_owned);
+    if text.is_empty() { None } else { Some(text) }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn parse_h_app_full() {
+        // arrange
+        let html = r#"<html><head>
+            <link rel="redirect_uri" href="https://app.example/callback">
+            <link rel="stylesheet redirect_uri" href="/other">
+            </head><body>
+            <div class="h-app">
+              <img class="u-logo" src="/logo.png" alt="">
+              <a class="u-url p-name" href="https://app.example/"> Example App </a>
+            </div></body></html>"#;
+
+        // act
+        let actual = parse_h_app(html).unwrap();
+
+        // assert
+        assert_eq!(Some("Example App".to_owned()), actual.name);
+        assert_eq!(Some("/logo.png".to_owned()), actual.logo);
+        assert_eq!(Some("https://app.example/".to_owned()), actual.url);
+        assert_eq!(
+            vec![
+                "https://app.example/callback".to_owned(),
+                "/other".to_owned()
+            ],
+            actual.redirect_uris
+        );
+    }
+
+    #[test]
+    fn parse_h_entry_reply() {
+        // arrange
+        let html = r#"<html><body>
+            <a href="/">Home</a>
+            <article class="h-entry">
+              <a class="p-author h-card" href="https://alice.example/">
+                <img class="u-photo" src="/alice.jpg" alt=""> <span class="p-name">Alice  Smith</span>
+              </a>
+              <a class="u-in-reply-to" href="https://www.egoroff.spb.ru/blog/2024/post">post</a>
+              <div class="e-content"><p>Great   post &amp; thanks!</p></div>
+              <time class="dt-published" datetime="2024-03-01T10:00:00Z">March 1</time>
+            </article>
+            <article class="h-entry"><div class="e-content">Second</div></article>
+            </body></html>"#;
+
+        // act
+        let actual = parse_h_entry(html).unwrap();
+
+        // assert
+        assert_eq!(Some("Alice Smith".to_owned()), actual.author_name);
+        assert_eq!(Some("https://alice.example/".to_owned()), actual.author_url);
+        assert_eq!(Some("/alice.jpg".to_owned()), actual.author_photo);
+        assert_eq!(Some("Great post & thanks!".to_owned()), actual.content);
+        assert_eq!(Some("2024-03-01T10:00:00Z".to_owned()), actual.published);
+        assert_eq!(
+            vec!["https://www.egoroff.spb.ru/blog/2024/post".to_owned()],
+            actual.in_reply_to
+        );
+        assert!(actual.like_of.is_empty());
+        assert_eq!(3, actual.links.len());
+    }
+
+    #[rstest]
+    #[case(
+        r#"<div class="h-entry"><span class="p-author">Bob</span><a class="u-like-of u-url" href="https://t/">t</a></div>"#,
+        Some("Bob"),
+        1
+    )]
+    #[case(r#"<p>Just a <a href="https://t/">link</a></p>"#, None, 0)]
+    fn parse_h_entry_like(#[case] html: &str, #[case] author: Option<&str>, #[case] likes: usize) {
+        // arrange
+
+        // act
+        let actual = parse_h_entry(html).unwrap();
+
+        // assert
+        assert_eq!(author.map(str::to_owned), actual.author_name);
+        assert_eq!(likes, actual.like_of.len());
+        assert_eq!(vec!["https://t/".to_owned()], actual.links);
+    }
+
+    #[rstest]
+    #[case(r#"<link rel="webmention" href="/wm">"#, Some("/wm"))]
+    #[case(r#"<a rel="nofollow Webmention" href="">a</a>"#, Some(""))]
+    #[case(
+        r#"<p rel="webmention" href="/p"></p><link rel="webmention" href="/1"><a rel="webmention" href="/2">a</a>"#,
+        Some("/1")
+    )]
+    #[case(r#"<link rel="webmention"><link rel="pingback" href="/xmlrpc">"#, None)]
+    fn find_rel_link_tests(#[case] html: &str, #[case] expected: Option<&str>) {
+        // arrange
+
+        // act
+        let actual = find_rel_link(html, "webmention").unwrap();
+
+        // assert
+        assert_eq!(expected.map(str::to_owned), actual);
+    }
+
+    #[rstest]
+    #[case(
+        r#"<div class="h-x-app"><span class="p-name">Legacy</span></div>"#,
+        Some("Legacy")
+    )]
+    #[case(r#"<span class="p-name">Not an app</span>"#, None)]
+    #[case("", None)]
+    fn parse_h_app_name(#[case] html: &str, #[case] expected: Option<&str>) {
+        // arrange
+
+        // act
+        let actual = parse_h_app(html).unwrap();
+
+        // assert
+        assert_eq!(expected.map(str::to_owned), actual.name);
+    }
+}
@@ -0,0 +1,607 @@
+//! Versioned schema migrations of the main site database.
+//!
+//! Current schema version is kept in `PRAGMA user_version` so that it
+//! travels together with the database file. Every migration is applied in
+//! its own transaction that also bumps the version, so a failed migration
+//! leaves the database at the previous consistent version.
+
+use rusqlite::{Connection, Error, Transaction, TransactionBehavior, ffi, params};
+
+use crate::{domain::Post, search, slug};
+
+/// Single up-migration that moves schema from `version - 1` to `version`.
+pub struct Migration {
+    /// Schema version after this migration is applied.
+    pub version: u32,
+    /// Short human readable description of the change.
+    pub description: &'static str,
+    /// Function that performs migration inside the transaction.
+    pub up: fn(&Transaction) -> Result<(), Error>,
+}
+
+/// All known migrations ordered by version.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "posts, tags, folders and files",
+        up: v1_initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "users, OAuth providers and posts remap",
+        up: v2_users_and_providers,
+    },
+    Migration {
+        version: 3,
+        description: "full-text search index over posts",
+        up: v3_post_search,
+    },
+    Migration {
+        version: 4,
+        description: "deleted posts kept for undelete",
+        up: v4_deleted_post,
+    },
+    Migration {
+        version: 5,
+        description: "issued IndieAuth tokens",
+        up: v5_indie_token,
+    },
+    Migration {
+        version: 6,
+        description: "human readable post slugs",
+        up: v6_post_slug,
+    },
+    Migration {
+        version: 7,
+        description: "draft, published and scheduled post status",
+        up: v7_post_status,
+    },
+    Migration {
+        version: 8,
+        description: "post revisions",
+        up: v8_post_revision,
+    },
+    Migration {
+        version: 9,
+        description: "tag display title and description",
+        up: v9_tag_description,
+    },
+    Migration {
+        version: 10,
+        description: "received webmentions",
+        up: v10_webmention,
+    },
+    Migration {
+        version: 11,
+        description: "sent webmentions",
+        up: v11_outgoing_webmention,
+    },
+    Migration {
+        version: 12,
+        description: "WebSub hub subscriptions",
+        up: v12_websub_subscription,
+    },
+    Migration {
+        version: 13,
+        description: "public flag derived from post status",
+        up: v13_public_from_status,
+    },
+    Migration {
+        version: 14,
+        description: "feed digests delivered to WebSub subscribers",
+        up: v14_websub_delivered,
+    },
+    Migration {
+        version: 15,
+        description: "previous post slugs",
+        up: v15_previous_slug,
+    },
+];
+
+/// The version schema will have after all known migrations are applied.
+#[must_use]
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// Reads schema version stored in the database.
+pub fn schema_version(conn: &Connection) -> Result<u32, Error> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Fails if database schema was created by a newer version of the application.
+pub fn ensure_supported(conn: &Connection) -> Result<u32, Error> {
+    let version = schema_version(conn)?;
+    let latest = latest_version();
+    if version > latest {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_CANTOPEN),
+            Some(format!(
+                "database schema version {version} is newer than the latest supported {latest}"
+            )),
+        ));
+    }
+    Ok(version)
+}
+
+/// Applies all pending migrations. Returns schema version after migration.
+pub fn migrate(conn: &mut Connection) -> Result<u32, Error> {
+    let version = ensure_supported(conn)?;
+    if version == 0 {
+        // Has effect only on a brand new database
+        conn.pragma_update(None, "encoding", "UTF-8")?;
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        // Immediate transaction prevents two processes from applying the same migration
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if schema_version(&tx)? >= migration.version {
+            continue;
+        }
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    schema_version(conn)
+}
+
+/// Initial schema. Uses IF NOT EXISTS because databases created before
+/// migrations were introduced already have these tables but zero version.
+fn v1_initial_schema(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS post (
+              id              INTEGER PRIMARY KEY,
+              title           TEXT NOT NULL,
+              short_text      TEXT NOT NULL,
+              text            TEXT NOT NULL,
+              markdown        INTEGER,
+              is_public       INTEGER,
+              created         INTEGER NOT NULL,
+              modified        INTEGER NOT NULL
+              );
+         CREATE INDEX IF NOT EXISTS created_ix ON post(created);
+         CREATE TABLE IF NOT EXISTS tag (
+              tag           TEXT PRIMARY KEY
+              );
+         CREATE TABLE IF NOT EXISTS post_tag (
+              post_id              INTEGER NOT NULL,
+              tag                  TEXT NOT NULL,
+              PRIMARY KEY (post_id, tag),
+              FOREIGN KEY(post_id) REFERENCES post(id) ON UPDATE CASCADE ON DELETE CASCADE,
+              FOREIGN KEY(tag) REFERENCES tag(tag)
+              );
+         CREATE TABLE IF NOT EXISTS folder (
+              bucket          TEXT PRIMARY KEY,
+              title           TEXT NOT NULL
+              );
+         CREATE TABLE IF NOT EXISTS file (
+              id              INTEGER PRIMARY KEY,
+              title           TEXT NOT NULL
+              );",
+    )
+}
+
+/// Tables that production database always had but were never created by code.
+fn v2_users_and_providers(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS user (
+              created         INTEGER NOT NULL,
+              email           TEXT NOT NULL,
+              name            TEXT NOT NULL,
+              login           TEXT NOT NULL,
+              avatar_url      TEXT NOT NULL,
+              federated_id    TEXT NOT NULL,
+              admin           INTEGER NOT NULL DEFAULT 0,
+              verified        INTEGER NOT NULL DEFAULT 0,
+              provider        TEXT NOT NULL,
+              PRIMARY KEY (federated_id, provider)
+              );
+         CREATE TABLE IF NOT EXISTS oauth_provider (
+              name            TEXT PRIMARY KEY,
+              clientid        TEXT NOT NULL,
+              secret          TEXT NOT NULL,
+              redirect_url    TEXT NOT NULL
+              );
+         CREATE TABLE IF NOT EXISTS oauth_provider_scopes (
+              provider        TEXT NOT NULL,
+              scope           TEXT NOT NULL,
+              PRIMARY KEY (provider, scope),
+              FOREIGN KEY(provider) REFERENCES oauth_provider(name) ON UPDATE CASCADE ON DELETE CASCADE
+              );
+         CREATE TABLE IF NOT EXISTS post_remap (
+              old_id          INTEGER PRIMARY KEY,
+              post_id         INTEGER NOT NULL
+              );",
+    )
+}
+
+/// FTS5 index of stemmed post titles and texts. Rowid is the post id.
+fn v3_post_search(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS post_search USING fts5(
+              title_terms,
+              body_terms,
+              body UNINDEXED,
+              tokenize = 'unicode61 remove_diacritics 0'
+              );",
+    )?;
+
+    let mut stmt = tx.prepare("SELECT id, title, text, markdown FROM post")?;
+    let posts = stmt.query_map([], |row| {
+        Ok(Post {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            text: row.get(2)?,
+            markdown: row.get(3)?,
+            ..Default::default()
+        })
+    })?;
+
+    let mut insert = tx.prepare(
+        "INSERT INTO post_search (rowid, title_terms, body_terms, body) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for post in posts {
+        let post = post?;
+        let body = search::plain_text(&post);
+        insert.execute(params![
+            post.id,
+            search::normalize(&post.title),
+            search::normalize(&body),
+            body
+        ])?;
+    }
+    Ok(())
+}
+
+/// Deleted posts together with their tags so that deletion can be undone.
+fn v4_deleted_post(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS deleted_post (
+              id              INTEGER PRIMARY KEY,
+              title           TEXT NOT NULL,
+              short_text      TEXT NOT NULL,
+              text            TEXT NOT NULL,
+              markdown        INTEGER,
+              is_public       INTEGER,
+              created         INTEGER NOT NULL,
+              modified        INTEGER NOT NULL,
+              tags            TEXT NOT NULL,
+              deleted         INTEGER NOT NULL
+              );",
+    )
+}
+
+/// Issued `IndieAuth` access tokens so that they can be listed and revoked.
+fn v5_indie_token(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS indie_token (
+              jti             TEXT PRIMARY KEY,
+              client_id       TEXT NOT NULL,
+              scope           TEXT NOT NULL,
+              issued          INTEGER NOT NULL,
+              expires         INTEGER NOT NULL,
+              revoked         INTEGER
+              );
+         CREATE INDEX IF NOT EXISTS indie_token_client_ix ON indie_token(client_id);",
+    )
+}
+
+/// Unique post slugs generated from titles of existing posts.
+fn v6_post_slug(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE post ADD COLUMN slug TEXT;
+         ALTER TABLE deleted_post ADD COLUMN slug TEXT;
+         CREATE UNIQUE INDEX IF NOT EXISTS post_slug_ix ON post(slug);",
+    )?;
+
+    let mut stmt = tx.prepare("SELECT id, title FROM post ORDER BY created")?;
+    let posts = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut update = tx.prepare("UPDATE post SET slug = ?2 WHERE id = ?1")?;
+    let mut exists = tx.prepare("SELECT COUNT(1) FROM post WHERE slug = ?1")?;
+    for (id, title) in posts {
+        let mut base = slug::slugify(&title);
+        if base.is_empty() {
+            base = format!("post-{id}");
+        }
+        let slug = slug::unique(&base, |s| {
+            exists.query_row([s], |row| row.get::<_, i32>(0).map(|c| c > 0))
+        })?;
+        update.execute(params![id, slug])?;
+    }
+    Ok(())
+}
+
+/// Post status derived from the public flag of existing posts.
+fn v7_post_status(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE post ADD COLUMN status TEXT NOT NULL DEFAULT 'draft';
+         ALTER TABLE deleted_post ADD COLUMN status TEXT NOT NULL DEFAULT 'draft';
+         UPDATE post SET status = 'published' WHERE is_public = 1;
+         UPDATE deleted_post SET status = 'published' WHERE is_public = 1;
+         CREATE INDEX IF NOT EXISTS post_status_ix ON post(status, created);",
+    )
+}
+
+/// Revisions history with the current content of existing posts as the first revision.
+fn v8_post_revision(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS post_revision (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              post_id         INTEGER NOT NULL,
+              created         INTEGER NOT NULL,
+              title           TEXT NOT NULL,
+              short_text      TEXT NOT NULL,
+              text            TEXT NOT NULL,
+              markdown        INTEGER NOT NULL,
+              tags            TEXT NOT NULL
+          );
+         CREATE INDEX IF NOT EXISTS post_revision_post_ix ON post_revision(post_id, id);
+         INSERT INTO post_revision (post_id, created, title, short_text, text, markdown, tags)
+              SELECT id, COALESCE(modified, created), title, short_text, text, markdown,
+                  (SELECT json_group_array(tag) FROM post_tag WHERE post_id = post.id)
+              FROM post ORDER BY id;",
+    )
+}
+
+/// Optional presentation settings of tags. Tags without them are shown as is.
+fn v9_tag_description(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE tag ADD COLUMN title TEXT;
+         ALTER TABLE tag ADD COLUMN description TEXT;",
+    )
+}
+
+/// Webmentions received from other sites. The same source may mention a post once.
+fn v10_webmention(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS webmention (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              source          TEXT NOT NULL,
+              target          TEXT NOT NULL,
+              post_id         INTEGER NOT NULL,
+              status          TEXT NOT NULL DEFAULT 'pending',
+              kind            TEXT NOT NULL DEFAULT 'mention',
+              author_name     TEXT,
+              author_url      TEXT,
+              author_photo    TEXT,
+              content         TEXT,
+              published       INTEGER,
+              received        INTEGER NOT NULL,
+              verified        INTEGER,
+              error           TEXT
+          );
+         CREATE UNIQUE INDEX IF NOT EXISTS webmention_source_target_ix ON webmention(source, target);
+         CREATE INDEX IF NOT EXISTS webmention_post_ix ON webmention(post_id, status);
+         CREATE INDEX IF NOT EXISTS webmention_status_ix ON webmention(status);",
+    )
+}
+
+fn v11_outgoing_webmention(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS outgoing_webmention (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              post_id         INTEGER NOT NULL,
+              source          TEXT NOT NULL,
+              target          TEXT NOT NULL,
+              status          TEXT NOT NULL DEFAULT 'pending',
+              endpoint        TEXT,
+              attempts        INTEGER NOT NULL DEFAULT 0,
+              next_attempt    INTEGER NOT NULL,
+              last_attempt    INTEGER,
+              error           TEXT
+          );
+         CREATE UNIQUE INDEX IF NOT EXISTS outgoing_webmention_post_target_ix ON outgoing_webmention(post_id, target);
+         CREATE INDEX IF NOT EXISTS outgoing_webmention_due_ix ON outgoing_webmention(status, next_attempt);",
+    )
+}
+
+/// Subscriptions of the built-in `WebSub` hub. A callback subscribes to a topic once.
+fn v12_websub_subscription(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS websub_subscription (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              topic           TEXT NOT NULL,
+              callback        TEXT NOT NULL,
+              secret          TEXT,
+              created         INTEGER NOT NULL,
+              expires         INTEGER NOT NULL
+          );
+         CREATE UNIQUE INDEX IF NOT EXISTS websub_subscription_topic_callback_ix ON websub_subscription(topic, callback);",
+    )
+}
+
+/// Post status becomes the only stored publication state. Public flag is kept
+/// as a generated column so that queries filtering public posts stay as they are.
+fn v13_public_from_status(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "UPDATE post SET status = 'published' WHERE is_public = 1;
+         UPDATE post SET status = 'draft' WHERE COALESCE(is_public, 0) = 0 AND status = 'published';
+         UPDATE deleted_post SET status = 'published' WHERE is_public = 1;
+         UPDATE deleted_post SET status = 'draft' WHERE COALESCE(is_public, 0) = 0 AND status = 'published';
+         ALTER TABLE post DROP COLUMN is_public;
+         ALTER TABLE post ADD COLUMN is_public INTEGER GENERATED ALWAYS AS (status = 'published') VIRTUAL;
+         ALTER TABLE deleted_post DROP COLUMN is_public;
+         ALTER TABLE deleted_post ADD COLUMN is_public INTEGER GENERATED ALWAYS AS (status = 'published') VIRTUAL;",
+    )
+}
+
+fn v14_websub_delivered(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch("ALTER TABLE websub_subscription ADD COLUMN delivered TEXT;")
+}
+
+/// Slugs posts had before they were changed, so that old post URLs keep working.
+fn v15_previous_slug(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS post_previous_slug (
+              slug            TEXT PRIMARY KEY,
+              post_id         INTEGER NOT NULL
+          );",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use rstest::{fixture, rstest};
+
+    const V1_FIXTURE: &str = include_str!("../fixtures/egoroff_v1.sql");
+
+    #[rstest]
+    fn migrate_empty_database_creates_latest_schema(mut empty: Connection) {
+        // arrange
+
+        // act
+        let version = migrate(&mut empty).unwrap();
+
+        // assert
+        assert_eq!(latest_version(), version);
+        assert_eq!(latest_version(), schema_version(&empty).unwrap());
+        assert!(table_exists(&empty, "post"));
+        assert!(table_exists(&empty, "user"));
+        assert!(table_exists(&empty, "post_remap"));
+        assert!(table_exists(&empty, "indie_token"));
+        assert!(table_exists(&empty, "webmention"));
+        assert!(table_exists(&empty, "outgoing_webmention"));
+        assert!(table_exists(&empty, "websub_subscription"));
+    }
+
+    #[rstest]
+    fn migrate_v1_database_upgrades_and_keeps_data(mut v1: Connection) {
+        // arrange
+
+        // act
+        let version = migrate(&mut v1).unwrap();
+
+        // assert
+        assert_eq!(latest_version(), version);
+        assert!(table_exists(&v1, "oauth_provider_scopes"));
+        let posts: i32 = v1
+            .query_row("SELECT COUNT(1) FROM post", [], |row| row.get(0))
+            .unwrap();
+        let tags: i32 = v1
+            .query_row("SELECT COUNT(1) FROM post_tag", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(2, posts);
+        assert_eq!(3, tags);
+        let indexed: i32 = v1
+            .query_row("SELECT COUNT(1) FROM post_search", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(2, indexed);
+        let slugs: i32 = v1
+            .query_row(
+                "SELECT COUNT(DISTINCT slug) FROM post WHERE slug IS NOT NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, slugs);
+        let statuses: Vec<String> = v1
+            .prepare("SELECT status FROM post ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(vec!["published", "draft"], statuses);
+        let revisions: i32 = v1
+            .query_row(
+                "SELECT COUNT(1) FROM post_revision WHERE tags <> '[]'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, revisions);
+        v1.execute("UPDATE post SET status = 'draft' WHERE id = 1", [])
+            .unwrap();
+        let public: i32 = v1
+            .query_row("SELECT COUNT(1) FROM post WHERE is_public = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(0, public);
+    }
+
+    #[rstest]
+    fn migrate_unversioned_legacy_database(mut v1: Connection) {
+        // arrange
+        v1.pragma_update(None, "user_version", 0).unwrap();
+
+        // act
+        let version = migrate(&mut v1).unwrap();
+
+        // assert
+        assert_eq!(latest_version(), version);
+        let title: String = v1
+            .query_row("SELECT title FROM post WHERE id = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!("First post", title);
+    }
+
+    #[rstest]
+    fn migrate_twice_is_noop(mut v1: Connection) {
+        // arrange
+        migrate(&mut v1).unwrap();
+
+        // act
+        let version = migrate(&mut v1).unwrap();
+
+        // assert
+        assert_eq!(latest_version(), version);
+    }
+
+    #[rstest]
+    fn migrate_newer_schema_fails(mut empty: Connection) {
+        // arrange
+        empty
+            .pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        // act
+        let result = migrate(&mut empty);
+
+        // assert
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn migrations_ordered_without_gaps() {
+        // arrange
+
+        // act
+        let versions: Vec<u32> = MIGRATIONS.iter().map(|m| m.version).collect();
+
+        // assert
+        let expected: Vec<u32> = (1..=latest_version()).collect();
+        assert_eq!(expected, versions);
+    }
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT COUNT(1) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |row| row.get::<_, i32>(0),
+        )
+        .unwrap()
+            == 1
+    }
+
+    #[fixture]
+    fn empty() -> Connection {
+        Connection::open_in_memory().unwrap()
+    }
+
+    #[fixture]
+    fn v1() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(V1_FIXTURE).unwrap();
+        conn
+    }
+}
@@ -0,0 +1,171 @@
+//! Storage connections pool: a single writer and several readers.
+//! Storage calls run on the blocking threads pool so they never block async workers.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::{Result, anyhow};
+use tokio::sync::Semaphore;
+
+use crate::{
+    domain::Storage,
+    sqlite::{Mode, Sqlite},
+};
+
+/// Number of read-only connections opened by default.
+pub const DEFAULT_READERS: usize = 4;
+
+pub struct Pool<S> {
+    writer: Arc<Mutex<S>>,
+    readers: Vec<Arc<Mutex<S>>>,
+    idle: Mutex<Vec<usize>>,
+    permits: Semaphore,
+}
+
+impl<S: Storage + Send + 'static> Pool<S> {
+    /// Creates pool from the writer and readers connections.
+    /// Without readers all reads go to the writer.
+    pub fn new(writer: S, readers: Vec<S>) -> Self {
+        let readers: Vec<_> = readers
+            .into_iter()
+            .map(|r| Arc::new(Mutex::new(r)))
+            .collect();
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+            idle: Mutex::new((0..readers.len()).collect()),
+            permits: Semaphore::new(readers.len()),
+            readers,
+        }
+    }
+
+    /// Runs read only storage access using one of the readers.
+    pub async fn read<T, E, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&S) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: Into<anyhow::Error>,
+    {
+        if self.readers.is_empty() {
+            return self.write(move |s| f(s)).await;
+        }
+
+        let _permit = self.permits.acquire().await?;
+        let checkout = Checkout::take(&self.idle)?;
+        let reader = Arc::clone(&self.readers[checkout.index]);
+        tokio::task::spawn_blocking(move || {
+            let reader = reader.lock().unwrap_or_else(PoisonError::into_inner);
+            f(&reader).map_err(Into::into)
+        })
+        .await?
+    }
+
+    /// Runs storage modification using the single writer.
+    pub async fn write<T, E, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut S) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: Into<anyhow::Error>,
+    {
+        let writer = Arc::clone(&self.writer);
+        tokio::task::spawn_blocking(move || {
+            let mut writer = writer.lock().unwrap_or_else(PoisonError::into_inner);
+            f(&mut writer).map_err(Into::into)
+        })
+        .await?
+    }
+}
+
+impl Pool<Sqlite> {
+    /// Creates pool over database file that is switched into WAL mode
+    /// so readers don't wait for the writer.
+    pub fn open<P: AsRef<Path>>(writer: Sqlite, path: P, readers: usize) -> Result<Self> {
+        writer.enable_wal()?;
+        let readers = (0..readers)
+            .map(|_| Sqlite::open(path.as_ref(), Mode::ReadOnly))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(writer, readers))
+    }
+}
+
+/// Reader index taken from idle list and returned back on drop.
+struct Checkout<'a> {
+    idle: &'a Mutex<Vec<usize>>,
+    index: usize,
+}
+
+impl<'a> Checkout<'a> {
+    fn take(idle: &'a Mutex<Vec<usize>>) -> Result<Self> {
+        let index = idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .ok_or_else(|| anyhow!("no idle storage reader"))?;
+        Ok(Self { idle, index })
+    }
+}
+
+impl Drop for Checkout<'_> {
+    fn drop(&mut self) {
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(self.index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::domain::Post;
+    use crate::testing::temp_database;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn readers_see_committed_writes() {
+        // arrange
+        let path = temp_database("pool_readers_see_writes");
+        let writer = Sqlite::open(&path, Mode::ReadWrite).unwrap();
+        let pool = Pool::open(writer, &path, 2).unwrap();
+
+        // act
+        pool.write(|s| {
+            s.upsert_post(Post {
+                id: 1,
+                title: "Пул".to_owned(),
+                is_public: true,
+                ..Default::default()
+            })
+        })
+        .await
+        .unwrap();
+        let reads = futures::future::join_all((0..8).map(|_| pool.read(|s| s.get_post(1)))).await;
+
+        // assert
+        for post in reads {
+            assert_eq!("Пул", post.unwrap().title);
+        }
+        drop(pool);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_errors_are_returned() {
+        // arrange
+        let path = temp_database("pool_read_errors");
+        let writer = Sqlite::open(&path, Mode::ReadWrite).unwrap();
+        let pool = Pool::open(writer, &path, 1).unwrap();
+
+        // act
+        let first = pool.read(|s| s.get_post(42)).await;
+        let second = pool.read(|s| s.get_posts_ids()).await;
+
+        // assert
+        assert!(first.is_err());
+        assert!(second.unwrap().is_empty());
+        drop(pool);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
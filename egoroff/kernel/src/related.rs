@@ -0,0 +1,171 @@
+//! Related posts ranking and cache of posts navigation.
+//!
+//! Posts are ranked by the number of shared tags first and by cosine similarity
+//! of stemmed words of their titles and texts then. Tags are chosen by author
+//! so a single shared tag outweighs any text similarity.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    cache::GenerationCache,
+    domain::{Post, PostNavigation, SmallPost, Storage},
+    search,
+};
+
+/// Number of related posts shown under a post.
+pub const DEFAULT_LIMIT: usize = 5;
+
+/// Words that short are mostly prepositions and conjunctions.
+const MIN_WORD_CHARS: usize = 3;
+
+/// Post compared with others by its title, tags and plain text of the body.
+/// Storages keep the plain text in the search index, so ranking doesn't
+/// render posts. Post text isn't needed.
+pub struct Candidate {
+    pub post: Post,
+    pub body: String,
+}
+
+impl Candidate {
+    /// Makes candidate rendering the post to get plain text of its body.
+    #[must_use]
+    pub fn rendered(post: Post) -> Self {
+        let body = search::plain_text(&post);
+        Self { post, body }
+    }
+}
+
+/// Ranks candidates by similarity to the post, the most similar first.
+/// Equally similar posts are ordered newest first. The post itself and
+/// candidates sharing neither tags nor words with it are skipped.
+#[must_use]
+pub fn rank(candidate: &Candidate, candidates: &[Candidate], limit: usize) -> Vec<SmallPost> {
+    let post = &candidate.post;
+    let post_terms = terms(candidate);
+    let mut scored: Vec<(f64, &Post)> = candidates
+        .iter()
+        .filter(|c| c.post.id != post.id)
+        .map(|c| {
+            let shared_tags = c.post.tags.iter().filter(|t| post.tags.contains(t)).count();
+            #[allow(clippy::cast_precision_loss)]
+            let score = shared_tags as f64 + cosine(&post_terms, &terms(c));
+            (score, &c.post)
+        })
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+    scored.sort_by(|(a, x), (b, y)| b.total_cmp(a).then_with(|| y.created.cmp(&x.created)));
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, p)| SmallPost::from(p))
+        .collect()
+}
+
+/// Reads previous, next and related posts of the post.
+pub fn navigation<S: Storage>(
+    storage: &S,
+    id: i64,
+    limit: usize,
+) -> Result<PostNavigation, S::Err> {
+    Ok(PostNavigation {
+        previous: storage.get_previous_post(id)?,
+        next: storage.get_next_post(id)?,
+        related: storage.get_related_posts(id, limit)?,
+    })
+}
+
+fn terms(candidate: &Candidate) -> HashMap<String, f64> {
+    let text = format!("{} {}", candidate.post.title, candidate.body);
+    let mut terms = HashMap::new();
+    for word in search::words(&text).filter(|w| w.chars().count() >= MIN_WORD_CHARS) {
+        *terms.entry(search::stem(&word)).or_default() += 1.0;
+    }
+    terms
+}
+
+fn cosine(a: &HashMap<String, f64>, b: &HashMap<String, f64>) -> f64 {
+    let dot: f64 = a
+        .iter()
+        .filter_map(|(term, x)| b.get(term).map(|y| x * y))
+        .sum();
+    if dot == 0.0 {
+        return 0.0;
+    }
+    let norm = |v: &HashMap<String, f64>| v.values().map(|x| x * x).sum::<f64>().sqrt();
+    dot / (norm(a) * norm(b))
+}
+
+/// Posts navigation by post ID shared by all storage connections.
+/// Must be invalidated whenever any post changes.
+pub type Cache = GenerationCache<i64, Arc<PostNavigation>>;
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn rank_shared_tags_outweigh_text() {
+        // arrange
+        let main = post(
+            1,
+            "Парсинг логов",
+            "парсер логов на rust",
+            &["rust", "logs"],
+        );
+        let candidates = [
+            post(2, "Парсинг логов", "парсер логов на rust", &[]),
+            post(3, "Про сборку", "сборка проекта", &["rust", "logs"]),
+            post(4, "Про тесты", "тесты проекта", &["rust"]),
+            post(5, "Рецепт пирога", "мука и яйца", &["food"]),
+            post(
+                1,
+                "Парсинг логов",
+                "парсер логов на rust",
+                &["rust", "logs"],
+            ),
+        ];
+
+        // act
+        let actual = rank(&main, &candidates, DEFAULT_LIMIT);
+
+        // assert
+        let ids: Vec<i64> = actual.iter().map(|p| p.id).collect();
+        assert_eq!(vec![3, 4, 2], ids);
+    }
+
+    #[rstest]
+    #[case(1, vec![3])]
+    #[case(0, vec![])]
+    fn rank_limit(#[case] limit: usize, #[case] expected: Vec<i64>) {
+        // arrange
+        let main = post(1, "Первый", "текст", &["rust"]);
+        let candidates = [
+            post(2, "Второй", "другое", &["rust"]),
+            post(3, "Третий", "другое", &["rust"]),
+        ];
+
+        // act
+        let actual = rank(&main, &candidates, limit);
+
+        // assert
+        let ids: Vec<i64> = actual.iter().map(|p| p.id).collect();
+        assert_eq!(expected, ids);
+    }
+
+    fn post(id: i64, title: &str, text: &str, tags: &[&str]) -> Candidate {
+        Candidate {
+            post: Post {
+                id,
+                title: title.to_owned(),
+                is_public: true,
+                created: chrono::DateTime::from_timestamp(id * 1000, 0).unwrap(),
+                tags: tags.iter().map(|t| (*t).to_owned()).collect(),
+                ..Default::default()
+            },
+            body: text.to_owned(),
+        }
+    }
+}
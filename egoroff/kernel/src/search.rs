@@ -0,0 +1,354 @@
+//! Full-text search helpers used to keep `post_search` FTS5 table in sync.
+//!
+//! FTS5 has no Russian stemmer, so words are normalized on the application
+//! side: lower cased, `ё` folded into `е` and Cyrillic words stemmed using
+//! Snowball Russian algorithm. The same normalization is applied both to
+//! indexed text and to search queries.
+
+use quick_xml::escape::escape;
+
+use crate::{
+    converter::{html2text, markdown2html, xml2html},
+    domain::Post,
+};
+
+const SNIPPET_WORDS: usize = 30;
+const SNIPPET_CONTEXT: usize = 5;
+
+const VOWELS: &[char] = &['а', 'е', 'и', 'о', 'у', 'ы', 'э', 'ю', 'я'];
+
+const PERFECTIVE_GERUND_1: &[&str] = &["в", "вши", "вшись"];
+const PERFECTIVE_GERUND_2: &[&str] = &["ив", "ивши", "ившись", "ыв", "ывши", "ывшись"];
+
+const ADJECTIVE: &[&str] = &[
+    "ее", "ие", "ые", "ое", "ими", "ыми", "ей", "ий", "ый", "ой", "ем", "им", "ым", "ом", "его",
+    "ого", "ему", "ому", "их", "ых", "ую", "юю", "ая", "яя", "ою", "ею",
+];
+
+const PARTICIPLE_1: &[&str] = &["ем", "нн", "вш", "ющ", "щ"];
+const PARTICIPLE_2: &[&str] = &["ивш", "ывш", "ующ"];
+
+const REFLEXIVE: &[&str] = &["ся", "сь"];
+
+const VERB_1: &[&str] = &[
+    "ла", "на", "ете", "йте", "ли", "й", "л", "ем", "н", "ло", "но", "ет", "ют", "ны", "ть", "ешь",
+    "нно",
+];
+const VERB_2: &[&str] = &[
+    "ила", "ыла", "ена", "ейте", "уйте", "ите", "или", "ыли", "ей", "уй", "ил", "ыл", "им", "ым",
+    "ен", "ило", "ыло", "ено", "ят", "ует", "уют", "ит", "ыт", "ены", "ить", "ыть", "ишь", "ую",
+    "ю",
+];
+
+const NOUN: &[&str] = &[
+    "а", "ев", "ов", "ие", "ье", "е", "иями", "ями", "ами", "еи", "ии", "и", "ией", "ей", "ой",
+    "ий", "й", "иям", "ям", "ием", "ем", "ам", "ом", "о", "у", "ах", "иях", "ях", "ы", "ь", "ию",
+    "ью", "ю", "ия", "ья", "я",
+];
+
+const SUPERLATIVE: &[&str] = &["ейше", "ейш"];
+const DERIVATIONAL: &[&str] = &["ость", "ост"];
+
+/// Extracts plain text from post content for indexing and snippets.
+#[must_use]
+pub fn plain_text(post: &Post) -> String {
+    let html = if post.markdown {
+        markdown2html(&post.text)
+    } else {
+        xml2html(&post.text)
+    };
+    html.and_then(|h| html2text(&h))
+        .unwrap_or_else(|_| post.text.clone())
+}
+
+/// Splits text into lower cased words with `ё` folded into `е`.
+pub fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            w.chars()
+                .flat_map(char::to_lowercase)
+                .map(|c| if c == 'ё' { 'е' } else { c })
+                .collect()
+        })
+}
+
+/// Normalizes text into space separated stems suitable for FTS5 index.
+#[must_use]
+pub fn normalize(text: &str) -> String {
+    words(text).map(|w| stem(&w)).collect::<Vec<_>>().join(" ")
+}
+
+/// Builds FTS5 MATCH expression that requires every query word as a prefix.
+/// Returns `None` if query has no words.
+#[must_use]
+pub fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = words(query)
+        .map(|w| format!("\"{}\"*", stem(&w).replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Makes HTML snippet of the text around the first word matching the query.
+/// Matched words are wrapped into `<b>` and everything else is escaped.
+#[must_use]
+pub fn snippet(text: &str, query: &str) -> String {
+    let stems = query_stems(query);
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+
+    let first = tokens
+        .iter()
+        .position(|t| is_match(t, &stems))
+        .unwrap_or_default();
+    let start = first.saturating_sub(SNIPPET_CONTEXT);
+    let end = tokens.len().min(start + SNIPPET_WORDS);
+
+    let mut result = String::new();
+    if start > 0 {
+        result.push_str("… ");
+    }
+    result.push_str(&highlight_tokens(&tokens[start..end], &stems));
+    if end < tokens.len() {
+        result.push_str(" …");
+    }
+    result
+}
+
+/// Escapes the whole text and wraps words matching the query into `<b>`.
+#[must_use]
+pub fn highlight(text: &str, query: &str) -> String {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    highlight_tokens(&tokens, &query_stems(query))
+}
+
+fn query_stems(query: &str) -> Vec<String> {
+    words(query).map(|w| stem(&w)).collect()
+}
+
+fn is_match(token: &str, stems: &[String]) -> bool {
+    words(token).any(|w| {
+        let s = stem(&w);
+        stems.iter().any(|q| s.starts_with(q.as_str()))
+    })
+}
+
+fn highlight_tokens(tokens: &[&str], stems: &[String]) -> String {
+    let mut result = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            result.push(' ');
+        }
+        if is_match(token, stems) {
+            result.push_str("<b>");
+            result.push_str(&escape(*token));
+            result.push_str("</b>");
+        } else {
+            result.push_str(&escape(*token));
+        }
+    }
+    result
+}
+
+/// Stems a single lower cased word. Only Cyrillic words are stemmed,
+/// other words are returned as is.
+#[must_use]
+pub fn stem(word: &str) -> String {
+    let mut w: Vec<char> = word.chars().collect();
+    if !w.iter().all(|c| ('а'..='я').contains(c)) {
+        return word.to_owned();
+    }
+
+    let Some(rv) = w.iter().position(|c| VOWELS.contains(c)).map(|p| p + 1) else {
+        return word.to_owned();
+    };
+    let r1 = region(&w, 0);
+    let r2 = region(&w, r1);
+
+    // Step 1
+    if let Some(n) = find_ending(&w, rv, PERFECTIVE_GERUND_1, PERFECTIVE_GERUND_2) {
+        w.truncate(w.len() - n);
+    } else {
+        if let Some(n) = find_ending(&w, rv, &[], REFLEXIVE) {
+            w.truncate(w.len() - n);
+        }
+        if let Some(n) = find_ending(&w, rv, &[], ADJECTIVE) {
+            w.truncate(w.len() - n);
+            if let Some(n) = find_ending(&w, rv, PARTICIPLE_1, PARTICIPLE_2) {
+                w.truncate(w.len() - n);
+            }
+        } else if let Some(n) =
+            find_ending(&w, rv, VERB_1, VERB_2).or_else(|| find_ending(&w, rv, &[], NOUN))
+        {
+            w.truncate(w.len() - n);
+        }
+    }
+
+    // Step 2
+    if w.len() > rv && w.last() == Some(&'и') {
+        w.pop();
+    }
+
+    // Step 3
+    if let Some(n) = find_ending(&w, r2.max(rv), &[], DERIVATIONAL) {
+        w.truncate(w.len() - n);
+    }
+
+    // Step 4
+    if let Some(n) = find_ending(&w, rv, &[], SUPERLATIVE) {
+        w.truncate(w.len() - n);
+        if ends_with(&w, rv, "нн") {
+            w.pop();
+        }
+    } else if ends_with(&w, rv, "нн") || (w.len() > rv && w.last() == Some(&'ь')) {
+        w.pop();
+    }
+
+    w.into_iter().collect()
+}
+
+/// Returns the start of region after the first non-vowel following a vowel.
+fn region(w: &[char], from: usize) -> usize {
+    (from + 1..w.len())
+        .find(|&i| VOWELS.contains(&w[i - 1]) && !VOWELS.contains(&w[i]))
+        .map_or(w.len(), |i| i + 1)
+}
+
+fn ends_with(w: &[char], limit: usize, suffix: &str) -> bool {
+    let n = suffix.chars().count();
+    w.len() >= limit + n && w[w.len() - n..].iter().copied().eq(suffix.chars())
+}
+
+/// Finds the longest ending from both groups that lies within the region
+/// starting at `limit`. Endings of the first group must follow `а` or `я`.
+/// Returns the number of chars to remove.
+fn find_ending(w: &[char], limit: usize, group1: &[&str], group2: &[&str]) -> Option<usize> {
+    let longest = group1
+        .iter()
+        .map(|s| (s, true))
+        .chain(group2.iter().map(|s| (s, false)))
+        .filter(|(s, _)| ends_with(w, limit, s))
+        .max_by_key(|(s, _)| s.chars().count())?;
+
+    let n = longest.0.chars().count();
+    if longest.1 {
+        let preceding = w.len() - n;
+        if preceding <= limit || !matches!(w[preceding - 1], 'а' | 'я') {
+            return None;
+        }
+    }
+    Some(n)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("вечер", "вечер")]
+    #[case("вечера", "вечер")]
+    #[case("вечерами", "вечер")]
+    #[case("красивая", "красив")]
+    #[case("красивыми", "красив")]
+    #[case("программирование", "программирован")]
+    #[case("программирования", "программирован")]
+    #[case("читавшись", "чита")]
+    #[case("делаешь", "дела")]
+    #[case("новейший", "нов")]
+    #[case("радость", "радост")]
+    #[case("длинный", "длин")]
+    #[case("ёлка", "елк")]
+    #[case("rust", "rust")]
+    #[case("и", "и")]
+    fn stem_tests(#[case] word: &str, #[case] expected: &str) {
+        // arrange
+        let word = words(word).next().unwrap();
+
+        // act
+        let actual = stem(&word);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case("Программирование на Rust", "программирован на rust")]
+    #[case("Ёлки, палки!", "елк палк")]
+    #[case("  ", "")]
+    fn normalize_tests(#[case] text: &str, #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = normalize(text);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case("Красивые ПРОГРАММЫ", Some("\"красив\"* \"программ\"*"))]
+    #[case("\"rust\"", Some("\"rust\"*"))]
+    #[case(" ,. ", None)]
+    fn match_expression_tests(#[case] query: &str, #[case] expected: Option<&str>) {
+        // arrange
+
+        // act
+        let actual = match_expression(query);
+
+        // assert
+        assert_eq!(expected.map(str::to_owned), actual);
+    }
+
+    #[rstest]
+    #[case(
+        "Пишем программу на Rust",
+        "программы",
+        "Пишем <b>программу</b> на Rust"
+    )]
+    #[case("a < b & c", "c", "a &lt; b &amp; <b>c</b>")]
+    #[case("no match here", "rust", "no match here")]
+    fn snippet_tests(#[case] text: &str, #[case] query: &str, #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = snippet(text, query);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case("Базы данных & SQL", "база", "<b>Базы</b> данных &amp; SQL")]
+    #[case("Заметка", "база", "Заметка")]
+    fn highlight_tests(#[case] text: &str, #[case] query: &str, #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = highlight(text, query);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn snippet_long_text_truncated_around_match() {
+        // arrange
+        let mut text = vec!["слово"; 100];
+        text[40] = "поиск";
+        let text = text.join(" ");
+
+        // act
+        let actual = snippet(&text, "поиска");
+
+        // assert
+        assert!(actual.starts_with("… "));
+        assert!(actual.ends_with(" …"));
+        assert!(actual.contains("<b>поиск</b>"));
+    }
+}
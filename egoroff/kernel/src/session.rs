@@ -14,6 +14,9 @@ use tower_sessions::{
     session::{Id, Record},
 };
 
+/// Sessions database file name.
+pub const DATABASE: &str = "egoroff_sessions.db";
+
 #[derive(Debug, Clone)]
 pub struct SqliteSessionStore {
     path: Arc<PathBuf>,
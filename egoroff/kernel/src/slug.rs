@@ -0,0 +1,138 @@
+//! Human readable post URL parts generated from titles.
+
+/// Maximum slug length in chars. Longer slugs are cut on a word boundary.
+const MAX_LENGTH: usize = 80;
+
+/// Makes URL friendly slug from text. Cyrillic is transliterated into Latin,
+/// everything that is not a letter or digit becomes a single dash.
+#[must_use]
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if let Some(latin) = transliterate(c) {
+            slug.push_str(latin);
+        } else if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    if slug.len() > MAX_LENGTH {
+        // Slug is ASCII only so byte index is char index
+        let cut = slug[..=MAX_LENGTH].rfind('-').unwrap_or(MAX_LENGTH);
+        slug.truncate(cut);
+    }
+    slug.trim_end_matches('-').to_owned()
+}
+
+/// Makes slug unique by adding numeric suffix until `exists` returns false.
+pub fn unique<E, F>(slug: &str, mut exists: F) -> Result<String, E>
+where
+    F: FnMut(&str) -> Result<bool, E>,
+{
+    if !exists(slug)? {
+        return Ok(slug.to_owned());
+    }
+    let mut n = 2;
+    loop {
+        let candidate = format!("{slug}-{n}");
+        if !exists(&candidate)? {
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
+
+fn transliterate(c: char) -> Option<&'static str> {
+    let latin = match c {
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' => "g",
+        'д' => "d",
+        'е' | 'э' => "e",
+        'ё' => "yo",
+        'ж' => "zh",
+        'з' => "z",
+        'и' => "i",
+        'й' | 'ы' => "y",
+        'к' => "k",
+        'л' => "l",
+        'м' => "m",
+        'н' => "n",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ю' => "yu",
+        'я' => "ya",
+        _ => return None,
+    };
+    Some(latin)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("Hello, World!", "hello-world")]
+    #[case("Программирование на Rust", "programmirovanie-na-rust")]
+    #[case(
+        "Съешь ещё этих мягких французских булок",
+        "sesh-eshchyo-etikh-myagkikh-frantsuzskikh-bulok"
+    )]
+    #[case("  --Blake3--  ", "blake3")]
+    #[case("C++ & C#", "c-c")]
+    #[case("日本語", "")]
+    #[case("", "")]
+    fn slugify_tests(#[case] text: &str, #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = slugify(text);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn slugify_long_text_cut_on_word_boundary() {
+        // arrange
+        let text = "слово ".repeat(30);
+
+        // act
+        let actual = slugify(&text);
+
+        // assert
+        assert!(actual.len() <= MAX_LENGTH);
+        assert!(actual.ends_with("slovo"));
+    }
+
+    #[rstest]
+    #[case(&[], "post")]
+    #[case(&["post"], "post-2")]
+    #[case(&["post", "post-2"], "post-3")]
+    fn unique_tests(#[case] existing: &[&str], #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = unique::<(), _>("post", |s| Ok(existing.contains(&s))).unwrap();
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+}
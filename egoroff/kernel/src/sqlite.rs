@@ -1,11 +1,22 @@
-use std::{collections::HashMap, path::Path, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    time::Duration,
+};
 
 use chrono::{DateTime, Utc};
 use itertools::Itertools;
-use rusqlite::{Connection, Error, ErrorCode, OpenFlags, Row, Transaction, params};
+use rusqlite::{
+    Connection, Error, ErrorCode, OpenFlags, OptionalExtension, Row, Transaction, params,
+};
 
-use crate::domain::{
-    Download, Folder, OAuthProvider, Post, PostsRequest, SmallPost, Storage, TagAggregate, User,
+use crate::{
+    domain::{
+        DeliveryStatus, Download, Folder, IndieToken, OAuthProvider, OutgoingWebmention, Post,
+        PostRevision, PostStatus, PostsRequest, SmallPost, Storage, TagAggregate, TagInfo, User,
+        WebSubSubscription, Webmention, WebmentionKind, WebmentionStatus,
+    },
+    migration, related, search, slug,
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -16,8 +27,22 @@ pub enum Mode {
 
 pub const DATABASE: &str = "egoroff.db";
 
+const WEBMENTION_COLUMNS: &str = "id, source, target, post_id, status, kind, author_name, author_url, \
+     author_photo, content, published, received, verified, error";
+
+const OUTGOING_WEBMENTION_COLUMNS: &str =
+    "id, post_id, source, target, status, endpoint, attempts, next_attempt, last_attempt, error";
+
+const WEBSUB_SUBSCRIPTION_COLUMNS: &str =
+    "id, topic, callback, secret, created, expires, delivered";
+
+/// Number of revisions kept per post by default.
+pub const DEFAULT_REVISIONS_LIMIT: usize = 20;
+
 pub struct Sqlite {
     conn: Connection,
+    revisions_limit: usize,
+    preserve_modified: bool,
 }
 
 macro_rules! datetime_from_row {
@@ -30,60 +55,8 @@ macro_rules! datetime_from_row {
 impl Storage for Sqlite {
     type Err = Error;
 
-    fn new_database(&self) -> Result<(), Self::Err> {
-        self.pragma_update("encoding", "UTF-8")?;
-
-        self.conn.execute(
-            "CREATE TABLE post (
-                  id              INTEGER PRIMARY KEY,
-                  title           TEXT NOT NULL,
-                  short_text      TEXT NOT NULL,
-                  text            TEXT NOT NULL,
-                  markdown        INTEGER,
-                  is_public       INTEGER,
-                  created         INTEGER NOT NULL,
-                  modified        INTEGER NOT NULL
-                  )",
-            [],
-        )?;
-
-        self.conn
-            .execute("CREATE INDEX created_ix ON post(created)", [])?;
-
-        self.conn.execute(
-            "CREATE TABLE tag (
-                   tag           TEXT PRIMARY KEY
-                  )",
-            [],
-        )?;
-
-        self.conn.execute(
-            "CREATE TABLE post_tag (
-                  post_id              INTEGER NOT NULL,
-                  tag                  TEXT NOT NULL,
-                  PRIMARY KEY (post_id, tag),
-                  FOREIGN KEY(post_id) REFERENCES post(id) ON UPDATE CASCADE ON DELETE CASCADE,
-                  FOREIGN KEY(tag) REFERENCES tag(tag)
-                  )",
-            [],
-        )?;
-
-        self.conn.execute(
-            "CREATE TABLE folder (
-                  bucket          TEXT PRIMARY KEY,
-                  title           TEXT NOT NULL
-                  )",
-            [],
-        )?;
-
-        self.conn.execute(
-            "CREATE TABLE file (
-                  id              INTEGER PRIMARY KEY,
-                  title           TEXT NOT NULL
-                  )",
-            [],
-        )?;
-
+    fn new_database(&mut self) -> Result<(), Self::Err> {
+        migration::migrate(&mut self.conn)?;
         Ok(())
     }
 
@@ -97,7 +70,7 @@ impl Storage for Sqlite {
 
         let files: Vec<crate::domain::SmallPost> = match request.tag {
             Some(tag) => {
-                let mut stmt = self.conn.prepare("SELECT id, title, created, short_text, markdown \
+                let mut stmt = self.conn.prepare("SELECT id, title, created, short_text, markdown, slug \
                                     FROM post INNER JOIN post_tag ON post_tag.post_id = post.id 
                                     WHERE is_public = 1 AND post_tag.tag = ?3 ORDER BY created DESC LIMIT ?1 OFFSET ?2")?;
                 let files = stmt.query_map(
@@ -109,7 +82,7 @@ impl Storage for Sqlite {
             None => {
                 if let Some(period) = request.as_query_period() {
                     let mut stmt = self.conn.prepare(
-                        "SELECT id, title, created, short_text, markdown \
+                        "SELECT id, title, created, short_text, markdown, slug \
                     FROM post WHERE is_public = 1 AND created > ?1 AND created < ?2 ORDER BY created DESC  LIMIT ?3 OFFSET ?4",
                     )?;
                     let files = stmt.query_map(
@@ -124,7 +97,7 @@ impl Storage for Sqlite {
                     files.filter_map(std::result::Result::ok).collect()
                 } else {
                     let mut stmt = self.conn.prepare(
-                        "SELECT id, title, created, short_text, markdown \
+                        "SELECT id, title, created, short_text, markdown, slug \
                     FROM post WHERE is_public = 1 ORDER BY created DESC LIMIT ?1 OFFSET ?2",
                     )?;
                     let files = stmt.query_map([limit, offset], Sqlite::map_small_post_row)?;
@@ -145,7 +118,7 @@ impl Storage for Sqlite {
         })?;
 
         let mut stmt = self.conn.prepare(
-            "SELECT title, created, short_text, markdown, text, is_public, modified FROM post WHERE id = ?1",
+            "SELECT title, created, short_text, markdown, text, is_public, modified, slug, status FROM post WHERE id = ?1",
         )?;
         let post: Post = stmt.query_row([id], |row| {
             let post = Post {
@@ -158,6 +131,8 @@ impl Storage for Sqlite {
                 markdown: row.get(3)?,
                 is_public: row.get(5)?,
                 tags: tags.filter_map(std::result::Result::ok).collect(),
+                slug: row.get(7)?,
+                status: Sqlite::status_from_row(row, 8)?,
             };
 
             Ok(post)
@@ -166,10 +141,72 @@ impl Storage for Sqlite {
         Ok(post)
     }
 
+    fn get_post_id(&self, slug: &str) -> Result<i64, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM (SELECT id, 0 AS source FROM post WHERE slug = ?1 \
+             UNION ALL SELECT id, 1 AS source FROM deleted_post WHERE slug = ?1 \
+             UNION ALL SELECT post_id, 2 AS source FROM post_previous_slug WHERE slug = ?1) \
+             ORDER BY source LIMIT 1",
+        )?;
+        stmt.query_row([slug], |row| row.get(0))
+    }
+
     fn upsert_post(&mut self, post: crate::domain::Post) -> Result<(), Self::Err> {
-        let items = vec![post];
-        self.upsert(&items, Sqlite::upsert_post)?;
-        Ok(())
+        let limit = self.revisions_limit;
+        let modified = if self.preserve_modified {
+            post.modified
+        } else {
+            Utc::now()
+        };
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+            Sqlite::upsert_post(&tx, &post, modified)?;
+            Sqlite::insert_revision(&tx, &post, limit)?;
+            tx.commit()
+        })
+    }
+
+    fn get_post_revisions(&self, post_id: i64) -> Result<Vec<PostRevision>, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, post_id, created, title, short_text, text, markdown, tags \
+             FROM post_revision WHERE post_id = ?1 ORDER BY id DESC",
+        )?;
+        let revisions = stmt.query_map([post_id], Sqlite::map_revision_row)?;
+        revisions.collect()
+    }
+
+    fn get_post_revision(&self, post_id: i64, id: i64) -> Result<PostRevision, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, post_id, created, title, short_text, text, markdown, tags \
+             FROM post_revision WHERE post_id = ?1 AND id = ?2",
+        )?;
+        stmt.query_row([post_id, id], Sqlite::map_revision_row)
+    }
+
+    fn get_deleted_post(&self, id: i64) -> Result<Post, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT value FROM deleted_post, json_each(deleted_post.tags) WHERE deleted_post.id = ?1",
+        )?;
+        let tags = stmt.query_map([id], |row| row.get(0))?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT title, created, short_text, markdown, text, is_public, modified, slug, status FROM deleted_post WHERE id = ?1",
+        )?;
+        stmt.query_row([id], |row| {
+            Ok(Post {
+                created: datetime_from_row!(row, 1),
+                modified: datetime_from_row!(row, 6),
+                id,
+                title: row.get(0)?,
+                short_text: row.get(2)?,
+                text: row.get(4)?,
+                markdown: row.get(3)?,
+                is_public: row.get(5)?,
+                tags: tags.filter_map(std::result::Result::ok).collect(),
+                slug: row.get(7)?,
+                status: Sqlite::status_from_row(row, 8)?,
+            })
+        })
     }
 
     fn upsert_download(&mut self, download: crate::domain::Download) -> Result<(), Self::Err> {
@@ -178,15 +215,31 @@ impl Storage for Sqlite {
         Ok(())
     }
 
+    fn upsert_folder(&mut self, folder: Folder) -> Result<(), Self::Err> {
+        let items = vec![folder];
+        self.upsert(&items, Sqlite::upsert_folder)?;
+        Ok(())
+    }
+
     fn delete_post(&mut self, id: i64) -> Result<usize, Self::Err> {
         self.enable_foreign_keys()?;
         Sqlite::execute_with_retry(|| {
             let tx = self.conn.transaction()?;
 
+            tx.execute(
+                "INSERT OR REPLACE INTO deleted_post (id, title, short_text, text, markdown, created, modified, slug, status, tags, deleted)
+                    SELECT id, title, short_text, text, markdown, created, modified, slug, status,
+                        (SELECT json_group_array(tag) FROM post_tag WHERE post_id = ?1), ?2
+                    FROM post WHERE id = ?1",
+                params![id, Utc::now().timestamp()],
+            )?;
+
             let mut stmt = tx.prepare("DELETE FROM post WHERE id = ?1")?;
             let deleted_count = stmt.execute(params![id])?;
             stmt.finalize()?;
 
+            tx.execute("DELETE FROM post_search WHERE rowid = ?1", params![id])?;
+
             tx.commit()?;
 
             Ok(deleted_count)
@@ -250,6 +303,59 @@ impl Storage for Sqlite {
         Ok(files.filter_map(std::result::Result::ok).collect())
     }
 
+    fn get_tags(&self) -> Result<Vec<TagInfo>, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tag, title, description, (SELECT COUNT(1) FROM post_tag WHERE post_tag.tag = tag.tag) \
+             FROM tag ORDER BY tag",
+        )?;
+        let tags = stmt.query_map([], Sqlite::map_tag_row)?;
+        tags.collect()
+    }
+
+    fn get_tag(&self, tag: &str) -> Result<TagInfo, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tag, title, description, (SELECT COUNT(1) FROM post_tag WHERE post_tag.tag = tag.tag) \
+             FROM tag WHERE tag = ?1",
+        )?;
+        stmt.query_row([tag], Sqlite::map_tag_row)
+    }
+
+    fn update_tag(&mut self, tag: &TagInfo) -> Result<usize, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute(
+                "UPDATE tag SET title = NULLIF(?2, ''), description = NULLIF(?3, '') WHERE tag = ?1",
+                params![tag.tag, tag.title, tag.description],
+            )
+        })
+    }
+
+    fn merge_tags(&mut self, tags: &[String], into: &str) -> Result<usize, Self::Err> {
+        self.enable_foreign_keys()?;
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+            let changed = Sqlite::merge_tags(&tx, tags, into)?;
+            tx.commit()?;
+            Ok(changed)
+        })
+    }
+
+    fn delete_orphan_tags(&mut self) -> Result<Vec<String>, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+
+            let tags = tx
+                .prepare(
+                    "DELETE FROM tag WHERE tag NOT IN (SELECT DISTINCT tag FROM post_tag) RETURNING tag",
+                )?
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+
+            tx.commit()?;
+
+            Ok(tags)
+        })
+    }
+
     fn get_posts_create_dates(&self) -> Result<Vec<DateTime<Utc>>, Self::Err> {
         let mut stmt = self
             .conn
@@ -318,24 +424,10 @@ impl Storage for Sqlite {
 
     fn get_posts(&self, limit: i32, offset: i32) -> Result<Vec<Post>, Self::Err> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, title, created, short_text, markdown, text, is_public, modified \
+            "SELECT id, title, created, short_text, markdown, text, is_public, modified, slug, status \
                  FROM post ORDER BY created DESC LIMIT ?1 OFFSET ?2",
         )?;
-        let posts_query = stmt.query_map([limit, offset], |row| {
-            let post = Post {
-                created: datetime_from_row!(row, 2),
-                modified: datetime_from_row!(row, 7),
-                id: row.get(0)?,
-                title: row.get(1)?,
-                short_text: row.get(3)?,
-                text: row.get(5)?,
-                markdown: row.get(4)?,
-                is_public: row.get(6)?,
-                ..Default::default()
-            };
-
-            Ok(post)
-        })?;
+        let posts_query = stmt.query_map([limit, offset], Sqlite::map_post_row)?;
 
         let mut stmt = self.conn.prepare(
             "SELECT post_id, tag FROM post_tag WHERE post_id IN (SELECT id FROM post ORDER BY created DESC LIMIT ?1 OFFSET ?2)",
@@ -343,24 +435,40 @@ impl Storage for Sqlite {
 
         let tags_query = stmt.query_map([limit, offset], |row| Ok((row.get(0)?, row.get(1)?)))?;
 
-        let mut post_tags: HashMap<i64, Vec<String>> = tags_query
-            .filter_map(std::result::Result::ok)
-            .chunk_by(|(id, _tag)| *id)
-            .into_iter()
-            .map(|(id, g)| (id, g.map(|(_, tag)| tag).collect()))
-            .collect();
+        Ok(Sqlite::with_tags(posts_query, tags_query))
+    }
 
-        let posts = posts_query
-            .filter_map(std::result::Result::ok)
-            .map(|mut post| {
-                if let Some(tags) = post_tags.get_mut(&post.id) {
-                    post.tags.append(tags);
-                }
-                post
-            })
-            .collect();
+    fn get_posts_by_status(&self, status: PostStatus) -> Result<Vec<Post>, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, created, short_text, markdown, text, is_public, modified, slug, status \
+                 FROM post WHERE status = ?1 AND is_public = 0 ORDER BY created",
+        )?;
+        let posts_query = stmt.query_map([status.as_str()], Sqlite::map_post_row)?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT post_id, tag FROM post_tag WHERE post_id IN (SELECT id FROM post WHERE status = ?1 AND is_public = 0) ORDER BY post_id",
+        )?;
+        let tags_query = stmt.query_map([status.as_str()], |row| Ok((row.get(0)?, row.get(1)?)))?;
 
-        Ok(posts)
+        Ok(Sqlite::with_tags(posts_query, tags_query))
+    }
+
+    fn publish_scheduled_posts(&mut self, now: DateTime<Utc>) -> Result<Vec<i64>, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+
+            let ids = tx
+                .prepare(
+                    "UPDATE post SET status = 'published' \
+                     WHERE status = 'scheduled' AND created <= ?1 RETURNING id",
+                )?
+                .query_map([now.timestamp()], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+
+            tx.commit()?;
+
+            Ok(ids)
+        })
     }
 
     fn get_new_post_id(&self, id: i64) -> Result<i64, Self::Err> {
@@ -372,6 +480,57 @@ impl Storage for Sqlite {
         Ok(post_id)
     }
 
+    fn get_previous_post(&self, id: i64) -> Result<Option<SmallPost>, Self::Err> {
+        let created = self.get_post_created(id)?;
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, created, short_text, markdown, slug FROM post \
+             WHERE is_public = 1 AND (created < ?1 OR (created = ?1 AND id < ?2)) \
+             ORDER BY created DESC, id DESC LIMIT 1",
+        )?;
+        stmt.query_row(params![created, id], Sqlite::map_small_post_row)
+            .optional()
+    }
+
+    fn get_next_post(&self, id: i64) -> Result<Option<SmallPost>, Self::Err> {
+        let created = self.get_post_created(id)?;
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, created, short_text, markdown, slug FROM post \
+             WHERE is_public = 1 AND (created > ?1 OR (created = ?1 AND id > ?2)) \
+             ORDER BY created, id LIMIT 1",
+        )?;
+        stmt.query_row(params![created, id], Sqlite::map_small_post_row)
+            .optional()
+    }
+
+    fn get_related_posts(&self, id: i64, limit: usize) -> Result<Vec<SmallPost>, Self::Err> {
+        // Plain text of posts indexed for search is read instead of post texts
+        // so that posts aren't rendered again.
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, created, short_text, markdown, COALESCE(post_search.body, ''), is_public, modified, slug, status \
+                 FROM post LEFT JOIN post_search ON post_search.rowid = post.id \
+                 WHERE is_public = 1 OR id = ?1 ORDER BY id",
+        )?;
+        let posts_query = stmt.query_map([id], Sqlite::map_post_row)?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT post_id, tag FROM post_tag WHERE post_id IN (SELECT id FROM post WHERE is_public = 1 OR id = ?1) ORDER BY post_id",
+        )?;
+        let tags_query = stmt.query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let candidates: Vec<related::Candidate> = Sqlite::with_tags(posts_query, tags_query)
+            .into_iter()
+            .map(|mut post| related::Candidate {
+                body: std::mem::take(&mut post.text),
+                post,
+            })
+            .collect();
+        let post = candidates
+            .iter()
+            .find(|c| c.post.id == id)
+            .ok_or(Error::QueryReturnedNoRows)?;
+        Ok(related::rank(post, &candidates, limit))
+    }
+
     fn next_post_id(&mut self) -> Result<i64, Self::Err> {
         let mut stmt = self.conn.prepare("SELECT MAX(id) + 1 FROM post")?;
         let post_id = stmt.query_row([], |row| row.get(0))?;
@@ -444,15 +603,413 @@ impl Storage for Sqlite {
         let mut stmt = self.conn.prepare("SELECT COUNT(1) FROM user")?;
         stmt.query_row([], |row| row.get(0))
     }
+
+    fn search_posts(
+        &self,
+        query: &str,
+        limit: i32,
+        offset: i32,
+        include_private: bool,
+    ) -> Result<Vec<SmallPost>, Self::Err> {
+        let Some(expression) = search::match_expression(query) else {
+            return Ok(vec![]);
+        };
+        let is_public = i32::from(!include_private);
+
+        // Matches in title weigh more than matches in text
+        let mut stmt = self.conn.prepare(
+            "SELECT post.id, post.title, post.created, post_search.body, post.slug \
+             FROM post_search INNER JOIN post ON post.id = post_search.rowid \
+             WHERE post_search MATCH ?1 AND (post.is_public = 1 OR post.is_public = ?2) \
+             ORDER BY bm25(post_search, 10.0, 1.0, 0.0) LIMIT ?3 OFFSET ?4",
+        )?;
+        let posts = stmt.query_map(params![expression, is_public, limit, offset], |row| {
+            let body: String = row.get(3)?;
+            Ok(SmallPost {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                created: datetime_from_row!(row, 2),
+                short_text: search::snippet(&body, query),
+                markdown: false,
+                slug: row.get(4)?,
+            })
+        })?;
+        Ok(posts.filter_map(std::result::Result::ok).collect())
+    }
+
+    fn count_search_results(&self, query: &str, include_private: bool) -> Result<i32, Self::Err> {
+        let Some(expression) = search::match_expression(query) else {
+            return Ok(0);
+        };
+        let is_public = i32::from(!include_private);
+
+        let mut stmt = self.conn.prepare(
+            "SELECT COUNT(1) FROM post_search INNER JOIN post ON post.id = post_search.rowid \
+             WHERE post_search MATCH ?1 AND (post.is_public = 1 OR post.is_public = ?2)",
+        )?;
+        stmt.query_row(params![expression, is_public], |row| row.get(0))
+    }
+
+    fn insert_token(&mut self, token: &IndieToken) -> Result<(), Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute(
+                "INSERT INTO indie_token (jti, client_id, scope, issued, expires, revoked) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    token.jti,
+                    token.client_id,
+                    token.scope,
+                    token.issued.timestamp(),
+                    token.expires.timestamp(),
+                    token.revoked.map(|r| r.timestamp())
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn get_token(&self, jti: &str) -> Result<IndieToken, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT jti, client_id, scope, issued, expires, revoked FROM indie_token WHERE jti = ?1",
+        )?;
+        stmt.query_row([jti], Sqlite::map_token_row)
+    }
+
+    fn revoke_token(&mut self, jti: &str) -> Result<usize, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute(
+                "UPDATE indie_token SET revoked = ?2 WHERE jti = ?1 AND revoked IS NULL",
+                params![jti, Utc::now().timestamp()],
+            )
+        })
+    }
+
+    fn get_active_tokens(&self, client_id: Option<&str>) -> Result<Vec<IndieToken>, Self::Err> {
+        let mut stmt = self.conn.prepare(
+            "SELECT jti, client_id, scope, issued, expires, revoked FROM indie_token \
+             WHERE revoked IS NULL AND expires > ?1 AND (?2 IS NULL OR client_id = ?2) \
+             ORDER BY issued DESC",
+        )?;
+        let rows = stmt.query_map(
+            params![Utc::now().timestamp(), client_id],
+            Sqlite::map_token_row,
+        )?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    fn queue_webmention(&mut self, mention: &Webmention) -> Result<i64, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.query_row(
+                "INSERT INTO webmention (source, target, post_id, status, received) VALUES (?1, ?2, ?3, 'pending', ?4) \
+                 ON CONFLICT(source, target) DO UPDATE SET post_id = excluded.post_id, received = excluded.received, \
+                 status = CASE WHEN status = 'rejected' THEN status ELSE 'pending' END \
+                 RETURNING id",
+                params![
+                    mention.source,
+                    mention.target,
+                    mention.post_id,
+                    mention.received.timestamp()
+                ],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    fn get_pending_webmentions(&self, limit: i32) -> Result<Vec<Webmention>, Self::Err> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {WEBMENTION_COLUMNS} FROM webmention WHERE status = 'pending' ORDER BY received, id LIMIT ?1"
+        ))?;
+        let rows = stmt.query_map([limit], Sqlite::map_webmention_row)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    fn get_webmention(&self, id: i64) -> Result<Webmention, Self::Err> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {WEBMENTION_COLUMNS} FROM webmention WHERE id = ?1"
+        ))?;
+        stmt.query_row([id], Sqlite::map_webmention_row)
+    }
+
+    fn get_webmentions(
+        &self,
+        status: Option<WebmentionStatus>,
+    ) -> Result<Vec<Webmention>, Self::Err> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {WEBMENTION_COLUMNS} FROM webmention WHERE ?1 IS NULL OR status = ?1 ORDER BY received DESC, id DESC"
+        ))?;
+        let rows = stmt.query_map([status.map(|s| s.as_str())], Sqlite::map_webmention_row)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    fn get_post_webmentions(&self, post_id: i64) -> Result<Vec<Webmention>, Self::Err> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {WEBMENTION_COLUMNS} FROM webmention WHERE post_id = ?1 AND status = 'approved' \
+             ORDER BY COALESCE(published, received), id"
+        ))?;
+        let rows = stmt.query_map([post_id], Sqlite::map_webmention_row)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    fn update_webmention(&mut self, mention: &Webmention) -> Result<usize, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute(
+                "UPDATE webmention SET status = ?2, kind = ?3, author_name = ?4, author_url = ?5, author_photo = ?6, \
+                 content = ?7, published = ?8, verified = ?9, error = ?10 WHERE id = ?1",
+                params![
+                    mention.id,
+                    mention.status.as_str(),
+                    mention.kind.as_str(),
+                    mention.author_name,
+                    mention.author_url,
+                    mention.author_photo,
+                    mention.content,
+                    mention.published.map(|p| p.timestamp()),
+                    mention.verified.map(|v| v.timestamp()),
+                    mention.error
+                ],
+            )
+        })
+    }
+
+    fn delete_webmention(&mut self, id: i64) -> Result<usize, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn
+                .execute("DELETE FROM webmention WHERE id = ?1", [id])
+        })
+    }
+
+    fn queue_outgoing_webmentions(
+        &mut self,
+        post_id: i64,
+        source: &str,
+        targets: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<usize, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            let tx = self.conn.transaction()?;
+            let mut queued = 0;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO outgoing_webmention (post_id, source, target, status, next_attempt) \
+                     VALUES (?1, ?2, ?3, 'pending', ?4) \
+                     ON CONFLICT(post_id, target) DO UPDATE SET source = excluded.source, status = 'pending', \
+                     attempts = 0, next_attempt = excluded.next_attempt, error = NULL",
+                )?;
+                for target in targets {
+                    queued += stmt.execute(params![post_id, source, target, now.timestamp()])?;
+                }
+            }
+            tx.commit()?;
+            Ok(queued)
+        })
+    }
+
+    fn get_due_outgoing_webmentions(
+        &self,
+        now: DateTime<Utc>,
+        limit: i32,
+    ) -> Result<Vec<OutgoingWebmention>, Self::Err> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {OUTGOING_WEBMENTION_COLUMNS} FROM outgoing_webmention \
+             WHERE status = 'pending' AND next_attempt <= ?1 ORDER BY next_attempt, id LIMIT ?2"
+        ))?;
+        let rows = stmt.query_map(
+            params![now.timestamp(), limit],
+            Sqlite::map_outgoing_webmention_row,
+        )?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    fn get_outgoing_webmentions(&self, post_id: i64) -> Result<Vec<OutgoingWebmention>, Self::Err> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {OUTGOING_WEBMENTION_COLUMNS} FROM outgoing_webmention WHERE post_id = ?1 ORDER BY target"
+        ))?;
+        let rows = stmt.query_map([post_id], Sqlite::map_outgoing_webmention_row)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    fn update_outgoing_webmention(
+        &mut self,
+        mention: &OutgoingWebmention,
+    ) -> Result<usize, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute(
+                "UPDATE outgoing_webmention SET status = ?2, endpoint = ?3, attempts = ?4, next_attempt = ?5, \
+                 last_attempt = ?6, error = ?7 WHERE id = ?1",
+                params![
+                    mention.id,
+                    mention.status.as_str(),
+                    mention.endpoint,
+                    mention.attempts,
+                    mention.next_attempt.timestamp(),
+                    mention.last_attempt.map(|a| a.timestamp()),
+                    mention.error
+                ],
+            )
+        })
+    }
+
+    fn upsert_subscription(&mut self, subscription: &WebSubSubscription) -> Result<i64, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.query_row(
+                "INSERT INTO websub_subscription (topic, callback, secret, created, expires) \
+                 VALUES (?1, ?2, ?3, ?4, ?5) \
+                 ON CONFLICT(topic, callback) DO UPDATE SET secret = excluded.secret, \
+                 created = excluded.created, expires = excluded.expires \
+                 RETURNING id",
+                params![
+                    subscription.topic,
+                    subscription.callback,
+                    subscription.secret,
+                    subscription.created.timestamp(),
+                    subscription.expires.timestamp()
+                ],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    fn delete_subscription(&mut self, topic: &str, callback: &str) -> Result<usize, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute(
+                "DELETE FROM websub_subscription WHERE topic = ?1 AND callback = ?2",
+                [topic, callback],
+            )
+        })
+    }
+
+    fn get_subscriptions(&self, now: DateTime<Utc>) -> Result<Vec<WebSubSubscription>, Self::Err> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {WEBSUB_SUBSCRIPTION_COLUMNS} FROM websub_subscription \
+             WHERE expires > ?1 ORDER BY topic, id"
+        ))?;
+        let rows = stmt.query_map([now.timestamp()], Sqlite::map_websub_subscription_row)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    fn set_subscription_delivered(&mut self, id: i64, digest: &str) -> Result<usize, Self::Err> {
+        Sqlite::execute_with_retry(|| {
+            self.conn.execute(
+                "UPDATE websub_subscription SET delivered = ?2 WHERE id = ?1",
+                params![id, digest],
+            )
+        })
+    }
 }
 
 impl Sqlite {
+    /// Sets how many revisions are kept per post. Zero disables revisions.
+    #[must_use]
+    pub fn with_revisions_limit(mut self, limit: usize) -> Self {
+        self.revisions_limit = limit;
+        self
+    }
+
+    /// Keeps `modified` time of upserted posts instead of setting the current time.
+    /// Used to import previously exported posts as they were.
+    #[must_use]
+    pub fn preserve_modified(mut self) -> Self {
+        self.preserve_modified = true;
+        self
+    }
+
+    /// Switches database into write-ahead log mode so readers don't block on writer.
+    /// Returns journal mode actually set, in-memory databases stay in `memory` mode.
+    pub fn enable_wal(&self) -> Result<String, Error> {
+        self.conn
+            .query_row("PRAGMA journal_mode=WAL", [], |row| row.get(0))
+    }
+
+    /// Opens database. Read-write mode applies all pending schema migrations
+    /// and read-only mode refuses schema created by a newer application version.
     pub fn open<P: AsRef<Path>>(path: P, mode: Mode) -> Result<Sqlite, Error> {
-        let c = match mode {
+        let mut conn = match mode {
             Mode::ReadWrite => Connection::open(path),
             Mode::ReadOnly => Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY),
+        }?;
+        match mode {
+            Mode::ReadWrite => migration::migrate(&mut conn)?,
+            Mode::ReadOnly => migration::ensure_supported(&conn)?,
         };
-        Ok(Self { conn: c? })
+        Ok(Self {
+            conn,
+            revisions_limit: DEFAULT_REVISIONS_LIMIT,
+            preserve_modified: false,
+        })
+    }
+
+    fn map_revision_row(row: &Row<'_>) -> Result<PostRevision, Error> {
+        let tags: String = row.get(7)?;
+        Ok(PostRevision {
+            id: row.get(0)?,
+            post_id: row.get(1)?,
+            created: datetime_from_row!(row, 2),
+            title: row.get(3)?,
+            short_text: row.get(4)?,
+            text: row.get(5)?,
+            markdown: row.get(6)?,
+            tags: serde_json::from_str(&tags).unwrap_or_default(),
+        })
+    }
+
+    fn map_tag_row(row: &Row<'_>) -> Result<TagInfo, Error> {
+        Ok(TagInfo {
+            tag: row.get(0)?,
+            title: row.get(1)?,
+            description: row.get(2)?,
+            posts: row.get(3)?,
+        })
+    }
+
+    fn map_post_row(row: &Row<'_>) -> Result<Post, Error> {
+        Ok(Post {
+            created: datetime_from_row!(row, 2),
+            modified: datetime_from_row!(row, 7),
+            id: row.get(0)?,
+            title: row.get(1)?,
+            short_text: row.get(3)?,
+            text: row.get(5)?,
+            markdown: row.get(4)?,
+            is_public: row.get(6)?,
+            slug: row.get(8)?,
+            status: Sqlite::status_from_row(row, 9)?,
+            ..Default::default()
+        })
+    }
+
+    fn status_from_row(row: &Row<'_>, idx: usize) -> Result<PostStatus, Error> {
+        let status: String = row.get(idx)?;
+        Ok(PostStatus::parse(&status).unwrap_or_default())
+    }
+
+    fn get_post_created(&self, id: i64) -> Result<i64, Error> {
+        self.conn
+            .query_row("SELECT created FROM post WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+    }
+
+    /// Attaches tags selected ordered by post ID to the posts.
+    fn with_tags<P, T>(posts: P, tags: T) -> Vec<Post>
+    where
+        P: Iterator<Item = Result<Post, Error>>,
+        T: Iterator<Item = Result<(i64, String), Error>>,
+    {
+        let mut post_tags: HashMap<i64, Vec<String>> = tags
+            .filter_map(std::result::Result::ok)
+            .chunk_by(|(id, _tag)| *id)
+            .into_iter()
+            .map(|(id, g)| (id, g.map(|(_, tag)| tag).collect()))
+            .collect();
+
+        posts
+            .filter_map(std::result::Result::ok)
+            .map(|mut post| {
+                if let Some(tags) = post_tags.get_mut(&post.id) {
+                    post.tags.append(tags);
+                }
+                post
+            })
+            .collect()
     }
 
     fn map_small_post_row<E: std::convert::From<Error>>(row: &Row<'_>) -> Result<SmallPost, E> {
@@ -462,6 +1019,7 @@ impl Sqlite {
             created: datetime_from_row!(row, 2),
             short_text: row.get(3)?,
             markdown: row.get(4)?,
+            slug: row.get(5)?,
         };
         Ok(post)
     }
@@ -480,6 +1038,70 @@ impl Sqlite {
         })
     }
 
+    fn map_token_row(row: &Row<'_>) -> Result<IndieToken, Error> {
+        let revoked: Option<i64> = row.get(5)?;
+        Ok(IndieToken {
+            jti: row.get(0)?,
+            client_id: row.get(1)?,
+            scope: row.get(2)?,
+            issued: datetime_from_row!(row, 3),
+            expires: datetime_from_row!(row, 4),
+            revoked: revoked.and_then(|r| DateTime::from_timestamp(r, 0)),
+        })
+    }
+
+    fn map_webmention_row(row: &Row<'_>) -> Result<Webmention, Error> {
+        let status: String = row.get(4)?;
+        let kind: String = row.get(5)?;
+        let published: Option<i64> = row.get(10)?;
+        let verified: Option<i64> = row.get(12)?;
+        Ok(Webmention {
+            id: row.get(0)?,
+            source: row.get(1)?,
+            target: row.get(2)?,
+            post_id: row.get(3)?,
+            status: WebmentionStatus::parse(&status).unwrap_or_default(),
+            kind: WebmentionKind::parse(&kind).unwrap_or_default(),
+            author_name: row.get(6)?,
+            author_url: row.get(7)?,
+            author_photo: row.get(8)?,
+            content: row.get(9)?,
+            published: published.and_then(|p| DateTime::from_timestamp(p, 0)),
+            received: datetime_from_row!(row, 11),
+            verified: verified.and_then(|v| DateTime::from_timestamp(v, 0)),
+            error: row.get(13)?,
+        })
+    }
+
+    fn map_outgoing_webmention_row(row: &Row<'_>) -> Result<OutgoingWebmention, Error> {
+        let status: String = row.get(4)?;
+        let last_attempt: Option<i64> = row.get(8)?;
+        Ok(OutgoingWebmention {
+            id: row.get(0)?,
+            post_id: row.get(1)?,
+            source: row.get(2)?,
+            target: row.get(3)?,
+            status: DeliveryStatus::parse(&status).unwrap_or_default(),
+            endpoint: row.get(5)?,
+            attempts: row.get(6)?,
+            next_attempt: datetime_from_row!(row, 7),
+            last_attempt: last_attempt.and_then(|a| DateTime::from_timestamp(a, 0)),
+            error: row.get(9)?,
+        })
+    }
+
+    fn map_websub_subscription_row(row: &Row<'_>) -> Result<WebSubSubscription, Error> {
+        Ok(WebSubSubscription {
+            id: row.get(0)?,
+            topic: row.get(1)?,
+            callback: row.get(2)?,
+            secret: row.get(3)?,
+            created: datetime_from_row!(row, 4),
+            expires: datetime_from_row!(row, 5),
+            delivered: row.get(6)?,
+        })
+    }
+
     fn enable_foreign_keys(&self) -> Result<(), Error> {
         self.pragma_update("foreign_keys", "ON")
     }
@@ -506,13 +1128,14 @@ impl Sqlite {
         }
     }
 
-    fn upsert_post(tx: &Transaction, p: &Post) -> Result<usize, Error> {
-        let now = Utc::now();
+    fn upsert_post(tx: &Transaction, p: &Post, modified: DateTime<Utc>) -> Result<usize, Error> {
+        let slug = Sqlite::post_slug(tx, p)?;
+        Sqlite::keep_previous_slug(tx, p.id, &slug)?;
         let result = tx.prepare_cached(
-            "INSERT INTO post (id, title, short_text, text, created, modified, is_public, markdown) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
-                ON CONFLICT(id) DO UPDATE SET title=?2, short_text=?3, text=?4, created=?5, modified=?6, is_public=?7, markdown=?8",
+            "INSERT INTO post (id, title, short_text, text, created, modified, markdown, slug, status) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                ON CONFLICT(id) DO UPDATE SET title=?2, short_text=?3, text=?4, created=?5, modified=?6, markdown=?7, slug=?8, status=?9",
         )?
-        .execute(params![p.id, p.title, p.short_text, p.text, p.created.timestamp(), now.timestamp(), p.is_public, p.markdown])?;
+        .execute(params![p.id, p.title, p.short_text, p.text, p.created.timestamp(), modified.timestamp(), p.markdown, slug, p.effective_status().as_str()])?;
 
         let mut tag_statement = tx.prepare_cached(
             "INSERT INTO tag (tag) VALUES (?1)
@@ -534,13 +1157,188 @@ impl Sqlite {
         }
 
         let mut cleanup_tags_statement = tx.prepare_cached(
-            "DELETE FROM tag WHERE tag NOT IN (SELECT DISTINCT tag FROM post_tag)",
+            "DELETE FROM tag WHERE title IS NULL AND description IS NULL \
+             AND tag NOT IN (SELECT DISTINCT tag FROM post_tag)",
         )?;
         cleanup_tags_statement.execute([])?;
 
+        Sqlite::index_post(tx, p)?;
+
+        tx.prepare_cached("DELETE FROM deleted_post WHERE id = ?1")?
+            .execute(params![p.id])?;
+
         Ok(result)
     }
 
+    /// Saves post content as a new revision unless it equals the latest one
+    /// and removes revisions beyond the limit. Zero limit disables revisions.
+    fn insert_revision(tx: &Transaction, p: &Post, limit: usize) -> Result<(), Error> {
+        if limit == 0 {
+            return Ok(());
+        }
+        let tags = serde_json::to_string(&p.tags).unwrap_or_else(|_| String::from("[]"));
+
+        let latest: Option<(String, String, String, bool, String)> = tx
+            .prepare_cached(
+                "SELECT title, short_text, text, markdown, tags FROM post_revision \
+                 WHERE post_id = ?1 ORDER BY id DESC LIMIT 1",
+            )?
+            .query_row([p.id], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .optional()?;
+        let current = (
+            p.title.clone(),
+            p.short_text.clone(),
+            p.text.clone(),
+            p.markdown,
+            tags,
+        );
+        if latest.as_ref() == Some(&current) {
+            return Ok(());
+        }
+
+        tx.prepare_cached(
+            "INSERT INTO post_revision (post_id, created, title, short_text, text, markdown, tags) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?
+        .execute(params![
+            p.id,
+            Utc::now().timestamp(),
+            current.0,
+            current.1,
+            current.2,
+            current.3,
+            current.4
+        ])?;
+
+        tx.prepare_cached(
+            "DELETE FROM post_revision WHERE post_id = ?1 AND id NOT IN \
+             (SELECT id FROM post_revision WHERE post_id = ?1 ORDER BY id DESC LIMIT ?2)",
+        )?
+        .execute(params![p.id, i64::try_from(limit).unwrap_or(i64::MAX)])?;
+        Ok(())
+    }
+
+    /// Slug to store: requested one, otherwise the current one, otherwise generated
+    /// from the title. Made unique among other posts by numeric suffix.
+    /// Remembers the current slug of the post if it's going to change.
+    fn keep_previous_slug(tx: &Transaction, id: i64, slug: &str) -> Result<(), Error> {
+        let current: Option<String> = tx
+            .prepare_cached("SELECT slug FROM post WHERE id = ?1")?
+            .query_row([id], |row| row.get(0))
+            .optional()?
+            .flatten();
+        tx.prepare_cached("DELETE FROM post_previous_slug WHERE slug = ?1")?
+            .execute([slug])?;
+        if let Some(current) = current.filter(|c| c != slug) {
+            tx.prepare_cached(
+                "INSERT INTO post_previous_slug (slug, post_id) VALUES (?1, ?2) \
+                 ON CONFLICT(slug) DO UPDATE SET post_id = ?2",
+            )?
+            .execute(params![current, id])?;
+        }
+        Ok(())
+    }
+
+    fn post_slug(tx: &Transaction, p: &Post) -> Result<String, Error> {
+        let requested = p
+            .slug
+            .as_deref()
+            .map(slug::slugify)
+            .filter(|s| !s.is_empty());
+        let base = match requested {
+            Some(s) => s,
+            None => {
+                let current: Option<String> = tx
+                    .prepare_cached("SELECT slug FROM post WHERE id = ?1")?
+                    .query_row([p.id], |row| row.get(0))
+                    .optional()?
+                    .flatten();
+                if let Some(current) = current {
+                    return Ok(current);
+                }
+                let generated = slug::slugify(&p.title);
+                if generated.is_empty() {
+                    format!("post-{}", p.id)
+                } else {
+                    generated
+                }
+            }
+        };
+
+        let mut exists =
+            tx.prepare_cached("SELECT COUNT(1) FROM post WHERE slug = ?1 AND id <> ?2")?;
+        slug::unique(&base, |s| {
+            exists.query_row(params![s, p.id], |row| row.get::<_, i32>(0).map(|c| c > 0))
+        })
+    }
+
+    /// Moves posts of every tag to `into` and removes the tag. Deleted posts
+    /// get the tag replaced too so that undelete does not bring it back.
+    fn merge_tags(tx: &Transaction, tags: &[String], into: &str) -> Result<usize, Error> {
+        tx.execute(
+            "INSERT INTO tag (tag) VALUES (?1) ON CONFLICT(tag) DO NOTHING",
+            [into],
+        )?;
+
+        let mut changed = HashSet::new();
+        for tag in tags.iter().filter(|t| *t != into) {
+            let ids = tx
+                .prepare_cached("SELECT post_id FROM post_tag WHERE tag = ?1")?
+                .query_map([tag], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            changed.extend(ids);
+
+            tx.execute(
+                "UPDATE tag SET title = COALESCE(title, (SELECT title FROM tag WHERE tag = ?2)),
+                    description = COALESCE(description, (SELECT description FROM tag WHERE tag = ?2))
+                 WHERE tag = ?1",
+                params![into, tag],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO post_tag (post_id, tag) SELECT post_id, ?1 FROM post_tag WHERE tag = ?2",
+                params![into, tag],
+            )?;
+            tx.execute("DELETE FROM post_tag WHERE tag = ?1", [tag])?;
+            tx.execute("DELETE FROM tag WHERE tag = ?1", [tag])?;
+            tx.execute(
+                "UPDATE deleted_post SET tags = (SELECT json_group_array(DISTINCT IIF(value = ?2, ?1, value)) FROM json_each(deleted_post.tags))
+                 WHERE EXISTS (SELECT 1 FROM json_each(deleted_post.tags) WHERE value = ?2)",
+                params![into, tag],
+            )?;
+        }
+        let modified = Utc::now().timestamp();
+        for id in &changed {
+            tx.prepare_cached("UPDATE post SET modified = ?2 WHERE id = ?1")?
+                .execute(params![id, modified])?;
+        }
+        Ok(changed.len())
+    }
+
+    fn index_post(tx: &Transaction, p: &Post) -> Result<(), Error> {
+        tx.prepare_cached("DELETE FROM post_search WHERE rowid = ?1")?
+            .execute(params![p.id])?;
+
+        let body = search::plain_text(p);
+        tx.prepare_cached(
+            "INSERT INTO post_search (rowid, title_terms, body_terms, body) VALUES (?1, ?2, ?3, ?4)",
+        )?
+        .execute(params![
+            p.id,
+            search::normalize(&p.title),
+            search::normalize(&body),
+            body
+        ])?;
+        Ok(())
+    }
+
     fn upsert_user(tx: &Transaction, u: &User) -> Result<usize, Error> {
         let result = tx.prepare_cached(
             "INSERT INTO user (created, email, name, login, avatar_url, federated_id, admin, verified, provider) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
@@ -561,6 +1359,16 @@ impl Sqlite {
         Ok(result)
     }
 
+    fn upsert_folder(tx: &Transaction, f: &Folder) -> Result<usize, Error> {
+        let result = tx
+            .prepare_cached(
+                "INSERT INTO folder (bucket, title) VALUES (?1, ?2) ON CONFLICT(bucket) DO UPDATE SET title=?2",
+            )?
+            .execute(params![f.bucket, f.title])?;
+
+        Ok(result)
+    }
+
     fn upsert<T>(
         &mut self,
         items: &[T],
@@ -579,3 +1387,822 @@ impl Sqlite {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::migration::{latest_version, schema_version};
+    use crate::testing::temp_database;
+    use rstest::{fixture, rstest};
+
+    #[test]
+    fn open_read_write_migrates_new_database() {
+        // arrange
+        let path = temp_database("open_read_write_migrates_new_database");
+
+        // act
+        let storage = Sqlite::open(&path, Mode::ReadWrite).unwrap();
+
+        // assert
+        assert_eq!(latest_version(), schema_version(&storage.conn).unwrap());
+        assert_eq!(0, storage.count_posts(PostsRequest::default()).unwrap());
+        drop(storage);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn open_read_only_newer_schema_fails() {
+        // arrange
+        let path = temp_database("open_read_only_newer_schema_fails");
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        drop(conn);
+
+        // act
+        let result = Sqlite::open(&path, Mode::ReadOnly);
+
+        // assert
+        assert!(result.is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn open_read_only_current_schema_succeeds() {
+        // arrange
+        let path = temp_database("open_read_only_current_schema_succeeds");
+        drop(Sqlite::open(&path, Mode::ReadWrite).unwrap());
+
+        // act
+        let result = Sqlite::open(&path, Mode::ReadOnly);
+
+        // assert
+        assert!(result.is_ok());
+        drop(result);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[rstest]
+    #[case("программы", false, vec![1])]
+    #[case("ПРОГРАММИРОВАНИЕ rust", false, vec![1])]
+    #[case("черновик", false, vec![])]
+    #[case("черновики", true, vec![2])]
+    #[case("программа", true, vec![1, 2])]
+    #[case("", true, vec![])]
+    fn search_posts_tests(
+        mut storage: Sqlite,
+        #[case] query: &str,
+        #[case] include_private: bool,
+        #[case] expected: Vec<i64>,
+    ) {
+        // arrange
+        storage
+            .upsert_post(search_post(
+                1,
+                "Программирование на Rust",
+                "Пишем программу",
+                true,
+            ))
+            .unwrap();
+        storage
+            .upsert_post(search_post(2, "Черновик программы", "Текст", false))
+            .unwrap();
+
+        // act
+        let actual = storage.search_posts(query, 10, 0, include_private).unwrap();
+        let count = storage
+            .count_search_results(query, include_private)
+            .unwrap();
+
+        // assert
+        let ids: Vec<i64> = actual.iter().map(|p| p.id).sorted().collect();
+        assert_eq!(expected, ids);
+        assert_eq!(expected.len(), usize::try_from(count).unwrap());
+    }
+
+    #[rstest]
+    fn search_posts_highlights_snippet(mut storage: Sqlite) {
+        // arrange
+        storage
+            .upsert_post(search_post(1, "Заметка", "Пишем программу на Rust", true))
+            .unwrap();
+
+        // act
+        let actual = storage.search_posts("программы", 10, 0, false).unwrap();
+
+        // assert
+        assert_eq!(1, actual.len());
+        assert_eq!("Пишем <b>программу</b> на Rust", actual[0].short_text);
+    }
+
+    #[rstest]
+    fn search_posts_ranks_title_matches_first(mut storage: Sqlite) {
+        // arrange
+        storage
+            .upsert_post(search_post(1, "Заметка", "Немного про базы данных", true))
+            .unwrap();
+        storage
+            .upsert_post(search_post(2, "Базы данных", "Заметка", true))
+            .unwrap();
+
+        // act
+        let actual = storage.search_posts("база", 10, 0, false).unwrap();
+
+        // assert
+        let ids: Vec<i64> = actual.iter().map(|p| p.id).collect();
+        assert_eq!(vec![2, 1], ids);
+    }
+
+    #[rstest]
+    fn search_posts_index_follows_updates_and_deletes(mut storage: Sqlite) {
+        // arrange
+        storage
+            .upsert_post(search_post(1, "Старый заголовок", "Текст", true))
+            .unwrap();
+        storage
+            .upsert_post(search_post(1, "Новый заголовок", "Текст", true))
+            .unwrap();
+
+        // act
+        let old = storage.count_search_results("старый", false).unwrap();
+        let new = storage.count_search_results("новый", false).unwrap();
+        storage.delete_post(1).unwrap();
+        let deleted = storage.count_search_results("новый", false).unwrap();
+
+        // assert
+        assert_eq!(0, old);
+        assert_eq!(1, new);
+        assert_eq!(0, deleted);
+    }
+
+    #[rstest]
+    fn delete_post_keeps_copy_for_undelete(mut storage: Sqlite) {
+        // arrange
+        let mut post = search_post(1, "Заметка", "Текст", true);
+        post.tags = vec!["rust".to_owned(), "sqlite".to_owned()];
+        storage.upsert_post(post).unwrap();
+
+        // act
+        let deleted = storage.delete_post(1).unwrap();
+        let restored = storage.get_deleted_post(1).unwrap();
+        storage.upsert_post(restored).unwrap();
+
+        // assert
+        assert_eq!(1, deleted);
+        let post = storage.get_post(1).unwrap();
+        assert_eq!("Заметка", post.title);
+        assert_eq!(
+            vec!["rust", "sqlite"],
+            post.tags.into_iter().sorted().collect::<Vec<_>>()
+        );
+        assert!(post.is_public);
+        assert!(storage.get_deleted_post(1).is_err());
+    }
+
+    #[rstest]
+    fn get_deleted_post_not_deleted_fails(mut storage: Sqlite) {
+        // arrange
+        storage
+            .upsert_post(search_post(1, "Заметка", "Текст", true))
+            .unwrap();
+
+        // act
+        let result = storage.get_deleted_post(1);
+
+        // assert
+        assert!(result.is_err());
+    }
+
+    #[rstest]
+    fn upsert_post_generates_unique_slugs(mut storage: Sqlite) {
+        // arrange
+        storage
+            .upsert_post(search_post(1, "Привет, мир", "Текст", true))
+            .unwrap();
+
+        // act
+        storage
+            .upsert_post(search_post(2, "Привет мир!", "Текст", true))
+            .unwrap();
+
+        // assert
+        assert_eq!(
+            Some("privet-mir".to_owned()),
+            storage.get_post(1).unwrap().slug
+        );
+        assert_eq!(
+            Some("privet-mir-2".to_owned()),
+            storage.get_post(2).unwrap().slug
+        );
+        assert_eq!(2, storage.get_post_id("privet-mir-2").unwrap());
+    }
+
+    #[rstest]
+    fn upsert_post_keeps_slug_unless_requested(mut storage: Sqlite) {
+        // arrange
+        storage
+            .upsert_post(search_post(1, "Первый заголовок", "Текст", true))
+            .unwrap();
+        let mut post = storage.get_post(1).unwrap();
+        post.title = "Новый заголовок".to_owned();
+        post.slug = None;
+
+        // act
+        storage.upsert_post(post.clone()).unwrap();
+        let kept = storage.get_post(1).unwrap().slug;
+        post.slug = Some("My Slug".to_owned());
+        storage.upsert_post(post).unwrap();
+
+        // assert
+        assert_eq!(Some("pervyy-zagolovok".to_owned()), kept);
+        assert_eq!(
+            Some("my-slug".to_owned()),
+            storage.get_post(1).unwrap().slug
+        );
+        assert!(storage.get_post_id("pervyy-zagolovok").is_err());
+    }
+
+    #[rstest]
+    fn upsert_post_stores_status_consistent_with_public_flag(mut storage: Sqlite) {
+        // arrange
+        let mut published = search_post(1, "Опубликовано", "Текст", true);
+        published.status = PostStatus::Draft;
+        let mut unpublished = search_post(2, "Снято", "Текст", false);
+        unpublished.status = PostStatus::Published;
+
+        // act
+        storage.upsert_post(published).unwrap();
+        storage.upsert_post(unpublished).unwrap();
+
+        // assert
+        assert_eq!(PostStatus::Published, storage.get_post(1).unwrap().status);
+        assert_eq!(PostStatus::Draft, storage.get_post(2).unwrap().status);
+    }
+
+    #[rstest]
+    fn get_posts_by_status_returns_only_unpublished(mut storage: Sqlite) {
+        // arrange
+        storage
+            .upsert_post(search_post(1, "Опубликовано", "Текст", true))
+            .unwrap();
+        let mut draft = search_post(2, "Черновик", "Текст", false);
+        draft.tags = vec!["rust".to_owned()];
+        storage.upsert_post(draft).unwrap();
+        let mut scheduled = search_post(3, "Запланировано", "Текст", false);
+        scheduled.status = PostStatus::Scheduled;
+        storage.upsert_post(scheduled).unwrap();
+
+        // act
+        let drafts = storage.get_posts_by_status(PostStatus::Draft).unwrap();
+        let scheduled = storage.get_posts_by_status(PostStatus::Scheduled).unwrap();
+        let published = storage.get_posts_by_status(PostStatus::Published).unwrap();
+
+        // assert
+        assert_eq!(vec![2], drafts.iter().map(|p| p.id).collect::<Vec<_>>());
+        assert_eq!(vec!["rust".to_owned()], drafts[0].tags);
+        assert_eq!(vec![3], scheduled.iter().map(|p| p.id).collect::<Vec<_>>());
+        assert!(published.is_empty());
+    }
+
+    #[rstest]
+    fn publish_scheduled_posts_publishes_only_due(mut storage: Sqlite) {
+        // arrange
+        let now = Utc::now();
+        let mut due = search_post(1, "Пора", "Текст", false);
+        due.status = PostStatus::Scheduled;
+        due.created = now - chrono::Duration::minutes(1);
+        storage.upsert_post(due).unwrap();
+        let mut future = search_post(2, "Позже", "Текст", false);
+        future.status = PostStatus::Scheduled;
+        future.created = now + chrono::Duration::days(1);
+        storage.upsert_post(future).unwrap();
+
+        // act
+        let published = storage.publish_scheduled_posts(now).unwrap();
+
+        // assert
+        assert_eq!(vec![1], published);
+        let post = storage.get_post(1).unwrap();
+        assert!(post.is_public);
+        assert_eq!(PostStatus::Published, post.status);
+        assert!(!storage.get_post(2).unwrap().is_public);
+        assert_eq!(
+            1,
+            storage
+                .get_small_posts(10, 0, PostsRequest::default())
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[rstest]
+    fn upsert_post_saves_revisions_skipping_unchanged(mut storage: Sqlite) {
+        // arrange
+        let mut post = search_post(1, "Заголовок", "Первый текст", true);
+        storage.upsert_post(post.clone()).unwrap();
+        storage.upsert_post(post.clone()).unwrap();
+        post.text = "Второй текст".to_owned();
+        post.tags = vec!["rust".to_owned()];
+
+        // act
+        storage.upsert_post(post).unwrap();
+
+        // assert
+        let revisions = storage.get_post_revisions(1).unwrap();
+        assert_eq!(2, revisions.len());
+        assert_eq!("Второй текст", revisions[0].text);
+        assert_eq!(vec!["rust".to_owned()], revisions[0].tags);
+        assert_eq!("Первый текст", revisions[1].text);
+        let first = storage.get_post_revision(1, revisions[1].id).unwrap();
+        assert!(first.tags.is_empty());
+        assert!(storage.get_post_revision(2, revisions[1].id).is_err());
+    }
+
+    #[rstest]
+    #[case(0, 0)]
+    #[case(2, 2)]
+    #[case(10, 5)]
+    fn upsert_post_keeps_revisions_limit(
+        storage: Sqlite,
+        #[case] limit: usize,
+        #[case] expected: usize,
+    ) {
+        // arrange
+        let mut storage = storage.with_revisions_limit(limit);
+
+        // act
+        for i in 0..5 {
+            storage
+                .upsert_post(search_post(1, "Заголовок", &format!("Текст {i}"), true))
+                .unwrap();
+        }
+
+        // assert
+        let revisions = storage.get_post_revisions(1).unwrap();
+        assert_eq!(expected, revisions.len());
+        if let Some(latest) = revisions.first() {
+            assert_eq!("Текст 4", latest.text);
+        }
+    }
+
+    #[rstest]
+    fn get_post_id_finds_deleted_post(mut storage: Sqlite) {
+        // arrange
+        storage
+            .upsert_post(search_post(1, "Заметка", "Текст", true))
+            .unwrap();
+        storage.delete_post(1).unwrap();
+
+        // act
+        let actual = storage.get_post_id("zametka").unwrap();
+
+        // assert
+        assert_eq!(1, actual);
+        assert_eq!(
+            Some("zametka".to_owned()),
+            storage.get_deleted_post(1).unwrap().slug
+        );
+    }
+
+    #[rstest]
+    fn get_post_id_finds_previous_slug(mut storage: Sqlite) {
+        // arrange
+        let mut post = search_post(1, "Заметка", "Текст", true);
+        storage.upsert_post(post.clone()).unwrap();
+        post.slug = Some("novaya".to_owned());
+        storage.upsert_post(post.clone()).unwrap();
+        post.slug = Some("zametka".to_owned());
+        storage.upsert_post(post).unwrap();
+
+        // act
+        let previous = storage.get_post_id("novaya");
+        let current = storage.get_post_id("zametka");
+
+        // assert
+        assert_eq!(1, previous.unwrap());
+        assert_eq!(1, current.unwrap());
+        assert_eq!(
+            Some("zametka".to_owned()),
+            storage.get_post(1).unwrap().slug
+        );
+    }
+
+    #[rstest]
+    fn revoke_token_excludes_from_active(mut storage: Sqlite) {
+        // arrange
+        storage
+            .insert_token(&indie_token("1", "https://a/", 1))
+            .unwrap();
+        storage
+            .insert_token(&indie_token("2", "https://a/", 1))
+            .unwrap();
+        storage
+            .insert_token(&indie_token("3", "https://b/", 1))
+            .unwrap();
+
+        // act
+        let revoked = storage.revoke_token("2").unwrap();
+
+        // assert
+        assert_eq!(1, revoked);
+        assert_eq!(0, storage.revoke_token("2").unwrap());
+        assert!(storage.get_token("2").unwrap().revoked.is_some());
+        let active: Vec<String> = storage
+            .get_active_tokens(Some("https://a/"))
+            .unwrap()
+            .into_iter()
+            .map(|t| t.jti)
+            .collect();
+        assert_eq!(vec!["1".to_owned()], active);
+        assert_eq!(2, storage.get_active_tokens(None).unwrap().len());
+    }
+
+    #[rstest]
+    fn get_active_tokens_skips_expired(mut storage: Sqlite) {
+        // arrange
+        storage
+            .insert_token(&indie_token("1", "https://a/", -1))
+            .unwrap();
+
+        // act
+        let active = storage.get_active_tokens(None).unwrap();
+
+        // assert
+        assert!(active.is_empty());
+        assert_eq!("https://a/", storage.get_token("1").unwrap().client_id);
+    }
+
+    #[rstest]
+    fn get_token_unknown_fails(storage: Sqlite) {
+        // arrange
+
+        // act
+        let result = storage.get_token("unknown");
+
+        // assert
+        assert!(result.is_err());
+    }
+
+    fn indie_token(jti: &str, client_id: &str, expires_in_days: i64) -> IndieToken {
+        let issued = Utc::now();
+        IndieToken {
+            jti: jti.to_owned(),
+            client_id: client_id.to_owned(),
+            scope: "create".to_owned(),
+            issued,
+            expires: issued + chrono::TimeDelta::days(expires_in_days),
+            revoked: None,
+        }
+    }
+
+    #[rstest]
+    fn merge_tags_moves_posts_and_settings(storage: Sqlite) {
+        // arrange
+        let mut storage = storage.preserve_modified();
+        let mut first = search_post(1, "Первый", "Текст", true);
+        first.tags = vec!["rust".to_owned(), "раст".to_owned()];
+        storage.upsert_post(first).unwrap();
+        let mut second = search_post(2, "Второй", "Текст", true);
+        second.tags = vec!["раст".to_owned()];
+        second.modified = DateTime::from_timestamp(1_600_000_000, 0).unwrap();
+        storage.upsert_post(second).unwrap();
+        storage
+            .update_tag(&TagInfo {
+                tag: "раст".to_owned(),
+                title: Some("Rust".to_owned()),
+                description: Some("Заметки о Rust".to_owned()),
+                posts: 0,
+            })
+            .unwrap();
+
+        // act
+        let changed = storage.merge_tags(&["раст".to_owned()], "rust").unwrap();
+
+        // assert
+        assert_eq!(2, changed);
+        let tags = storage.get_tags().unwrap();
+        assert_eq!(1, tags.len());
+        assert_eq!("rust", tags[0].tag);
+        assert_eq!(2, tags[0].posts);
+        assert_eq!("Rust", tags[0].display_title());
+        assert_eq!(Some("Заметки о Rust"), tags[0].description.as_deref());
+        let second = storage.get_post(2).unwrap();
+        assert_eq!(vec!["rust"], second.tags);
+        assert!(second.modified > DateTime::from_timestamp(1_600_000_000, 0).unwrap());
+    }
+
+    #[rstest]
+    fn merge_tags_renames_deleted_posts_tags(mut storage: Sqlite) {
+        // arrange
+        let mut post = search_post(1, "Первый", "Текст", true);
+        post.tags = vec!["опечатк".to_owned()];
+        storage.upsert_post(post).unwrap();
+        storage.delete_post(1).unwrap();
+
+        // act
+        storage
+            .merge_tags(&["опечатк".to_owned()], "опечатка")
+            .unwrap();
+
+        // assert
+        assert_eq!(vec!["опечатка"], storage.get_deleted_post(1).unwrap().tags);
+    }
+
+    #[rstest]
+    fn delete_orphan_tags_keeps_used(mut storage: Sqlite) {
+        // arrange
+        let mut post = search_post(1, "Первый", "Текст", true);
+        post.tags = vec!["used".to_owned(), "described".to_owned()];
+        storage.upsert_post(post.clone()).unwrap();
+        storage
+            .update_tag(&TagInfo {
+                tag: "described".to_owned(),
+                description: Some("Описание".to_owned()),
+                ..Default::default()
+            })
+            .unwrap();
+        post.tags = vec!["used".to_owned()];
+        storage.upsert_post(post).unwrap();
+
+        // act
+        let deleted = storage.delete_orphan_tags().unwrap();
+
+        // assert
+        assert_eq!(vec!["described"], deleted);
+        let tags: Vec<String> = storage
+            .get_tags()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.tag)
+            .collect();
+        assert_eq!(vec!["used"], tags);
+    }
+
+    #[rstest]
+    #[case("used", 1)]
+    #[case("unknown", 0)]
+    fn update_tag_tests(mut storage: Sqlite, #[case] tag: &str, #[case] expected: usize) {
+        // arrange
+        let mut post = search_post(1, "Первый", "Текст", true);
+        post.tags = vec!["used".to_owned()];
+        storage.upsert_post(post).unwrap();
+
+        // act
+        let actual = storage
+            .update_tag(&TagInfo {
+                tag: tag.to_owned(),
+                title: Some(String::new()),
+                description: Some("Описание".to_owned()),
+                posts: 0,
+            })
+            .unwrap();
+
+        // assert
+        assert_eq!(expected, actual);
+        let used = storage.get_tag("used").unwrap();
+        assert_eq!(None, used.title);
+        assert_eq!("used", used.display_title());
+    }
+
+    #[rstest]
+    #[case(1, None, Some(3))]
+    #[case(3, Some(1), Some(4))]
+    #[case(2, Some(1), Some(3))]
+    #[case(4, Some(3), None)]
+    fn adjacent_posts_skip_private(
+        mut storage: Sqlite,
+        #[case] id: i64,
+        #[case] previous: Option<i64>,
+        #[case] next: Option<i64>,
+    ) {
+        // arrange
+        for (id, is_public) in [(1, true), (2, false), (3, true), (4, true)] {
+            let mut post = search_post(id, "Пост", "Текст", is_public);
+            post.created = DateTime::from_timestamp(id * 1000, 0).unwrap();
+            storage.upsert_post(post).unwrap();
+        }
+
+        // act
+        let actual_previous = storage.get_previous_post(id).unwrap();
+        let actual_next = storage.get_next_post(id).unwrap();
+
+        // assert
+        assert_eq!(previous, actual_previous.map(|p| p.id));
+        assert_eq!(next, actual_next.map(|p| p.id));
+    }
+
+    #[rstest]
+    fn adjacent_posts_of_unknown_post(storage: Sqlite) {
+        // act
+        let actual = storage.get_previous_post(1);
+
+        // assert
+        assert!(actual.is_err());
+    }
+
+    #[rstest]
+    fn related_posts_ranked_by_tags_then_text(mut storage: Sqlite) {
+        // arrange
+        let posts = [
+            (1, "Парсинг логов", vec!["rust", "logs"], true),
+            (2, "Парсинг логов быстрее", vec![], true),
+            (3, "Сборка проекта", vec!["rust", "logs"], true),
+            (4, "Тесты проекта", vec!["rust"], true),
+            (5, "Черновик про логи", vec!["rust", "logs"], false),
+            (6, "Рецепт пирога", vec!["food"], true),
+        ];
+        for (id, title, tags, is_public) in posts {
+            let mut post = search_post(id, title, title, is_public);
+            post.tags = tags.into_iter().map(str::to_owned).collect();
+            storage.upsert_post(post).unwrap();
+        }
+
+        // act
+        let actual = storage.get_related_posts(1, 5).unwrap();
+
+        // assert
+        let ids: Vec<i64> = actual.iter().map(|p| p.id).collect();
+        assert_eq!(vec![3, 4, 2], ids);
+    }
+
+    #[rstest]
+    #[case(WebmentionStatus::Approved, WebmentionStatus::Pending)]
+    #[case(WebmentionStatus::Invalid, WebmentionStatus::Pending)]
+    #[case(WebmentionStatus::Rejected, WebmentionStatus::Rejected)]
+    fn queue_webmention_again(
+        mut storage: Sqlite,
+        #[case] status: WebmentionStatus,
+        #[case] expected: WebmentionStatus,
+    ) {
+        // arrange
+        let mention = webmention("https://a.example/1", 1);
+        let id = storage.queue_webmention(&mention).unwrap();
+        let mut queued = storage.get_webmention(id).unwrap();
+        queued.status = status;
+        storage.update_webmention(&queued).unwrap();
+
+        // act
+        let actual = storage.queue_webmention(&mention).unwrap();
+
+        // assert
+        assert_eq!(id, actual);
+        assert_eq!(expected, storage.get_webmention(id).unwrap().status);
+    }
+
+    #[rstest]
+    fn webmentions_by_status(mut storage: Sqlite) {
+        // arrange
+        for (source, status) in [
+            ("https://a.example/1", WebmentionStatus::Approved),
+            ("https://a.example/2", WebmentionStatus::Verified),
+            ("https://a.example/3", WebmentionStatus::Approved),
+            ("https://a.example/4", WebmentionStatus::Pending),
+        ] {
+            let id = storage.queue_webmention(&webmention(source, 1)).unwrap();
+            let mut mention = storage.get_webmention(id).unwrap();
+            mention.status = status;
+            mention.kind = WebmentionKind::Like;
+            mention.author_name = Some("Alice".to_owned());
+            storage.update_webmention(&mention).unwrap();
+        }
+
+        // act
+        let approved = storage.get_post_webmentions(1).unwrap();
+        let pending = storage.get_pending_webmentions(10).unwrap();
+        let all = storage.get_webmentions(None).unwrap();
+
+        // assert
+        let sources: Vec<&str> = approved.iter().map(|m| m.source.as_str()).collect();
+        assert_eq!(vec!["https://a.example/1", "https://a.example/3"], sources);
+        assert_eq!(WebmentionKind::Like, approved[0].kind);
+        assert_eq!(Some("Alice".to_owned()), approved[0].author_name);
+        assert_eq!(1, pending.len());
+        assert_eq!(4, all.len());
+        assert!(storage.get_post_webmentions(2).unwrap().is_empty());
+        assert_eq!(1, storage.delete_webmention(approved[0].id).unwrap());
+        assert_eq!(3, storage.get_webmentions(None).unwrap().len());
+    }
+
+    #[rstest]
+    fn outgoing_webmentions_due_and_requeue(mut storage: Sqlite) {
+        // arrange
+        let now: DateTime<Utc> = "2024-03-01T10:00:00Z".parse().unwrap();
+        let source = "https://www.egoroff.spb.ru/blog/1.html";
+        let targets = vec![
+            "https://a.example/1".to_owned(),
+            "https://b.example/".to_owned(),
+        ];
+        storage
+            .queue_outgoing_webmentions(1, source, &targets, now)
+            .unwrap();
+        let mut failed = storage
+            .get_due_outgoing_webmentions(now, 10)
+            .unwrap()
+            .remove(0);
+        failed.attempts = 1;
+        failed.next_attempt = now + chrono::TimeDelta::minutes(5);
+        failed.last_attempt = Some(now);
+        failed.error = Some("timeout".to_owned());
+        storage.update_outgoing_webmention(&failed).unwrap();
+
+        // act
+        let due = storage.get_due_outgoing_webmentions(now, 10).unwrap();
+        let later = storage
+            .get_due_outgoing_webmentions(now + chrono::TimeDelta::minutes(5), 10)
+            .unwrap();
+        storage
+            .queue_outgoing_webmentions(1, source, &targets[..1], now)
+            .unwrap();
+        let requeued = storage.get_outgoing_webmentions(1).unwrap();
+
+        // assert
+        assert_eq!(1, due.len());
+        assert_eq!("https://b.example/", due[0].target);
+        assert_eq!(2, later.len());
+        assert_eq!("https://a.example/1", later[1].target);
+        assert_eq!(Some("timeout".to_owned()), later[1].error);
+        assert_eq!(2, requeued.len());
+        assert_eq!(0, requeued[0].attempts);
+        assert_eq!(now, requeued[0].next_attempt);
+        assert!(requeued[0].error.is_none());
+        assert_eq!(DeliveryStatus::Pending, requeued[0].status);
+        assert!(storage.get_outgoing_webmentions(2).unwrap().is_empty());
+    }
+
+    #[rstest]
+    fn websub_subscriptions_renew_expire_and_delete(mut storage: Sqlite) {
+        // arrange
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let topic = "https://www.egoroff.spb.ru/blog/recent.atom";
+        let subscription = WebSubSubscription {
+            topic: topic.to_owned(),
+            callback: "https://reader.example/cb".to_owned(),
+            created: now,
+            expires: now + chrono::TimeDelta::hours(1),
+            ..Default::default()
+        };
+        let id = storage.upsert_subscription(&subscription).unwrap();
+        storage.set_subscription_delivered(id, "digest").unwrap();
+
+        // act
+        let renewed = storage
+            .upsert_subscription(&WebSubSubscription {
+                secret: Some("s3cret".to_owned()),
+                expires: now + chrono::TimeDelta::days(1),
+                ..subscription.clone()
+            })
+            .unwrap();
+        let active = storage
+            .get_subscriptions(now + chrono::TimeDelta::hours(2))
+            .unwrap();
+        let expired = storage
+            .get_subscriptions(now + chrono::TimeDelta::days(2))
+            .unwrap();
+        let deleted = storage
+            .delete_subscription(topic, &subscription.callback)
+            .unwrap();
+
+        // assert
+        assert_eq!(id, renewed);
+        assert_eq!(1, active.len());
+        assert_eq!(Some("s3cret".to_owned()), active[0].secret);
+        assert_eq!(Some("digest".to_owned()), active[0].delivered);
+        assert!(expired.is_empty());
+        assert_eq!(1, deleted);
+        assert!(storage.get_subscriptions(now).unwrap().is_empty());
+    }
+
+    #[fixture]
+    fn storage() -> Sqlite {
+        let mut storage = Sqlite {
+            conn: Connection::open_in_memory().unwrap(),
+            revisions_limit: DEFAULT_REVISIONS_LIMIT,
+            preserve_modified: false,
+        };
+        storage.new_database().unwrap();
+        storage
+    }
+
+    fn search_post(id: i64, title: &str, text: &str, is_public: bool) -> Post {
+        Post {
+            id,
+            title: title.to_owned(),
+            text: text.to_owned(),
+            markdown: true,
+            is_public,
+            ..Default::default()
+        }
+    }
+
+    fn webmention(source: &str, post_id: i64) -> Webmention {
+        Webmention {
+            source: source.to_owned(),
+            target: format!("https://www.egoroff.spb.ru/blog/{post_id}.html"),
+            post_id,
+            received: Utc::now(),
+            ..Default::default()
+        }
+    }
+}
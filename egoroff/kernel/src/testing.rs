@@ -0,0 +1,25 @@
+//! Helpers shared by tests of the crate.
+
+#![allow(clippy::unwrap_used)]
+
+use std::{fs, path::PathBuf};
+
+/// Path to a database file in the temp directory that doesn't exist yet.
+/// Process ID in the name keeps parallel test runs apart.
+pub fn temp_database(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{name}_{}.db", std::process::id()));
+    if path.exists() {
+        fs::remove_file(&path).unwrap();
+    }
+    path
+}
+
+/// Empty directory in the temp directory. Leftovers of previous runs are removed.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+    if path.exists() {
+        fs::remove_dir_all(&path).unwrap();
+    }
+    fs::create_dir_all(&path).unwrap();
+    path
+}
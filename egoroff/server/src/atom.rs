@@ -3,22 +3,18 @@ use std::iter;
 use anyhow::Result;
 use anyhow::anyhow;
 use chrono::SecondsFormat;
-use kernel::{domain::SmallPost, xml::Builder};
+use kernel::xml::Builder;
+
+use crate::feed::{FEED_AUTHOR, Feed};
 
 const FEED_ELT: &str = "feed";
 const LINK_ELT: &str = "link";
 const ENTRY_ELT: &str = "entry";
 const AUTHOR_ELT: &str = "author";
 
-static LINK_ATTRS: std::sync::LazyLock<Vec<(&'static str, &'static str)>> =
-    std::sync::LazyLock::new(|| {
-        vec![
-            ("href", "https://www.egoroff.spb.ru/blog/recent.atom"),
-            ("rel", "self"),
-        ]
-    });
-
-pub fn from_small_posts(posts: Vec<SmallPost>) -> Result<String> {
+/// Renders Atom feed. Pages of the feed are linked as
+/// [RFC 5005](https://www.rfc-editor.org/rfc/rfc5005) paged feed.
+pub fn render(feed: &Feed) -> Result<String> {
     let mut builder = Builder::new();
 
     builder.write_attributed_start_tag(
@@ -26,55 +22,76 @@ pub fn from_small_posts(posts: Vec<SmallPost>) -> Result<String> {
         iter::once(("xmlns", "http://www.w3.org/2005/Atom")),
     )?;
 
+    builder.write_attributed_element("title", &feed.title, iter::once(("type", "text")))?;
     builder.write_attributed_element(
-        "title",
-        "egoroff.spb.ru feed",
+        "subtitle",
+        &feed.description,
         iter::once(("type", "text")),
     )?;
 
-    builder.write_element("id", "https://www.egoroff.spb.ru/blog/recent.atom")?;
+    let self_url = feed.page_url(feed.page);
+    builder.write_element("id", &self_url)?;
 
-    let first_post = posts.first().ok_or(anyhow!("No posts passed"))?;
-    let updated = first_post
-        .created
-        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    let updated = feed.updated().ok_or(anyhow!("No posts passed"))?;
+    let updated = updated.to_rfc3339_opts(SecondsFormat::Secs, true);
     builder.write_element("updated", &updated)?;
 
     builder.write_empty_attributed_element(
         LINK_ELT,
-        iter::once(("href", "https://www.egoroff.spb.ru/")),
+        iter::once(("href", feed.home_page_url.as_str())),
     )?;
 
-    builder.write_empty_attributed_element(LINK_ELT, LINK_ATTRS.iter().copied())?;
+    builder.write_empty_attributed_element(
+        LINK_ELT,
+        [("href", self_url.as_str()), ("rel", "self")].into_iter(),
+    )?;
+
+    if let Some(hub) = feed.hub.as_deref().filter(|_| feed.is_first_page()) {
+        builder.write_empty_attributed_element(
+            LINK_ELT,
+            [("href", hub), ("rel", "hub")].into_iter(),
+        )?;
+    }
+
+    for (rel, href) in feed.paging_links() {
+        builder.write_empty_attributed_element(
+            LINK_ELT,
+            [("href", href.as_str()), ("rel", rel)].into_iter(),
+        )?;
+    }
+
+    builder.write_start_tag(AUTHOR_ELT)?;
+    builder.write_element("name", FEED_AUTHOR)?;
+    builder.write_end_tag(AUTHOR_ELT)?;
 
-    for post in posts {
+    for item in &feed.items {
         builder.write_attributed_start_tag(
             ENTRY_ELT,
-            iter::once(("xml:base", "https://www.egoroff.spb.ru/blog/recent.atom")),
+            iter::once(("xml:base", feed.feed_url.as_str())),
         )?;
 
-        builder.write_attributed_element("title", &post.title, iter::once(("type", "text")))?;
+        builder.write_attributed_element("title", &item.title, iter::once(("type", "text")))?;
 
-        let uri = format!("https://www.egoroff.spb.ru/blog/{}.html", post.id);
-        builder.write_element("id", &uri)?;
-        builder.write_empty_attributed_element(LINK_ELT, iter::once(("href", uri.as_str())))?;
+        builder.write_element("id", &item.url)?;
+        builder
+            .write_empty_attributed_element(LINK_ELT, iter::once(("href", item.url.as_str())))?;
 
-        let updated = post.created.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let updated = item.updated.to_rfc3339_opts(SecondsFormat::Secs, true);
         builder.write_element("updated", &updated)?;
 
-        builder.write_element("published", &updated)?;
-
-        builder.write_attributed_element(
-            "content",
-            &post.short_text,
-            iter::once(("type", "html")),
-        )?;
+        let published = item.published.to_rfc3339_opts(SecondsFormat::Secs, true);
+        builder.write_element("published", &published)?;
 
-        builder.write_start_tag(AUTHOR_ELT)?;
+        for tag in &item.tags {
+            builder
+                .write_empty_attributed_element("category", iter::once(("term", tag.as_str())))?;
+        }
 
-        builder.write_element("name", "Alexander Egorov")?;
+        builder.write_attributed_element("summary", &item.summary, iter::once(("type", "html")))?;
 
-        builder.write_end_tag(AUTHOR_ELT)?;
+        if let Some(content) = &item.content {
+            builder.write_attributed_element("content", content, iter::once(("type", "html")))?;
+        }
 
         builder.write_end_tag(ENTRY_ELT)?;
     }
@@ -89,8 +106,10 @@ mod tests {
     #![allow(clippy::unwrap_in_result)]
     #![allow(clippy::unwrap_used)]
     use chrono::{NaiveDate, Utc};
+    use kernel::domain::{Post, SmallPost};
 
     use super::*;
+    use crate::feed::{FeedFormat, FeedItem};
 
     #[test]
     fn from_small_posts_tests() {
@@ -108,6 +127,7 @@ mod tests {
             title: "title 1".to_string(),
             short_text: "txt 1".to_string(),
             markdown: true,
+            slug: None,
         };
 
         let dt2 = NaiveDate::from_ymd_opt(2015, 2, 2)
@@ -123,11 +143,13 @@ mod tests {
             title: "title 2".to_string(),
             short_text: "txt 2".to_string(),
             markdown: true,
+            slug: Some("title-2".to_string()),
         };
-        let posts = vec![p1, p2];
+        let posts = [p1, p2];
+        let feed = Feed::new(FeedFormat::Atom, posts.iter().map(FeedItem::from).collect());
 
         // act
-        let actual = from_small_posts(posts);
+        let actual = render(&feed);
 
         // assert
         assert!(actual.is_ok());
@@ -135,13 +157,75 @@ mod tests {
         assert!(!result.is_empty());
     }
 
+    #[test]
+    fn from_posts_test() {
+        // arrange
+        let post = Post {
+            id: 1,
+            title: "title 1".to_string(),
+            short_text: "txt 1".to_string(),
+            text: "full & *text*".to_string(),
+            markdown: true,
+            created: "2015-02-02T02:00:00Z".parse().unwrap(),
+            modified: "2015-03-02T02:00:00Z".parse().unwrap(),
+            tags: vec!["rust".to_string()],
+            ..Default::default()
+        };
+        let feed = Feed::new(FeedFormat::Atom, vec![FeedItem::new(&post, true)]);
+
+        // act
+        let actual = render(&feed).unwrap();
+
+        // assert
+        assert!(actual.contains("<updated>2015-03-02T02:00:00Z</updated>"));
+        assert!(actual.contains("<published>2015-02-02T02:00:00Z</published>"));
+        assert!(actual.contains(r#"<category term="rust"></category>"#));
+        assert!(actual.contains(
+            r#"<content type="html">&lt;p&gt;full &amp;amp; &lt;em&gt;text&lt;/em&gt;&lt;/p&gt;"#
+        ));
+        assert!(
+            actual.contains(
+                r#"<link href="https://www.egoroff.spb.ru/blog/recent.atom" rel="self">"#
+            )
+        );
+        assert!(actual.contains(r#"<link href="https://www.egoroff.spb.ru/websub" rel="hub">"#));
+    }
+
+    #[test]
+    fn paged_feed_test() {
+        // arrange
+        let item = FeedItem {
+            url: "https://www.egoroff.spb.ru/blog/1.html".to_owned(),
+            summary: "<p>Кратко</p>".to_owned(),
+            ..Default::default()
+        };
+        let mut feed = Feed::tagged(FeedFormat::Atom, "rust", vec![item]);
+        feed.page = 2;
+        feed.pages = 3;
+
+        // act
+        let actual = render(&feed).unwrap();
+
+        // assert
+        let url = "https://www.egoroff.spb.ru/blog/tag/rust/recent.atom";
+        assert!(actual.contains(&format!("<id>{url}?page=2</id>")));
+        assert!(actual.contains(&format!(r#"<link href="{url}?page=2" rel="self">"#)));
+        assert!(actual.contains(&format!(r#"<link href="{url}" rel="first">"#)));
+        assert!(actual.contains(&format!(r#"<link href="{url}" rel="previous">"#)));
+        assert!(actual.contains(&format!(r#"<link href="{url}?page=3" rel="next">"#)));
+        assert!(actual.contains(&format!(r#"<link href="{url}?page=3" rel="last">"#)));
+        assert!(!actual.contains("archive"));
+        assert!(!actual.contains(r#"rel="hub""#));
+        assert!(!actual.contains("<content"));
+    }
+
     #[test]
     fn from_empty_posts_test() {
         // arrange
-        let posts = vec![];
+        let feed = Feed::new(FeedFormat::Atom, vec![]);
 
         // act
-        let actual = from_small_posts(posts);
+        let actual = render(&feed);
 
         // assert
         assert!(actual.is_err());
@@ -50,6 +50,10 @@ impl AppUser {
         Self { user }
     }
 
+    pub fn is_admin(&self) -> bool {
+        self.user.admin
+    }
+
     pub fn into_authorized(self) -> AuthorizedUser {
         AuthorizedUser {
             login_or_name: self.user.login,
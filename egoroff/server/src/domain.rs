@@ -1,17 +1,24 @@
-use std::{collections::HashSet, path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc};
 
+use chrono::{DateTime, Utc};
 use futures::lock::Mutex;
 use kernel::{
-    domain::ApiResult,
+    domain::{ApiResult, PostStatus, PostsRequest, Storage, WebmentionStatus},
     graph::{SiteGraph, SiteSection},
+    pool::Pool,
+    related,
     sqlite::Sqlite,
 };
 use oauth2::CsrfToken;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-pub type Database = Arc<Mutex<Sqlite>>;
-pub type Cache = Arc<Mutex<HashSet<String>>>;
+use crate::indie::AuthorizationCodes;
+use crate::{sitemap, websub};
+
+pub type Database<S = Sqlite> = Arc<Pool<S>>;
+pub type AuthCodes = Arc<Mutex<AuthorizationCodes>>;
 
 /// Represents a URI, which is a string representing a Uniform Resource Identifier.
 #[derive(Deserialize)]
@@ -34,6 +41,130 @@ pub struct BlogRequest {
     pub tag: Option<String>,
 }
 
+/// Query of Atom feeds.
+#[derive(Deserialize, Serialize, Default)]
+pub struct FeedQuery {
+    /// The feed page. Pages after the first one are archives of older posts.
+    pub page: Option<i32>,
+    /// Whether to include full post text (default: true).
+    pub full: Option<bool>,
+}
+
+/// Characters kept as is in tag segment of blog paths.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Path parameters of blog tag and date archive pages.
+#[derive(Deserialize, Default)]
+pub struct ArchivePath {
+    pub tag: Option<String>,
+    pub year: Option<String>,
+    pub month: Option<String>,
+    pub page: Option<String>,
+}
+
+impl ArchivePath {
+    /// Makes posts request if all parameters are valid.
+    #[must_use]
+    pub fn to_request(&self) -> Option<PostsRequest> {
+        let year = match &self.year {
+            Some(y) => Some(y.parse::<i32>().ok().filter(|y| *y > 0)?),
+            None => None,
+        };
+        let month = match &self.month {
+            Some(m) => Some(m.parse::<i32>().ok().filter(|m| (1..=12).contains(m))?),
+            None => None,
+        };
+        let page = match &self.page {
+            Some(p) => Some(p.parse::<i32>().ok().filter(|p| *p > 0)?),
+            None => None,
+        };
+        Some(PostsRequest {
+            tag: self.tag.clone(),
+            page,
+            year,
+            month,
+            ..Default::default()
+        })
+    }
+}
+
+/// Path of the first page of blog index filtered by request tag or date.
+#[must_use]
+pub fn blog_index_path(request: &PostsRequest) -> String {
+    match (&request.tag, request.year, request.month) {
+        (Some(tag), _, _) => format!("/blog/tag/{}/", utf8_percent_encode(tag, PATH_SEGMENT)),
+        (None, Some(year), Some(month)) => format!("/blog/{year}/{month:02}/"),
+        (None, Some(year), None) => format!("/blog/{year}/"),
+        _ => String::from("/blog/"),
+    }
+}
+
+/// Request for not yet published posts.
+#[derive(Deserialize, Default)]
+pub struct DraftsRequest {
+    /// Posts status: drafts if not set.
+    pub status: Option<PostStatus>,
+}
+
+/// Request to publish post later.
+#[derive(Deserialize)]
+pub struct ScheduleRequest {
+    /// The time the post becomes public.
+    pub published: DateTime<Utc>,
+}
+
+/// Request to set tag presentation settings. Empty or missing values are cleared.
+#[derive(Deserialize)]
+pub struct TagUpdateRequest {
+    /// The title shown instead of the tag.
+    pub title: Option<String>,
+    /// The tag description.
+    pub description: Option<String>,
+}
+
+/// Request to rename a tag.
+#[derive(Deserialize)]
+pub struct TagRenameRequest {
+    /// The new tag. If such tag exists tags are merged.
+    pub to: String,
+}
+
+/// Request to merge several tags into one.
+#[derive(Deserialize)]
+pub struct TagsMergeRequest {
+    /// Tags to replace.
+    pub tags: Vec<String>,
+    /// The tag that replaces them.
+    pub into: String,
+}
+
+/// Query of received webmentions.
+#[derive(Deserialize, Default)]
+pub struct WebmentionsQuery {
+    /// Webmentions status: all if not set.
+    pub status: Option<WebmentionStatus>,
+}
+
+/// Request to approve or reject a webmention.
+#[derive(Deserialize)]
+pub struct WebmentionModerationRequest {
+    /// Either approved or rejected.
+    pub status: WebmentionStatus,
+}
+
+/// Request to compare two revisions of a post.
+#[derive(Deserialize)]
+pub struct RevisionsDiffRequest {
+    /// Older revision ID.
+    pub from: i64,
+    /// Newer revision ID.
+    pub to: i64,
+}
+
 /// Represents navigation data in the application.
 #[derive(Deserialize, Serialize, Default)]
 pub struct Navigation {
@@ -46,6 +177,29 @@ pub struct Navigation {
     pub breadcrumbs: Option<Vec<SiteSection>>,
 }
 
+/// Search backend used by the search API.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchBackend {
+    /// Google Custom Search when configured, local index otherwise or on failure.
+    #[default]
+    Auto,
+    /// Google Custom Search only.
+    Google,
+    /// Local full-text index only.
+    Local,
+}
+
+impl From<&str> for SearchBackend {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "google" => SearchBackend::Google,
+            "local" => SearchBackend::Local,
+            _ => SearchBackend::Auto,
+        }
+    }
+}
+
 /// Represents the application's configuration data.
 #[derive(Serialize, Deserialize, Default)]
 pub struct Config {
@@ -53,16 +207,18 @@ pub struct Config {
     pub search_api_key: String,
     /// Google Custom Search Engine ID (`cx`, server-only).
     pub google_site_id: String,
+    /// Search backend used by the search API.
+    pub search_backend: SearchBackend,
     /// The analytics ID.
     pub analytics_id: String,
 }
 
 /// Represents the context of a page in the application.
-pub struct PageContext<'a> {
+pub struct PageContext<'a, S> {
     /// The base path of the page.
     pub base_path: PathBuf,
     /// The database storage instance.
-    pub storage: Database,
+    pub storage: Database<S>,
     /// The site graph instance.
     pub site_graph: Arc<SiteGraph<'a>>,
     /// The site configuration data.
@@ -71,8 +227,47 @@ pub struct PageContext<'a> {
     pub store_uri: String,
     /// The certificates path.
     pub certs_path: String,
-    /// The cache instance.
-    pub cache: Cache,
+    /// Authorization codes issued by `IndieAuth` endpoint.
+    pub auth_codes: AuthCodes,
+    /// Previous, next and related posts of post pages.
+    pub navigation: Arc<related::Cache>,
+    /// `WebSub` hub feeds subscribers are notified by.
+    pub hub: Arc<websub::Hub>,
+    /// Images of posts listed by sitemap.
+    pub sitemap_images: Arc<sitemap::ImageCache>,
+}
+
+impl<'a, S: Storage + Send + 'static> PageContext<'a, S> {
+    /// Makes the context with empty caches.
+    pub fn new(
+        base_path: PathBuf,
+        storage: Database<S>,
+        site_graph: Arc<SiteGraph<'a>>,
+        site_config: Config,
+        store_uri: String,
+        certs_path: String,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            base_path,
+            storage,
+            site_graph,
+            site_config,
+            store_uri,
+            certs_path,
+            auth_codes: Arc::new(Mutex::new(AuthorizationCodes::default())),
+            navigation: Arc::new(related::Cache::new()),
+            hub: Arc::new(websub::Hub::new()?),
+            sitemap_images: Arc::new(sitemap::ImageCache::new()),
+        })
+    }
+
+    /// Drops cached posts navigation and sitemap images and notifies feeds subscribers.
+    /// Must be called only after posts were changed successfully.
+    pub fn posts_changed(&self) {
+        self.navigation.invalidate();
+        self.sitemap_images.invalidate();
+        self.hub.publish();
+    }
 }
 
 /// Represents Apache-related data in the application.
@@ -307,4 +502,63 @@ mod tests {
         assert_eq!(poster.prev_page, 1);
         assert_eq!(poster.next_page, 1);
     }
+
+    #[rstest::rstest]
+    #[case(Some("rust"), None, None, "/blog/tag/rust/")]
+    #[case(
+        Some("веб сервер"),
+        None,
+        None,
+        "/blog/tag/%D0%B2%D0%B5%D0%B1%20%D1%81%D0%B5%D1%80%D0%B2%D0%B5%D1%80/"
+    )]
+    #[case(None, Some(2024), None, "/blog/2024/")]
+    #[case(None, Some(2024), Some(3), "/blog/2024/03/")]
+    #[case(None, None, None, "/blog/")]
+    fn blog_index_path_tests(
+        #[case] tag: Option<&str>,
+        #[case] year: Option<i32>,
+        #[case] month: Option<i32>,
+        #[case] expected: &str,
+    ) {
+        // arrange
+        let request = PostsRequest {
+            tag: tag.map(str::to_owned),
+            year,
+            month,
+            ..Default::default()
+        };
+
+        // act
+        let actual = blog_index_path(&request);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest::rstest]
+    #[case(Some("2024"), Some("03"), Some("2"), true)]
+    #[case(Some("2024"), None, None, true)]
+    #[case(Some("2024"), Some("13"), None, false)]
+    #[case(Some("year"), None, None, false)]
+    #[case(Some("2024"), None, Some("0"), false)]
+    fn archive_path_to_request_tests(
+        #[case] year: Option<&str>,
+        #[case] month: Option<&str>,
+        #[case] page: Option<&str>,
+        #[case] valid: bool,
+    ) {
+        // arrange
+        let path = ArchivePath {
+            tag: None,
+            year: year.map(str::to_owned),
+            month: month.map(str::to_owned),
+            page: page.map(str::to_owned),
+        };
+
+        // act
+        let actual = path.to_request();
+
+        // assert
+        assert_eq!(valid, actual.is_some());
+    }
 }
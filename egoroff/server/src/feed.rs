@@ -0,0 +1,357 @@
+//! Syndication feed model rendered as Atom, RSS 2.0 or JSON Feed.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use kernel::{
+    archive,
+    converter::{markdown2html, post2html},
+    domain::{Post, PostsRequest, SmallPost, Storage},
+};
+
+use crate::{atom, domain::blog_index_path, indie::ME, json_feed, rss, websub};
+
+pub const FEED_TITLE: &str = "egoroff.spb.ru feed";
+pub const FEED_DESCRIPTION: &str = "Блог Александра Егорова";
+pub const FEED_AUTHOR: &str = "Alexander Egorov";
+pub const FEED_LANGUAGE: &str = "ru";
+/// Number of the newest posts on a feed page.
+pub const FEED_SIZE: i32 = 20;
+
+/// Feed formats served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+    JsonFeed,
+}
+
+impl FeedFormat {
+    #[must_use]
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::JsonFeed => "application/feed+json; charset=utf-8",
+        }
+    }
+
+    /// The feed file name under `/blog/`.
+    #[must_use]
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "recent.atom",
+            FeedFormat::Rss => "recent.rss",
+            FeedFormat::JsonFeed => "feed.json",
+        }
+    }
+
+    pub fn render(&self, feed: &Feed) -> Result<String> {
+        match self {
+            FeedFormat::Atom => atom::render(feed),
+            FeedFormat::Rss => rss::render(feed),
+            FeedFormat::JsonFeed => json_feed::render(feed),
+        }
+    }
+}
+
+/// Feed of the newest posts.
+#[derive(Debug, Clone, Default)]
+pub struct Feed {
+    pub title: String,
+    pub description: String,
+    /// The site home page.
+    pub home_page_url: String,
+    /// The URL the feed is served from.
+    pub feed_url: String,
+    pub items: Vec<FeedItem>,
+    /// The page of the feed starting from 1 which is the newest posts.
+    pub page: i32,
+    /// The number of the feed pages.
+    pub pages: i32,
+    /// The `WebSub` hub subscribers are notified by.
+    pub hub: Option<String>,
+}
+
+impl Feed {
+    /// Makes the site feed served in the format.
+    #[must_use]
+    pub fn new(format: FeedFormat, items: Vec<FeedItem>) -> Self {
+        Self {
+            title: FEED_TITLE.to_owned(),
+            description: FEED_DESCRIPTION.to_owned(),
+            home_page_url: ME.to_owned(),
+            feed_url: format!("{ME}blog/{}", format.file_name()),
+            items,
+            page: 1,
+            pages: 1,
+            hub: Some(websub::hub_url()),
+        }
+    }
+
+    /// Makes the feed of posts with the tag served next to the tag archive page.
+    #[must_use]
+    pub fn tagged(format: FeedFormat, tag: &str, items: Vec<FeedItem>) -> Self {
+        let request = PostsRequest {
+            tag: Some(tag.to_owned()),
+            ..Default::default()
+        };
+        let index_path = blog_index_path(&request);
+        let home_page_url = format!("{ME}{}", index_path.trim_start_matches('/'));
+        Self {
+            title: format!("{FEED_TITLE}: {tag}"),
+            description: FEED_DESCRIPTION.to_owned(),
+            feed_url: format!("{home_page_url}{}", format.file_name()),
+            home_page_url,
+            items,
+            page: 1,
+            pages: 1,
+            hub: Some(websub::hub_url()),
+        }
+    }
+
+    /// Whether the feed page is the newest posts page subscribers follow.
+    /// Other pages shift when posts are published so they aren't archives.
+    #[must_use]
+    pub fn is_first_page(&self) -> bool {
+        self.page <= 1
+    }
+
+    /// The URL of the feed page. The first page is the subscription document itself.
+    #[must_use]
+    pub fn page_url(&self, page: i32) -> String {
+        if page > 1 {
+            format!("{}?page={page}", self.feed_url)
+        } else {
+            self.feed_url.clone()
+        }
+    }
+
+    /// Links between pages of the paged feed
+    /// as defined by [RFC 5005](https://www.rfc-editor.org/rfc/rfc5005#section-3).
+    /// Older posts are on the next pages.
+    #[must_use]
+    pub fn paging_links(&self) -> Vec<(&'static str, String)> {
+        let mut links = vec![];
+        if !self.is_first_page() {
+            links.push(("first", self.feed_url.clone()));
+            links.push(("previous", self.page_url(self.page - 1)));
+        }
+        if self.page < self.pages {
+            links.push(("next", self.page_url(self.page + 1)));
+            links.push(("last", self.page_url(self.pages)));
+        }
+        links
+    }
+
+    /// The latest update time of items if there are any.
+    #[must_use]
+    pub fn updated(&self) -> Option<DateTime<Utc>> {
+        self.items.iter().map(|i| i.updated).max()
+    }
+}
+
+/// Reads the feed page of public posts with the tag or of all public posts.
+/// Gives `None` if there is no such page or tag. Full text of posts is included if requested.
+pub fn read_feed<S: Storage>(
+    storage: &S,
+    format: FeedFormat,
+    tag: Option<&str>,
+    page: i32,
+    full: bool,
+) -> Result<Option<Feed>> {
+    if page < 1 {
+        return Ok(None);
+    }
+    let request = PostsRequest {
+        tag: tag.map(str::to_owned),
+        page: Some(page),
+        ..Default::default()
+    };
+    let posts = archive::get_public_posts(storage, FEED_SIZE, Some(request))?;
+    if page > posts.pages.max(1) || (tag.is_some() && posts.count == 0) {
+        return Ok(None);
+    }
+
+    let items = posts
+        .result
+        .iter()
+        .map(|post| FeedItem::new(post, full))
+        .collect();
+    let mut feed = match tag {
+        Some(t) => Feed::tagged(format, t, items),
+        None => Feed::new(format, items),
+    };
+    feed.page = posts.page;
+    feed.pages = posts.pages;
+    Ok(Some(feed))
+}
+
+/// Feed entry made from a post.
+#[derive(Debug, Clone, Default)]
+pub struct FeedItem {
+    /// The post URL that is also the item ID.
+    pub url: String,
+    pub title: String,
+    /// Rendered short text of the post.
+    pub summary: String,
+    /// Rendered full text of the post if it's included into the feed.
+    pub content: Option<String>,
+    pub published: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Post tags used as categories.
+    pub tags: Vec<String>,
+}
+
+impl FeedItem {
+    /// Makes the item of the post. Full text is rendered only if it's included.
+    #[must_use]
+    pub fn new(post: &Post, full: bool) -> Self {
+        let summary = if post.markdown {
+            markdown2html(&post.short_text).unwrap_or_else(|_| post.short_text.clone())
+        } else {
+            post.short_text.clone()
+        };
+        let content = full.then(|| {
+            post2html(post).unwrap_or_else(|e| {
+                tracing::error!("Failed to render post {} for feed: {e:#}", post.id);
+                summary.clone()
+            })
+        });
+        Self {
+            url: format!("{ME}{}", post.path()),
+            title: post.title.clone(),
+            summary,
+            content,
+            published: post.created,
+            updated: post.modified.max(post.created),
+            tags: post.tags.clone(),
+        }
+    }
+}
+
+/// Item of a post which text isn't read. Short text must be already rendered.
+impl From<&SmallPost> for FeedItem {
+    fn from(post: &SmallPost) -> Self {
+        Self {
+            url: format!("{ME}{}", post.path()),
+            title: post.title.clone(),
+            summary: post.short_text.clone(),
+            content: Some(post.short_text.clone()),
+            published: post.created,
+            updated: post.created,
+            tags: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(true, Some("<p>Текст <strong>поста</strong></p>\n"))]
+    #[case(false, None)]
+    fn item_from_post(#[case] full: bool, #[case] expected_content: Option<&str>) {
+        // arrange
+        let post = Post {
+            id: 1,
+            title: "Пост".to_owned(),
+            short_text: "Кратко".to_owned(),
+            text: "Текст **поста**".to_owned(),
+            markdown: true,
+            created: "2024-01-10T10:00:00Z".parse().unwrap(),
+            modified: "2024-02-01T10:00:00Z".parse().unwrap(),
+            tags: vec!["rust".to_owned()],
+            slug: Some("post".to_owned()),
+            ..Default::default()
+        };
+
+        // act
+        let actual = FeedItem::new(&post, full);
+
+        // assert
+        assert_eq!("https://www.egoroff.spb.ru/blog/2024/post", actual.url);
+        assert_eq!("<p>Кратко</p>\n", actual.summary);
+        assert_eq!(expected_content, actual.content.as_deref());
+        assert_eq!(post.modified, actual.updated);
+        assert_eq!(vec!["rust"], actual.tags);
+    }
+
+    #[test]
+    fn feed_updated_is_latest_item_update() {
+        // arrange
+        let item = |updated: &str| FeedItem {
+            updated: updated.parse().unwrap(),
+            ..Default::default()
+        };
+        let feed = Feed::new(
+            FeedFormat::Rss,
+            vec![item("2024-01-10T10:00:00Z"), item("2024-02-01T10:00:00Z")],
+        );
+
+        // act
+        let actual = feed.updated();
+
+        // assert
+        assert_eq!(Some("2024-02-01T10:00:00Z".parse().unwrap()), actual);
+        assert_eq!("https://www.egoroff.spb.ru/blog/recent.rss", feed.feed_url);
+    }
+
+    #[test]
+    fn tagged_feed_urls() {
+        // act
+        let actual = Feed::tagged(FeedFormat::Atom, "веб сервер", vec![]);
+
+        // assert
+        assert_eq!("egoroff.spb.ru feed: веб сервер", actual.title);
+        assert_eq!(
+            "https://www.egoroff.spb.ru/blog/tag/%D0%B2%D0%B5%D0%B1%20%D1%81%D0%B5%D1%80%D0%B2%D0%B5%D1%80/",
+            actual.home_page_url
+        );
+        assert_eq!(
+            format!("{}recent.atom", actual.home_page_url),
+            actual.feed_url
+        );
+    }
+
+    #[rstest]
+    #[case(1, 1, vec![])]
+    #[case(1, 3, vec![
+        ("next", "recent.atom?page=2"),
+        ("last", "recent.atom?page=3"),
+    ])]
+    #[case(2, 3, vec![
+        ("first", "recent.atom"),
+        ("previous", "recent.atom"),
+        ("next", "recent.atom?page=3"),
+        ("last", "recent.atom?page=3"),
+    ])]
+    #[case(3, 3, vec![
+        ("first", "recent.atom"),
+        ("previous", "recent.atom?page=2"),
+    ])]
+    fn paging_links_tests(
+        #[case] page: i32,
+        #[case] pages: i32,
+        #[case] expected: Vec<(&str, &str)>,
+    ) {
+        // arrange
+        let mut feed = Feed::new(FeedFormat::Atom, vec![]);
+        feed.page = page;
+        feed.pages = pages;
+
+        // act
+        let actual = feed.paging_links();
+
+        // assert
+        let expected: Vec<(&str, String)> = expected
+            .into_iter()
+            .map(|(rel, file)| (rel, format!("https://www.egoroff.spb.ru/blog/{file}")))
+            .collect();
+        assert_eq!(expected, actual);
+        assert_eq!(page == 1, feed.is_first_page());
+    }
+}
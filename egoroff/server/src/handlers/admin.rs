@@ -19,14 +19,20 @@ pub struct DashboardStats {
     pub users: i32,
 }
 
-pub async fn serve_dashboard_api(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_dashboard_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
-    let storage = page_context.storage.lock().await;
-
-    let posts_count = storage.count_posts(PostsRequest::default()).unwrap_or(0);
-    let downloads_count = storage.count_downloads().unwrap_or(0);
-    let users_count = storage.count_users().unwrap_or(0);
+    let counts = page_context
+        .storage
+        .read(|s| {
+            anyhow::Ok((
+                s.count_posts(PostsRequest::default()).unwrap_or(0),
+                s.count_downloads().unwrap_or(0),
+                s.count_users().unwrap_or(0),
+            ))
+        })
+        .await;
+    let (posts_count, downloads_count, users_count) = counts.unwrap_or_default();
 
     success_response(Json(DashboardStats {
         posts: posts_count,
@@ -14,7 +14,7 @@ use serde::Deserialize;
 use tower_sessions::Session;
 use url::Url;
 
-type AuthSession = axum_login::AuthSession<AuthBackend>;
+pub type AuthSession = axum_login::AuthSession<AuthBackend>;
 
 const GOOGLE_CSRF_KEY: &str = "google_csrf_state";
 const GITHUB_CSRF_KEY: &str = "github_csrf_state";
@@ -145,16 +145,14 @@ pub async fn serve_profile() -> impl IntoResponse {
     .into_response()
 }
 
-pub async fn serve_users_api(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_users_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
-    let storage = page_context.storage.lock().await;
-
-    let users = match storage.get_users() {
+    let users = match page_context.storage.read(|s| s.get_users()).await {
         Ok(u) => u,
         Err(e) => {
             tracing::error!("Failed to get users: {e:#?}");
-            return make_json_response::<ApiResult<User>>(Err(anyhow::anyhow!(e)));
+            return make_json_response::<ApiResult<User>>(Err(e));
         }
     };
     let users_count = i32::try_from(users.len()).unwrap_or(i32::MAX);
@@ -170,10 +168,10 @@ pub async fn serve_users_api(
     make_json_response(Ok(result))
 }
 
-async fn oauth_callback<T: OAuthProfile>(
+async fn oauth_callback<T: OAuthProfile, S: Storage + Send + 'static>(
     query: AuthRequest,
     authorizer: Arc<OAuthAuthorizer<T>>,
-    page_context: Arc<PageContext<'static>>,
+    page_context: Arc<PageContext<'static, S>>,
     session: Session,
     pkce_code_verifier_key: &str,
     csrf_key: &str,
@@ -210,15 +208,18 @@ async fn oauth_callback<T: OAuthProfile>(
         let token = authorizer
             .exchange_code(query.code.clone(), pkce_code_verifier)
             .await;
-        let mut storage = page_context.storage.lock().await;
-
         match token {
             Ok(token) => {
                 let user = authorizer.get_user(token.access_token()).await;
                 match user {
                     Ok(user) => {
                         drop(session);
-                        if let Err(e) = storage.upsert_user(&user) {
+                        let stored = user.clone();
+                        let upserted = page_context
+                            .storage
+                            .write(move |s| s.upsert_user(&stored))
+                            .await;
+                        if let Err(e) = upserted {
                             tracing::error!("login error: {e:#?}");
                             return Redirect::to(LOGIN_URI);
                         }
@@ -258,11 +259,10 @@ async fn oauth_callback<T: OAuthProfile>(
     }
 }
 
-#[axum::debug_handler]
-pub async fn google_oauth_callback(
+pub async fn google_oauth_callback<S: Storage + Send + 'static>(
     Query(query): Query<AuthRequest>,
     Extension(google_authorizer): Extension<Arc<GoogleAuthorizer>>,
-    State(page_context): State<Arc<PageContext<'static>>>,
+    State(page_context): State<Arc<PageContext<'static, S>>>,
     session: Session,
     auth: AuthSession,
 ) -> impl IntoResponse {
@@ -278,11 +278,10 @@ pub async fn google_oauth_callback(
     .await
 }
 
-#[axum::debug_handler]
-pub async fn github_oauth_callback(
+pub async fn github_oauth_callback<S: Storage + Send + 'static>(
     Query(query): Query<AuthRequest>,
     Extension(github_authorizer): Extension<Arc<GithubAuthorizer>>,
-    State(page_context): State<Arc<PageContext<'static>>>,
+    State(page_context): State<Arc<PageContext<'static, S>>>,
     session: Session,
     auth: AuthSession,
 ) -> impl IntoResponse {
@@ -298,11 +297,10 @@ pub async fn github_oauth_callback(
     .await
 }
 
-#[axum::debug_handler]
-pub async fn yandex_oauth_callback(
+pub async fn yandex_oauth_callback<S: Storage + Send + 'static>(
     Query(query): Query<AuthRequest>,
     Extension(yandex_authorizer): Extension<Arc<YandexAuthorizer>>,
-    State(page_context): State<Arc<PageContext<'static>>>,
+    State(page_context): State<Arc<PageContext<'static, S>>>,
     session: Session,
     auth: AuthSession,
 ) -> impl IntoResponse {
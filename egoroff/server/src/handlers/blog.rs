@@ -1,10 +1,20 @@
+use chrono::Utc;
 use kernel::{
-    converter::html2text,
-    domain::{ApiResult, Post, SmallPost},
+    converter::{HtmlDocument, html2text, post2document},
+    diff,
+    domain::{
+        ApiResult, Post, PostEdit, PostNavigation, PostRevision, PostStatus, SmallPost, TagInfo,
+    },
+    related,
 };
 
 use crate::body::Content;
-use axum::response::Redirect;
+use crate::domain::{
+    ArchivePath, DraftsRequest, FeedQuery, RevisionsDiffRequest, ScheduleRequest, TagRenameRequest,
+    TagUpdateRequest, TagsMergeRequest, blog_index_path,
+};
+use crate::feed::{self, FeedFormat};
+use crate::{webmention, websub};
 
 use super::{
     template::{BlogIndex, BlogPost},
@@ -32,64 +42,140 @@ const OPINIONS_REMAP: &[(&str, &str)] = &[
 ];
 
 const BLOG_PATH: &str = "/blog/";
+/// Posts with fewer headings are short enough to read without table of contents.
+const TOC_MIN_HEADINGS: usize = 3;
+
+const MONTHS: [&str; 12] = [
+    "январь",
+    "февраль",
+    "март",
+    "апрель",
+    "май",
+    "июнь",
+    "июль",
+    "август",
+    "сентябрь",
+    "октябрь",
+    "ноябрь",
+    "декабрь",
+];
 
 static REPLACES_MAP: std::sync::LazyLock<HashMap<&'static str, &'static str>> =
     std::sync::LazyLock::new(|| OPINIONS_REMAP.iter().map(|(k, v)| (*k, *v)).collect());
 
-pub async fn serve_index_default(
+pub async fn serve_index_default<S: Storage + Send + 'static>(
     Query(request): Query<BlogRequest>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
-    serve_index(request, page_context, None).await
+    if let Some(tag) = request.tag {
+        return redirect_to_tag_index(tag, 1);
+    }
+    serve_index(page_context, ArchivePath::default()).await
 }
 
-pub async fn serve_index_not_default(
+pub async fn serve_index_not_default<S: Storage + Send + 'static>(
     Query(request): Query<BlogRequest>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     extract::Path(page): extract::Path<String>,
 ) -> impl IntoResponse {
-    serve_index(request, page_context, Some(page)).await
+    if let Some(tag) = request.tag {
+        return match page.parse() {
+            Ok(page) => redirect_to_tag_index(tag, page),
+            Err(_) => not_found_page(),
+        };
+    }
+    let path = ArchivePath {
+        page: Some(page),
+        ..Default::default()
+    };
+    serve_index(page_context, path).await
 }
 
-async fn serve_index(
-    request: BlogRequest,
-    page_context: Arc<PageContext<'_>>,
-    page: Option<String>,
-) -> impl IntoResponse {
-    let page = if let Some(page) = page {
-        match page.parse() {
-            Ok(item) => item,
-            Err(e) => {
-                tracing::error!("Invalid page: {e:#?}");
-                return not_found_page();
-            }
-        }
+/// Tag filter used to be a query string of blog index pages.
+fn redirect_to_tag_index(tag: String, page: i32) -> Response {
+    let request = PostsRequest {
+        tag: Some(tag),
+        ..Default::default()
+    };
+    let path = blog_index_path(&request);
+    if page > 1 {
+        redirect_response(&format!("{path}page/{page}/"))
     } else {
-        1
+        redirect_response(&path)
+    }
+}
+
+/// Serves tag, year and month archive pages.
+pub async fn serve_archive_index<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    extract::Path(path): extract::Path<ArchivePath>,
+) -> impl IntoResponse {
+    serve_index(page_context, path).await
+}
+
+async fn serve_index<S: Storage + Send + 'static>(
+    page_context: Arc<PageContext<'_, S>>,
+    path: ArchivePath,
+) -> Response {
+    let Some(req) = path.to_request() else {
+        return not_found_page();
     };
+    let page = req.page.unwrap_or(1);
+    let index_path = blog_index_path(&req);
+    let is_archive = index_path != BLOG_PATH;
+    let period = req.year.map(|year| (year, req.month));
 
     let Some(section) = page_context.site_graph.get_section("blog") else {
         return internal_server_error_page();
     };
 
-    let req = PostsRequest {
-        page: Some(page),
-        ..Default::default()
-    };
-
-    let storage = page_context.storage.lock().await;
-    let result = archive::get_small_posts(&storage, PAGE_SIZE, Some(req));
-
-    let api_result = match result {
-        Ok(ar) => ar,
+    let tag = req.tag.clone();
+    let result = page_context
+        .storage
+        .read(move |s| {
+            let posts = archive::get_small_posts(s, PAGE_SIZE, Some(req))?;
+            let tag = tag.and_then(|t| s.get_tag(&t).ok());
+            anyhow::Ok((posts, tag))
+        })
+        .await;
+
+    let (api_result, tag) = match result {
+        Ok(r) => r,
         Err(e) => {
             tracing::error!("Get posts error: {e:#?}");
             return internal_server_error_page();
         }
     };
 
+    if is_archive && (api_result.count == 0 || page > api_result.pages) {
+        return not_found_page();
+    }
+
     let poster = Poster::new(api_result, page);
 
+    let archive_title = match (&tag, period) {
+        (Some(t), _) => Some(t.display_title().to_owned()),
+        (None, Some((year, Some(month)))) => Some(format!(
+            "Записи за {} {year}",
+            MONTHS[usize::try_from(month - 1).unwrap_or_default()]
+        )),
+        (None, Some((year, None))) => Some(format!("Записи за {year} год")),
+        (None, None) => None,
+    };
+
+    let title = match (&archive_title, page) {
+        (Some(t), 1) => t.clone(),
+        (Some(t), _) => format!("{t}: {page}-я страница"),
+        (None, _) => format!("{page}-я страница"),
+    };
+    let description = match &tag {
+        Some(TagInfo {
+            description: Some(d),
+            ..
+        }) if !d.is_empty() => d.clone(),
+        _ => format!("{} {title}", section.descr),
+    };
+
     let mut tpl = BlogIndex {
         html_class: "blog",
         title: &section.title,
@@ -97,28 +183,27 @@ async fn serve_index(
         keywords: get_keywords(section),
         meta_description: section.descr.as_str(),
         poster: &poster,
-        request: &request,
+        base_path: &index_path,
+        tag: tag.as_ref(),
         year: get_year(),
     };
 
-    let title = format!("{page}-я страница");
-    let description = format!("{} {title}", section.descr);
-    let title_path = if page == 1 {
-        page_context.site_graph.make_title_path(BLOG_PATH)
-    } else {
+    let title_path = if is_archive || page > 1 {
         tpl.title = &title;
         tpl.meta_description = &description;
         page_context
             .site_graph
             .make_title_path(&format!("{BLOG_PATH}{page}"))
+    } else {
+        page_context.site_graph.make_title_path(BLOG_PATH)
     };
     tpl.title_path = &title_path;
 
     tpl.into_response()
 }
 
-pub async fn serve_document(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_document<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     extract::Path(path): extract::Path<String>,
 ) -> impl IntoResponse {
     let doc = strip_extension(&path);
@@ -131,14 +216,14 @@ pub async fn serve_document(
         }
     };
 
-    let storage = page_context.storage.lock().await;
+    // Posts moved to new IDs are redirected straight to their canonical URL
+    let id = page_context
+        .storage
+        .read(move |s| s.get_new_post_id(id))
+        .await
+        .unwrap_or(id);
 
-    if let Ok(id) = storage.get_new_post_id(id) {
-        let new_path = format!("/blog/{id}.html");
-        return redirect_response(&new_path);
-    }
-
-    let post = match storage.get_post(id) {
+    let post = match page_context.storage.read(move |s| s.get_post(id)).await {
         Ok(item) if item.is_public => item,
         Ok(_) => return not_found_page(),
         Err(e) => {
@@ -146,20 +231,68 @@ pub async fn serve_document(
             return not_found_page();
         }
     };
-    drop(storage);
-    let uri = format!("{BLOG_PATH}{path}");
-    let title_path = page_context.site_graph.make_title_path(&uri);
-
-    let content = if post.markdown {
-        markdown2html(&post.text)
-    } else if post.text.starts_with("<?xml version=\"1.0\"?>") {
-        xml2html(&post.text)
-    } else {
-        Ok(post.text.clone())
+
+    let canonical = post.path();
+    if canonical != format!("blog/{path}") {
+        return redirect_response(&format!("/{canonical}"));
+    }
+    render_post(&page_context, &post, &format!("/{canonical}")).await
+}
+
+/// Serves post by its human-readable URL `/blog/{year}/{slug}`
+pub async fn serve_post_by_slug<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    extract::Path((year, slug)): extract::Path<(String, String)>,
+) -> impl IntoResponse {
+    let post_slug = slug.clone();
+    let post = match page_context
+        .storage
+        .read(move |s| s.get_post_id(&post_slug).and_then(|id| s.get_post(id)))
+        .await
+    {
+        Ok(item) if item.is_public => item,
+        Ok(_) => return not_found_page(),
+        Err(e) => {
+            tracing::error!("Post '{slug}' not found: {e:#?}");
+            return not_found_page();
+        }
     };
 
-    match content {
-        Ok(c) => {
+    let path = post.path();
+    if path != format!("blog/{year}/{slug}") {
+        return redirect_response(&format!("/{path}"));
+    }
+    render_post(&page_context, &post, &format!("/{path}")).await
+}
+
+async fn render_post<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    post: &Post,
+    uri: &str,
+) -> Response {
+    let title_path = page_context.site_graph.make_title_path(uri);
+    let navigation = read_navigation(page_context, post.id)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Failed to read post {} navigation: {e:#?}", post.id);
+            Arc::default()
+        });
+    let post_id = post.id;
+    let (reactions, replies): (Vec<_>, Vec<_>) = page_context
+        .storage
+        .read(move |s| s.get_post_webmentions(post_id))
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Failed to read post {post_id} webmentions: {e:#?}");
+            Vec::new()
+        })
+        .into_iter()
+        .partition(|m| m.kind.is_reaction());
+
+    let document = post2document(post);
+
+    match document {
+        Ok(HtmlDocument { html: c, toc }) => {
             let meta_description = if c.is_empty() {
                 post.title.clone()
             } else {
@@ -183,9 +316,17 @@ pub async fn serve_document(
                 title: &post.title,
                 title_path: &title_path,
                 keywords: &keywords,
-                main_post: &post,
+                main_post: post,
                 content: &c,
+                toc: if toc.len() >= TOC_MIN_HEADINGS {
+                    &toc
+                } else {
+                    &[]
+                },
                 meta_description,
+                navigation: &navigation,
+                reactions: &reactions,
+                replies: &replies,
                 year: get_year(),
             }
             .into_response()
@@ -197,6 +338,26 @@ pub async fn serve_document(
     }
 }
 
+/// Gets previous, next and related posts of the post from cache or storage.
+async fn read_navigation<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    id: i64,
+) -> Result<Arc<PostNavigation>> {
+    if let Some(navigation) = page_context.navigation.get(&id) {
+        return Ok(navigation);
+    }
+    let generation = page_context.navigation.generation();
+    let navigation = page_context
+        .storage
+        .read(move |s| related::navigation(s, id, related::DEFAULT_LIMIT))
+        .await?;
+    let navigation = Arc::new(navigation);
+    page_context
+        .navigation
+        .insert(generation, id, Arc::clone(&navigation));
+    Ok(navigation)
+}
+
 /// Just redirects to /blog/ page using 308 code
 pub async fn redirect() -> impl IntoResponse {
     (
@@ -205,30 +366,82 @@ pub async fn redirect() -> impl IntoResponse {
     )
 }
 
-pub async fn serve_atom(State(page_context): State<Arc<PageContext<'_>>>) -> impl IntoResponse {
-    let storage = page_context.storage.lock().await;
-    let result = archive::get_small_posts(&storage, 20, None);
+pub async fn serve_atom<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Query(query): Query<FeedQuery>,
+) -> impl IntoResponse {
+    serve_feed(&page_context, FeedFormat::Atom, None, query).await
+}
+
+pub async fn serve_tag_atom<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    extract::Path(tag): extract::Path<String>,
+    Query(query): Query<FeedQuery>,
+) -> impl IntoResponse {
+    serve_feed(&page_context, FeedFormat::Atom, Some(tag), query).await
+}
+
+pub async fn serve_rss<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    serve_feed(&page_context, FeedFormat::Rss, None, FeedQuery::default()).await
+}
+
+pub async fn serve_json_feed<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    serve_feed(
+        &page_context,
+        FeedFormat::JsonFeed,
+        None,
+        FeedQuery::default(),
+    )
+    .await
+}
+
+async fn serve_feed<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    format: FeedFormat,
+    tag: Option<String>,
+    query: FeedQuery,
+) -> Response {
+    let page = query.page.unwrap_or(1);
+    let full = query.full.unwrap_or(true);
+    let result = page_context
+        .storage
+        .read(move |s| feed::read_feed(s, format, tag.as_deref(), page, full))
+        .await;
 
     match result {
-        Ok(r) => match atom::from_small_posts(r.result) {
-            Ok(xml) => success_response(Content(xml, "application/atom+xml; charset=utf-8")),
+        Ok(Some(feed)) => match format.render(&feed) {
+            Ok(content) => (
+                [(
+                    axum::http::header::LINK,
+                    websub::link_header(&feed.feed_url),
+                )],
+                success_response(Content(content, format.content_type())),
+            )
+                .into_response(),
             Err(e) => {
-                tracing::error!("Convert atom posts error: {e:#?}");
+                tracing::error!("Convert feed posts error: {e:#?}");
                 internal_server_error_response(Content(e.to_string(), "text/plain; charset=utf-8"))
+                    .into_response()
             }
         },
+        Ok(None) => not_found_response(Content("Feed page not found", "text/plain; charset=utf-8"))
+            .into_response(),
         Err(e) => {
             tracing::error!("Get posts error: {e:#?}");
             internal_server_error_response(Content(e.to_string(), "text/plain; charset=utf-8"))
+                .into_response()
         }
     }
 }
 
-pub async fn serve_archive_api(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_archive_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
-    let storage = page_context.storage.lock().await;
-    let result = archive::archive(storage);
+    let result = page_context.storage.read(archive::archive).await;
     make_json_response(result)
 }
 
@@ -244,63 +457,405 @@ pub async fn serve_archive_api(
         (status = 200, description = "Get posts successfully", body = ApiResult<SmallPost>),
     ),
 )]
-pub async fn serve_posts_api(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_posts_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     Query(request): Query<PostsRequest>,
 ) -> impl IntoResponse {
-    let storage = page_context.storage.lock().await;
-    let result = archive::get_small_posts(&storage, PAGE_SIZE, Some(request));
+    let result = page_context
+        .storage
+        .read(move |s| archive::get_small_posts(s, PAGE_SIZE, Some(request)))
+        .await;
     make_json_response(result)
 }
 
-pub async fn serve_posts_admin_api(
-    State(page_context): State<Arc<PageContext<'_>>>,
+/// Gets previous and next by date and related posts of the public post.
+#[utoipa::path(
+    get,
+    path = "/api/v2/blog/posts/{id}/related/",
+    params(
+        ("id" = i64, Path, description = "Post ID")
+    ),
+    tag = "blog",
+    responses(
+        (status = 200, description = "Get post navigation successfully", body = PostNavigation),
+        (status = 404, description = "Post not found"),
+    ),
+)]
+pub async fn serve_post_navigation_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    extract::Path(id): extract::Path<i64>,
+) -> impl IntoResponse {
+    match page_context.storage.read(move |s| s.get_post(id)).await {
+        Ok(post) if post.is_public => {}
+        _ => {
+            return not_found_response(Json(OperationResult {
+                result: "post not found",
+            }))
+            .into_response();
+        }
+    }
+    let result = read_navigation(&page_context, id)
+        .await
+        .map(|n| PostNavigation::clone(&n));
+    make_json_response(result).into_response()
+}
+
+pub async fn serve_posts_admin_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     Query(request): Query<PostsRequest>,
 ) -> impl IntoResponse {
-    let storage = page_context.storage.lock().await;
-    let result = archive::get_posts(&storage, 10, request);
+    let result = page_context
+        .storage
+        .read(move |s| archive::get_posts(s, 10, request))
+        .await;
     make_json_response(result)
 }
 
-pub async fn serve_post_create(
-    State(page_context): State<Arc<PageContext<'_>>>,
-    Json(mut post): Json<Post>,
+/// Lists drafts or scheduled posts depending on requested status.
+pub async fn serve_drafts_admin_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Query(request): Query<DraftsRequest>,
+) -> impl IntoResponse {
+    let status = request.status.unwrap_or(PostStatus::Draft);
+    let posts = match page_context
+        .storage
+        .read(move |s| s.get_posts_by_status(status))
+        .await
+    {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::error!("Failed to get {} posts: {e:#?}", status.as_str());
+            return make_json_response::<ApiResult<Post>>(Err(e));
+        }
+    };
+    let posts_count = i32::try_from(posts.len()).unwrap_or(i32::MAX);
+
+    let result = ApiResult {
+        result: posts,
+        pages: 1,
+        page: 1,
+        count: posts_count,
+        status: "success",
+    };
+
+    make_json_response(Ok(result))
+}
+
+/// Schedules post to become public at the time requested.
+pub async fn serve_post_schedule<S: Storage + Send + 'static>(
+    extract::Path(id): extract::Path<i64>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Json(request): Json<ScheduleRequest>,
 ) -> impl IntoResponse {
-    let mut storage = page_context.storage.lock().await;
+    if request.published <= Utc::now() {
+        return bad_request_error_response(Json(OperationResult {
+            result: "publication time must be in the future",
+        }))
+        .into_response();
+    }
+
+    let mut post = match page_context.storage.read(move |s| s.get_post(id)).await {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::error!("Post ID '{id}' not found: {e:#?}");
+            return not_found_response(Json(OperationResult {
+                result: "post not found",
+            }))
+            .into_response();
+        }
+    };
+    post.created = request.published;
+    post.is_public = false;
+    post.status = PostStatus::Scheduled;
+
+    let result = page_context
+        .storage
+        .write(move |s| {
+            let id = post.id;
+            s.upsert_post(post)?;
+            webmention::queue_post_links_logged(s, id);
+            Ok::<_, S::Err>(())
+        })
+        .await;
+    if result.is_ok() {
+        page_context.posts_changed();
+    }
+    updated_response(result).into_response()
+}
 
-    // Get next ID for the post
-    let new_id = match storage.next_post_id() {
-        Ok(id) => id,
+/// Lists saved revisions of the post newest first.
+pub async fn serve_post_revisions_admin_api<S: Storage + Send + 'static>(
+    extract::Path(id): extract::Path<i64>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    let revisions = match page_context
+        .storage
+        .read(move |s| s.get_post_revisions(id))
+        .await
+    {
+        Ok(r) => r,
         Err(e) => {
-            tracing::error!("Failed to generate post ID: {e:#?}");
-            return created_response(Err(e));
+            tracing::error!("Failed to get post {id} revisions: {e:#?}");
+            return make_json_response::<ApiResult<PostRevision>>(Err(e));
         }
     };
+    let revisions_count = i32::try_from(revisions.len()).unwrap_or(i32::MAX);
+
+    let result = ApiResult {
+        result: revisions,
+        pages: 1,
+        page: 1,
+        count: revisions_count,
+        status: "success",
+    };
+
+    make_json_response(Ok(result))
+}
+
+/// Renders unified diff between two revisions of the post.
+pub async fn serve_post_revisions_diff<S: Storage + Send + 'static>(
+    extract::Path(id): extract::Path<i64>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Query(request): Query<RevisionsDiffRequest>,
+) -> impl IntoResponse {
+    let revisions = page_context
+        .storage
+        .read(move |s| {
+            s.get_post_revision(id, request.from)
+                .and_then(|from| Ok((from, s.get_post_revision(id, request.to)?)))
+        })
+        .await;
+
+    match revisions {
+        Ok((from, to)) => {
+            let diff = diff::unified(
+                &from.document(),
+                &to.document(),
+                &format!("revision {}", from.id),
+                &format!("revision {}", to.id),
+            );
+            success_response(Content(diff, "text/plain; charset=utf-8"))
+        }
+        Err(e) => {
+            tracing::error!("Post {id} revisions not found: {e:#?}");
+            not_found_response(Content(
+                String::from("revision not found"),
+                "text/plain; charset=utf-8",
+            ))
+        }
+    }
+}
 
-    post.id = new_id;
+/// Restores post content from the revision. Restored content is saved as a new revision.
+pub async fn serve_post_revision_restore<S: Storage + Send + 'static>(
+    extract::Path((id, revision)): extract::Path<(i64, i64)>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    let found = page_context
+        .storage
+        .read(move |s| {
+            s.get_post(id)
+                .and_then(|post| Ok((post, s.get_post_revision(id, revision)?)))
+        })
+        .await;
+    let (mut post, revision) = match found {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::error!("Post {id} revision {revision} not found: {e:#?}");
+            return not_found_response(Json(OperationResult {
+                result: "revision not found",
+            }))
+            .into_response();
+        }
+    };
+    revision.restore_into(&mut post);
+
+    let result = page_context
+        .storage
+        .write(move |s| {
+            let id = post.id;
+            s.upsert_post(post)?;
+            webmention::queue_post_links_logged(s, id);
+            Ok::<_, S::Err>(())
+        })
+        .await;
+    if result.is_ok() {
+        page_context.posts_changed();
+    }
+    updated_response(result).into_response()
+}
 
-    let result = storage.upsert_post(post);
+pub async fn serve_post_create<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Json(mut post): Json<Post>,
+) -> impl IntoResponse {
+    let result = page_context
+        .storage
+        .write(move |s| {
+            // Get next ID for the post
+            post.id = s.next_post_id()?;
+            let id = post.id;
+            s.upsert_post(post)?;
+            webmention::queue_post_links_logged(s, id);
+            Ok::<_, S::Err>(())
+        })
+        .await;
+    if result.is_ok() {
+        page_context.posts_changed();
+    }
+    if let Err(e) = &result {
+        tracing::error!("Failed to create post: {e:#?}");
+    }
     created_response(result)
 }
 
-pub async fn serve_post_update(
-    State(page_context): State<Arc<PageContext<'_>>>,
-    Json(post): Json<Post>,
+pub async fn serve_post_update<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Json(edit): Json<PostEdit>,
 ) -> impl IntoResponse {
-    let mut storage = page_context.storage.lock().await;
-    let result = storage.upsert_post(post);
+    let result = page_context
+        .storage
+        .write(move |s| {
+            let stored = s.get_post(edit.post.id).ok();
+            let post = edit.into_post(stored.as_ref());
+            let id = post.id;
+            s.upsert_post(post)?;
+            webmention::queue_post_links_logged(s, id);
+            Ok::<_, S::Err>(())
+        })
+        .await;
+    if result.is_ok() {
+        page_context.posts_changed();
+    }
     updated_response(result)
 }
 
-pub async fn serve_post_delete(
+pub async fn serve_post_delete<S: Storage + Send + 'static>(
     extract::Path(id): extract::Path<i64>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
-    let mut storage = page_context.storage.lock().await;
-    let result = storage.delete_post(id);
+    let result = page_context.storage.write(move |s| s.delete_post(id)).await;
+    if result.is_ok() {
+        page_context.posts_changed();
+    }
     updated_response(result)
 }
 
+/// Lists all tags with their presentation settings and number of posts.
+pub async fn serve_tags_admin_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    let tags = match page_context.storage.read(|s| s.get_tags()).await {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!("Failed to get tags: {e:#?}");
+            return make_json_response::<ApiResult<TagInfo>>(Err(e));
+        }
+    };
+    let tags_count = i32::try_from(tags.len()).unwrap_or(i32::MAX);
+
+    let result = ApiResult {
+        result: tags,
+        pages: 1,
+        page: 1,
+        count: tags_count,
+        status: "success",
+    };
+
+    make_json_response(Ok(result))
+}
+
+/// Sets title and description of the tag.
+pub async fn serve_tag_update<S: Storage + Send + 'static>(
+    extract::Path(tag): extract::Path<String>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Json(request): Json<TagUpdateRequest>,
+) -> impl IntoResponse {
+    let info = TagInfo {
+        tag,
+        title: request.title.map(|t| t.trim().to_owned()),
+        description: request.description.map(|d| d.trim().to_owned()),
+        posts: 0,
+    };
+    let result = page_context
+        .storage
+        .write(move |s| s.update_tag(&info))
+        .await;
+    match result {
+        Ok(0) => not_found_response(Json(OperationResult {
+            result: "tag not found",
+        }))
+        .into_response(),
+        result => updated_response(result).into_response(),
+    }
+}
+
+/// Renames the tag in all posts. Renaming into existing tag merges them.
+pub async fn serve_tag_rename<S: Storage + Send + 'static>(
+    extract::Path(tag): extract::Path<String>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Json(request): Json<TagRenameRequest>,
+) -> impl IntoResponse {
+    merge_tags(page_context, vec![tag], request.to)
+        .await
+        .into_response()
+}
+
+/// Replaces several tags with one in all posts.
+pub async fn serve_tags_merge<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Json(request): Json<TagsMergeRequest>,
+) -> impl IntoResponse {
+    merge_tags(page_context, request.tags, request.into)
+        .await
+        .into_response()
+}
+
+/// Deletes tags no post has.
+pub async fn serve_orphan_tags_delete<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    let result = page_context.storage.write(|s| s.delete_orphan_tags()).await;
+    make_json_response(result)
+}
+
+async fn merge_tags<S: Storage + Send + 'static>(
+    page_context: Arc<PageContext<'_, S>>,
+    tags: Vec<String>,
+    into: String,
+) -> impl IntoResponse {
+    let into = into.trim().to_owned();
+    if into.is_empty() || tags.is_empty() {
+        return bad_request_error_response(Json(OperationResult {
+            result: "tags to merge and the tag to merge into must be set",
+        }))
+        .into_response();
+    }
+
+    let result = page_context
+        .storage
+        .write(move |s| {
+            if let Some(missing) = tags.iter().find(|t| s.get_tag(t).is_err()) {
+                return Ok(Err(missing.clone()));
+            }
+            s.merge_tags(&tags, &into).map(Ok)
+        })
+        .await;
+    if matches!(result, Ok(Ok(_))) {
+        page_context.posts_changed();
+    }
+    match result {
+        Ok(Err(missing)) => {
+            tracing::error!("Tag '{missing}' not found");
+            not_found_response(Json(OperationResult {
+                result: "tag not found",
+            }))
+            .into_response()
+        }
+        result => updated_response(result).into_response(),
+    }
+}
+
 pub async fn redirect_to_real_document(
     extract::Path(path): extract::Path<String>,
 ) -> impl IntoResponse {
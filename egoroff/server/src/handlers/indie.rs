@@ -1,93 +1,237 @@
 use super::*;
 
+use anyhow::Context;
 use axum::extract::Form;
 use axum_extra::{
     TypedHeader,
     headers::{Authorization, authorization::Bearer},
 };
 use chrono::{TimeDelta, Utc};
+use kernel::domain::{ApiResult, IndieToken};
+use oauth2::CsrfToken;
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+use url::Url;
+use uuid::Uuid;
 
 use crate::{
     domain::PageContext,
     indie::{
-        Claims, IndieQuery, ME, SCOPES, Token, TokenRequest, TokenValidationResult, generate_jwt,
-        read_from_client, validate_jwt,
+        AuthorizationCode, Claims, ClientMetadata, IndieQuery, IntrospectionRequest,
+        IntrospectionResult, ME, Token, TokenRequest, TokenValidationResult, code_challenge,
+        discover_client, generate_jwt, is_token_active, scope_description, supported_scopes,
+        validate_jwt,
     },
 };
 use axum::http::header::LOCATION;
 
-pub async fn serve_auth(
-    Query(query): Query<IndieQuery>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+use super::template::IndieConsent;
+
+/// Session key of the authorization request waiting for admin consent
+const CONSENT_KEY: &str = "indie_consent";
+
+/// Authorization request validated and shown on consent page. It's kept in the session
+/// so that approval issues the code exactly for what the admin has seen.
+#[derive(Serialize, Deserialize)]
+struct PendingConsent {
+    csrf_token: String,
+    query: IndieQuery,
+}
+
+/// Consent form posted by the admin
+#[derive(Deserialize)]
+pub struct ConsentForm {
+    #[serde(default)]
+    pub csrf_token: String,
+}
+
+/// Shows consent page of `IndieAuth` authorization request to the logged in admin
+pub async fn serve_auth(session: Session, Query(query): Query<IndieQuery>) -> impl IntoResponse {
+    let client = match discover_authorization_client(&query).await {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Invalid authorization request: {e:#}");
+            return bad_request_error_response(e.to_string());
+        }
+    };
+
+    let redirect_uri = query.redirect_uri.as_deref().unwrap_or_default();
+    let state = query.state.as_deref().unwrap_or_default();
+    let scope = query
+        .scope
+        .as_deref()
+        .and_then(supported_scopes)
+        .unwrap_or_default();
+    let scopes = scope
+        .split_whitespace()
+        .map(|s| (s, scope_description(s)))
+        .collect();
+
+    let Some(deny_uri) = redirect_with_query(
+        redirect_uri,
+        &[("error", "access_denied"), ("state", state)],
+    ) else {
+        return bad_request_error_response(Body::empty());
+    };
+
+    let csrf_token = CsrfToken::new_random().secret().clone();
+    let client_id = client.client_id.as_str();
+    let page = IndieConsent {
+        title: "Авторизация приложения",
+        client_id,
+        client_name: client.metadata.client_name.as_deref().unwrap_or(client_id),
+        client_uri: client.metadata.client_uri.as_deref().unwrap_or(client_id),
+        logo_uri: client.metadata.logo_uri.as_deref(),
+        scopes,
+        redirect_uri,
+        csrf_token: &csrf_token,
+        deny_uri: &deny_uri,
+        year: get_year(),
+        ..Default::default()
+    };
+    let page = page.into_response();
+
+    let pending = PendingConsent {
+        csrf_token: csrf_token.clone(),
+        query,
+    };
+    if let Err(e) = session.insert(CONSENT_KEY, pending).await {
+        tracing::error!("Failed to keep authorization request in session: {e:#?}");
+        return internal_server_error_response(e.to_string());
+    }
+    success_response(page)
+}
+
+/// Issues authorization code after admin approved the request on consent page
+pub async fn serve_auth_approve<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    session: Session,
+    Form(form): Form<ConsentForm>,
 ) -> impl IntoResponse {
+    // Request is taken from the session so the consent can be given only once
+    let pending = match session.remove::<PendingConsent>(CONSENT_KEY).await {
+        Ok(Some(p)) => p,
+        Ok(None) => {
+            tracing::error!("No authorization request waiting for consent");
+            return bad_request_error_response("no authorization request".to_string());
+        }
+        Err(e) => {
+            tracing::error!("Failed to read authorization request from session: {e:#?}");
+            return internal_server_error_response(e.to_string());
+        }
+    };
+    if form.csrf_token.is_empty() || form.csrf_token != pending.csrf_token {
+        tracing::error!("Consent form CSRF token mismatch");
+        return bad_request_error_response("invalid CSRF token".to_string());
+    }
+    let query = pending.query;
+
+    let code_challenge = match code_challenge(&query) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("{e}");
+            return bad_request_error_response(e.to_string());
+        }
+    };
     let private_key_path = PathBuf::from(&page_context.certs_path).join("egoroffspbrupri.pem");
 
     let redirect = query.redirect_uri.unwrap_or_default();
     let client_id = query.client_id.unwrap_or_default();
+    // Unsupported scopes are silently dropped so the client sees what was actually granted
+    let scope = query.scope.as_deref().and_then(supported_scopes);
+    let state = query.state.unwrap_or_default();
 
-    if redirect.starts_with(&client_id) {
-        let now = Utc::now();
-        let issued = now.timestamp() as usize;
-        let Some(lifetime_minutes) = TimeDelta::try_minutes(10) else {
-            return bad_request_error_response(Body::empty());
-        };
-        let Some(expired) = now.checked_add_signed(lifetime_minutes) else {
-            return bad_request_error_response(Body::empty());
-        };
-        let expired = expired.timestamp() as usize;
-        let claims = Claims {
-            client_id,
-            redirect_uri: Some(redirect.clone()),
-            aud: None,
-            exp: Some(expired),
-            iat: Some(issued),
-            iss: Some(ME.to_string()),
-            nbf: None,
-            sub: None,
-            jti: None,
-        };
-
-        let Some(state) = query.state else {
-            tracing::error!("No state extracted from query");
-            return bad_request_error_response(Body::empty());
-        };
-
-        // generate token and if success redirect to uri specified
-        match generate_jwt(&claims, private_key_path) {
-            Ok(token) => {
-                let q = format!("state={state}&code={token}");
-                let Some(mut to) = Resource::new(&redirect) else {
-                    return bad_request_error_response(Body::empty());
-                };
-                let mut c = page_context.cache.lock().await;
-                c.insert(token);
-                to.append_query(&q);
-                let to = to.to_string();
-                // redirect to uri with state and new token (302 Found)
-                (StatusCode::FOUND, [(LOCATION, to)].into_response())
-            }
-            Err(e) => {
-                tracing::error!("generate jwt token error: {e:#?}");
-                bad_request_error_response(e.to_string())
-            }
+    let now = Utc::now();
+    let issued = now.timestamp() as usize;
+    let Some(lifetime_minutes) = TimeDelta::try_minutes(10) else {
+        return bad_request_error_response(Body::empty());
+    };
+    let Some(expires) = now.checked_add_signed(lifetime_minutes) else {
+        return bad_request_error_response(Body::empty());
+    };
+    let expired = expires.timestamp() as usize;
+    let claims = Claims {
+        client_id: client_id.clone(),
+        redirect_uri: Some(redirect.clone()),
+        aud: None,
+        exp: Some(expired),
+        iat: Some(issued),
+        iss: Some(ME.to_string()),
+        nbf: None,
+        sub: None,
+        // Codes are never put into the token store so they cannot be used as access tokens
+        jti: Some(Uuid::new_v4().to_string()),
+        scope,
+    };
+
+    // generate token and if success redirect to uri specified
+    match generate_jwt(&claims, private_key_path) {
+        Ok(token) => {
+            let Some(to) = redirect_with_query(
+                &redirect,
+                &[("state", state.as_str()), ("code", token.as_str())],
+            ) else {
+                return bad_request_error_response(Body::empty());
+            };
+            let authorization = AuthorizationCode {
+                client_id,
+                redirect_uri: redirect.clone(),
+                code_challenge,
+                expires,
+            };
+            let mut codes = page_context.auth_codes.lock().await;
+            codes.insert(token, authorization, now);
+            // redirect to uri with state and new token (302 Found)
+            (StatusCode::FOUND, [(LOCATION, to)].into_response())
         }
-    } else if let Some(u) = Resource::new(&client_id) {
-        match read_from_client(&u.to_string()).await {
-            Ok(resp) => {
-                tracing::info!("Response from client: {resp}");
-                success_response(Body::empty())
-            }
-            Err(e) => {
-                tracing::error!("Error reading data from client: {e:#?}");
-                bad_request_error_response(Body::empty())
-            }
+        Err(e) => {
+            tracing::error!("generate jwt token error: {e:#?}");
+            bad_request_error_response(e.to_string())
         }
-    } else {
-        tracing::error!("invalid client ID: {client_id}");
-        bad_request_error_response(Body::empty())
     }
 }
 
+/// Appends URL encoded parameters to the redirect URI keeping its own query.
+fn redirect_with_query(redirect_uri: &str, params: &[(&str, &str)]) -> Option<String> {
+    let mut url = Url::parse(redirect_uri).ok()?;
+    url.query_pairs_mut().extend_pairs(params);
+    Some(url.into())
+}
+
+struct AuthorizationClient {
+    client_id: Url,
+    metadata: ClientMetadata,
+}
+
+/// Validates authorization request parameters and discovers the client.
+/// Client that cannot be fetched may still use redirect URIs on its own host.
+async fn discover_authorization_client(query: &IndieQuery) -> Result<AuthorizationClient> {
+    code_challenge(query)?;
+    let client_id =
+        Url::parse(query.client_id.as_deref().unwrap_or_default()).context("invalid client ID")?;
+    let Some(redirect_uri) = query.redirect_uri.as_deref() else {
+        anyhow::bail!("no redirect URI");
+    };
+    if query.state.is_none() {
+        anyhow::bail!("no state");
+    }
+
+    let metadata = match discover_client(&client_id).await {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::warn!("Error reading data from client {client_id}: {e:#}");
+            ClientMetadata::default()
+        }
+    };
+    if !metadata.is_redirect_allowed(&client_id, redirect_uri) {
+        anyhow::bail!("redirect URI {redirect_uri} is not allowed for client {client_id}");
+    }
+    Ok(AuthorizationClient {
+        client_id,
+        metadata,
+    })
+}
+
 /// Generates Indie authorization JWT token
 #[utoipa::path(
     post,
@@ -95,26 +239,47 @@ pub async fn serve_auth(
     request_body(content = TokenRequest, content_type = "application/x-www-form-urlencoded"),
     responses(
         (status = 200, description = "Configuration read successfully", body = Token),
+        (status = 400, description = "Authorization code is unknown, reused, expired or does not match the request", body = String),
         (status = 401, description = "Claims validation failed", body = String),
     ),
     tag = "indie",
 )]
-pub async fn serve_token_generate(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_token_generate<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     Form(req): Form<TokenRequest>,
 ) -> impl IntoResponse {
+    if req.action.as_deref() == Some("revoke") {
+        return revoke_token(&page_context, req.token.as_deref().unwrap_or_default()).await;
+    }
+
     let public_key_path = PathBuf::from(&page_context.certs_path).join("egoroffspbrupub.pem");
 
-    match validate_jwt(&req.code, public_key_path) {
-        Ok(_claims) => {
-            let mut cache = page_context.cache.lock().await;
-            cache.remove(&req.code);
+    let scope = match validate_jwt(&req.code, public_key_path) {
+        Ok(code_claims) => {
+            let mut codes = page_context.auth_codes.lock().await;
+            let redeemed = codes.redeem(
+                &req.code,
+                &req.client_id,
+                &req.redirect_uri,
+                req.code_verifier.as_deref(),
+                Utc::now(),
+            );
+            if let Err(e) = redeemed {
+                tracing::error!("authorization code exchange error: {e}");
+                return bad_request_error_response(e.to_string());
+            }
+            code_claims.scope
         }
         Err(e) => {
             tracing::error!("validate jwt token error: {e:#?}");
             return unauthorized_response(e.to_string());
         }
-    }
+    };
+    // Code issued without scope may only be used for authentication, not for access token
+    let Some(scope) = scope else {
+        tracing::error!("Authorization code has no scope");
+        return bad_request_error_response("no scope was authorized".to_string());
+    };
 
     let client_id = req.client_id;
     let redirect_uri = req.redirect_uri;
@@ -124,9 +289,11 @@ pub async fn serve_token_generate(
     let Some(lifetime) = TimeDelta::try_days(90) else {
         return internal_server_error_response("invalid Indie token lifetime".to_string());
     };
-    let expired = now
-        .checked_add_signed(lifetime)
-        .map(|dt| dt.timestamp() as usize);
+    let Some(expires) = now.checked_add_signed(lifetime) else {
+        return internal_server_error_response("invalid Indie token lifetime".to_string());
+    };
+    let expired = Some(expires.timestamp() as usize);
+    let jti = Uuid::new_v4().to_string();
 
     let claims = Claims {
         client_id,
@@ -137,16 +304,33 @@ pub async fn serve_token_generate(
         iss: Some(ME.to_string()),
         nbf: None,
         sub: None,
-        jti: None,
+        jti: Some(jti.clone()),
+        scope: Some(scope.clone()),
     };
 
     let private_key_path = PathBuf::from(&page_context.certs_path).join("egoroffspbrupri.pem");
     match generate_jwt(&claims, private_key_path) {
         Ok(token) => {
+            let issued_token = IndieToken {
+                jti,
+                client_id: claims.client_id,
+                scope: scope.clone(),
+                issued: now,
+                expires,
+                revoked: None,
+            };
+            let inserted = page_context
+                .storage
+                .write(move |s| s.insert_token(&issued_token))
+                .await;
+            if let Err(e) = inserted {
+                tracing::error!("Failed to store token: {e:#?}");
+                return internal_server_error_response(e.to_string());
+            }
             let t = Token {
                 access_token: token,
                 token_type: "Bearer".to_string(),
-                scope: SCOPES.to_string(),
+                scope,
                 me: ME.to_string(),
             };
             success_response(Json(t))
@@ -172,14 +356,18 @@ pub async fn serve_token_generate(
         ("authorization" = [])
     )
 )]
-pub async fn serve_token_validate(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_token_validate<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
 ) -> impl IntoResponse {
     let public_key_path = PathBuf::from(&page_context.certs_path).join("egoroffspbrupub.pem");
 
     match validate_jwt(authorization.token(), public_key_path) {
         Ok(claims) => {
+            if !is_active(&page_context, &claims).await {
+                return unauthorized_response("token revoked".to_string());
+            }
+            let scope = claims.granted_scope().to_string();
             let Some(me) = claims.iss else {
                 return unauthorized_response("no iss".to_string());
             };
@@ -187,7 +375,7 @@ pub async fn serve_token_validate(
             let response = TokenValidationResult {
                 me,
                 client_id: claims.client_id,
-                scope: SCOPES.to_string(),
+                scope,
             };
             success_response(Json(response))
         }
@@ -197,3 +385,151 @@ pub async fn serve_token_validate(
         }
     }
 }
+
+/// Checks token revocation. Storage errors make token inactive.
+async fn is_active<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    claims: &Claims,
+) -> bool {
+    let claims = claims.clone();
+    page_context
+        .storage
+        .read(move |s| anyhow::Ok(is_token_active(s, &claims)))
+        .await
+        .unwrap_or(false)
+}
+
+/// Revokes token. Responds with success even if the token is invalid or unknown (RFC 7009).
+async fn revoke_token<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    token: &str,
+) -> (StatusCode, Response) {
+    let public_key_path = PathBuf::from(&page_context.certs_path).join("egoroffspbrupub.pem");
+
+    let jti = match validate_jwt(token, public_key_path) {
+        Ok(claims) => claims.jti,
+        Err(e) => {
+            tracing::info!("revoking invalid token: {e:#?}");
+            None
+        }
+    };
+    if let Some(jti) = jti {
+        let revoked = page_context
+            .storage
+            .write(move |s| s.revoke_token(&jti))
+            .await;
+        if let Err(e) = revoked {
+            tracing::error!("Failed to revoke token: {e:#?}");
+            return internal_server_error_response(e.to_string());
+        }
+    }
+    success_response(Body::empty())
+}
+
+/// Introspects Indie authorization JWT token (RFC 7662)
+#[utoipa::path(
+    post,
+    path = "/token/introspect",
+    request_body(content = IntrospectionRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token introspected successfully", body = IntrospectionResult),
+        (status = 401, description = "Request is not authorized"),
+    ),
+    tag = "indie",
+    security(
+        ("authorization" = [])
+    )
+)]
+pub async fn serve_token_introspect<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Form(req): Form<IntrospectionRequest>,
+) -> impl IntoResponse {
+    let public_key_path = PathBuf::from(&page_context.certs_path).join("egoroffspbrupub.pem");
+
+    let claims = match validate_jwt(&req.token, public_key_path) {
+        Ok(claims) => claims,
+        Err(e) => {
+            tracing::info!("introspecting invalid token: {e:#?}");
+            return success_response(Json(IntrospectionResult::default()));
+        }
+    };
+    if !is_active(&page_context, &claims).await {
+        return success_response(Json(IntrospectionResult::default()));
+    }
+
+    let result = IntrospectionResult {
+        active: true,
+        scope: Some(claims.granted_scope().to_string()),
+        me: claims.iss,
+        client_id: Some(claims.client_id),
+        exp: claims.exp,
+        iat: claims.iat,
+    };
+    success_response(Json(result))
+}
+
+#[derive(Deserialize)]
+pub struct TokensRequest {
+    pub client_id: Option<String>,
+}
+
+pub async fn serve_tokens_admin_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Query(request): Query<TokensRequest>,
+) -> impl IntoResponse {
+    let tokens = page_context
+        .storage
+        .read(move |s| s.get_active_tokens(request.client_id.as_deref()))
+        .await;
+    let tokens = match tokens {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!("Failed to get tokens: {e:#?}");
+            return make_json_response::<ApiResult<IndieToken>>(Err(e));
+        }
+    };
+    let tokens_count = i32::try_from(tokens.len()).unwrap_or(i32::MAX);
+
+    let result = ApiResult {
+        result: tokens,
+        pages: 1,
+        page: 1,
+        count: tokens_count,
+        status: "success",
+    };
+
+    make_json_response(Ok(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::redirect_with_query;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        "https://app.example/callback",
+        "a&b=c#d",
+        Some("https://app.example/callback?error=access_denied&state=a%26b%3Dc%23d")
+    )]
+    #[case(
+        "https://app.example/callback?x=1",
+        "s",
+        Some("https://app.example/callback?x=1&error=access_denied&state=s")
+    )]
+    #[case("not a url", "s", None)]
+    fn redirect_with_query_tests(
+        #[case] redirect_uri: &str,
+        #[case] state: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        // Arrange / Act
+        let actual = redirect_with_query(
+            redirect_uri,
+            &[("error", "access_denied"), ("state", state)],
+        );
+
+        // Assert
+        assert_eq!(expected.map(str::to_string), actual);
+    }
+}
@@ -7,8 +7,12 @@ use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
-    indie::ME,
-    micropub::{MicropubConfig, MicropubForm, MicropubFormError, MicropubSource, parse_post_url},
+    indie::{Claims, ME},
+    micropub::{
+        MicropubAction, MicropubConfig, MicropubError, MicropubForm, MicropubFormError,
+        MicropubSource, MicropubUpdate, resolve_post_id,
+    },
+    webmention,
 };
 
 use super::*;
@@ -57,9 +61,9 @@ pub struct MediaResponse {
         ("authorization" = [])
     )
 )]
-pub async fn serve_index_get(
+pub async fn serve_index_get<S: Storage + Send + 'static>(
     Query(query): Query<MicropubRequest>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
     if let Some(q) = query.q {
         let media_endpoint = Some(format!("{ME}micropub/media"));
@@ -83,15 +87,18 @@ pub async fn serve_index_get(
                         "url query parameter is required for source",
                     );
                 };
-                let Some(post_id) = parse_post_url(ME, &post_url) else {
-                    return bad_request_error_response("invalid post url");
-                };
-
-                let storage = page_context.storage.lock().await;
-                let post = match storage.get_post(post_id) {
+                let post = page_context
+                    .storage
+                    .read(move |s| {
+                        let post_id = resolve_post_id(s, &post_url)
+                            .ok_or_else(|| anyhow::anyhow!("invalid post url {post_url}"))?;
+                        s.get_post(post_id).map_err(anyhow::Error::from)
+                    })
+                    .await;
+                let post = match post {
                     Ok(post) => post,
                     Err(e) => {
-                        tracing::error!("micropub source post {post_id} not found: {e:#?}");
+                        tracing::error!("micropub source post not found: {e:#?}");
                         return not_found_response("post not found");
                     }
                 };
@@ -120,15 +127,17 @@ pub async fn serve_index_get(
     }
 }
 
-/// Tries to create a new Post or fails with 400 error in case of invalid request.
+/// Creates a new post, updates, deletes or undeletes existing one depending on `action`.
 #[utoipa::path(
     post,
     path = "/micropub/",
     request_body(content = String, description = "Post content", content_type = "application/json"),
     responses(
         (status = 201, description = "Post created successfully"),
-        (status = 400, description = "Invalid request syntax", body = MicropubFormError),
+        (status = 204, description = "Post updated, deleted or undeleted successfully"),
+        (status = 400, description = "Invalid request syntax", body = MicropubError),
         (status = 401, description = "Unauthorized to create post"),
+        (status = 403, description = "Token has no scope required by the action", body = MicropubError),
         (status = 500, description = "Server error", body = String),
     ),
     tag = "micropub",
@@ -136,44 +145,150 @@ pub async fn serve_index_get(
         ("authorization" = []),
     )
 )]
-pub async fn serve_index_post(
+pub async fn serve_index_post<S: Storage + Send + 'static>(
     TypedHeader(content_type): TypedHeader<ContentType>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Extension(claims): Extension<Claims>,
     body: Bytes,
 ) -> impl IntoResponse {
     tracing::info!("content type header: {content_type}");
-    let form = if content_type
+    let action = if content_type
         .to_string()
         .eq_ignore_ascii_case("application/json")
     {
-        MicropubForm::from_json_bytes(&body.slice(..))
+        MicropubAction::from_json_bytes(&body.slice(..))
     } else {
         // x-www-form-urlencoded
-        MicropubForm::from_form_bytes(&body.slice(..))
+        MicropubAction::from_form_bytes(&body.slice(..))
     };
-    let form = match form {
-        Ok(f) => f,
-        Err(e) => {
-            return bad_request_error_response(e.to_string());
-        }
+    let action = match action {
+        Ok(a) => a,
+        Err(e) => return micropub_error_response(&e),
     };
 
-    let mut storage = page_context.storage.lock().await;
-    let post_id = match storage.next_post_id() {
-        Ok(id) => id,
-        Err(e) => return internal_server_error_response(e.to_string()),
+    let scope = action.scope();
+    if !claims.has_scope(scope) {
+        return micropub_error_response(&MicropubFormError::InsufficientScope(scope.into()));
+    }
+
+    let response = match action {
+        MicropubAction::Create(form) => create_post(&page_context, &form).await,
+        MicropubAction::Update(update) => update_post(&page_context, &update).await,
+        MicropubAction::Delete(url) => delete_post(&page_context, &url).await,
+        MicropubAction::Undelete(url) => undelete_post(&page_context, &url).await,
     };
-    tracing::info!("content type: {:?}", form.content_type);
-    let post = form.to_post(post_id);
-    if let Err(e) = storage.upsert_post(post) {
-        return internal_server_error_response(e.to_string());
+    // Every successful action changes some post so cached posts navigation is no longer valid.
+    if response.0.is_success() {
+        page_context.posts_changed();
     }
+    response
+}
+
+async fn create_post<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    form: &MicropubForm,
+) -> (StatusCode, Response) {
+    tracing::info!("content type: {:?}", form.content_type);
+    let form = form.clone();
+    let created = page_context
+        .storage
+        .write(move |s| {
+            let post_id = s.next_post_id()?;
+            s.upsert_post(form.to_post(post_id))?;
+            webmention::queue_post_links_logged(s, post_id);
+            s.get_post(post_id)
+        })
+        .await;
+    let post = match created {
+        Ok(post) => post,
+        Err(e) => return internal_server_error_response(e.to_string()),
+    };
     (
         StatusCode::CREATED,
-        [(http::header::LOCATION, format!("{ME}blog/{post_id}.html"))].into_response(),
+        [(http::header::LOCATION, format!("{ME}{}", post.path()))].into_response(),
     )
 }
 
+async fn update_post<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    update: &MicropubUpdate,
+) -> (StatusCode, Response) {
+    let update = update.clone();
+    let updated = page_context
+        .storage
+        .write(move |s| {
+            let not_found = || MicropubFormError::PostNotFound(update.url.clone());
+            let Some(post_id) = resolve_post_id(s, &update.url) else {
+                return Ok(Err(not_found()));
+            };
+            let Ok(mut post) = s.get_post(post_id) else {
+                return Ok(Err(not_found()));
+            };
+            if let Err(e) = update.apply(&mut post) {
+                return Ok(Err(e));
+            }
+            s.upsert_post(post)?;
+            webmention::queue_post_links_logged(s, post_id);
+            Ok::<_, S::Err>(Ok(()))
+        })
+        .await;
+    match updated {
+        Ok(Ok(())) => (StatusCode::NO_CONTENT, Body::empty().into_response()),
+        Ok(Err(e)) => micropub_error_response(&e),
+        Err(e) => internal_server_error_response(e.to_string()),
+    }
+}
+
+async fn delete_post<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    url: &str,
+) -> (StatusCode, Response) {
+    let post_url = url.to_owned();
+    let deleted = page_context
+        .storage
+        .write(move |s| match resolve_post_id(s, &post_url) {
+            Some(post_id) => s.delete_post(post_id),
+            None => Ok(0),
+        })
+        .await;
+    match deleted {
+        Ok(0) => micropub_error_response(&MicropubFormError::PostNotFound(url.to_string())),
+        Ok(_) => (StatusCode::NO_CONTENT, Body::empty().into_response()),
+        Err(e) => internal_server_error_response(e.to_string()),
+    }
+}
+
+async fn undelete_post<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    url: &str,
+) -> (StatusCode, Response) {
+    let post_url = url.to_owned();
+    let restored = page_context
+        .storage
+        .write(move |s| {
+            let Some(post) =
+                resolve_post_id(s, &post_url).and_then(|post_id| s.get_deleted_post(post_id).ok())
+            else {
+                return Ok(false);
+            };
+            let post_id = post.id;
+            s.upsert_post(post)?;
+            webmention::queue_post_links_logged(s, post_id);
+            Ok::<_, S::Err>(true)
+        })
+        .await;
+    match restored {
+        Ok(true) => (StatusCode::NO_CONTENT, Body::empty().into_response()),
+        Ok(false) => micropub_error_response(&MicropubFormError::PostNotFound(url.to_string())),
+        Err(e) => internal_server_error_response(e.to_string()),
+    }
+}
+
+fn micropub_error_response(e: &MicropubFormError) -> (StatusCode, Response) {
+    tracing::error!("micropub request error: {e}");
+    (e.status(), Json(e.to_body()).into_response())
+}
+
 /// Tries to create a new media or fails with 400 error in case of invalid request.
 #[utoipa::path(
     post,
@@ -190,9 +305,9 @@ pub async fn serve_index_post(
         ("authorization" = []),
     )
 )]
-pub async fn serve_media_endpoint_post(
+pub async fn serve_media_endpoint_post<S: Storage + Send + 'static>(
     TypedHeader(content_type): TypedHeader<ContentType>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     mut multipart: Multipart,
 ) -> impl IntoResponse {
     tracing::info!("content type header: {content_type}");
@@ -283,8 +398,8 @@ pub async fn serve_media_endpoint_post(
         ("authorization" = []),
     )
 )]
-pub async fn serve_media_endpoint_get(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_media_endpoint_get<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     Query(req): Query<MicropubRequest>,
 ) -> impl IntoResponse {
     if let Some(q) = req.q {
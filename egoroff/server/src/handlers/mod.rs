@@ -3,11 +3,10 @@
 
 use anyhow::Result;
 use axum::body::{Body, Bytes};
-use axum::response::Redirect;
 use axum::{
     Extension, Json,
     extract::{self, Query, State},
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
 use chrono::{Datelike, Utc};
@@ -16,9 +15,9 @@ use futures_util::StreamExt;
 use kernel::graph::SiteSection;
 use kernel::{
     archive,
-    converter::{markdown2html, xml2html},
+    converter::markdown2html,
     domain::{PostsRequest, Storage},
-    graph,
+    graph, highlight,
     resource::Resource,
 };
 use percent_encoding::percent_decode_str;
@@ -28,7 +27,7 @@ use std::{
     fs::File,
     io::{self, BufReader},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, LazyLock},
 };
 use tokio_util::io::StreamReader;
 
@@ -38,7 +37,6 @@ use serde::Serialize;
 
 use crate::domain::OperationResult;
 use crate::{
-    atom,
     body::{Binary, FileReply, Xml},
     domain::{BlogRequest, Error, Navigation, PageContext, Poster, Uri},
     sitemap,
@@ -56,11 +54,24 @@ pub mod micropub;
 pub mod portfolio;
 pub mod search;
 mod template;
+pub mod webmention;
+pub mod websub;
 
 #[derive(RustEmbed)]
 #[folder = "../../static/dist/css"]
 struct Css;
 
+/// Stylesheet of code highlighted when posts are rendered. It's generated
+/// from the highlighting theme instead of being built with the UI.
+const HIGHLIGHT_CSS: &str = "highlight.css";
+
+static HIGHLIGHT_STYLE: LazyLock<String> = LazyLock::new(|| {
+    highlight::css().unwrap_or_else(|e| {
+        tracing::error!("Failed to make highlighting stylesheet: {e:#}");
+        String::new()
+    })
+});
+
 #[derive(RustEmbed)]
 #[folder = "../../static/dist/js"]
 struct Js;
@@ -85,9 +96,13 @@ struct Static;
 #[exclude = "*.dtd"]
 struct Apache;
 
-pub async fn serve_index(State(page_context): State<Arc<PageContext<'_>>>) -> impl IntoResponse {
-    let storage = page_context.storage.lock().await;
-    let result = archive::get_small_posts(&storage, 5, None);
+pub async fn serve_index<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    let result = page_context
+        .storage
+        .read(|s| archive::get_small_posts(s, 5, None))
+        .await;
 
     let blog_posts = match result {
         Ok(r) => r,
@@ -122,7 +137,9 @@ pub async fn serve_index(State(page_context): State<Arc<PageContext<'_>>>) -> im
     }
 }
 
-pub async fn serve_search(State(page_context): State<Arc<PageContext<'_>>>) -> impl IntoResponse {
+pub async fn serve_search<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
     if let Some(section) = page_context.site_graph.get_section("search") {
         Search {
             html_class: "search",
@@ -139,36 +156,65 @@ pub async fn serve_search(State(page_context): State<Arc<PageContext<'_>>>) -> i
     }
 }
 
-pub async fn serve_sitemap(State(page_context): State<Arc<PageContext<'_>>>) -> impl IntoResponse {
-    let apache_documents = portfolio::read_apache_documents(&page_context.base_path);
+pub async fn serve_sitemap<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    let xml = read_sitemap_urls(&page_context)
+        .await
+        .and_then(|urls| sitemap::render(&urls));
+    match xml {
+        Ok(xml) => success_response(Xml(xml)),
+        Err(e) => sitemap_error_response(&e),
+    }
+}
 
-    let apache_documents = match apache_documents {
-        Ok(docs) => docs,
-        Err(e) => {
-            tracing::error!("{e:#?}");
-            let content = format!("<?xml version=\"1.0\"?><error>{e}</error>");
-            return internal_server_error_response(Xml(content));
-        }
+/// Serves numbered sitemap page like `1.xml` of sitemap index.
+pub async fn serve_sitemap_page<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    extract::Path(file): extract::Path<String>,
+) -> impl IntoResponse {
+    let Some(page) = file
+        .strip_suffix(".xml")
+        .and_then(|n| n.parse::<usize>().ok())
+    else {
+        return not_found_response(Xml(String::from("<?xml version=\"1.0\"?><error/>")));
     };
+    let xml = read_sitemap_urls(&page_context)
+        .await
+        .and_then(|urls| sitemap::render_page(&urls, page));
+    match xml {
+        Ok(Some(xml)) => success_response(Xml(xml)),
+        Ok(None) => not_found_response(Xml(String::from("<?xml version=\"1.0\"?><error/>"))),
+        Err(e) => sitemap_error_response(&e),
+    }
+}
 
-    let storage = page_context.storage.lock().await;
-    let post_ids = match storage.get_posts_ids() {
-        Ok(ids) => ids,
-        Err(e) => {
-            return internal_server_error_response(format!(
-                "<?xml version=\"1.0\"?><error>{e}</error>"
-            ));
-        }
-    };
-    let xml = match sitemap::make_site_map(apache_documents, post_ids) {
-        Ok(xml) => xml,
-        Err(e) => {
-            return internal_server_error_response(format!(
-                "<?xml version=\"1.0\"?><error>{e}</error>"
-            ));
-        }
-    };
-    success_response(Xml(xml))
+async fn read_sitemap_urls<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+) -> Result<Vec<sitemap::SitemapUrl>> {
+    let apache_documents = portfolio::read_apache_documents(&page_context.base_path)?;
+    let generation = page_context.sitemap_images.generation();
+    let (posts, archive) = page_context
+        .storage
+        .read(|s| {
+            let posts = archive::get_public_posts(s, i32::MAX, None)?;
+            let archive = archive::archive(s)?;
+            anyhow::Ok((posts.result, archive))
+        })
+        .await?;
+    Ok(sitemap::make_urls(
+        page_context.site_graph.uris(),
+        apache_documents,
+        &posts,
+        &archive,
+        &page_context.sitemap_images,
+        generation,
+    ))
+}
+
+fn sitemap_error_response(e: &anyhow::Error) -> (StatusCode, Response) {
+    tracing::error!("{e:#?}");
+    internal_server_error_response(Xml(format!("<?xml version=\"1.0\"?><error>{e}</error>")))
 }
 
 pub async fn serve_js(extract::Path(path): extract::Path<String>) -> impl IntoResponse {
@@ -189,8 +235,12 @@ pub async fn serve_root(extract::Path(path): extract::Path<String>) -> impl Into
 
 pub async fn serve_css(extract::Path(path): extract::Path<String>) -> impl IntoResponse {
     let path = path.as_str();
+    if path == HIGHLIGHT_CSS {
+        return success_response(Binary::new(HIGHLIGHT_STYLE.as_str(), HIGHLIGHT_CSS))
+            .into_response();
+    }
     let asset = Css::get(path);
-    get_embed(path, asset)
+    get_embed(path, asset).into_response()
 }
 
 pub async fn serve_img(extract::Path(path): extract::Path<String>) -> impl IntoResponse {
@@ -214,9 +264,9 @@ pub async fn serve_apache_images(extract::Path(path): extract::Path<String>) ->
     get_embed(path, asset)
 }
 
-pub async fn serve_storage(
+pub async fn serve_storage<S: Storage + Send + 'static>(
     extract::Path((bucket, path)): extract::Path<(String, String)>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
     let Some(mut resource) = Resource::new(&page_context.store_uri) else {
         tracing::error!("Invalid storage uri {}", page_context.store_uri);
@@ -287,6 +337,11 @@ fn bad_request_error_response<R: IntoResponse>(r: R) -> (StatusCode, Response) {
     (StatusCode::BAD_REQUEST, r.into_response())
 }
 
+/// makes HTTP (CONFLICT) response code 409
+fn conflict_response<R: IntoResponse>(r: R) -> (StatusCode, Response) {
+    (StatusCode::CONFLICT, r.into_response())
+}
+
 /// makes HTTP (UNAUTHORIZED) response code 401
 fn unauthorized_response<R: IntoResponse>(r: R) -> (StatusCode, Response) {
     (StatusCode::UNAUTHORIZED, r.into_response())
@@ -298,9 +353,9 @@ fn get_content_length(headers: &axum::http::HeaderMap) -> Option<i64> {
     val.parse().ok()
 }
 
-pub async fn serve_navigation(
+pub async fn serve_navigation<S: Storage + Send + 'static>(
     Query(query): Query<Uri>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
     let q = query.uri;
 
@@ -357,8 +412,13 @@ fn internal_server_error_page() -> Response {
     internal_server_error_response(error_page_response("500")).into_response()
 }
 
+/// Permanent redirect with 301 code that crawlers and feed readers understand best.
 fn redirect_response(new_path: &str) -> Response {
-    Redirect::permanent(new_path).into_response()
+    (
+        StatusCode::MOVED_PERMANENTLY,
+        [(header::LOCATION, new_path.to_owned())],
+    )
+        .into_response()
 }
 
 fn error_page_response(code: &str) -> Response {
@@ -27,7 +27,9 @@ pub struct StoredFile {
     pub size: u64,
 }
 
-pub async fn serve_index(State(page_context): State<Arc<PageContext<'_>>>) -> impl IntoResponse {
+pub async fn serve_index<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
     let Some(section) = page_context.site_graph.get_section("portfolio") else {
         return internal_server_error_page();
     };
@@ -56,8 +58,8 @@ pub async fn serve_index(State(page_context): State<Arc<PageContext<'_>>>) -> im
     }
 }
 
-pub async fn serve_apache_document(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_apache_document<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     extract::Path(path): extract::Path<String>,
 ) -> impl IntoResponse {
     let apache_documents = match read_apache_documents(&page_context.base_path) {
@@ -110,8 +112,8 @@ pub async fn serve_apache_document(
         (status = 200, description = "Get files successfully", body = ApiResult<FilesContainer>),
     ),
 )]
-pub async fn serve_downloadable_files(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_downloadable_files<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
     let downloads = read_downloads(page_context.clone()).await;
     if let Some(downloads) = downloads {
@@ -146,10 +148,10 @@ pub async fn redirect_to_real_document(
     Redirect::permanent(&new_path)
 }
 
-async fn read_downloads(page_context: Arc<PageContext<'_>>) -> Option<Vec<FilesContainer>> {
-    let storage = page_context.storage.lock().await;
-
-    let folders = storage.get_folders().ok()?;
+async fn read_downloads<S: Storage + Send + 'static>(
+    page_context: Arc<PageContext<'_, S>>,
+) -> Option<Vec<FilesContainer>> {
+    let folders = page_context.storage.read(|s| s.get_folders()).await.ok()?;
 
     let mut result = vec![];
     for f in folders {
@@ -169,19 +171,36 @@ async fn read_downloads(page_context: Arc<PageContext<'_>>) -> Option<Vec<FilesC
                 let files = r.json::<Vec<StoredFile>>().await;
                 match files {
                     Ok(files) => {
+                        // Storage is accessed only after files are received
+                        // so that slow file store doesn't hold a storage connection
+                        let ids: Vec<i64> = files.iter().map(|file| file.id).collect();
+                        let titles = page_context
+                            .storage
+                            .read(move |s| {
+                                let titles: HashMap<i64, String> = ids
+                                    .into_iter()
+                                    .filter_map(|id| match s.get_download(id) {
+                                        Ok(meta_info) => Some((id, meta_info.title)),
+                                        Err(e) => {
+                                            tracing::trace!("{e:#?}");
+                                            None
+                                        }
+                                    })
+                                    .collect();
+                                anyhow::Ok(titles)
+                            })
+                            .await
+                            .unwrap_or_default();
                         for file in files {
-                            match storage.get_download(file.id) {
-                                Ok(meta_info) => {
-                                    let downloadable = Downloadable {
-                                        title: meta_info.title,
-                                        path: format!("/storage/{}/{}", f.bucket, file.path),
-                                        filename: file.path,
-                                        size: file.size,
-                                        blake3_hash: file.blake3_hash,
-                                    };
-                                    container.files.push(downloadable);
-                                }
-                                Err(e) => tracing::trace!("{e:#?}"),
+                            if let Some(title) = titles.get(&file.id) {
+                                let downloadable = Downloadable {
+                                    title: title.clone(),
+                                    path: format!("/storage/{}/{}", f.bucket, file.path),
+                                    filename: file.path,
+                                    size: file.size,
+                                    blake3_hash: file.blake3_hash,
+                                };
+                                container.files.push(downloadable);
                             }
                         }
                     }
@@ -195,34 +214,45 @@ async fn read_downloads(page_context: Arc<PageContext<'_>>) -> Option<Vec<FilesC
     Some(result)
 }
 
-pub async fn serve_download_update(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_download_update<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     Json(download): Json<Download>,
 ) -> impl IntoResponse {
-    let mut storage = page_context.storage.lock().await;
-    let result = storage.upsert_download(download);
+    let result = page_context
+        .storage
+        .write(move |s| s.upsert_download(download))
+        .await;
     updated_response(result)
 }
 
-pub async fn serve_download_delete(
+pub async fn serve_download_delete<S: Storage + Send + 'static>(
     extract::Path(id): extract::Path<i64>,
-    State(page_context): State<Arc<PageContext<'_>>>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
 ) -> impl IntoResponse {
-    let mut storage = page_context.storage.lock().await;
-    let result = storage.delete_download(id);
+    let result = page_context
+        .storage
+        .write(move |s| s.delete_download(id))
+        .await;
     updated_response(result)
 }
 
-pub async fn serve_downloads_admin_api(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_downloads_admin_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
     Query(request): Query<DownloadsRequest>,
 ) -> impl IntoResponse {
     let page_size = 10;
     let page = request.page.unwrap_or(1);
-    let storage = page_context.storage.lock().await;
-
-    let total_downloads_count = match storage.count_downloads() {
-        Ok(c) => c,
+    let found = page_context
+        .storage
+        .read(move |s| {
+            let count = s.count_downloads()?;
+            let downloads = s.get_downloads(page_size, page_size * (page - 1))?;
+            anyhow::Ok((count, downloads))
+        })
+        .await;
+
+    let (total_downloads_count, downloads) = match found {
+        Ok(r) => r,
         Err(e) => {
             tracing::error!("{e:#?}");
             return internal_server_error_page().into_response();
@@ -231,14 +261,6 @@ pub async fn serve_downloads_admin_api(
 
     let pages_count = count_pages(total_downloads_count, page_size);
 
-    let downloads = match storage.get_downloads(page_size, page_size * (page - 1)) {
-        Ok(downloads) => downloads,
-        Err(e) => {
-            tracing::error!("{e:#?}");
-            return internal_server_error_page().into_response();
-        }
-    };
-
     let result = ApiResult {
         result: downloads,
         pages: pages_count,
@@ -1,6 +1,8 @@
 use core::fmt::NumBuffer;
+use std::time::Instant;
 
-use crate::indie::ME;
+use crate::{auth::AppUser, domain::SearchBackend, handlers::auth::AuthSession, indie::ME};
+use kernel::{converter::html2text, domain::SmallPost, search};
 use serde::Deserialize;
 use serde_json::Value;
 use url::Url;
@@ -9,6 +11,7 @@ use utoipa::IntoParams;
 use super::*;
 
 const GOOGLE_CUSTOM_SEARCH_URL: &str = "https://www.googleapis.com/customsearch/v1";
+const LOCAL_PAGE_SIZE: i32 = 10;
 
 #[derive(Debug, Deserialize, IntoParams)]
 pub struct SearchApiRequest {
@@ -18,7 +21,11 @@ pub struct SearchApiRequest {
     pub start: Option<u32>,
 }
 
-/// Proxies Google Custom Search so the API key stays server-side.
+/// Searches posts using Google Custom Search or the local full-text index.
+///
+/// Local index is used when configured, when Google search is not configured
+/// or fails, and always for admins so that private posts can be found too.
+/// Local results mimic Google response shape so the UI handles both.
 #[utoipa::path(
     get,
     path = "/api/v2/search/",
@@ -26,13 +33,14 @@ pub struct SearchApiRequest {
     responses(
         (status = 200, description = "Search completed successfully"),
         (status = 400, description = "Missing or empty query"),
-        (status = 500, description = "Search is not configured"),
+        (status = 500, description = "Search is not configured or failed"),
         (status = 502, description = "Upstream search provider error"),
     ),
     tag = "search",
 )]
-pub async fn serve_search_api(
-    State(page_context): State<Arc<PageContext<'_>>>,
+pub async fn serve_search_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    auth: AuthSession,
     Query(request): Query<SearchApiRequest>,
 ) -> impl IntoResponse {
     let Some(q) = request
@@ -43,7 +51,124 @@ pub async fn serve_search_api(
     else {
         return bad_request_error_response("q is required");
     };
+    let start = request.start.unwrap_or(1).max(1);
+    let is_admin = auth.user.as_ref().is_some_and(AppUser::is_admin);
+
+    let config = &page_context.site_config;
+    let google_configured = !config.search_api_key.is_empty() && !config.google_site_id.is_empty();
+
+    match config.search_backend {
+        SearchBackend::Local => local_search(&page_context, q, start, is_admin).await,
+        SearchBackend::Google => google_search(&page_context, q, start).await,
+        SearchBackend::Auto if is_admin || !google_configured => {
+            local_search(&page_context, q, start, is_admin).await
+        }
+        SearchBackend::Auto => {
+            let response = google_search(&page_context, q, start).await;
+            if response.0.is_success() {
+                response
+            } else {
+                tracing::warn!("Google search failed, falling back to local search");
+                local_search(&page_context, q, start, is_admin).await
+            }
+        }
+    }
+}
+
+/// Search result in the subset of Google Custom Search format used by the UI.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct LocalSearchResult {
+    kind: &'static str,
+    search_information: SearchInformation,
+    items: Vec<LocalSearchItem>,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct SearchInformation {
+    search_time: f64,
+    formatted_search_time: String,
+    total_results: String,
+    formatted_total_results: String,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct LocalSearchItem {
+    title: String,
+    html_title: String,
+    link: String,
+    display_link: String,
+    snippet: String,
+    html_snippet: String,
+    cache_id: String,
+    formatted_url: String,
+    html_formatted_url: String,
+}
+
+async fn local_search<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    q: &str,
+    start: u32,
+    include_private: bool,
+) -> (StatusCode, Response) {
+    let started = Instant::now();
+    let offset = i32::try_from(start - 1).unwrap_or(i32::MAX);
+
+    let query = q.to_owned();
+    let found = page_context
+        .storage
+        .read(move |s| {
+            let posts = s.search_posts(&query, LOCAL_PAGE_SIZE, offset, include_private)?;
+            let total = s.count_search_results(&query, include_private)?;
+            anyhow::Ok((posts, total))
+        })
+        .await;
+
+    let (posts, total) = match found {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::error!("local search error: {e:#?}");
+            return internal_server_error_response("search failed");
+        }
+    };
+
+    let elapsed = started.elapsed().as_secs_f64();
+    let result = LocalSearchResult {
+        kind: "customsearch#search",
+        search_information: SearchInformation {
+            search_time: elapsed,
+            formatted_search_time: format!("{elapsed:.2}"),
+            total_results: total.to_string(),
+            formatted_total_results: total.to_string(),
+        },
+        items: posts.into_iter().map(|p| to_search_item(p, q)).collect(),
+    };
+    success_response(Json(result))
+}
 
+fn to_search_item(post: SmallPost, q: &str) -> LocalSearchItem {
+    let link = format!("{ME}{}", post.path());
+    let display = link.trim_start_matches("https://").to_string();
+    LocalSearchItem {
+        snippet: html2text(&post.short_text).unwrap_or_default(),
+        html_snippet: post.short_text,
+        html_title: search::highlight(&post.title, q),
+        title: post.title,
+        display_link: display.split('/').next().unwrap_or_default().to_string(),
+        cache_id: post.id.to_string(),
+        formatted_url: display.clone(),
+        html_formatted_url: display,
+        link,
+    }
+}
+
+async fn google_search<S: Storage + Send + 'static>(
+    page_context: &PageContext<'_, S>,
+    q: &str,
+    start: u32,
+) -> (StatusCode, Response) {
     let key = page_context.site_config.search_api_key.as_str();
     let cx = page_context.site_config.google_site_id.as_str();
     if key.is_empty() || cx.is_empty() {
@@ -51,7 +176,6 @@ pub async fn serve_search_api(
         return internal_server_error_response("search is not configured");
     }
 
-    let start = request.start.unwrap_or(1).max(1);
     let Some(url) = google_search_url(key, cx, q, start) else {
         tracing::error!("failed to build Google Custom Search URL");
         return internal_server_error_response("search is not configured");
@@ -128,7 +252,8 @@ fn bad_gateway_response<R: IntoResponse>(r: R) -> (StatusCode, Response) {
 mod tests {
     #![allow(clippy::unwrap_in_result)]
     #![allow(clippy::unwrap_used)]
-    use super::google_search_url;
+    use super::{google_search_url, to_search_item};
+    use kernel::domain::SmallPost;
     use rstest::rstest;
 
     #[test]
@@ -181,4 +306,52 @@ mod tests {
             "API_KEY_HTTP_REFERRER_BLOCKED: Requests from referer <empty> are blocked."
         );
     }
+
+    #[test]
+    fn to_search_item_mimics_google_item() {
+        // Arrange
+        let post = SmallPost {
+            id: 42,
+            title: "Rust & C".to_string(),
+            short_text: "Пишем <b>программу</b>".to_string(),
+            ..Default::default()
+        };
+
+        // Act
+        let actual = to_search_item(post, "rust");
+
+        // Assert
+        assert_eq!(actual.link, "https://www.egoroff.spb.ru/blog/42.html");
+        assert_eq!(actual.display_link, "www.egoroff.spb.ru");
+        assert_eq!(actual.formatted_url, "www.egoroff.spb.ru/blog/42.html");
+        assert_eq!(actual.html_title, "<b>Rust</b> &amp; C");
+        assert_eq!(actual.title, "Rust & C");
+        assert_eq!(actual.html_snippet, "Пишем <b>программу</b>");
+        assert_eq!(actual.cache_id, "42");
+    }
+
+    #[test]
+    fn to_search_item_links_post_by_slug() {
+        // Arrange
+        let post = SmallPost {
+            id: 42,
+            title: "Rust".to_string(),
+            created: "2024-03-01T10:00:00Z".parse().unwrap(),
+            slug: Some("rust-intro".to_string()),
+            ..Default::default()
+        };
+
+        // Act
+        let actual = to_search_item(post, "rust");
+
+        // Assert
+        assert_eq!(
+            actual.link,
+            "https://www.egoroff.spb.ru/blog/2024/rust-intro"
+        );
+        assert_eq!(
+            actual.formatted_url,
+            "www.egoroff.spb.ru/blog/2024/rust-intro"
+        );
+    }
 }
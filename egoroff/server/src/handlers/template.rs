@@ -1,11 +1,18 @@
 use askama::Template;
 use axum::http::{self, StatusCode};
 use axum::response::{IntoResponse, Response};
-use kernel::domain::{Post, SmallPost};
+use kernel::converter::TocEntry;
+use kernel::domain::{Post, PostNavigation, SmallPost, TagInfo, Webmention};
 
-use crate::domain::{Apache, BlogRequest, Error, Poster};
+use crate::domain::{Apache, Error, Poster};
+
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; script-src 'self'; frame-ancestors 'self'; connect-src 'self'; img-src 'self' data: *.ggpht.com avatars.githubusercontent.com *.googleusercontent.com i.imgur.com; style-src 'self' 'unsafe-inline' fonts.googleapis.com; font-src 'self' fonts.googleapis.com fonts.gstatic.com;";
 
 fn text_html_respose<T: Template>(t: T) -> Response {
+    text_html_with_policy_respose(t, http::HeaderValue::from_static(CONTENT_SECURITY_POLICY))
+}
+
+fn text_html_with_policy_respose<T: Template>(t: T, policy: http::HeaderValue) -> Response {
     match t.render() {
         Ok(body) => {
             let headers = [
@@ -25,12 +32,7 @@ fn text_html_respose<T: Template>(t: T) -> Response {
                     http::header::X_FRAME_OPTIONS,
                     http::HeaderValue::from_static("sameorigin"),
                 ),
-                (
-                    http::header::CONTENT_SECURITY_POLICY,
-                    http::HeaderValue::from_static(
-                        "default-src 'none'; script-src 'self'; frame-ancestors 'self'; connect-src 'self'; img-src 'self' data: *.ggpht.com avatars.githubusercontent.com *.googleusercontent.com i.imgur.com; style-src 'self' 'unsafe-inline' fonts.googleapis.com; font-src 'self' fonts.googleapis.com fonts.gstatic.com;",
-                    ),
-                ),
+                (http::header::CONTENT_SECURITY_POLICY, policy),
                 (
                     http::header::REFERRER_POLICY,
                     http::HeaderValue::from_static("strict-origin-when-cross-origin"),
@@ -142,7 +144,10 @@ pub struct BlogIndex<'a> {
     pub keywords: &'a str,
     pub meta_description: &'a str,
     pub poster: &'a Poster<SmallPost>,
-    pub request: &'a BlogRequest,
+    /// Path pages links are built from.
+    pub base_path: &'a str,
+    /// The tag posts are filtered by.
+    pub tag: Option<&'a TagInfo>,
     pub year: u32,
 }
 
@@ -162,6 +167,13 @@ pub struct BlogPost<'a> {
     pub meta_description: String,
     pub main_post: &'a Post,
     pub content: &'a str,
+    /// Table of contents shown for long posts only.
+    pub toc: &'a [TocEntry],
+    pub navigation: &'a PostNavigation,
+    /// Approved likes, reposts and bookmarks.
+    pub reactions: &'a [Webmention],
+    /// Approved replies and mentions.
+    pub replies: &'a [Webmention],
     pub year: u32,
 }
 
@@ -225,8 +237,55 @@ impl IntoResponse for Admin<'_> {
     }
 }
 
+#[derive(Template, Default)]
+#[template(path = "consent.html")]
+pub struct IndieConsent<'a> {
+    pub html_class: &'a str,
+    pub title: &'a str,
+    pub title_path: &'a str,
+    pub keywords: &'a str,
+    pub meta_description: &'a str,
+    pub client_id: &'a str,
+    pub client_name: &'a str,
+    pub client_uri: &'a str,
+    pub logo_uri: Option<&'a str>,
+    /// Requested scopes together with their descriptions.
+    pub scopes: Vec<(&'a str, &'a str)>,
+    pub redirect_uri: &'a str,
+    /// Token tying the consent form to the admin session.
+    pub csrf_token: &'a str,
+    pub deny_uri: &'a str,
+    pub year: u32,
+}
+
+/// Client logo is loaded from the client site, so its origin is allowed on consent page only.
+impl IntoResponse for IndieConsent<'_> {
+    fn into_response(self) -> axum::response::Response {
+        match self.logo_uri.and_then(image_origin_policy) {
+            Some(policy) => text_html_with_policy_respose(self, policy),
+            None => text_html_respose(self),
+        }
+    }
+}
+
+/// Content security policy that allows images from the origin of the URL too.
+fn image_origin_policy(url: &str) -> Option<http::HeaderValue> {
+    let origin = url::Url::parse(url).ok()?.origin();
+    if !origin.is_tuple() {
+        return None;
+    }
+    let policy = CONTENT_SECURITY_POLICY.replacen(
+        "img-src ",
+        &format!("img-src {} ", origin.ascii_serialization()),
+        1,
+    );
+    http::HeaderValue::from_str(&policy).ok()
+}
+
 mod filters {
-    use kernel::typograph as kernel_typograph;
+    use kernel::{domain::PostsRequest, typograph as kernel_typograph};
+
+    use crate::domain::blog_index_path;
 
     #[askama::filter_fn]
     pub fn typograph<T: std::fmt::Display>(
@@ -239,4 +298,17 @@ mod filters {
             Err(_) => Err(::askama::Error::Fmt),
         }
     }
+
+    /// Percent-encoded path of the blog page listing posts with the tag.
+    #[askama::filter_fn]
+    pub fn tag_path<T: std::fmt::Display>(
+        tag: T,
+        _: &dyn askama::Values,
+    ) -> ::askama::Result<String> {
+        let request = PostsRequest {
+            tag: Some(tag.to_string()),
+            ..Default::default()
+        };
+        Ok(blog_index_path(&request))
+    }
 }
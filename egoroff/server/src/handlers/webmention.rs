@@ -0,0 +1,170 @@
+use axum::extract::Form;
+use chrono::Utc;
+use kernel::domain::{ApiResult, OutgoingWebmention, Webmention, WebmentionStatus};
+
+use crate::{
+    domain::{WebmentionModerationRequest, WebmentionsQuery},
+    micropub::resolve_post_id,
+    webmention::WebmentionRequest,
+};
+
+use super::*;
+
+/// Webmention endpoint. Valid requests are queued for asynchronous verification.
+pub async fn serve_webmention<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Form(request): Form<WebmentionRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = request.validate() {
+        return bad_request_error_response(e.to_string()).into_response();
+    }
+    let result = page_context
+        .storage
+        .write(move |s| {
+            let Some(post_id) = resolve_post_id(s, request.target.trim()) else {
+                return Ok(None);
+            };
+            match s.get_post(post_id) {
+                Ok(post) if post.is_public => {}
+                _ => return Ok(None),
+            }
+            let mention = Webmention {
+                source: request.source.trim().to_owned(),
+                target: request.target.trim().to_owned(),
+                post_id,
+                received: Utc::now(),
+                ..Default::default()
+            };
+            s.queue_webmention(&mention).map(Some)
+        })
+        .await;
+    match result {
+        Ok(Some(id)) => {
+            tracing::info!("webmention {id} queued");
+            (StatusCode::ACCEPTED, "webmention queued for verification").into_response()
+        }
+        Ok(None) => bad_request_error_response("target post not found").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to queue webmention: {e:#?}");
+            internal_server_error_response(e.to_string()).into_response()
+        }
+    }
+}
+
+/// Lists received webmentions newest first optionally filtered by status.
+pub async fn serve_webmentions_admin_api<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Query(query): Query<WebmentionsQuery>,
+) -> impl IntoResponse {
+    let mentions = match page_context
+        .storage
+        .read(move |s| s.get_webmentions(query.status))
+        .await
+    {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::error!("Failed to get webmentions: {e:#?}");
+            return make_json_response::<ApiResult<Webmention>>(Err(e));
+        }
+    };
+    let count = i32::try_from(mentions.len()).unwrap_or(i32::MAX);
+
+    let result = ApiResult {
+        result: mentions,
+        pages: 1,
+        page: 1,
+        count,
+        status: "success",
+    };
+
+    make_json_response(Ok(result))
+}
+
+/// Approves or rejects the webmention.
+pub async fn serve_webmention_moderate<S: Storage + Send + 'static>(
+    extract::Path(id): extract::Path<i64>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Json(request): Json<WebmentionModerationRequest>,
+) -> impl IntoResponse {
+    if !matches!(
+        request.status,
+        WebmentionStatus::Approved | WebmentionStatus::Rejected
+    ) {
+        return bad_request_error_response(Json(OperationResult {
+            result: "webmention can only be approved or rejected",
+        }))
+        .into_response();
+    }
+    let result = page_context
+        .storage
+        .write(move |s| {
+            let Ok(mut mention) = s.get_webmention(id) else {
+                return Ok(Moderation::NotFound);
+            };
+            if !mention.status.can_moderate_to(request.status) {
+                return Ok(Moderation::Conflict);
+            }
+            mention.status = request.status;
+            s.update_webmention(&mention).map(|_| Moderation::Updated)
+        })
+        .await;
+    match result {
+        Ok(Moderation::NotFound) => not_found_response(Json(OperationResult {
+            result: "webmention not found",
+        }))
+        .into_response(),
+        Ok(Moderation::Conflict) => conflict_response(Json(OperationResult {
+            result: "only verified webmentions can be moderated",
+        }))
+        .into_response(),
+        result => updated_response(result).into_response(),
+    }
+}
+
+/// Outcome of webmention moderation.
+enum Moderation {
+    NotFound,
+    /// Webmention status doesn't allow requested change.
+    Conflict,
+    Updated,
+}
+
+pub async fn serve_webmention_delete<S: Storage + Send + 'static>(
+    extract::Path(id): extract::Path<i64>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    let result = page_context
+        .storage
+        .write(move |s| s.delete_webmention(id))
+        .await;
+    updated_response(result)
+}
+
+/// Lists webmentions sent from the post with their delivery status.
+pub async fn serve_post_webmentions_admin_api<S: Storage + Send + 'static>(
+    extract::Path(id): extract::Path<i64>,
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+) -> impl IntoResponse {
+    let mentions = match page_context
+        .storage
+        .read(move |s| s.get_outgoing_webmentions(id))
+        .await
+    {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::error!("Failed to get post {id} webmentions: {e:#?}");
+            return make_json_response::<ApiResult<OutgoingWebmention>>(Err(e));
+        }
+    };
+    let count = i32::try_from(mentions.len()).unwrap_or(i32::MAX);
+
+    let result = ApiResult {
+        result: mentions,
+        pages: 1,
+        page: 1,
+        count,
+        status: "success",
+    };
+
+    make_json_response(Ok(result))
+}
@@ -0,0 +1,29 @@
+use axum::extract::Form;
+use chrono::Utc;
+
+use crate::websub::SubscriptionRequest;
+
+use super::*;
+
+/// `WebSub` hub endpoint. Subscriber intent is verified asynchronously.
+pub async fn serve_hub<S: Storage + Send + 'static>(
+    State(page_context): State<Arc<PageContext<'_, S>>>,
+    Form(request): Form<SubscriptionRequest>,
+) -> impl IntoResponse {
+    let intent = match request
+        .validate()
+        .and_then(|i| page_context.hub.check(&i).map(|()| i))
+    {
+        Ok(i) => i,
+        Err(e) => return bad_request_error_response(e.to_string()).into_response(),
+    };
+    let hub = page_context.hub.clone();
+    let storage = page_context.storage.clone();
+    tokio::spawn(async move {
+        let callback = intent.callback.clone();
+        if let Err(e) = hub.verify(&storage, intent, Utc::now()).await {
+            tracing::warn!("WebSub subscriber {callback} not verified: {e:#}");
+        }
+    });
+    (StatusCode::ACCEPTED, "subscription request accepted").into_response()
+}
@@ -1,12 +1,13 @@
 #![allow(clippy::module_name_repetitions)]
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs,
     marker::PhantomData,
-    net::{Ipv4Addr, Ipv6Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::Path,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{Context, Result, bail};
@@ -14,16 +15,33 @@ use axum::{
     body::HttpBody,
     http::{self, Request, Response},
 };
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
-use reqwest::Client;
+use kernel::{domain::Storage, microformats::parse_h_app};
+use oauth2::{PkceCodeChallenge, PkceCodeVerifier};
+use reqwest::{
+    Client,
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::Policy,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tower_http::validate_request::ValidateRequest;
+use tower_http::auth::{AsyncAuthorizeRequest, AsyncRequireAuthorizationLayer};
 use url::{Host, Url};
 use utoipa::ToSchema;
 
+use crate::{domain::Database, micropub::MicropubFormError};
+
 pub const ME: &str = "https://www.egoroff.spb.ru/";
-pub const SCOPES: &str = "create media delete";
+/// Scopes the server can grant.
+pub const SCOPES: &str = "create update delete media";
+/// Scopes granted to tokens issued before `scope` claim was introduced.
+const LEGACY_SCOPES: &str = "create media delete";
+/// Pages of other sites larger than this are not read.
+const MAX_PAGE_SIZE: usize = 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Our claims struct, it needs to derive `Serialize` and/or `Deserialize`
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -37,6 +55,39 @@ pub struct Claims {
     pub nbf: Option<usize>, // Optional. Not Before (as UTC timestamp)
     pub sub: Option<String>, // Optional. Subject (whom token refers to)
     pub jti: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // Space separated scopes granted
+}
+
+impl Claims {
+    /// Checks whether the token grants the scope.
+    #[must_use]
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.granted_scope().split_whitespace().any(|s| s == scope)
+    }
+
+    /// Space separated scopes granted by the token. Tokens issued without `scope` claim
+    /// keep scopes they always had.
+    #[must_use]
+    pub fn granted_scope(&self) -> &str {
+        self.scope.as_deref().unwrap_or(LEGACY_SCOPES)
+    }
+}
+
+/// Keeps only scopes from [`SCOPES`] out of requested ones. Returns `None` if nothing left.
+#[must_use]
+pub fn supported_scopes(requested: &str) -> Option<String> {
+    let mut granted: Vec<&str> = Vec::new();
+    for scope in requested.split_whitespace() {
+        if SCOPES.split_whitespace().any(|s| s == scope) && !granted.contains(&scope) {
+            granted.push(scope);
+        }
+    }
+    if granted.is_empty() {
+        None
+    } else {
+        Some(granted.join(" "))
+    }
 }
 
 /// Query parameters received in the `IndieAuth` authorization request.
@@ -49,21 +100,38 @@ pub struct IndieQuery {
     pub redirect_uri: Option<String>,
     /// Optional state value to maintain state between request and callback.
     pub state: Option<String>,
+    /// Optional space separated scopes requested by the client.
+    pub scope: Option<String>,
+    /// Optional PKCE code challenge.
+    pub code_challenge: Option<String>,
+    /// PKCE code challenge method. Only `S256` is supported.
+    pub code_challenge_method: Option<String>,
 }
 
 /// Request payload sent by the client to exchange an authorization code for an access token.
 #[derive(Deserialize, Serialize, ToSchema)]
 pub struct TokenRequest {
     /// The type of grant requested; for `IndieAuth` this is typically `"authorization_code"`.
+    #[serde(default)]
     pub grant_type: String,
     /// The authorization code received from the `IndieAuth` provider.
+    #[serde(default)]
     pub code: String,
     /// The client identifier of the application making the request.
+    #[serde(default)]
     pub client_id: String,
     /// The redirect URI that matches the one used during authorization.
+    #[serde(default)]
     pub redirect_uri: String,
     /// The resource owner's identifier (the “me” URL).
+    #[serde(default)]
     pub me: String,
+    /// The action requested; `"revoke"` revokes the token passed in `token`.
+    pub action: Option<String>,
+    /// The access token to revoke.
+    pub token: Option<String>,
+    /// PKCE code verifier required if the code was issued with a challenge.
+    pub code_verifier: Option<String>,
 }
 
 /// Access token response returned to the client.
@@ -90,6 +158,35 @@ pub struct TokenValidationResult {
     pub scope: String,
 }
 
+/// Token introspection request (RFC 7662).
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct IntrospectionRequest {
+    /// The access token to introspect.
+    pub token: String,
+}
+
+/// Token introspection response (RFC 7662). Only `active` is set for inactive tokens.
+#[derive(Deserialize, Serialize, Default, ToSchema)]
+pub struct IntrospectionResult {
+    /// Whether the token is valid, not expired and not revoked.
+    pub active: bool,
+    /// The resource owner's identifier (the “me” URL).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub me: Option<String>,
+    /// The client identifier the token was issued to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Space separated scopes granted to the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Expiration time as UTC timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    /// Issue time as UTC timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+}
+
 #[derive(Debug, Error)]
 pub enum IndieAuthError {
     #[error("No authorization header extracted from request")]
@@ -98,6 +195,108 @@ pub enum IndieAuthError {
     MissingAuthorizationHeaderValue,
     #[error("Authorization header not started from Bearer")]
     NotStarterFromBearer,
+    #[error("Token has been revoked or never issued")]
+    InactiveToken,
+}
+
+/// Reasons for rejecting authorization code exchange.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AuthorizationCodeError {
+    #[error("Authorization code is unknown or has already been used")]
+    UnknownCode,
+    #[error("Authorization code has expired")]
+    Expired,
+    #[error("Client ID does not match the one the code was issued to")]
+    ClientMismatch,
+    #[error("Redirect URI does not match the one the code was issued for")]
+    RedirectMismatch,
+    #[error("Code verifier is required")]
+    MissingVerifier,
+    #[error("Code verifier does not match code challenge")]
+    VerifierMismatch,
+    #[error("Code challenge method '{0}' is not supported")]
+    UnsupportedChallengeMethod(String),
+}
+
+/// Authorization code issued by `/auth` and waiting to be exchanged for a token.
+pub struct AuthorizationCode {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub code_challenge: Option<String>,
+    pub expires: DateTime<Utc>,
+}
+
+/// Issued authorization codes. Every code can be exchanged only once
+/// and expired codes are purged on every store access.
+#[derive(Default)]
+pub struct AuthorizationCodes {
+    codes: HashMap<String, AuthorizationCode>,
+}
+
+impl AuthorizationCodes {
+    pub fn insert(&mut self, code: String, authorization: AuthorizationCode, now: DateTime<Utc>) {
+        self.purge_expired(now);
+        self.codes.insert(code, authorization);
+    }
+
+    /// Removes the code from the store and checks that it may be exchanged
+    /// by the client. A failed attempt also invalidates the code.
+    pub fn redeem(
+        &mut self,
+        code: &str,
+        client_id: &str,
+        redirect_uri: &str,
+        code_verifier: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<(), AuthorizationCodeError> {
+        let authorization = self
+            .codes
+            .remove(code)
+            .ok_or(AuthorizationCodeError::UnknownCode)?;
+        self.purge_expired(now);
+
+        if authorization.expires <= now {
+            return Err(AuthorizationCodeError::Expired);
+        }
+        if authorization.client_id != client_id {
+            return Err(AuthorizationCodeError::ClientMismatch);
+        }
+        if authorization.redirect_uri != redirect_uri {
+            return Err(AuthorizationCodeError::RedirectMismatch);
+        }
+        if let Some(challenge) = authorization.code_challenge {
+            let verifier = code_verifier.ok_or(AuthorizationCodeError::MissingVerifier)?;
+            // RFC 7636 verifier length, PkceCodeVerifier panics on other lengths
+            if !(43..=128).contains(&verifier.len()) {
+                return Err(AuthorizationCodeError::VerifierMismatch);
+            }
+            let expected = PkceCodeChallenge::from_code_verifier_sha256(&PkceCodeVerifier::new(
+                verifier.to_string(),
+            ));
+            if expected.as_str() != challenge {
+                return Err(AuthorizationCodeError::VerifierMismatch);
+            }
+        }
+        Ok(())
+    }
+
+    fn purge_expired(&mut self, now: DateTime<Utc>) {
+        self.codes.retain(|_, c| c.expires > now);
+    }
+}
+
+/// Extracts PKCE code challenge from authorization request. Only `S256` method is supported,
+/// missing method means `plain` according to RFC 7636 so it is rejected too.
+pub fn code_challenge(query: &IndieQuery) -> Result<Option<String>, AuthorizationCodeError> {
+    let Some(challenge) = &query.code_challenge else {
+        return Ok(None);
+    };
+    match query.code_challenge_method.as_deref() {
+        Some("S256") => Ok(Some(challenge.clone())),
+        method => Err(AuthorizationCodeError::UnsupportedChallengeMethod(
+            method.unwrap_or("plain").to_string(),
+        )),
+    }
 }
 
 pub fn generate_jwt<P: AsRef<Path>>(claims: &Claims, private_key_path: P) -> Result<String> {
@@ -111,6 +310,16 @@ pub fn generate_jwt<P: AsRef<Path>>(claims: &Claims, private_key_path: P) -> Res
     Ok(token)
 }
 
+/// Checks that the token is known to the token store and has not been revoked.
+/// Tokens issued before the store was introduced have no `jti` and cannot be revoked,
+/// so they stay active until they expire.
+pub fn is_token_active<S: Storage>(storage: &S, claims: &Claims) -> bool {
+    claims
+        .jti
+        .as_deref()
+        .is_none_or(|jti| storage.get_token(jti).is_ok_and(|t| t.revoked.is_none()))
+}
+
 pub fn validate_jwt<P: AsRef<Path>>(token: &str, public_key_path: P) -> Result<Claims> {
     let data = fs::read(public_key_path)
         .with_context(|| "Public key cannot be read using path specified")?;
@@ -127,15 +336,16 @@ pub fn validate_jwt<P: AsRef<Path>>(token: &str, public_key_path: P) -> Result<C
     Ok(claims.claims)
 }
 
-fn validate_uri(uri: &str) -> Result<()> {
+/// Fails unless URI is an http URL of a public host.
+pub fn validate_uri(uri: &str) -> Result<()> {
     let parsed = Url::parse(uri).context("Invalid URL")?;
 
     validate_scheme(parsed.scheme())?;
 
     match parsed.host() {
         Some(Host::Domain(d)) => validate_domain(d)?,
-        Some(Host::Ipv4(ip)) => validate_ipv4(ip)?,
-        Some(Host::Ipv6(ip)) => validate_ipv6(ip)?,
+        Some(Host::Ipv4(ip)) => validate_ip(IpAddr::V4(ip))?,
+        Some(Host::Ipv6(ip)) => validate_ip(IpAddr::V6(ip))?,
         None => bail!("URL must have a host"),
     }
 
@@ -175,6 +385,13 @@ fn validate_port(parsed: &Url) -> Result<()> {
     Ok(())
 }
 
+fn validate_ip(ip: IpAddr) -> Result<()> {
+    match ip {
+        IpAddr::V4(ip) => validate_ipv4(ip),
+        IpAddr::V6(ip) => validate_ipv6(ip),
+    }
+}
+
 fn validate_ipv6(ip: Ipv6Addr) -> Result<()> {
     if ip.is_loopback() || ip.is_multicast() || ip.is_unspecified() {
         bail!("Reserved IPv6 address not allowed: {ip}");
@@ -237,81 +454,737 @@ fn ip_in_range(ip: Ipv4Addr, network: Ipv4Addr, prefix: u8) -> bool {
     (u32::from(ip) & mask) == (u32::from(network) & mask)
 }
 
-pub async fn read_from_client(uri: &str) -> Result<String> {
+/// Client information discovered by fetching client ID URL. Either JSON client
+/// metadata document or `h-app` microformat of the client home page.
+#[derive(Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct ClientMetadata {
+    /// The human readable client name.
+    #[serde(default)]
+    pub client_name: Option<String>,
+    /// The client home page.
+    #[serde(default)]
+    pub client_uri: Option<String>,
+    /// The client logo.
+    #[serde(default)]
+    pub logo_uri: Option<String>,
+    /// Redirect URIs the client may use besides ones on the client ID host.
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+}
+
+impl ClientMetadata {
+    /// Parses metadata fetched from client ID URL. Relative URLs are resolved against client ID.
+    /// Client and logo URLs are shown on consent page, so only http and https ones are kept.
+    pub fn parse(body: &str, is_json: bool, client_id: &Url) -> Result<Self> {
+        let metadata = if is_json {
+            serde_json::from_str(body)?
+        } else {
+            let app = parse_h_app(body)?;
+            ClientMetadata {
+                client_name: app.name,
+                client_uri: app.url,
+                logo_uri: app.logo,
+                redirect_uris: app.redirect_uris,
+            }
+        };
+        let resolve = |u: String| client_id.join(&u).ok().map(String::from);
+        let resolve_web = |u: String| {
+            client_id
+                .join(&u)
+                .ok()
+                .filter(|u| matches!(u.scheme(), "http" | "https"))
+                .map(String::from)
+        };
+        Ok(ClientMetadata {
+            client_name: metadata.client_name,
+            client_uri: metadata.client_uri.and_then(resolve_web),
+            logo_uri: metadata.logo_uri.and_then(resolve_web),
+            redirect_uris: metadata
+                .redirect_uris
+                .into_iter()
+                .filter_map(resolve)
+                .collect(),
+        })
+    }
+
+    /// Redirect URI is allowed if it has the same scheme, host and port as client ID
+    /// or it is listed in client metadata.
+    #[must_use]
+    pub fn is_redirect_allowed(&self, client_id: &Url, redirect_uri: &str) -> bool {
+        let Ok(redirect) = Url::parse(redirect_uri) else {
+            return false;
+        };
+        redirect.origin() == client_id.origin()
+            || self
+                .redirect_uris
+                .iter()
+                .any(|u| Url::parse(u).is_ok_and(|u| u == redirect))
+    }
+}
+
+/// Fetches client ID URL and parses client metadata from the response.
+pub async fn discover_client(client_id: &Url) -> Result<ClientMetadata> {
+    let page = read_from_client(client_id.as_str()).await?;
+    let is_json = page
+        .content_type
+        .as_deref()
+        .is_some_and(|v| v.contains("json"));
+
+    ClientMetadata::parse(&page.body, is_json, client_id)
+}
+
+/// Page fetched from another site.
+pub struct FetchedPage {
+    /// The page URL after redirects.
+    pub url: Url,
+    /// The value of `Content-Type` header if any.
+    pub content_type: Option<String>,
+    /// Values of `Link` headers.
+    pub links: Vec<String>,
+    pub body: String,
+}
+
+/// Fetches page of another site. The URL and every redirect must point to a public
+/// host and page size is limited, so that untrusted URLs can't reach internal services.
+pub async fn read_from_client(uri: &str) -> Result<FetchedPage> {
     validate_uri(uri)?;
-    let client = Client::builder().build()?;
+    let response = public_client()?.get(uri).send().await?;
+    read_page(response).await
+}
+
+/// HTTP client for untrusted URLs. Follows only a few redirects to public hosts
+/// and connects only to public addresses host names resolve to.
+pub fn public_client() -> Result<Client> {
+    let client = Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .dns_resolver(Arc::new(PublicResolver))
+        .redirect(Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if let Err(e) = validate_uri(attempt.url().as_str()) {
+                attempt.error(e.to_string())
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()?;
+    Ok(client)
+}
 
-    let response = client.get(uri).send().await?.text().await?;
+/// Resolver failing when any address of the host isn't public, so that a public
+/// name can't point requests to internal services.
+struct PublicResolver;
 
-    Ok(response)
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Addrs = Box::new(resolve_public(name.as_str()).await?.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+async fn resolve_public(host: &str) -> Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+    if addrs.is_empty() {
+        bail!("Host {host} has no addresses");
+    }
+    for addr in &addrs {
+        validate_ip(addr.ip())?;
+    }
+    Ok(addrs)
 }
 
-pub struct Indie<ResBody> {
+/// Reads successful response with body not larger than the limit.
+pub async fn read_page(response: reqwest::Response) -> Result<FetchedPage> {
+    let mut response = response.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|l| l > MAX_PAGE_SIZE as u64)
+    {
+        bail!("Page is larger than {MAX_PAGE_SIZE} bytes");
+    }
+    let content_type = response
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    let links = response
+        .headers()
+        .get_all(http::header::LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .map(str::to_owned)
+        .collect();
+    let final_url = response.url().clone();
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > MAX_PAGE_SIZE {
+            bail!("Page is larger than {MAX_PAGE_SIZE} bytes");
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(FetchedPage {
+        url: final_url,
+        content_type,
+        links,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// Human readable description of the scope shown on consent page.
+#[must_use]
+pub fn scope_description(scope: &str) -> &'static str {
+    match scope {
+        "create" => "Создание новых записей",
+        "update" => "Изменение записей",
+        "delete" => "Удаление и восстановление записей",
+        "media" => "Загрузка файлов",
+        _ => "",
+    }
+}
+
+pub struct Indie<S, ResBody> {
     public_key_path: Arc<String>,
+    storage: Database<S>,
+    scope: Option<&'static str>,
     _body_type: PhantomData<fn() -> ResBody>,
 }
 
-impl<ResBody> Clone for Indie<ResBody> {
+impl<S, ResBody> Clone for Indie<S, ResBody> {
     fn clone(&self) -> Self {
         Self {
             public_key_path: self.public_key_path.clone(),
+            storage: self.storage.clone(),
+            scope: self.scope,
             _body_type: PhantomData,
         }
     }
 }
 
-impl<Req, Resp> ValidateRequest<Req> for Indie<Resp>
+impl<S, Req, Resp> AsyncAuthorizeRequest<Req> for Indie<S, Resp>
 where
-    Resp: HttpBody + Default,
+    S: Storage + Send + 'static,
+    Req: Send + 'static,
+    Resp: HttpBody + Default + From<String> + Send + 'static,
 {
+    type RequestBody = Req;
     type ResponseBody = Resp;
+    type Future = BoxFuture<'static, Result<Request<Req>, Response<Resp>>>;
 
-    fn validate(&mut self, request: &mut Request<Req>) -> Result<(), Response<Self::ResponseBody>> {
-        let unauthorized_response = Response::builder()
-            .status(http::StatusCode::UNAUTHORIZED)
-            .body(Default::default())
-            .unwrap_or_default();
+    fn authorize(&mut self, mut request: Request<Req>) -> Self::Future {
+        let token = bearer_token(&request);
+        let public_key_path = self.public_key_path.clone();
+        let storage = self.storage.clone();
+        let scope = self.scope;
 
-        let Some(value) = request.headers().get("authorization") else {
-            tracing::error!("{}", IndieAuthError::MissingAuthorizationHeader.to_string());
-            return Err(unauthorized_response);
-        };
-        let Ok(auth_header) = value.to_str() else {
-            tracing::error!(
-                "{}",
-                IndieAuthError::MissingAuthorizationHeaderValue.to_string()
-            );
-            return Err(unauthorized_response);
-        };
+        Box::pin(async move {
+            let unauthorized_response = Response::builder()
+                .status(http::StatusCode::UNAUTHORIZED)
+                .body(Default::default())
+                .unwrap_or_default();
 
-        let Some(token) = auth_header.strip_prefix("Bearer ") else {
-            tracing::error!("{}", IndieAuthError::NotStarterFromBearer.to_string());
-            return Err(unauthorized_response);
-        };
+            let token = match token {
+                Ok(t) => t,
+                Err(e) => {
+                    tracing::error!("{e}");
+                    return Err(unauthorized_response);
+                }
+            };
+
+            let claims = match validate_jwt(&token, public_key_path.as_str()) {
+                Ok(claims) => claims,
+                Err(e) => {
+                    tracing::error!("Token {token} validation error: {e:#?}");
+                    return Err(unauthorized_response);
+                }
+            };
 
-        match validate_jwt(token, self.public_key_path.as_str()) {
-            Ok(_claims) => Ok(()),
-            Err(e) => {
-                tracing::error!("Token {token} validation error: {e:#?}");
-                Err(unauthorized_response)
+            let checked = claims.clone();
+            let active = match storage
+                .read(move |s| anyhow::Ok(is_token_active(s, &checked)))
+                .await
+            {
+                Ok(active) => active,
+                Err(e) => {
+                    tracing::error!("Failed to read token store: {e:#?}");
+                    false
+                }
+            };
+            if !active {
+                tracing::error!("{}", IndieAuthError::InactiveToken.to_string());
+                return Err(unauthorized_response);
             }
-        }
+            if let Some(scope) = scope
+                && !claims.has_scope(scope)
+            {
+                tracing::error!("Token of {} has no scope '{scope}'", claims.client_id);
+                return Err(insufficient_scope_response(scope));
+            }
+            // Handlers use claims to check scopes
+            request.extensions_mut().insert(claims);
+            Ok(request)
+        })
     }
 }
 
-/// A wrapper around [`tower_http::validate_request::ValidateRequestHeaderLayer`] which
+/// Extracts bearer token from authorization header.
+fn bearer_token<Req>(request: &Request<Req>) -> Result<String, IndieAuthError> {
+    let value = request
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .ok_or(IndieAuthError::MissingAuthorizationHeader)?;
+    let auth_header = value
+        .to_str()
+        .map_err(|_| IndieAuthError::MissingAuthorizationHeaderValue)?;
+    auth_header
+        .strip_prefix("Bearer ")
+        .map(str::to_owned)
+        .ok_or(IndieAuthError::NotStarterFromBearer)
+}
+
+fn insufficient_scope_response<Resp: Default + From<String>>(scope: &str) -> Response<Resp> {
+    let body = MicropubFormError::InsufficientScope(scope.to_string()).to_body();
+    Response::builder()
+        .status(http::StatusCode::FORBIDDEN)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .header(
+            http::header::WWW_AUTHENTICATE,
+            format!("Bearer error=\"insufficient_scope\", scope=\"{scope}\""),
+        )
+        .body(Resp::from(serde_json::to_string(&body).unwrap_or_default()))
+        .unwrap_or_default()
+}
+
+/// A wrapper around [`tower_http::auth::AsyncRequireAuthorizationLayer`] which
 /// provides login authorization.
 pub struct RequireIndieAuthorizationLayer;
 
 impl RequireIndieAuthorizationLayer {
     /// Authorizes requests by requiring valid Indie auth token in authorization header, otherwise it rejects
-    /// with [`http::StatusCode::UNAUTHORIZED`].
-    pub fn auth<Resp: HttpBody + Default>(
+    /// with [`http::StatusCode::UNAUTHORIZED`]. Token revocation is checked using the storage pool.
+    pub fn auth<S: Storage + Send + 'static, Resp: HttpBody + Default + From<String>>(
+        public_key_path: Arc<String>,
+        storage: Database<S>,
+    ) -> AsyncRequireAuthorizationLayer<Indie<S, Resp>> {
+        AsyncRequireAuthorizationLayer::new(Indie::<_, _> {
+            public_key_path,
+            storage,
+            scope: None,
+            _body_type: PhantomData,
+        })
+    }
+
+    /// Same as [`RequireIndieAuthorizationLayer::auth`] but also requires token to have the scope,
+    /// otherwise it rejects with [`http::StatusCode::FORBIDDEN`] and `insufficient_scope` error.
+    pub fn scoped<S: Storage + Send + 'static, Resp: HttpBody + Default + From<String>>(
         public_key_path: Arc<String>,
-    ) -> tower_http::validate_request::ValidateRequestHeaderLayer<Indie<Resp>> {
-        tower_http::validate_request::ValidateRequestHeaderLayer::custom(Indie::<_> {
+        storage: Database<S>,
+        scope: &'static str,
+    ) -> AsyncRequireAuthorizationLayer<Indie<S, Resp>> {
+        AsyncRequireAuthorizationLayer::new(Indie::<_, _> {
             public_key_path,
+            storage,
+            scope: Some(scope),
             _body_type: PhantomData,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use chrono::TimeDelta;
+    use kernel::{domain::IndieToken, memory::Memory};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Some("create update"), "create", true)]
+    #[case(Some("create update"), "update", true)]
+    #[case(Some("create update"), "media", false)]
+    #[case(Some(""), "create", false)]
+    #[case(None, "delete", true)]
+    #[case(None, "update", false)]
+    fn has_scope_tests(#[case] granted: Option<&str>, #[case] scope: &str, #[case] expected: bool) {
+        // arrange
+        let claims = Claims {
+            client_id: "https://quill.p3k.io/".into(),
+            redirect_uri: None,
+            aud: None,
+            exp: None,
+            iat: None,
+            iss: None,
+            nbf: None,
+            sub: None,
+            jti: None,
+            scope: granted.map(str::to_string),
+        };
+
+        // act
+        let actual = claims.has_scope(scope);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case(Some("active"), true)]
+    #[case(Some("revoked"), false)]
+    #[case(Some("unknown"), false)]
+    #[case(None, true)]
+    fn is_token_active_tests(#[case] jti: Option<&str>, #[case] expected: bool) {
+        // arrange
+        let mut storage = Memory::new();
+        let now = Utc::now();
+        for id in ["active", "revoked"] {
+            let token = IndieToken {
+                jti: id.to_string(),
+                client_id: CLIENT.to_string(),
+                scope: "create".to_string(),
+                issued: now,
+                expires: now + TimeDelta::days(1),
+                revoked: None,
+            };
+            storage.insert_token(&token).unwrap();
+        }
+        storage.revoke_token("revoked").unwrap();
+        let claims = Claims {
+            client_id: CLIENT.into(),
+            redirect_uri: None,
+            aud: None,
+            exp: None,
+            iat: None,
+            iss: None,
+            nbf: None,
+            sub: None,
+            jti: jti.map(str::to_string),
+            scope: None,
+        };
+
+        // act
+        let actual = is_token_active(&storage, &claims);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case("create update", Some("create update"))]
+    #[case("profile create media create", Some("create media"))]
+    #[case("draft", None)]
+    #[case("", None)]
+    fn supported_scopes_tests(#[case] requested: &str, #[case] expected: Option<&str>) {
+        // arrange
+
+        // act
+        let actual = supported_scopes(requested);
+
+        // assert
+        assert_eq!(expected.map(str::to_string), actual);
+    }
+
+    #[test]
+    fn insufficient_scope_response_test() {
+        // arrange
+
+        // act
+        let response: Response<String> = insufficient_scope_response("media");
+
+        // assert
+        assert_eq!(http::StatusCode::FORBIDDEN, response.status());
+        assert_eq!(
+            "Bearer error=\"insufficient_scope\", scope=\"media\"",
+            response.headers()[http::header::WWW_AUTHENTICATE]
+        );
+        assert!(response.body().contains("\"error\":\"insufficient_scope\""));
+    }
+
+    #[test]
+    fn inactive_introspection_result_has_only_active() {
+        // arrange
+        let result = IntrospectionResult::default();
+
+        // act
+        let actual = serde_json::to_string(&result).unwrap();
+
+        // assert
+        assert_eq!(r#"{"active":false}"#, actual);
+    }
+
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+    const CLIENT: &str = "https://quill.p3k.io/";
+    const REDIRECT: &str = "https://quill.p3k.io/auth/callback";
+
+    #[rstest]
+    #[case(None, Err(AuthorizationCodeError::MissingVerifier))]
+    #[case(Some(VERIFIER), Ok(()))]
+    #[case(Some("wrong"), Err(AuthorizationCodeError::VerifierMismatch))]
+    #[case(
+        Some("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXK"),
+        Err(AuthorizationCodeError::VerifierMismatch)
+    )]
+    fn redeem_with_challenge(
+        #[case] verifier: Option<&str>,
+        #[case] expected: Result<(), AuthorizationCodeError>,
+    ) {
+        // arrange
+        let now = Utc::now();
+        let mut codes = codes_with("code", Some(CHALLENGE), now);
+
+        // act
+        let actual = codes.redeem("code", CLIENT, REDIRECT, verifier, now);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case("code", CLIENT, REDIRECT, Ok(()))]
+    #[case("other", CLIENT, REDIRECT, Err(AuthorizationCodeError::UnknownCode))]
+    #[case(
+        "code",
+        "https://evil.example/",
+        REDIRECT,
+        Err(AuthorizationCodeError::ClientMismatch)
+    )]
+    #[case(
+        "code",
+        CLIENT,
+        "https://quill.p3k.io/other",
+        Err(AuthorizationCodeError::RedirectMismatch)
+    )]
+    fn redeem_without_challenge(
+        #[case] code: &str,
+        #[case] client_id: &str,
+        #[case] redirect_uri: &str,
+        #[case] expected: Result<(), AuthorizationCodeError>,
+    ) {
+        // arrange
+        let now = Utc::now();
+        let mut codes = codes_with("code", None, now);
+
+        // act
+        let actual = codes.redeem(code, client_id, redirect_uri, None, now);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn redeem_twice_fails() {
+        // arrange
+        let now = Utc::now();
+        let mut codes = codes_with("code", None, now);
+        codes.redeem("code", CLIENT, REDIRECT, None, now).unwrap();
+
+        // act
+        let actual = codes.redeem("code", CLIENT, REDIRECT, None, now);
+
+        // assert
+        assert_eq!(Err(AuthorizationCodeError::UnknownCode), actual);
+    }
+
+    #[test]
+    fn redeem_after_failed_attempt_fails() {
+        // arrange
+        let now = Utc::now();
+        let mut codes = codes_with("code", None, now);
+        let _ = codes.redeem("code", "https://evil.example/", REDIRECT, None, now);
+
+        // act
+        let actual = codes.redeem("code", CLIENT, REDIRECT, None, now);
+
+        // assert
+        assert_eq!(Err(AuthorizationCodeError::UnknownCode), actual);
+    }
+
+    #[test]
+    fn redeem_expired_fails() {
+        // arrange
+        let now = Utc::now();
+        let mut codes = codes_with("code", None, now);
+
+        // act
+        let actual = codes.redeem("code", CLIENT, REDIRECT, None, now + TimeDelta::minutes(11));
+
+        // assert
+        assert_eq!(Err(AuthorizationCodeError::Expired), actual);
+    }
+
+    #[test]
+    fn insert_purges_expired() {
+        // arrange
+        let now = Utc::now();
+        let mut codes = codes_with("code", None, now);
+        let later = now + TimeDelta::minutes(11);
+
+        // act
+        codes.insert("new".to_string(), authorization(None, later), later);
+
+        // assert
+        assert_eq!(1, codes.codes.len());
+        assert!(codes.codes.contains_key("new"));
+    }
+
+    #[rstest]
+    #[case(None, None, Ok(None))]
+    #[case(Some(CHALLENGE), Some("S256"), Ok(Some(CHALLENGE.to_string())))]
+    #[case(
+        Some(CHALLENGE),
+        Some("plain"),
+        Err(AuthorizationCodeError::UnsupportedChallengeMethod("plain".to_string()))
+    )]
+    #[case(
+        Some(CHALLENGE),
+        None,
+        Err(AuthorizationCodeError::UnsupportedChallengeMethod("plain".to_string()))
+    )]
+    fn code_challenge_tests(
+        #[case] challenge: Option<&str>,
+        #[case] method: Option<&str>,
+        #[case] expected: Result<Option<String>, AuthorizationCodeError>,
+    ) {
+        // arrange
+        let query = IndieQuery {
+            client_id: Some(CLIENT.to_string()),
+            redirect_uri: Some(REDIRECT.to_string()),
+            state: None,
+            scope: None,
+            code_challenge: challenge.map(str::to_string),
+            code_challenge_method: method.map(str::to_string),
+        };
+
+        // act
+        let actual = code_challenge(&query);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    fn codes_with(code: &str, challenge: Option<&str>, now: DateTime<Utc>) -> AuthorizationCodes {
+        let mut codes = AuthorizationCodes::default();
+        codes.insert(code.to_string(), authorization(challenge, now), now);
+        codes
+    }
+
+    fn authorization(challenge: Option<&str>, now: DateTime<Utc>) -> AuthorizationCode {
+        AuthorizationCode {
+            client_id: CLIENT.to_string(),
+            redirect_uri: REDIRECT.to_string(),
+            code_challenge: challenge.map(str::to_string),
+            expires: now + TimeDelta::minutes(10),
+        }
+    }
+
+    #[test]
+    fn client_metadata_parse_json() {
+        // arrange
+        let client_id = Url::parse("https://app.example/").unwrap();
+        let body = r#"{"client_id":"https://app.example/","client_name":"Example","logo_uri":"/logo.png","redirect_uris":["https://cb.example/auth"]}"#;
+
+        // act
+        let actual = ClientMetadata::parse(body, true, &client_id).unwrap();
+
+        // assert
+        assert_eq!(Some("Example".to_string()), actual.client_name);
+        assert_eq!(
+            Some("https://app.example/logo.png".to_string()),
+            actual.logo_uri
+        );
+        assert_eq!(
+            vec!["https://cb.example/auth".to_string()],
+            actual.redirect_uris
+        );
+    }
+
+    #[rstest]
+    #[case("javascript:alert(1)")]
+    #[case("data:image/svg+xml;base64,PHN2Zz48L3N2Zz4=")]
+    fn client_metadata_parse_rejects_not_web_uris(#[case] uri: &str) {
+        // arrange
+        let client_id = Url::parse("https://app.example/").unwrap();
+        let body = serde_json::json!({
+            "client_id": "https://app.example/",
+            "client_uri": uri,
+            "logo_uri": uri,
+        })
+        .to_string();
+
+        // act
+        let actual = ClientMetadata::parse(&body, true, &client_id).unwrap();
+
+        // assert
+        assert_eq!(None, actual.client_uri);
+        assert_eq!(None, actual.logo_uri);
+    }
+
+    #[test]
+    fn client_metadata_parse_h_app() {
+        // arrange
+        let client_id = Url::parse("https://app.example/").unwrap();
+        let body = r#"<link rel="redirect_uri" href="/callback"><div class="h-app"><a class="u-url p-name" href="/">Example</a></div>"#;
+
+        // act
+        let actual = ClientMetadata::parse(body, false, &client_id).unwrap();
+
+        // assert
+        assert_eq!(Some("Example".to_string()), actual.client_name);
+        assert_eq!(Some("https://app.example/".to_string()), actual.client_uri);
+        assert_eq!(
+            vec!["https://app.example/callback".to_string()],
+            actual.redirect_uris
+        );
+    }
+
+    #[rstest]
+    #[case("https://app.example/callback", true)]
+    #[case("https://app.example:443/other?x=1", true)]
+    #[case("https://cb.example/auth", true)]
+    #[case("https://cb.example/other", false)]
+    #[case("http://app.example/callback", false)]
+    #[case("https://evil.example/", false)]
+    #[case("not a url", false)]
+    fn is_redirect_allowed_tests(#[case] redirect_uri: &str, #[case] expected: bool) {
+        // arrange
+        let client_id = Url::parse("https://app.example/").unwrap();
+        let metadata = ClientMetadata {
+            redirect_uris: vec!["https://cb.example/auth".to_string()],
+            ..Default::default()
+        };
+
+        // act
+        let actual = metadata.is_redirect_allowed(&client_id, redirect_uri);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case("localhost")]
+    #[case("127.0.0.1")]
+    #[case("10.0.0.1")]
+    #[case("::1")]
+    #[case("::ffff:192.168.1.1")]
+    #[tokio::test]
+    async fn resolve_public_rejects_internal_addresses(#[case] host: &str) {
+        // act
+        let actual = resolve_public(host).await;
+
+        // assert
+        assert!(actual.is_err());
+    }
+
+    #[tokio::test]
+    async fn resolve_public_accepts_public_address() {
+        // act
+        let actual = resolve_public("93.184.216.34").await.unwrap();
+
+        // assert
+        assert_eq!(vec![SocketAddr::from(([93, 184, 216, 34], 0))], actual);
+    }
+}
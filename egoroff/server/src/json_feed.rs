@@ -0,0 +1,135 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use kernel::converter::html2text;
+use serde::Serialize;
+
+use crate::feed::{FEED_AUTHOR, FEED_LANGUAGE, Feed};
+
+const VERSION: &str = "https://jsonfeed.org/version/1.1";
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    description: &'a str,
+    home_page_url: &'a str,
+    feed_url: &'a str,
+    language: &'static str,
+    authors: [Author; 1],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hubs: Vec<Hub<'a>>,
+    items: Vec<Item<'a>>,
+}
+
+#[derive(Serialize)]
+struct Hub<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    url: &'a str,
+}
+
+#[derive(Serialize)]
+struct Author {
+    name: &'static str,
+}
+
+#[derive(Serialize)]
+struct Item<'a> {
+    id: &'a str,
+    url: &'a str,
+    title: &'a str,
+    content_html: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    date_published: String,
+    date_modified: String,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+}
+
+/// Renders [JSON Feed 1.1](https://www.jsonfeed.org/version/1.1/). Summary is plain text there
+/// and it becomes content if full text isn't included.
+pub fn render(feed: &Feed) -> Result<String> {
+    let items = feed
+        .items
+        .iter()
+        .map(|item| Item {
+            id: &item.url,
+            url: &item.url,
+            title: &item.title,
+            content_html: item.content.as_deref().unwrap_or(&item.summary),
+            summary: html2text(&item.summary).ok().filter(|s| !s.is_empty()),
+            date_published: rfc3339(item.published),
+            date_modified: rfc3339(item.updated),
+            tags: &item.tags,
+        })
+        .collect();
+    let json = JsonFeed {
+        version: VERSION,
+        title: &feed.title,
+        description: &feed.description,
+        home_page_url: &feed.home_page_url,
+        feed_url: &feed.feed_url,
+        language: FEED_LANGUAGE,
+        authors: [Author { name: FEED_AUTHOR }],
+        hubs: feed
+            .hub
+            .iter()
+            .map(|url| Hub {
+                kind: "WebSub",
+                url,
+            })
+            .collect(),
+        items,
+    };
+    Ok(serde_json::to_string(&json)?)
+}
+
+fn rfc3339(dt: DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::feed::{FeedFormat, FeedItem};
+    use serde_json::Value;
+
+    #[test]
+    fn render_items() {
+        // arrange
+        let item = FeedItem {
+            url: "https://www.egoroff.spb.ru/blog/2024/post".to_owned(),
+            title: "Пост".to_owned(),
+            summary: "<p>Кратко</p>".to_owned(),
+            content: Some("<p>Полностью</p>".to_owned()),
+            published: "2024-01-10T10:00:00Z".parse().unwrap(),
+            updated: "2024-02-12T10:00:00Z".parse().unwrap(),
+            tags: vec!["rust".to_owned()],
+        };
+        let feed = Feed::new(FeedFormat::JsonFeed, vec![item]);
+
+        // act
+        let actual = render(&feed).unwrap();
+
+        // assert
+        let json: Value = serde_json::from_str(&actual).unwrap();
+        assert_eq!(VERSION, json["version"]);
+        assert_eq!(
+            "https://www.egoroff.spb.ru/blog/feed.json",
+            json["feed_url"]
+        );
+        assert_eq!(FEED_AUTHOR, json["authors"][0]["name"]);
+        assert_eq!("WebSub", json["hubs"][0]["type"]);
+        assert_eq!("https://www.egoroff.spb.ru/websub", json["hubs"][0]["url"]);
+        let item = &json["items"][0];
+        assert_eq!("https://www.egoroff.spb.ru/blog/2024/post", item["id"]);
+        assert_eq!("<p>Полностью</p>", item["content_html"]);
+        assert_eq!("Кратко", item["summary"]);
+        assert_eq!("2024-01-10T10:00:00Z", item["date_published"]);
+        assert_eq!("2024-02-12T10:00:00Z", item["date_modified"]);
+        assert_eq!("rust", item["tags"][0]);
+    }
+}
@@ -6,7 +6,11 @@
 use anyhow::{Context, Result, anyhow};
 use axum::Router;
 
+use chrono::TimeDelta;
+use kernel::backup::Retention;
 use kernel::graph::{SiteGraph, SiteSection};
+use kernel::pool::{DEFAULT_READERS, Pool};
+use kernel::sqlite::{Mode, Sqlite};
 use std::env;
 use std::sync::Arc;
 use std::{fs::File, io::BufReader};
@@ -15,19 +19,26 @@ use tokio::signal;
 use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-use crate::domain::Config;
+use crate::domain::{Config, PageContext, SearchBackend};
 
 mod atom;
 mod auth;
 mod body;
 mod domain;
+mod feed;
 mod handlers;
 mod indie;
+mod json_feed;
 mod micropub;
 mod rest;
+mod rss;
+mod scheduler;
 mod sitemap;
+mod webmention;
+mod websub;
 
-pub const SESSIONS_DATABASE: &str = "egoroff_sessions.db";
+pub const SESSIONS_DATABASE: &str = kernel::session::DATABASE;
+const DEFAULT_BACKUP_INTERVAL_HOURS: u64 = 24;
 
 static BASE_PATH: std::sync::LazyLock<PathBuf> = std::sync::LazyLock::new(base_path);
 static SITE_MAP: std::sync::LazyLock<Option<SiteSection>> = std::sync::LazyLock::new(make_site_map);
@@ -39,7 +50,13 @@ pub struct ServerConfig {
     pub data_path: PathBuf,
     pub search_api_key: String,
     pub google_site_id: String,
+    pub search_backend: String,
     pub analytics_id: String,
+    pub post_revisions: usize,
+    /// Scheduled backups are made only when the directory is set.
+    pub backup_dir: Option<PathBuf>,
+    pub backup_interval: std::time::Duration,
+    pub backup_retention: Retention,
 }
 
 impl ServerConfig {
@@ -55,6 +72,46 @@ impl ServerConfig {
             env::current_dir().context("Cannot determine current directory for data path")?
         };
 
+        let post_revisions = match env::var("EGOROFF_POST_REVISIONS") {
+            Ok(v) => v
+                .parse::<usize>()
+                .context("EGOROFF_POST_REVISIONS must be a non negative number")?,
+            Err(_) => kernel::sqlite::DEFAULT_REVISIONS_LIMIT,
+        };
+
+        let backup_interval = match env::var("EGOROFF_BACKUP_INTERVAL_HOURS") {
+            Ok(v) => v
+                .parse::<u64>()
+                .ok()
+                .filter(|h| *h > 0)
+                .context("EGOROFF_BACKUP_INTERVAL_HOURS must be a positive number")?,
+            Err(_) => DEFAULT_BACKUP_INTERVAL_HOURS,
+        };
+        // The first backup is scheduled one interval after start, so the interval
+        // must fit into instants as well as into seconds.
+        let backup_interval = backup_interval
+            .checked_mul(3600)
+            .map(std::time::Duration::from_secs)
+            .filter(|d| std::time::Instant::now().checked_add(*d).is_some())
+            .context("EGOROFF_BACKUP_INTERVAL_HOURS is too large")?;
+
+        let keep = match env::var("EGOROFF_BACKUP_KEEP") {
+            Ok(v) => v
+                .parse::<usize>()
+                .context("EGOROFF_BACKUP_KEEP must be a non negative number")?,
+            Err(_) => kernel::backup::DEFAULT_KEEP,
+        };
+
+        let max_age = match env::var("EGOROFF_BACKUP_MAX_AGE_DAYS") {
+            Ok(v) => Some(
+                v.parse::<i64>()
+                    .ok()
+                    .and_then(TimeDelta::try_days)
+                    .context("EGOROFF_BACKUP_MAX_AGE_DAYS must be a number of days")?,
+            ),
+            Err(_) => None,
+        };
+
         Ok(Self {
             http_port,
             store_uri: env::var("EGOROFF_STORE_URI").unwrap_or_default(),
@@ -62,7 +119,12 @@ impl ServerConfig {
             data_path,
             search_api_key: env::var("EGOROFF_SEARCH_API_KEY").unwrap_or_default(),
             google_site_id: env::var("EGOROFF_SITE_ID").unwrap_or_default(),
+            search_backend: env::var("EGOROFF_SEARCH_BACKEND").unwrap_or_default(),
             analytics_id: env::var("EGOROFF_ANALYTYCS_ID").unwrap_or_default(),
+            post_revisions,
+            backup_dir: env::var("EGOROFF_BACKUP_DIR").ok().map(PathBuf::from),
+            backup_interval,
+            backup_retention: Retention { keep, max_age },
         })
     }
 }
@@ -115,6 +177,7 @@ pub async fn run() -> Result<()> {
     let site_config = Config {
         search_api_key: cfg.search_api_key,
         google_site_id: cfg.google_site_id,
+        search_backend: SearchBackend::from(cfg.search_backend.as_str()),
         analytics_id: cfg.analytics_id,
     };
 
@@ -123,15 +186,38 @@ pub async fn run() -> Result<()> {
         .ok_or(anyhow!("Site root cannot be created"))?;
     let site_graph = Arc::new(SiteGraph::new(root));
 
-    let app = rest::create_routes(
+    if let Some(backup_dir) = cfg.backup_dir {
+        scheduler::spawn_backup(
+            cfg.data_path.clone(),
+            backup_dir,
+            cfg.backup_interval,
+            cfg.backup_retention,
+        );
+    }
+
+    let storage_path = cfg.data_path.join(kernel::sqlite::DATABASE);
+    let writer = Sqlite::open(&storage_path, Mode::ReadWrite)
+        .context("Failed to open database")?
+        .with_revisions_limit(cfg.post_revisions);
+    let storage = Pool::open(writer, &storage_path, DEFAULT_READERS)
+        .context("Failed to create storage pool")?;
+
+    let page_context = Arc::new(PageContext::new(
         BASE_PATH.to_path_buf(),
+        Arc::new(storage),
         site_graph,
         site_config,
-        &cfg.data_path,
         cfg.store_uri,
         cfg.certs_path,
-    )
-    .context("Routes creation error")?;
+    )?);
+
+    let storage = &page_context.storage;
+    scheduler::spawn_publisher(page_context.clone());
+    scheduler::spawn_webmention_receiver(storage.clone());
+    scheduler::spawn_webmention_sender(storage.clone())?;
+    scheduler::spawn_feed_distributor(storage.clone(), page_context.hub.clone());
+
+    let app = rest::create_routes(page_context, &cfg.data_path).context("Routes creation error")?;
 
     http_server(cfg.http_port, app)
         .await
@@ -3,14 +3,17 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
+use axum::http::StatusCode;
 use chrono::{DateTime, NaiveDateTime, Utc};
-use kernel::domain::Post;
+use kernel::domain::{Post, PostStatus, Storage};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value, json};
 use thiserror::Error;
 use url::form_urlencoded::parse;
 use utoipa::ToSchema;
 
+use crate::indie::ME;
+
 /// Configuration options for the Micropub endpoint.
 #[derive(Serialize, Default, ToSchema)]
 pub struct MicropubConfig {
@@ -43,11 +46,66 @@ pub struct SyndicateTo {
 }
 
 /// Errors that can occur while processing a Micropub form submission.
-#[derive(Debug, Error, ToSchema)]
+#[derive(Debug, Error, ToSchema, PartialEq)]
 pub enum MicropubFormError {
     /// Indicates that a required field is missing.
     #[error("Required field '{0}' is missing.")]
     MissingField(String),
+    /// Request is syntactically valid but cannot be applied.
+    #[error("{0}")]
+    InvalidRequest(String),
+    /// Action is not one of create, update, delete or undelete.
+    #[error("Action '{0}' is not supported.")]
+    UnsupportedAction(String),
+    /// URL does not point to an existing post.
+    #[error("Post '{0}' not found.")]
+    PostNotFound(String),
+    /// Token has no scope required by the action.
+    #[error("Scope '{0}' is required for this action.")]
+    InsufficientScope(String),
+}
+
+impl MicropubFormError {
+    /// Micropub error code as defined by the specification.
+    #[must_use]
+    pub fn error(&self) -> &'static str {
+        match self {
+            MicropubFormError::InsufficientScope(_) => "insufficient_scope",
+            _ => "invalid_request",
+        }
+    }
+
+    #[must_use]
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MicropubFormError::InsufficientScope(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    #[must_use]
+    pub fn to_body(&self) -> MicropubError {
+        MicropubError {
+            error: self.error(),
+            error_description: self.to_string(),
+            scope: match self {
+                MicropubFormError::InsufficientScope(scope) => Some(scope.clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Micropub error response body.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MicropubError {
+    /// Error code: `invalid_request` or `insufficient_scope`.
+    pub error: &'static str,
+    /// Human readable error description.
+    pub error_description: String,
+    /// Scope required by the action if that was the reason of the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -383,7 +441,7 @@ impl MicropubFormBuilder {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Default, PartialEq, Clone)]
 pub struct MicropubForm {
     /// Access token (token used to authenticate the operation).
     /// May be used in place of a bearer token authorization header.
@@ -456,11 +514,15 @@ impl MicropubForm {
         Ok(MicropubFormBuilder::from_json(b)?.build()?)
     }
 
+    /// Post status requested by `post-status`. Posts published in the future are scheduled,
+    /// posts without status are drafts.
     #[must_use]
-    pub fn is_public(&self) -> bool {
-        self.post_status
-            .as_deref()
-            .is_some_and(|status| status.eq_ignore_ascii_case("published"))
+    pub fn status(&self, published: DateTime<Utc>, now: DateTime<Utc>) -> PostStatus {
+        match self.post_status.as_deref().and_then(PostStatus::parse) {
+            Some(PostStatus::Published) if published > now => PostStatus::Scheduled,
+            Some(status) => status,
+            None => PostStatus::Draft,
+        }
     }
 
     #[must_use]
@@ -477,6 +539,7 @@ impl MicropubForm {
             .and_then(parse_micropub_datetime)
             .unwrap_or(created);
         let markdown = matches!(self.content_type.as_deref(), None | Some("" | "markdown"));
+        let status = self.status(created, now);
 
         Post {
             id,
@@ -486,8 +549,10 @@ impl MicropubForm {
             short_text: String::new(),
             text: self.content.clone(),
             markdown,
-            is_public: self.is_public(),
+            is_public: status == PostStatus::Published,
             tags: self.category.clone(),
+            slug: self.slug.clone(),
+            status,
         }
     }
 }
@@ -508,6 +573,301 @@ fn parse_micropub_datetime(value: &str) -> Option<DateTime<Utc>> {
         })
 }
 
+/// Request to the Micropub endpoint.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MicropubAction {
+    Create(MicropubForm),
+    Update(MicropubUpdate),
+    /// Delete post identified by URL.
+    Delete(String),
+    /// Restore deleted post identified by URL.
+    Undelete(String),
+}
+
+impl MicropubAction {
+    pub fn from_json_bytes(b: &[u8]) -> Result<Self, MicropubFormError> {
+        let value: Value = serde_json::from_slice(b)
+            .map_err(|e| MicropubFormError::InvalidRequest(e.to_string()))?;
+        let Some(action) = value.get("action") else {
+            return MicropubForm::from_json_bytes(b)
+                .map(MicropubAction::Create)
+                .map_err(into_form_error);
+        };
+        let action = action.as_str().unwrap_or_default();
+        let url = || {
+            value
+                .get("url")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| MicropubFormError::MissingField("url".into()))
+        };
+
+        match action {
+            "update" => {
+                let mut update = MicropubUpdate {
+                    url: url()?,
+                    ..Default::default()
+                };
+                if let Some(replace) = value.get("replace") {
+                    update.replace = property_values(replace, "replace")?;
+                }
+                if let Some(add) = value.get("add") {
+                    update.add = property_values(add, "add")?;
+                }
+                match value.get("delete") {
+                    Some(Value::Array(names)) => {
+                        update.delete_properties = names
+                            .iter()
+                            .map(|n| {
+                                n.as_str().map(str::to_string).ok_or_else(|| {
+                                    MicropubFormError::InvalidRequest(
+                                        "delete must be an array of property names".into(),
+                                    )
+                                })
+                            })
+                            .collect::<Result<_, _>>()?;
+                    }
+                    Some(delete @ Value::Object(_)) => {
+                        update.delete_values = property_values(delete, "delete")?;
+                    }
+                    Some(_) => {
+                        return Err(MicropubFormError::InvalidRequest(
+                            "delete must be an array or an object".into(),
+                        ));
+                    }
+                    None => {}
+                }
+                Ok(MicropubAction::Update(update))
+            }
+            "delete" => Ok(MicropubAction::Delete(url()?)),
+            "undelete" => Ok(MicropubAction::Undelete(url()?)),
+            "create" => MicropubForm::from_json_bytes(b)
+                .map(MicropubAction::Create)
+                .map_err(into_form_error),
+            other => Err(MicropubFormError::UnsupportedAction(other.to_string())),
+        }
+    }
+
+    /// Parses form encoded request. Updates use `replace[name]=value`,
+    /// `add[category][]=value`, `delete[category][]=value` and `delete[]=name` keys.
+    pub fn from_form_bytes(b: &[u8]) -> Result<Self, MicropubFormError> {
+        let pairs: Vec<(String, String)> = parse(b).into_owned().collect();
+        let field = |name: &str| {
+            pairs
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+        };
+        let url = || field("url").ok_or_else(|| MicropubFormError::MissingField("url".into()));
+
+        match field("action").as_deref() {
+            None | Some("create") => MicropubForm::from_form_bytes(b)
+                .map(MicropubAction::Create)
+                .map_err(into_form_error),
+            Some("update") => {
+                let mut update = MicropubUpdate {
+                    url: url()?,
+                    ..Default::default()
+                };
+                for (k, v) in &pairs {
+                    if let Some(name) = form_property(k, "replace") {
+                        push_value(&mut update.replace, name, v);
+                    } else if let Some(name) = form_property(k, "add") {
+                        push_value(&mut update.add, name, v);
+                    } else if k == "delete[]" || k == "delete" {
+                        update.delete_properties.push(v.clone());
+                    } else if let Some(name) = form_property(k, "delete") {
+                        push_value(&mut update.delete_values, name, v);
+                    }
+                }
+                Ok(MicropubAction::Update(update))
+            }
+            Some("delete") => Ok(MicropubAction::Delete(url()?)),
+            Some("undelete") => Ok(MicropubAction::Undelete(url()?)),
+            Some(other) => Err(MicropubFormError::UnsupportedAction(other.to_string())),
+        }
+    }
+
+    /// Scope the token must have to perform the action.
+    #[must_use]
+    pub fn scope(&self) -> &'static str {
+        match self {
+            MicropubAction::Create(_) => "create",
+            MicropubAction::Update(_) => "update",
+            MicropubAction::Delete(_) | MicropubAction::Undelete(_) => "delete",
+        }
+    }
+}
+
+fn into_form_error(e: anyhow::Error) -> MicropubFormError {
+    match e.downcast::<MicropubFormError>() {
+        Ok(e) => e,
+        Err(e) => MicropubFormError::InvalidRequest(e.to_string()),
+    }
+}
+
+/// Extracts property name from keys like `replace[name]` or `add[category][]`.
+fn form_property<'a>(key: &'a str, operation: &str) -> Option<&'a str> {
+    let rest = key.strip_prefix(operation)?.strip_prefix('[')?;
+    let rest = rest.strip_suffix("[]").unwrap_or(rest);
+    rest.strip_suffix(']').filter(|name| !name.is_empty())
+}
+
+fn push_value(properties: &mut Vec<(String, Vec<Value>)>, name: &str, value: &str) {
+    let value = Value::String(value.to_string());
+    if let Some((_, values)) = properties.iter_mut().find(|(n, _)| n == name) {
+        values.push(value);
+    } else {
+        properties.push((name.to_string(), vec![value]));
+    }
+}
+
+fn property_values(
+    value: &Value,
+    operation: &str,
+) -> Result<Vec<(String, Vec<Value>)>, MicropubFormError> {
+    let Value::Object(properties) = value else {
+        return Err(MicropubFormError::InvalidRequest(format!(
+            "{operation} must be an object"
+        )));
+    };
+    properties
+        .iter()
+        .map(|(name, values)| match values {
+            Value::Array(values) => Ok((name.clone(), values.clone())),
+            _ => Err(MicropubFormError::InvalidRequest(format!(
+                "values of '{name}' in {operation} must be an array"
+            ))),
+        })
+        .collect()
+}
+
+/// Micropub update request: properties to replace, values to add or delete
+/// and properties to delete entirely.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct MicropubUpdate {
+    /// URL of the post to update.
+    pub url: String,
+    pub replace: Vec<(String, Vec<Value>)>,
+    pub add: Vec<(String, Vec<Value>)>,
+    pub delete_values: Vec<(String, Vec<Value>)>,
+    pub delete_properties: Vec<String>,
+}
+
+impl MicropubUpdate {
+    /// Applies update to the post. Unknown properties are ignored.
+    pub fn apply(&self, post: &mut Post) -> Result<(), MicropubFormError> {
+        for (name, values) in &self.replace {
+            match name.as_str() {
+                "name" => post.title = first_string(values).unwrap_or_default(),
+                "summary" => post.short_text = first_string(values).unwrap_or_default(),
+                "content" => {
+                    let (text, markdown) = content_value(values).ok_or_else(|| {
+                        MicropubFormError::InvalidRequest("content cannot be empty".into())
+                    })?;
+                    post.text = text;
+                    post.markdown = markdown;
+                }
+                "category" => post.tags = strings(values),
+                "post-status" => {
+                    let status = first_string(values).as_deref().and_then(PostStatus::parse);
+                    post.status = status.unwrap_or_default();
+                    post.is_public = post.status == PostStatus::Published;
+                }
+                "published" => {
+                    post.created = first_string(values)
+                        .as_deref()
+                        .and_then(parse_micropub_datetime)
+                        .ok_or_else(|| {
+                            MicropubFormError::InvalidRequest("invalid published date".into())
+                        })?;
+                }
+                other => tracing::warn!("update of unsupported property '{other}' ignored"),
+            }
+        }
+        // Published in the future means scheduled
+        if post.status == PostStatus::Published && post.created > Utc::now() {
+            post.status = PostStatus::Scheduled;
+            post.is_public = false;
+        }
+
+        for (name, values) in &self.add {
+            match name.as_str() {
+                "category" => {
+                    for tag in strings(values) {
+                        if !post.tags.contains(&tag) {
+                            post.tags.push(tag);
+                        }
+                    }
+                }
+                other => {
+                    return Err(MicropubFormError::InvalidRequest(format!(
+                        "cannot add values to '{other}'"
+                    )));
+                }
+            }
+        }
+
+        for (name, values) in &self.delete_values {
+            match name.as_str() {
+                "category" => {
+                    let removed = strings(values);
+                    post.tags.retain(|t| !removed.contains(t));
+                }
+                other => {
+                    return Err(MicropubFormError::InvalidRequest(format!(
+                        "cannot delete values of '{other}'"
+                    )));
+                }
+            }
+        }
+
+        for name in &self.delete_properties {
+            match name.as_str() {
+                "name" => post.title.clear(),
+                "summary" => post.short_text.clear(),
+                "category" => post.tags.clear(),
+                "content" => {
+                    return Err(MicropubFormError::InvalidRequest(
+                        "content cannot be deleted".into(),
+                    ));
+                }
+                other => tracing::warn!("delete of unsupported property '{other}' ignored"),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn strings(values: &[Value]) -> Vec<String> {
+    values
+        .iter()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect()
+}
+
+fn first_string(values: &[Value]) -> Option<String> {
+    values.iter().find_map(Value::as_str).map(str::to_string)
+}
+
+/// Content text and markdown flag from either plain, `{"html": ..}` or `{"markdown": ..}` value.
+fn content_value(values: &[Value]) -> Option<(String, bool)> {
+    match values.first()? {
+        Value::String(text) => Some((text.clone(), true)),
+        Value::Object(map) => {
+            if let Some(html) = map.get("html").and_then(Value::as_str) {
+                Some((html.to_string(), false))
+            } else {
+                map.get("markdown")
+                    .and_then(Value::as_str)
+                    .map(|md| (md.to_string(), true))
+            }
+        }
+        _ => None,
+    }
+}
+
 /// Microformats2 JSON representation of a post for Micropub `q=source` responses.
 #[derive(Debug, PartialEq, Serialize)]
 pub struct MicropubSource {
@@ -541,9 +901,14 @@ impl MicropubSource {
             json!([post.created.format("%Y-%m-%d %H:%M:%S").to_string()]),
         );
 
-        let status = if post.is_public { "published" } else { "draft" };
+        let status = post.effective_status().as_str();
         properties.insert("post-status".into(), json!([status]));
 
+        if let Some(slug) = &post.slug {
+            properties.insert("mp-slug".into(), json!([slug]));
+        }
+        properties.insert("url".into(), json!([format!("{ME}{}", post.path())]));
+
         Self {
             entry_type: vec!["h-entry".into()],
             properties,
@@ -551,7 +916,7 @@ impl MicropubSource {
     }
 }
 
-/// Extracts a blog post id from a canonical post URL.
+/// Extracts a blog post id from a numeric post URL `/blog/{id}.html`.
 #[must_use]
 pub fn parse_post_url(site_url: &str, post_url: &str) -> Option<i64> {
     let site_url = site_url.trim_end_matches('/');
@@ -564,11 +929,33 @@ pub fn parse_post_url(site_url: &str, post_url: &str) -> Option<i64> {
     id_part.parse().ok()
 }
 
+/// Extracts a blog post slug from a human-readable post URL `/blog/{year}/{slug}`.
+#[must_use]
+pub fn parse_post_slug(site_url: &str, post_url: &str) -> Option<String> {
+    let site_url = site_url.trim_end_matches('/');
+    let post_url = post_url.trim().trim_end_matches('/');
+    let prefix = format!("{site_url}/blog/");
+    let (year, slug) = post_url.strip_prefix(&prefix)?.split_once('/')?;
+    if year.parse::<i32>().is_err() || slug.is_empty() || slug.contains('/') {
+        return None;
+    }
+    Some(slug.to_owned())
+}
+
+/// Finds post ID by either numeric or human-readable post URL.
+pub fn resolve_post_id<S: Storage>(storage: &S, url: &str) -> Option<i64> {
+    parse_post_url(ME, url).or_else(|| {
+        let slug = parse_post_slug(ME, url)?;
+        storage.get_post_id(&slug).ok()
+    })
+}
+
 #[cfg(test)]
 mod test {
     #![allow(clippy::unwrap_in_result)]
     #![allow(clippy::unwrap_used)]
     use super::*;
+    use rstest::rstest;
 
     #[test]
     fn micropub_form_decode_category_as_array() {
@@ -887,10 +1274,62 @@ mod test {
         let post = form.to_post(7);
 
         assert!(!post.is_public);
+        assert_eq!(post.status, PostStatus::Draft);
         assert_eq!(post.tags, vec!["micropub"]);
         assert!(post.markdown);
     }
 
+    #[rstest]
+    #[case(None, "2020-04-04T15:30:00Z", PostStatus::Draft)]
+    #[case(Some("draft"), "2020-04-04T15:30:00Z", PostStatus::Draft)]
+    #[case(Some("published"), "2020-04-04T15:30:00Z", PostStatus::Published)]
+    #[case(Some("published"), "2020-04-05T15:30:00Z", PostStatus::Scheduled)]
+    #[case(Some("scheduled"), "2020-04-05T15:30:00Z", PostStatus::Scheduled)]
+    #[case(Some("unknown"), "2020-04-04T15:30:00Z", PostStatus::Draft)]
+    fn micropub_form_status_tests(
+        #[case] post_status: Option<&str>,
+        #[case] published: &str,
+        #[case] expected: PostStatus,
+    ) {
+        // arrange
+        let form = MicropubForm {
+            post_status: post_status.map(str::to_owned),
+            ..Default::default()
+        };
+        let now = parse_micropub_datetime("2020-04-04T16:00:00Z").unwrap();
+        let published = parse_micropub_datetime(published).unwrap();
+
+        // act
+        let actual = form.status(published, now);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn micropub_update_apply_schedules_post_published_in_future() {
+        // arrange
+        let mut post = Post {
+            text: "text".into(),
+            ..Default::default()
+        };
+        let update = MicropubUpdate {
+            url: "u".into(),
+            replace: vec![
+                ("post-status".into(), vec![json!("published")]),
+                ("published".into(), vec![json!("2999-01-01T00:00:00Z")]),
+            ],
+            ..Default::default()
+        };
+
+        // act
+        update.apply(&mut post).unwrap();
+
+        // assert
+        assert!(!post.is_public);
+        assert_eq!(PostStatus::Scheduled, post.status);
+    }
+
     #[test]
     fn parse_post_url_extracts_numeric_id() {
         assert_eq!(
@@ -916,6 +1355,29 @@ mod test {
         );
     }
 
+    #[rstest]
+    #[case(
+        "https://www.egoroff.spb.ru/blog/2024/hello-world",
+        Some("hello-world")
+    )]
+    #[case(
+        "https://www.egoroff.spb.ru/blog/2024/hello-world/",
+        Some("hello-world")
+    )]
+    #[case("https://www.egoroff.spb.ru/blog/42.html", None)]
+    #[case("https://www.egoroff.spb.ru/blog/tag/hello", None)]
+    #[case("https://www.egoroff.spb.ru/blog/2024/a/b", None)]
+    #[case("https://example.com/blog/2024/hello-world", None)]
+    fn parse_post_slug_tests(#[case] url: &str, #[case] expected: Option<&str>) {
+        // arrange
+
+        // act
+        let actual = parse_post_slug("https://www.egoroff.spb.ru/", url);
+
+        // assert
+        assert_eq!(expected.map(str::to_owned), actual);
+    }
+
     #[test]
     fn micropub_source_from_published_markdown_post() {
         let post = Post {
@@ -928,6 +1390,8 @@ mod test {
             markdown: true,
             is_public: true,
             tags: vec!["publish-date".into()],
+            slug: Some("testing-published".into()),
+            status: PostStatus::Published,
         };
 
         let source = MicropubSource::from_post(&post);
@@ -944,6 +1408,11 @@ mod test {
             json!(["2020-04-04 15:30:00"])
         );
         assert_eq!(source.properties["post-status"], json!(["published"]));
+        assert_eq!(source.properties["mp-slug"], json!(["testing-published"]));
+        assert_eq!(
+            source.properties["url"],
+            json!(["https://www.egoroff.spb.ru/blog/2020/testing-published"])
+        );
     }
 
     #[test]
@@ -958,6 +1427,8 @@ mod test {
             markdown: false,
             is_public: false,
             tags: vec![],
+            slug: None,
+            status: PostStatus::Draft,
         };
 
         let source = MicropubSource::from_post(&post);
@@ -968,5 +1439,191 @@ mod test {
             json!([{"html": "<p>draft</p>"}])
         );
         assert_eq!(source.properties["post-status"], json!(["draft"]));
+        assert!(!source.properties.contains_key("mp-slug"));
+        assert_eq!(
+            source.properties["url"],
+            json!(["https://www.egoroff.spb.ru/blog/2.html"])
+        );
+    }
+
+    #[test]
+    fn micropub_action_json_without_action_is_create() {
+        // arrange
+        let bytes = b"{\"type\":[\"h-entry\"],\"properties\":{\"content\":[\"hello\"]}}";
+
+        // act
+        let action = MicropubAction::from_json_bytes(&bytes[..]).unwrap();
+
+        // assert
+        assert!(matches!(action, MicropubAction::Create(f) if f.content == "hello"));
+    }
+
+    #[test]
+    fn micropub_action_json_update() {
+        // arrange
+        let bytes = br#"{"action":"update","url":"https://www.egoroff.spb.ru/blog/1.html","replace":{"content":["new"]},"add":{"category":["b"]},"delete":{"category":["a"]}}"#;
+
+        // act
+        let action = MicropubAction::from_json_bytes(&bytes[..]).unwrap();
+
+        // assert
+        let expected = MicropubUpdate {
+            url: "https://www.egoroff.spb.ru/blog/1.html".into(),
+            replace: vec![("content".into(), vec![json!("new")])],
+            add: vec![("category".into(), vec![json!("b")])],
+            delete_values: vec![("category".into(), vec![json!("a")])],
+            delete_properties: vec![],
+        };
+        assert_eq!(MicropubAction::Update(expected), action);
+    }
+
+    #[test]
+    fn micropub_action_json_update_delete_properties() {
+        // arrange
+        let bytes = br#"{"action":"update","url":"u","delete":["category","name"]}"#;
+
+        // act
+        let action = MicropubAction::from_json_bytes(&bytes[..]).unwrap();
+
+        // assert
+        assert!(matches!(
+            action,
+            MicropubAction::Update(u) if u.delete_properties == vec!["category", "name"]
+        ));
+    }
+
+    #[rstest]
+    #[case(br#"{"action":"update","url":"u","replace":{"content":"x"}}"#.as_slice(), MicropubFormError::InvalidRequest("values of 'content' in replace must be an array".into()))]
+    #[case(br#"{"action":"update","url":"u","delete":"name"}"#.as_slice(), MicropubFormError::InvalidRequest("delete must be an array or an object".into()))]
+    #[case(br#"{"action":"delete"}"#.as_slice(), MicropubFormError::MissingField("url".into()))]
+    #[case(br#"{"action":"publish","url":"u"}"#.as_slice(), MicropubFormError::UnsupportedAction("publish".into()))]
+    #[case(br#"{"type":["h-entry"],"properties":{}}"#.as_slice(), MicropubFormError::MissingField("content".into()))]
+    fn micropub_action_json_errors(#[case] bytes: &[u8], #[case] expected: MicropubFormError) {
+        // arrange
+
+        // act
+        let actual = MicropubAction::from_json_bytes(bytes).unwrap_err();
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case(b"action=delete&url=https%3A%2F%2Fwww.egoroff.spb.ru%2Fblog%2F1.html".as_slice(), MicropubAction::Delete("https://www.egoroff.spb.ru/blog/1.html".into()))]
+    #[case(b"action=undelete&url=u".as_slice(), MicropubAction::Undelete("u".into()))]
+    fn micropub_action_form_delete_undelete(
+        #[case] bytes: &[u8],
+        #[case] expected: MicropubAction,
+    ) {
+        // arrange
+
+        // act
+        let actual = MicropubAction::from_form_bytes(bytes).unwrap();
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn micropub_action_form_update() {
+        // arrange
+        let qs = b"action=update&url=u&replace%5Bname%5D=Title&add%5Bcategory%5D%5B%5D=a&add%5Bcategory%5D%5B%5D=b&delete%5B%5D=summary";
+
+        // act
+        let action = MicropubAction::from_form_bytes(&qs[..]).unwrap();
+
+        // assert
+        let expected = MicropubUpdate {
+            url: "u".into(),
+            replace: vec![("name".into(), vec![json!("Title")])],
+            add: vec![("category".into(), vec![json!("a"), json!("b")])],
+            delete_values: vec![],
+            delete_properties: vec!["summary".into()],
+        };
+        assert_eq!(MicropubAction::Update(expected), action);
+    }
+
+    #[rstest]
+    #[case(MicropubAction::Create(MicropubForm { access_token: None, h: "entry".into(), content: String::new(), content_type: None, category: vec![], name: None, created_at: None, updated_at: None, slug: None, bookmark_of: None, photos: None, post_status: None }), "create")]
+    #[case(MicropubAction::Update(MicropubUpdate::default()), "update")]
+    #[case(MicropubAction::Delete(String::new()), "delete")]
+    #[case(MicropubAction::Undelete(String::new()), "delete")]
+    fn micropub_action_scope(#[case] action: MicropubAction, #[case] expected: &str) {
+        // arrange
+
+        // act
+        let actual = action.scope();
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn micropub_update_apply_replaces_adds_and_deletes() {
+        // arrange
+        let mut post = Post {
+            title: "Old".into(),
+            short_text: "Summary".into(),
+            text: "old".into(),
+            markdown: true,
+            tags: vec!["a".into(), "b".into()],
+            ..Default::default()
+        };
+        let update = MicropubUpdate {
+            url: "u".into(),
+            replace: vec![
+                ("content".into(), vec![json!({"html": "<p>new</p>"})]),
+                ("post-status".into(), vec![json!("published")]),
+            ],
+            add: vec![("category".into(), vec![json!("b"), json!("c")])],
+            delete_values: vec![("category".into(), vec![json!("a")])],
+            delete_properties: vec!["name".into()],
+        };
+
+        // act
+        update.apply(&mut post).unwrap();
+
+        // assert
+        assert_eq!("", post.title);
+        assert_eq!("Summary", post.short_text);
+        assert_eq!("<p>new</p>", post.text);
+        assert!(!post.markdown);
+        assert!(post.is_public);
+        assert_eq!(vec!["b", "c"], post.tags);
+    }
+
+    #[rstest]
+    #[case(MicropubUpdate { add: vec![("name".into(), vec![json!("x")])], ..Default::default() })]
+    #[case(MicropubUpdate { delete_properties: vec!["content".into()], ..Default::default() })]
+    #[case(MicropubUpdate { replace: vec![("published".into(), vec![json!("yesterday")])], ..Default::default() })]
+    fn micropub_update_apply_invalid(#[case] update: MicropubUpdate) {
+        // arrange
+        let mut post = Post::default();
+
+        // act
+        let result = update.apply(&mut post);
+
+        // assert
+        assert!(matches!(result, Err(MicropubFormError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn micropub_form_error_body_for_insufficient_scope() {
+        // arrange
+        let error = MicropubFormError::InsufficientScope("update".into());
+
+        // act
+        let body = serde_json::to_value(error.to_body()).unwrap();
+
+        // assert
+        assert_eq!(StatusCode::FORBIDDEN, error.status());
+        assert_eq!(
+            json!({
+                "error": "insufficient_scope",
+                "error_description": "Scope 'update' is required for this action.",
+                "scope": "update"
+            }),
+            body
+        );
     }
 }
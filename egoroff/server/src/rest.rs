@@ -6,25 +6,22 @@ use axum::handler::Handler;
 use axum::routing::{delete, post, put};
 use axum::{Router, routing::get};
 use axum_prometheus::PrometheusMetricLayer;
+use axum_prometheus::metrics_exporter_prometheus::PrometheusHandle;
 
-use axum_login::{AuthManagerLayerBuilder, login_required, permission_required};
 use axum::http::{Method, header};
+use axum_login::{AuthManagerLayerBuilder, login_required, permission_required};
 use tower_sessions::cookie::{SameSite, time::Duration};
 use tower_sessions::{Expiry, SessionManagerLayer};
 
-use crate::domain::PageContext;
-use futures::lock::Mutex;
+use crate::domain::{Database, PageContext};
 use indie::RequireIndieAuthorizationLayer;
-use kernel::domain::{ApiResult, SmallPost};
-use kernel::graph::SiteGraph;
+use kernel::domain::{ApiResult, PostNavigation, SmallPost, Storage};
 use kernel::session::SqliteSessionStore;
-use kernel::sqlite::{Mode, Sqlite};
 use rand::RngExt;
-use std::collections::HashSet;
 use std::env;
 use std::path::Path;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
 use tower::ServiceBuilder;
 use tower_http::classify::ServerErrorsFailureClass;
 use tower_http::compression::predicate::NotForContentType;
@@ -38,9 +35,12 @@ use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
 use utoipa::{Modify, OpenApi};
 use utoipa_swagger_ui::SwaggerUi;
 
-use crate::domain::Config;
 use crate::{handlers, indie, micropub};
 
+/// Prometheus recorder is global so it's installed once even if routes are created many times.
+static METRIC_HANDLE: LazyLock<PrometheusHandle> =
+    LazyLock::new(|| PrometheusMetricLayer::pair().1);
+
 struct SecurityAddon;
 
 impl Modify for SecurityAddon {
@@ -58,6 +58,7 @@ impl Modify for SecurityAddon {
 #[openapi(
         paths(
             handlers::blog::serve_posts_api,
+            handlers::blog::serve_post_navigation_api,
             handlers::portfolio::serve_downloadable_files,
             handlers::search::serve_search_api,
             handlers::micropub::serve_index_get,
@@ -66,9 +67,10 @@ impl Modify for SecurityAddon {
             handlers::micropub::serve_media_endpoint_get,
             handlers::indie::serve_token_generate,
             handlers::indie::serve_token_validate,
+            handlers::indie::serve_token_introspect,
         ),
         components(
-            schemas(SmallPost, ApiResult<SmallPost>, micropub::MicropubConfig, micropub::SyndicateTo, micropub::MicropubFormError, indie::TokenValidationResult, indie::Token, indie::TokenRequest, handlers::micropub::MediaResponse),
+            schemas(SmallPost, ApiResult<SmallPost>, PostNavigation, micropub::MicropubConfig, micropub::SyndicateTo, micropub::MicropubFormError, micropub::MicropubError, indie::TokenValidationResult, indie::Token, indie::TokenRequest, indie::IntrospectionRequest, indie::IntrospectionResult, handlers::micropub::MediaResponse),
         ),
         modifiers(&SecurityAddon),
         tags(
@@ -77,33 +79,16 @@ impl Modify for SecurityAddon {
     )]
 struct ApiDoc;
 
-pub fn create_routes(
-    base_path: PathBuf,
-    site_graph: Arc<SiteGraph<'static>>,
-    site_config: Config,
+pub fn create_routes<S: Storage + Send + 'static>(
+    page_context: Arc<PageContext<'static, S>>,
     data_path: &Path,
-    store_uri: String,
-    certs_path: String,
 ) -> Result<Router> {
     let storage_path = data_path.join(kernel::sqlite::DATABASE);
     let sessions_path = data_path.join(crate::SESSIONS_DATABASE);
 
     let auth_backend = AuthBackend::from(storage_path.clone());
 
-    let storage = Sqlite::open(&storage_path, Mode::ReadWrite)?;
-    let storage = Arc::new(Mutex::new(storage));
-    let cache = Arc::new(Mutex::new(HashSet::new()));
-    let micropub_api = micropub_api(&certs_path);
-
-    let page_context = Arc::new(PageContext {
-        base_path,
-        storage,
-        site_graph,
-        site_config,
-        store_uri,
-        certs_path,
-        cache,
-    });
+    let micropub_api = micropub_api(&page_context.certs_path, page_context.storage.clone());
 
     let secret = rand::rng().random::<[u8; 64]>();
     let session_store = SqliteSessionStore::open(sessions_path, &secret)?;
@@ -141,43 +126,58 @@ pub fn create_routes(
         .and(NotForContentType::new("application/octet-stream"))
         .and(NotForContentType::new("application/json"));
 
-    let (prometheus_layer, metric_handle) = PrometheusMetricLayer::pair();
+    let prometheus_layer = PrometheusMetricLayer::new();
+    let metric_handle = METRIC_HANDLE.clone();
     let router = Router::new()
-        .route("/auth", get(handlers::indie::serve_auth))
+        .route(
+            "/auth",
+            get(handlers::indie::serve_auth).post(handlers::indie::serve_auth_approve::<S>),
+        )
         .route("/admin", get(handlers::admin::serve))
-        .nest("/api/v2/admin", admin_api())
+        .nest("/api/v2/admin", admin_api::<S>())
         // Important all admin protected routes must be the first in the list
         .route_layer(permission_required!(
             AuthBackend,
             login_url = handlers::auth::LOGIN_URI,
             Role::Admin
         ))
-        .merge(auth_routes())
+        .merge(auth_routes::<S>())
         // Important all protected routes must be the first in the list
         .route_layer(login_required!(
             AuthBackend,
             login_url = handlers::auth::LOGIN_URI
         ))
-        .route("/", get(handlers::serve_index))
-        .route("/sitemap.xml", get(handlers::serve_sitemap))
-        .route("/search/", get(handlers::serve_search))
-        .route("/storage/{bucket}/{path}", get(handlers::serve_storage))
+        .route("/", get(handlers::serve_index::<S>))
+        .route("/sitemap.xml", get(handlers::serve_sitemap::<S>))
+        .route("/sitemap/{file}", get(handlers::serve_sitemap_page::<S>))
+        .route("/search/", get(handlers::serve_search::<S>))
+        .route(
+            "/storage/{bucket}/{path}",
+            get(handlers::serve_storage::<S>),
+        )
         .route(
             "/token",
-            post(handlers::indie::serve_token_generate).get(handlers::indie::serve_token_validate),
+            post(handlers::indie::serve_token_generate::<S>)
+                .get(handlers::indie::serve_token_validate::<S>),
         )
         .route(
             "/token/",
-            post(handlers::indie::serve_token_generate).get(handlers::indie::serve_token_validate),
+            post(handlers::indie::serve_token_generate::<S>)
+                .get(handlers::indie::serve_token_validate::<S>),
         )
+        .route(
+            "/webmention",
+            post(handlers::webmention::serve_webmention::<S>),
+        )
+        .route("/websub", post(handlers::websub::serve_hub::<S>))
         .route("/metrics", get(|| async move { metric_handle.render() }))
         .merge(SwaggerUi::new("/api/v2").url("/api/v2/openapi.json", ApiDoc::openapi()))
         .merge(micropub_api)
-        .merge(blog_routes())
-        .nest("/portfolio/", portfolio_routes())
-        .merge(static_resources_routes())
-        .nest("/api/v2", public_api())
-        .merge(oauth2_routes(&storage_path)?)
+        .merge(blog_routes::<S>())
+        .nest("/portfolio/", portfolio_routes::<S>())
+        .merge(static_resources_routes::<S>())
+        .nest("/api/v2", public_api::<S>())
+        .merge(oauth2_routes::<S>(&storage_path)?)
         .layer(session_service)
         .layer(CompressionLayer::new().compress_when(compress_predicate))
         .layer(RequestBodyLimitLayer::new(20 * 1024 * 1024))
@@ -188,42 +188,100 @@ pub fn create_routes(
     Ok(router)
 }
 
-fn admin_api() -> Router<Arc<PageContext<'static>>> {
+fn admin_api<S: Storage + Send + 'static>() -> Router<Arc<PageContext<'static, S>>> {
     Router::new()
-        .route("/posts/", get(handlers::blog::serve_posts_admin_api))
+        .route("/posts/", get(handlers::blog::serve_posts_admin_api::<S>))
         .route(
             "/post",
-            put(handlers::blog::serve_post_update).post(handlers::blog::serve_post_create),
+            put(handlers::blog::serve_post_update::<S>)
+                .post(handlers::blog::serve_post_create::<S>),
+        )
+        .route("/post/{id}", delete(handlers::blog::serve_post_delete::<S>))
+        .route(
+            "/post/{id}/schedule",
+            put(handlers::blog::serve_post_schedule::<S>),
+        )
+        .route("/drafts/", get(handlers::blog::serve_drafts_admin_api::<S>))
+        .route("/tags/", get(handlers::blog::serve_tags_admin_api::<S>))
+        .route("/tags/merge", post(handlers::blog::serve_tags_merge::<S>))
+        .route(
+            "/tags/orphans",
+            delete(handlers::blog::serve_orphan_tags_delete::<S>),
+        )
+        .route("/tags/{tag}", put(handlers::blog::serve_tag_update::<S>))
+        .route(
+            "/tags/{tag}/rename",
+            put(handlers::blog::serve_tag_rename::<S>),
+        )
+        .route(
+            "/post/{id}/webmentions/",
+            get(handlers::webmention::serve_post_webmentions_admin_api::<S>),
+        )
+        .route(
+            "/post/{id}/revisions/",
+            get(handlers::blog::serve_post_revisions_admin_api::<S>),
+        )
+        .route(
+            "/post/{id}/revisions/diff",
+            get(handlers::blog::serve_post_revisions_diff::<S>),
+        )
+        .route(
+            "/post/{id}/revisions/{revision}/restore",
+            put(handlers::blog::serve_post_revision_restore::<S>),
         )
-        .route("/post/{id}", delete(handlers::blog::serve_post_delete))
         .route(
             "/download/",
-            put(handlers::portfolio::serve_download_update)
-                .get(handlers::portfolio::serve_downloads_admin_api),
+            put(handlers::portfolio::serve_download_update::<S>)
+                .get(handlers::portfolio::serve_downloads_admin_api::<S>),
         )
         .route(
             "/download/{id}",
-            delete(handlers::portfolio::serve_download_delete),
+            delete(handlers::portfolio::serve_download_delete::<S>),
+        )
+        .route(
+            "/dashboard/",
+            get(handlers::admin::serve_dashboard_api::<S>),
+        )
+        .route("/users/", get(handlers::auth::serve_users_api::<S>))
+        .route(
+            "/tokens/",
+            get(handlers::indie::serve_tokens_admin_api::<S>),
+        )
+        .route(
+            "/webmentions/",
+            get(handlers::webmention::serve_webmentions_admin_api::<S>),
+        )
+        .route(
+            "/webmentions/{id}",
+            put(handlers::webmention::serve_webmention_moderate::<S>)
+                .delete(handlers::webmention::serve_webmention_delete::<S>),
         )
-        .route("/dashboard/", get(handlers::admin::serve_dashboard_api))
-        .route("/users/", get(handlers::auth::serve_users_api))
 }
 
-fn public_api() -> Router<Arc<PageContext<'static>>> {
+fn public_api<S: Storage + Send + 'static>() -> Router<Arc<PageContext<'static, S>>> {
     Router::new()
-        .route("/navigation/", get(handlers::serve_navigation))
-        .route("/blog/archive/", get(handlers::blog::serve_archive_api))
-        .route("/blog/posts/", get(handlers::blog::serve_posts_api))
-        .route("/search/", get(handlers::search::serve_search_api))
+        .route("/navigation/", get(handlers::serve_navigation::<S>))
+        .route(
+            "/blog/archive/",
+            get(handlers::blog::serve_archive_api::<S>),
+        )
+        .route("/blog/posts/", get(handlers::blog::serve_posts_api::<S>))
+        .route(
+            "/blog/posts/{id}/related/",
+            get(handlers::blog::serve_post_navigation_api::<S>),
+        )
+        .route("/search/", get(handlers::search::serve_search_api::<S>))
         .route(
             "/portfolio/files/",
-            get(handlers::portfolio::serve_downloadable_files),
+            get(handlers::portfolio::serve_downloadable_files::<S>),
         )
         .route("/auth/user/", get(handlers::auth::serve_user_api_call))
         .route("/auth/user", get(handlers::auth::serve_user_api_call))
 }
 
-fn oauth2_routes(storage_path: &Path) -> Result<Router<Arc<PageContext<'static>>>> {
+fn oauth2_routes<S: Storage + Send + 'static>(
+    storage_path: &Path,
+) -> Result<Router<Arc<PageContext<'static, S>>>> {
     let google_authorizer = GoogleAuthorizer::new(storage_path)?;
     let github_authorizer = GithubAuthorizer::new(storage_path)?;
     let yandex_authorizer = YandexAuthorizer::new(storage_path)?;
@@ -240,22 +298,25 @@ fn oauth2_routes(storage_path: &Path) -> Result<Router<Arc<PageContext<'static>>
     let callbacks = Router::new()
         .route(
             "/google/authorized/",
-            get(handlers::auth::google_oauth_callback).layer(Extension(google_authorizer)),
+            get(handlers::auth::google_oauth_callback::<S>).layer(Extension(google_authorizer)),
         )
         .route(
             "/github/authorized/",
-            get(handlers::auth::github_oauth_callback).layer(Extension(github_authorizer)),
+            get(handlers::auth::github_oauth_callback::<S>).layer(Extension(github_authorizer)),
         )
         .route(
             "/yandex/authorized/",
-            get(handlers::auth::yandex_oauth_callback).layer(Extension(yandex_authorizer)),
+            get(handlers::auth::yandex_oauth_callback::<S>).layer(Extension(yandex_authorizer)),
         );
     Ok(Router::new()
         .route(handlers::auth::LOGIN_URI, get(login_handler))
         .nest("/_s/callback", callbacks))
 }
 
-fn micropub_api(certs_path: &str) -> Router<Arc<PageContext<'static>>> {
+fn micropub_api<S: Storage + Send + 'static>(
+    certs_path: &str,
+    storage: Database<S>,
+) -> Router<Arc<PageContext<'static, S>>> {
     let public_key_path = PathBuf::from(certs_path)
         .join("egoroffspbrupub.pem")
         .to_str()
@@ -266,56 +327,105 @@ fn micropub_api(certs_path: &str) -> Router<Arc<PageContext<'static>>> {
     Router::new()
         .route(
             "/micropub/",
-            get(handlers::micropub::serve_index_get)
-                .post(handlers::micropub::serve_index_post)
+            get(handlers::micropub::serve_index_get::<S>)
+                .post(handlers::micropub::serve_index_post::<S>)
                 .layer(RequireIndieAuthorizationLayer::auth(
                     public_key_path.clone(),
+                    storage.clone(),
                 )),
         )
         .route(
             "/micropub",
-            get(handlers::micropub::serve_index_get)
-                .post(handlers::micropub::serve_index_post)
+            get(handlers::micropub::serve_index_get::<S>)
+                .post(handlers::micropub::serve_index_post::<S>)
                 .layer(RequireIndieAuthorizationLayer::auth(
                     public_key_path.clone(),
+                    storage.clone(),
                 )),
         )
         .route(
             "/micropub/media",
-            get(handlers::micropub::serve_media_endpoint_get)
-                .post(handlers::micropub::serve_media_endpoint_post)
-                .layer(RequireIndieAuthorizationLayer::auth(public_key_path)),
+            get(handlers::micropub::serve_media_endpoint_get::<S>)
+                .post(handlers::micropub::serve_media_endpoint_post::<S>)
+                .layer(RequireIndieAuthorizationLayer::scoped(
+                    public_key_path.clone(),
+                    storage.clone(),
+                    "media",
+                )),
+        )
+        .route(
+            "/token/introspect",
+            post(handlers::indie::serve_token_introspect::<S>).layer(
+                RequireIndieAuthorizationLayer::auth(public_key_path, storage),
+            ),
         )
 }
 
-fn blog_routes() -> Router<Arc<PageContext<'static>>> {
+fn blog_routes<S: Storage + Send + 'static>() -> Router<Arc<PageContext<'static, S>>> {
     Router::new()
-        .route("/blog/", get(handlers::blog::serve_index_default))
+        .route("/blog/", get(handlers::blog::serve_index_default::<S>))
         .route("/news/", get(handlers::blog::redirect))
-        .route("/opinions/", get(handlers::blog::serve_index_default))
+        .route("/opinions/", get(handlers::blog::serve_index_default::<S>))
         .route(
             "/blog/page/{page}",
-            get(handlers::blog::serve_index_not_default),
+            get(handlers::blog::serve_index_not_default::<S>),
         )
         .route(
             "/blog/page/{page}/",
-            get(handlers::blog::serve_index_not_default),
+            get(handlers::blog::serve_index_not_default::<S>),
+        )
+        .route("/blog/recent.atom", get(handlers::blog::serve_atom::<S>))
+        .route("/blog/recent.rss", get(handlers::blog::serve_rss::<S>))
+        .route("/blog/feed.json", get(handlers::blog::serve_json_feed::<S>))
+        .route(
+            "/blog/tag/{tag}/",
+            get(handlers::blog::serve_archive_index::<S>),
+        )
+        .route(
+            "/blog/tag/{tag}/recent.atom",
+            get(handlers::blog::serve_tag_atom::<S>),
+        )
+        .route(
+            "/blog/tag/{tag}/page/{page}/",
+            get(handlers::blog::serve_archive_index::<S>),
+        )
+        .route(
+            "/blog/{year}/",
+            get(handlers::blog::serve_archive_index::<S>),
+        )
+        .route(
+            "/blog/{year}/page/{page}/",
+            get(handlers::blog::serve_archive_index::<S>),
+        )
+        .route(
+            "/blog/{year}/{month}/",
+            get(handlers::blog::serve_archive_index::<S>),
+        )
+        .route(
+            "/blog/{year}/{month}/page/{page}/",
+            get(handlers::blog::serve_archive_index::<S>),
+        )
+        .route("/blog/{path}", get(handlers::blog::serve_document::<S>))
+        .route(
+            "/blog/{year}/{slug}",
+            get(handlers::blog::serve_post_by_slug::<S>),
         )
-        .route("/blog/recent.atom", get(handlers::blog::serve_atom))
-        .route("/blog/{path}", get(handlers::blog::serve_document))
         .route(
             "/opinions/{path}",
             get(handlers::blog::redirect_to_real_document),
         )
-        .route("/news/rss", get(handlers::blog::serve_atom))
-        .route("/news/rss/", get(handlers::blog::serve_atom))
-        .route("/recent.atom", get(handlers::blog::serve_atom))
+        .route("/news/rss", get(handlers::blog::serve_rss::<S>))
+        .route("/news/rss/", get(handlers::blog::serve_rss::<S>))
+        .route("/recent.atom", get(handlers::blog::serve_atom::<S>))
 }
 
-fn portfolio_routes() -> Router<Arc<PageContext<'static>>> {
+fn portfolio_routes<S: Storage + Send + 'static>() -> Router<Arc<PageContext<'static, S>>> {
     Router::new()
-        .route("/", get(handlers::portfolio::serve_index))
-        .route("/{path}", get(handlers::portfolio::serve_apache_document))
+        .route("/", get(handlers::portfolio::serve_index::<S>))
+        .route(
+            "/{path}",
+            get(handlers::portfolio::serve_apache_document::<S>),
+        )
         .route(
             "/apache/{path}",
             get(handlers::portfolio::redirect_to_real_document),
@@ -326,7 +436,7 @@ fn portfolio_routes() -> Router<Arc<PageContext<'static>>> {
         )
 }
 
-fn auth_routes() -> Router<Arc<PageContext<'static>>> {
+fn auth_routes<S: Storage + Send + 'static>() -> Router<Arc<PageContext<'static, S>>> {
     Router::new()
         .route("/profile", get(handlers::auth::serve_profile))
         .route(
@@ -345,7 +455,7 @@ fn auth_routes() -> Router<Arc<PageContext<'static>>> {
         )
 }
 
-fn static_resources_routes() -> Router<Arc<PageContext<'static>>> {
+fn static_resources_routes<S: Storage + Send + 'static>() -> Router<Arc<PageContext<'static, S>>> {
     Router::new()
         .route("/{path}", get(handlers::serve_root))
         .route("/js/{path}", get(handlers::serve_js))
@@ -354,3 +464,985 @@ fn static_resources_routes() -> Router<Arc<PageContext<'static>>> {
         .route("/apache/{path}", get(handlers::serve_apache))
         .route("/apache/images/{path}", get(handlers::serve_apache_images))
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::domain::Config;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use chrono::{DateTime, Utc};
+    use kernel::domain::{Post, PostStatus, TagInfo, Webmention, WebmentionKind, WebmentionStatus};
+    use kernel::graph::{SiteGraph, SiteSection};
+    use kernel::memory::Memory;
+    use kernel::pool::Pool;
+    use kernel::sqlite::{DATABASE, Mode, Sqlite};
+    use rstest::rstest;
+    use serde_json::Value;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    static SITE_MAP: LazyLock<SiteSection> = LazyLock::new(|| {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../static/map.json");
+        serde_json::from_reader(std::fs::File::open(path).unwrap()).unwrap()
+    });
+
+    static NEXT_APP: AtomicUsize = AtomicUsize::new(0);
+
+    /// Router over in-memory blog. Sessions and OAuth settings
+    /// still need `SQLite` databases so they are kept in temp directory.
+    struct TestApp {
+        router: Router,
+        /// Admin API over the same blog without authentication layers.
+        admin: Router,
+        data_path: PathBuf,
+    }
+
+    impl TestApp {
+        fn new() -> Self {
+            let n = NEXT_APP.fetch_add(1, Ordering::Relaxed);
+            let data_path =
+                std::env::temp_dir().join(format!("egoroff_rest_{}_{n}", std::process::id()));
+            std::fs::create_dir_all(&data_path).unwrap();
+            create_oauth_providers(&data_path.join(DATABASE));
+
+            let base_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+            let site_config = Config {
+                search_backend: crate::domain::SearchBackend::Local,
+                ..Default::default()
+            };
+            let storage = Arc::new(Pool::new(blog(), vec![]));
+            let page_context = PageContext::new(
+                base_path,
+                storage,
+                Arc::new(SiteGraph::new(&SITE_MAP)),
+                site_config,
+                String::new(),
+                data_path.to_string_lossy().into_owned(),
+            )
+            .unwrap();
+            let page_context = Arc::new(page_context);
+            let admin = admin_api::<Memory>().with_state(page_context.clone());
+            let router = create_routes(page_context, &data_path).unwrap();
+            Self {
+                router,
+                admin,
+                data_path,
+            }
+        }
+
+        async fn get(&self, uri: &str) -> (StatusCode, Option<String>, String) {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let response = self.router.clone().oneshot(request).await.unwrap();
+            let status = response.status();
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .map(|l| l.to_str().unwrap().to_owned());
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (
+                status,
+                location,
+                String::from_utf8_lossy(&body).into_owned(),
+            )
+        }
+
+        /// Like `get` but gives `Content-Type` instead of `Location`.
+        async fn get_with_type(&self, uri: &str) -> (StatusCode, Option<String>, String) {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let response = self.router.clone().oneshot(request).await.unwrap();
+            let status = response.status();
+            let content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .map(|l| l.to_str().unwrap().to_owned());
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (
+                status,
+                content_type,
+                String::from_utf8_lossy(&body).into_owned(),
+            )
+        }
+
+        async fn post_form(&self, uri: &str, form: &[(&str, &str)]) -> StatusCode {
+            let body = form
+                .iter()
+                .map(|(k, v)| {
+                    let v: String = url::form_urlencoded::byte_serialize(v.as_bytes()).collect();
+                    format!("{k}={v}")
+                })
+                .collect::<Vec<_>>()
+                .join("&");
+            let request = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(body))
+                .unwrap();
+            let response = self.router.clone().oneshot(request).await.unwrap();
+            response.status()
+        }
+
+        /// Calls admin API with JSON body if any. Gives JSON response.
+        async fn admin(&self, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json");
+            let body = body.map_or_else(Body::empty, |b| Body::from(b.to_string()));
+            let response = self
+                .admin
+                .clone()
+                .oneshot(request.body(body).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, serde_json::from_slice(&body).unwrap())
+        }
+
+        async fn admin_tags(&self) -> Vec<(String, i64)> {
+            let (_, json) = self.admin("GET", "/tags/", None).await;
+            json["result"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| {
+                    (
+                        t["tag"].as_str().unwrap().to_owned(),
+                        t["posts"].as_i64().unwrap(),
+                    )
+                })
+                .collect()
+        }
+    }
+
+    impl Drop for TestApp {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.data_path);
+        }
+    }
+
+    #[tokio::test]
+    async fn blog_index_lists_public_posts() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/blog/").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains("Первый пост"));
+        assert!(body.contains("Второй пост"));
+        assert!(!body.contains("Черновик"));
+    }
+
+    #[tokio::test]
+    async fn blog_index_filters_by_tag() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/blog/tag/rust/").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains("Первый пост"));
+        assert!(!body.contains("Второй пост"));
+        assert!(body.contains("<title>Rust | "));
+        assert!(body.contains(r#"<meta name="description" content="Заметки о Rust""#));
+        assert!(body.contains(r#"<p class="lead" id="tagDescription">Заметки о Rust</p>"#));
+    }
+
+    #[rstest]
+    #[case("/blog/?tag=rust", "/blog/tag/rust/")]
+    #[case("/blog/page/2/?tag=rust", "/blog/tag/rust/page/2/")]
+    #[tokio::test]
+    async fn blog_index_tag_query_redirects(#[case] uri: &str, #[case] expected: &str) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, location, _) = app.get(uri).await;
+
+        // assert
+        assert_eq!(StatusCode::MOVED_PERMANENTLY, status);
+        assert_eq!(Some(expected), location.as_deref());
+    }
+
+    #[rstest]
+    #[case("/blog/2024/", "Записи за 2024 год", "Второй пост", "Первый пост")]
+    #[case("/blog/2023/05/", "Записи за май 2023", "Первый пост", "Второй пост")]
+    #[case("/blog/tag/axum/", "axum", "Второй пост", "Первый пост")]
+    #[tokio::test]
+    async fn blog_archive_pages(
+        #[case] uri: &str,
+        #[case] title: &str,
+        #[case] included: &str,
+        #[case] excluded: &str,
+    ) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get(uri).await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains(&format!("<title>{title} | ")));
+        assert!(body.contains(included));
+        assert!(!body.contains(excluded));
+        assert!(!body.contains("Черновик"));
+    }
+
+    #[rstest]
+    #[case("/blog/tag/unknown/")]
+    #[case("/blog/2022/")]
+    #[case("/blog/2024/02/")]
+    #[case("/blog/2024/13/")]
+    #[case("/blog/2024/page/2/")]
+    #[case("/blog/tag/rust/page/0/")]
+    #[tokio::test]
+    async fn blog_archive_pages_not_found(#[case] uri: &str) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, _) = app.get(uri).await;
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[rstest]
+    #[case("/api/v2/blog/posts/", 2, vec![3, 1])]
+    #[case("/api/v2/blog/posts/?tag=rust", 1, vec![1])]
+    #[case("/api/v2/blog/posts/?year=2024", 1, vec![3])]
+    #[case("/api/v2/blog/posts/?year=2022", 0, vec![])]
+    #[tokio::test]
+    async fn posts_api_returns_public_posts(
+        #[case] uri: &str,
+        #[case] count: i64,
+        #[case] ids: Vec<i64>,
+    ) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get(uri).await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        let json: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(count, json["count"].as_i64().unwrap());
+        let actual: Vec<i64> = json["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(ids, actual);
+    }
+
+    #[tokio::test]
+    async fn post_by_slug_renders_post() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/blog/2023/first").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains("<strong>первого</strong>"));
+    }
+
+    #[tokio::test]
+    async fn post_page_links_adjacent_and_related_posts() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/blog/2024/second").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains(r#"<a href="/blog/2023/first" rel="prev" class="page-link">"#));
+        assert!(!body.contains(r#"rel="next""#));
+        assert!(body.contains(r#"<div class="mt-2" id="relatedPosts">"#));
+        assert!(!body.contains("/blog/2024/draft"));
+        assert!(body.contains(r#"<a href="/blog/tag/axum/" class="btn"#));
+    }
+
+    #[tokio::test]
+    async fn highlight_css_generated() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, content_type, body) = app.get_with_type("/css/highlight.css").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(Some("text/css"), content_type.as_deref());
+        assert!(body.contains(".hl-"));
+    }
+
+    #[tokio::test]
+    async fn long_post_page_has_toc() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (_, _, long) = app.get("/blog/2024/second").await;
+        let (_, _, short) = app.get("/blog/2023/first").await;
+
+        // assert
+        assert!(long.contains(r#"<nav class="card card-body mb-3" id="toc">"#));
+        assert!(long.contains(r##"<li class="ms-1"><a href="#середина">Середина</a></li>"##));
+        assert!(long.contains(r#"<h2 id="середина">"#));
+        assert!(!short.contains(r#"id="toc""#));
+    }
+
+    #[rstest]
+    #[case("/api/v2/blog/posts/1/related/", StatusCode::OK)]
+    #[case("/api/v2/blog/posts/2/related/", StatusCode::NOT_FOUND)]
+    #[case("/api/v2/blog/posts/100/related/", StatusCode::NOT_FOUND)]
+    #[tokio::test]
+    async fn post_navigation_api_status(#[case] uri: &str, #[case] expected: StatusCode) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, _) = app.get(uri).await;
+
+        // assert
+        assert_eq!(expected, status);
+    }
+
+    #[tokio::test]
+    async fn post_navigation_api_returns_public_posts() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (_, _, body) = app.get("/api/v2/blog/posts/1/related/").await;
+
+        // assert
+        let navigation: Value = serde_json::from_str(&body).unwrap();
+        assert!(navigation["previous"].is_null());
+        assert_eq!(3, navigation["next"]["id"]);
+        let related: Vec<i64> = navigation["related"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(vec![3], related);
+    }
+
+    #[rstest]
+    #[case("/blog/1.html", "/blog/2023/first", StatusCode::MOVED_PERMANENTLY)]
+    #[case("/blog/2020/first", "/blog/2023/first", StatusCode::MOVED_PERMANENTLY)]
+    #[case("/blog/77.html", "/blog/2023/first", StatusCode::MOVED_PERMANENTLY)]
+    #[case("/news/", "/blog/", StatusCode::PERMANENT_REDIRECT)]
+    #[tokio::test]
+    async fn old_post_urls_redirect(
+        #[case] uri: &str,
+        #[case] expected: &str,
+        #[case] expected_status: StatusCode,
+    ) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, location, _) = app.get(uri).await;
+
+        // assert
+        assert_eq!(expected_status, status);
+        assert_eq!(Some(expected), location.as_deref());
+    }
+
+    #[tokio::test]
+    async fn previous_slug_redirects_to_new_one() {
+        // arrange
+        let app = TestApp::new();
+        let (_, posts) = app.admin("GET", "/posts/", None).await;
+        let mut post = posts["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|p| p["id"] == 1)
+            .unwrap()
+            .clone();
+        post["Slug"] = Value::from("renamed");
+
+        // act
+        let (status, _) = app.admin("PUT", "/post", Some(post)).await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        let (status, location, _) = app.get("/blog/2023/first").await;
+        assert_eq!(StatusCode::MOVED_PERMANENTLY, status);
+        assert_eq!(Some("/blog/2023/renamed"), location.as_deref());
+    }
+
+    #[rstest]
+    #[case("/blog/2.html")]
+    #[case("/blog/2024/draft")]
+    #[case("/blog/2024/missing")]
+    #[case("/blog/100.html")]
+    #[case("/blog/page/x")]
+    #[tokio::test]
+    async fn private_and_missing_posts_not_found(#[case] uri: &str) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, _) = app.get(uri).await;
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn archive_api_aggregates_public_posts() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/api/v2/blog/archive/").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        let json: Value = serde_json::from_str(&body).unwrap();
+        let tags: Vec<&str> = json["tags"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(vec!["axum", "rust"], tags);
+        assert_eq!("Заметки о Rust", json["tags"][1]["description"]);
+        assert!(json["tags"][0].get("description").is_none());
+        let years: Vec<i64> = json["years"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|y| y["year"].as_i64().unwrap())
+            .collect();
+        assert_eq!(vec![2024, 2023], years);
+    }
+
+    #[tokio::test]
+    async fn atom_feed_contains_public_posts() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/blog/recent.atom").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains("Второй пост"));
+        assert!(!body.contains("Черновик"));
+    }
+
+    #[rstest]
+    #[case("/blog/recent.atom", "application/atom+xml; charset=utf-8")]
+    #[case("/recent.atom", "application/atom+xml; charset=utf-8")]
+    #[case("/blog/recent.rss", "application/rss+xml; charset=utf-8")]
+    #[case("/news/rss", "application/rss+xml; charset=utf-8")]
+    #[case("/blog/feed.json", "application/feed+json; charset=utf-8")]
+    #[tokio::test]
+    async fn feeds_have_full_content(#[case] uri: &str, #[case] content_type: &str) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, actual_type, body) = app.get_with_type(uri).await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(Some(content_type), actual_type.as_deref());
+        assert!(body.contains("Второй пост"));
+        assert!(body.contains("первого"));
+        assert!(body.contains("strong"));
+        assert!(body.contains("rust"));
+        assert!(!body.contains("Черновик"));
+    }
+
+    #[tokio::test]
+    async fn tag_atom_feed_contains_tagged_posts() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, content_type, body) = app.get_with_type("/blog/tag/rust/recent.atom").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            Some("application/atom+xml; charset=utf-8"),
+            content_type.as_deref()
+        );
+        assert!(body.contains("Первый пост"));
+        assert!(!body.contains("Второй пост"));
+        assert!(body.contains(
+            r#"<link href="https://www.egoroff.spb.ru/blog/tag/rust/recent.atom" rel="self">"#
+        ));
+        assert!(!body.contains("prev-archive"));
+    }
+
+    #[rstest]
+    #[case("/blog/tag/unknown/recent.atom")]
+    #[case("/blog/recent.atom?page=2")]
+    #[case("/blog/recent.atom?page=0")]
+    #[case("/blog/tag/rust/recent.atom?page=2")]
+    #[tokio::test]
+    async fn atom_feed_not_found(#[case] uri: &str) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, _) = app.get(uri).await;
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[rstest]
+    #[case("/blog/recent.atom?full=false", false)]
+    #[case("/blog/recent.atom?full=true", true)]
+    #[case("/blog/tag/axum/recent.atom?full=false", false)]
+    #[tokio::test]
+    async fn atom_feed_full_content_option(#[case] uri: &str, #[case] expected: bool) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get(uri).await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains("<summary"));
+        assert_eq!(expected, body.contains("<content"));
+    }
+
+    #[tokio::test]
+    async fn tag_index_links_tag_feed() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (_, _, body) = app.get("/blog/tag/rust/").await;
+
+        // assert
+        assert!(body.contains(r#"<link href="/blog/tag/rust/recent.atom""#));
+    }
+
+    #[tokio::test]
+    async fn sitemap_contains_post_urls() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/sitemap.xml").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains("blog/2023/first"));
+        assert!(body.contains("blog/2024/second"));
+        assert!(!body.contains("blog/2024/draft"));
+        assert!(body.contains("blog/tag/rust/"));
+        assert!(body.contains("blog/2023/"));
+        assert!(body.contains("blog/2023/05/"));
+        assert!(!body.contains("blog/2024/02/"));
+    }
+
+    #[tokio::test]
+    async fn sitemap_has_sections_and_lastmod() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/sitemap.xml").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains("<loc>https://www.egoroff.spb.ru/portfolio/</loc>"));
+        assert!(body.contains("<loc>https://www.egoroff.spb.ru/search/</loc>"));
+        assert!(body.contains(r#"xmlns:image="http://www.google.com/schemas/sitemap-image/1.1""#));
+        assert!(body.contains("<lastmod>"));
+    }
+
+    #[rstest]
+    #[case("/sitemap/1.xml", StatusCode::OK)]
+    #[case("/sitemap/2.xml", StatusCode::NOT_FOUND)]
+    #[case("/sitemap/0.xml", StatusCode::NOT_FOUND)]
+    #[case("/sitemap/x.xml", StatusCode::NOT_FOUND)]
+    #[tokio::test]
+    async fn sitemap_pages(#[case] uri: &str, #[case] expected: StatusCode) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, _) = app.get(uri).await;
+
+        // assert
+        assert_eq!(expected, status);
+    }
+
+    #[tokio::test]
+    async fn micropub_without_token_unauthorized() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, _) = app.get("/micropub?q=config").await;
+
+        // assert
+        assert_eq!(StatusCode::UNAUTHORIZED, status);
+    }
+
+    #[rstest]
+    #[case("https://www.egoroff.spb.ru/blog/2023/first", StatusCode::ACCEPTED)]
+    #[case("https://www.egoroff.spb.ru/blog/3.html", StatusCode::ACCEPTED)]
+    #[case("https://www.egoroff.spb.ru/blog/2024/draft", StatusCode::BAD_REQUEST)]
+    #[case(
+        "https://www.egoroff.spb.ru/blog/2024/missing",
+        StatusCode::BAD_REQUEST
+    )]
+    #[case("https://other.example/blog/2023/first", StatusCode::BAD_REQUEST)]
+    #[case("https://alice.example/reply", StatusCode::BAD_REQUEST)]
+    #[case("not a url", StatusCode::BAD_REQUEST)]
+    #[tokio::test]
+    async fn webmention_endpoint(#[case] target: &str, #[case] expected: StatusCode) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let status = app
+            .post_form(
+                "/webmention",
+                &[
+                    ("source", "https://alice.example/reply"),
+                    ("target", target),
+                ],
+            )
+            .await;
+
+        // assert
+        assert_eq!(expected, status);
+    }
+
+    #[rstest]
+    #[case(
+        "subscribe",
+        "https://www.egoroff.spb.ru/blog/recent.atom",
+        "https://reader.invalid/cb",
+        StatusCode::ACCEPTED
+    )]
+    #[case(
+        "unsubscribe",
+        "https://www.egoroff.spb.ru/blog/tag/rust/recent.atom",
+        "https://reader.invalid/cb",
+        StatusCode::ACCEPTED
+    )]
+    #[case(
+        "subscribe",
+        "https://example.com/feed",
+        "https://reader.invalid/cb",
+        StatusCode::BAD_REQUEST
+    )]
+    #[case(
+        "subscribe",
+        "https://www.egoroff.spb.ru/blog/recent.atom",
+        "http://127.0.0.1:8080/cb",
+        StatusCode::BAD_REQUEST
+    )]
+    #[case(
+        "publish",
+        "https://www.egoroff.spb.ru/blog/recent.atom",
+        "https://reader.invalid/cb",
+        StatusCode::BAD_REQUEST
+    )]
+    #[tokio::test]
+    async fn websub_hub_endpoint(
+        #[case] mode: &str,
+        #[case] topic: &str,
+        #[case] callback: &str,
+        #[case] expected: StatusCode,
+    ) {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let status = app
+            .post_form(
+                "/websub",
+                &[
+                    ("hub.mode", mode),
+                    ("hub.topic", topic),
+                    ("hub.callback", callback),
+                ],
+            )
+            .await;
+
+        // assert
+        assert_eq!(expected, status);
+    }
+
+    #[rstest]
+    #[case("/blog/recent.atom", "https://www.egoroff.spb.ru/blog/recent.atom")]
+    #[case("/blog/feed.json", "https://www.egoroff.spb.ru/blog/feed.json")]
+    #[case(
+        "/blog/tag/rust/recent.atom",
+        "https://www.egoroff.spb.ru/blog/tag/rust/recent.atom"
+    )]
+    #[tokio::test]
+    async fn feeds_advertise_hub(#[case] uri: &str, #[case] topic: &str) {
+        // arrange
+        let app = TestApp::new();
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        // act
+        let response = app.router.clone().oneshot(request).await.unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let link = response.headers()[header::LINK]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert_eq!(
+            format!(r#"<https://www.egoroff.spb.ru/websub>; rel="hub", <{topic}>; rel="self""#),
+            link
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("https://www.egoroff.spb.ru/websub"));
+    }
+
+    #[tokio::test]
+    async fn post_page_shows_approved_webmentions() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _, body) = app.get("/blog/2023/first").await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert!(body.contains(r#"<div class="mt-2" id="webmentions">"#));
+        assert!(body.contains("Отличный пост"));
+        assert!(body.contains(r#"href="https://alice.example/""#));
+        assert!(!body.contains("Спам"));
+    }
+
+    #[rstest]
+    #[case(2, "approved", StatusCode::OK)]
+    #[case(2, "rejected", StatusCode::OK)]
+    #[case(1, "rejected", StatusCode::OK)]
+    #[case(1, "approved", StatusCode::CONFLICT)]
+    #[case(3, "approved", StatusCode::CONFLICT)]
+    #[case(3, "rejected", StatusCode::CONFLICT)]
+    #[case(100, "approved", StatusCode::NOT_FOUND)]
+    #[tokio::test]
+    async fn admin_webmention_moderate(
+        #[case] id: i64,
+        #[case] moderated: &str,
+        #[case] expected: StatusCode,
+    ) {
+        // arrange
+        let app = TestApp::new();
+        app.post_form(
+            "/webmention",
+            &[
+                ("source", "https://bob.example/post"),
+                ("target", "https://www.egoroff.spb.ru/blog/2023/first"),
+            ],
+        )
+        .await;
+
+        // act
+        let (status, _) = app
+            .admin(
+                "PUT",
+                &format!("/webmentions/{id}"),
+                Some(serde_json::json!({ "status": moderated })),
+            )
+            .await;
+
+        // assert
+        assert_eq!(expected, status);
+    }
+
+    #[tokio::test]
+    async fn admin_tag_rename() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _) = app
+            .admin(
+                "PUT",
+                "/tags/rust/rename",
+                Some(serde_json::json!({ "to": "раст" })),
+            )
+            .await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        let expected = vec![("axum".to_owned(), 2), ("раст".to_owned(), 1)];
+        assert_eq!(expected, app.admin_tags().await);
+        let (status, _, _) = app.get("/blog/tag/rust/").await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn admin_tags_merge() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _) = app
+            .admin(
+                "POST",
+                "/tags/merge",
+                Some(serde_json::json!({ "tags": ["axum"], "into": "rust" })),
+            )
+            .await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(vec![("rust".to_owned(), 3)], app.admin_tags().await);
+    }
+
+    #[tokio::test]
+    async fn admin_tags_merge_unknown_tag_not_found() {
+        // arrange
+        let app = TestApp::new();
+
+        // act
+        let (status, _) = app
+            .admin(
+                "POST",
+                "/tags/merge",
+                Some(serde_json::json!({ "tags": ["unknown"], "into": "rust" })),
+            )
+            .await;
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn admin_orphan_tags_delete() {
+        // arrange
+        let app = TestApp::new();
+        app.admin("DELETE", "/post/1", None).await;
+
+        // act
+        let (status, deleted) = app.admin("DELETE", "/tags/orphans", None).await;
+
+        // assert
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(serde_json::json!(["rust"]), deleted);
+        assert_eq!(vec![("axum".to_owned(), 2)], app.admin_tags().await);
+    }
+
+    fn blog() -> Memory {
+        let mut storage = Memory::new().with_post_remap(77, 1);
+        let posts = [
+            post(1, "Первый пост", "2023-05-01T10:00:00Z", "first", true),
+            post(2, "Черновик", "2024-02-01T10:00:00Z", "draft", false),
+            post(3, "Второй пост", "2024-01-10T10:00:00Z", "second", true),
+        ];
+        for mut p in posts {
+            if p.id == 1 {
+                p.text = "Текст **первого** поста".to_owned();
+                p.tags = vec!["rust".to_owned()];
+            } else {
+                p.tags = vec!["axum".to_owned()];
+            }
+            if p.id == 3 {
+                p.text = "Второй пост\n\n## Начало\n\n## Середина\n\n## Конец".to_owned();
+            }
+            storage.upsert_post(p).unwrap();
+        }
+        storage
+            .update_tag(&TagInfo {
+                tag: "rust".to_owned(),
+                title: Some("Rust".to_owned()),
+                description: Some("Заметки о Rust".to_owned()),
+                posts: 0,
+            })
+            .unwrap();
+        for (source, content, status) in [
+            (
+                "https://alice.example/reply",
+                "Отличный пост",
+                WebmentionStatus::Approved,
+            ),
+            ("https://spam.example/", "Спам", WebmentionStatus::Verified),
+        ] {
+            let mut mention = Webmention {
+                source: source.to_owned(),
+                target: "https://www.egoroff.spb.ru/blog/2023/first".to_owned(),
+                post_id: 1,
+                kind: WebmentionKind::Reply,
+                author_name: Some("Alice".to_owned()),
+                author_url: Some("https://alice.example/".to_owned()),
+                content: Some(content.to_owned()),
+                ..Default::default()
+            };
+            mention.id = storage.queue_webmention(&mention).unwrap();
+            mention.status = status;
+            storage.update_webmention(&mention).unwrap();
+        }
+        storage
+    }
+
+    fn post(id: i64, title: &str, created: &str, slug: &str, is_public: bool) -> Post {
+        Post {
+            id,
+            title: title.to_owned(),
+            short_text: title.to_owned(),
+            text: title.to_owned(),
+            markdown: true,
+            is_public,
+            created: created.parse::<DateTime<Utc>>().unwrap(),
+            slug: Some(slug.to_owned()),
+            status: if is_public {
+                PostStatus::Published
+            } else {
+                PostStatus::Draft
+            },
+            ..Default::default()
+        }
+    }
+
+    /// OAuth authorizers are created from providers kept in `SQLite` database.
+    fn create_oauth_providers(path: &Path) {
+        drop(Sqlite::open(path, Mode::ReadWrite).unwrap());
+        let conn = rusqlite::Connection::open(path).unwrap();
+        for name in ["google", "github", "yandex"] {
+            conn.execute(
+                "INSERT INTO oauth_provider (name, clientid, secret, redirect_url) VALUES (?1, 'id', 'secret', 'http://localhost/')",
+                [name],
+            )
+            .unwrap();
+        }
+    }
+}
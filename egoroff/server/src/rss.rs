@@ -0,0 +1,130 @@
+use std::iter;
+
+use anyhow::Result;
+use kernel::xml::Builder;
+
+use crate::feed::{FEED_LANGUAGE, Feed};
+
+const RSS_ELT: &str = "rss";
+const CHANNEL_ELT: &str = "channel";
+const ITEM_ELT: &str = "item";
+
+/// Renders [RSS 2.0](https://www.rssboard.org/rss-specification) feed. Short text
+/// goes into `description` and full text into `content:encoded`.
+pub fn render(feed: &Feed) -> Result<String> {
+    let mut builder = Builder::new();
+
+    builder.write_attributed_start_tag(
+        RSS_ELT,
+        [
+            ("version", "2.0"),
+            ("xmlns:atom", "http://www.w3.org/2005/Atom"),
+            ("xmlns:content", "http://purl.org/rss/1.0/modules/content/"),
+        ]
+        .into_iter(),
+    )?;
+    builder.write_start_tag(CHANNEL_ELT)?;
+
+    builder.write_element("title", &feed.title)?;
+    builder.write_element("link", &feed.home_page_url)?;
+    builder.write_element("description", &feed.description)?;
+    builder.write_element("language", FEED_LANGUAGE)?;
+    if let Some(updated) = feed.updated() {
+        builder.write_element("lastBuildDate", &updated.to_rfc2822())?;
+    }
+    builder.write_empty_attributed_element(
+        "atom:link",
+        [
+            ("href", feed.feed_url.as_str()),
+            ("rel", "self"),
+            ("type", "application/rss+xml"),
+        ]
+        .into_iter(),
+    )?;
+    if let Some(hub) = &feed.hub {
+        builder.write_empty_attributed_element(
+            "atom:link",
+            [("href", hub.as_str()), ("rel", "hub")].into_iter(),
+        )?;
+    }
+
+    for item in &feed.items {
+        builder.write_start_tag(ITEM_ELT)?;
+
+        builder.write_element("title", &item.title)?;
+        builder.write_element("link", &item.url)?;
+        builder.write_attributed_element("guid", &item.url, iter::once(("isPermaLink", "true")))?;
+        builder.write_element("pubDate", &item.published.to_rfc2822())?;
+        for tag in &item.tags {
+            builder.write_element("category", tag)?;
+        }
+        builder.write_element("description", &item.summary)?;
+        if let Some(content) = &item.content {
+            builder.write_element("content:encoded", content)?;
+        }
+
+        builder.write_end_tag(ITEM_ELT)?;
+    }
+
+    builder.write_end_tag(CHANNEL_ELT)?;
+    builder.write_end_tag(RSS_ELT)?;
+
+    builder.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::feed::{FeedFormat, FeedItem};
+
+    #[test]
+    fn render_items() {
+        // arrange
+        let item = FeedItem {
+            url: "https://www.egoroff.spb.ru/blog/2024/post".to_owned(),
+            title: "Пост".to_owned(),
+            summary: "<p>Кратко</p>".to_owned(),
+            content: Some("<p>Полностью</p>".to_owned()),
+            published: "2024-01-10T10:00:00Z".parse().unwrap(),
+            updated: "2024-02-12T10:00:00Z".parse().unwrap(),
+            tags: vec!["rust".to_owned(), "axum".to_owned()],
+        };
+        let feed = Feed::new(FeedFormat::Rss, vec![item]);
+
+        // act
+        let actual = render(&feed).unwrap();
+
+        // assert
+        assert!(actual.contains(r#"<rss version="2.0""#));
+        assert!(actual.contains("<lastBuildDate>Mon, 12 Feb 2024 10:00:00 +0000</lastBuildDate>"));
+        assert!(actual.contains("<pubDate>Wed, 10 Jan 2024 10:00:00 +0000</pubDate>"));
+        assert!(actual.contains(
+            r#"<guid isPermaLink="true">https://www.egoroff.spb.ru/blog/2024/post</guid>"#
+        ));
+        assert!(actual.contains("<category>rust</category><category>axum</category>"));
+        assert!(actual.contains("<description>&lt;p&gt;Кратко&lt;/p&gt;</description>"));
+        assert!(actual.contains("<content:encoded>&lt;p&gt;Полностью&lt;/p&gt;</content:encoded>"));
+        assert!(actual.contains(
+            r#"<atom:link href="https://www.egoroff.spb.ru/blog/recent.rss" rel="self" type="application/rss+xml">"#
+        ));
+        assert!(
+            actual.contains(r#"<atom:link href="https://www.egoroff.spb.ru/websub" rel="hub">"#)
+        );
+    }
+
+    #[test]
+    fn render_empty() {
+        // arrange
+        let feed = Feed::new(FeedFormat::Rss, vec![]);
+
+        // act
+        let actual = render(&feed).unwrap();
+
+        // assert
+        assert!(actual.contains("<channel><title>egoroff.spb.ru feed</title>"));
+        assert!(!actual.contains("<item>"));
+        assert!(!actual.contains("lastBuildDate"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Background tasks: publishing of scheduled posts, verification of received
+//! webmentions, sending of webmentions, distribution of feeds and
+//! database backups.
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chrono::Utc;
+use kernel::{
+    backup::{self, Retention},
+    domain::Storage,
+};
+use tokio::{task::JoinHandle, time::Instant};
+
+use crate::{
+    domain::{Database, PageContext},
+    webmention,
+    websub::{self, Hub},
+};
+
+/// How often scheduled posts are checked.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(60);
+/// How often queued webmentions are verified.
+const WEBMENTION_INTERVAL: Duration = Duration::from_secs(30);
+/// How often due webmentions of published posts are sent.
+const SEND_INTERVAL: Duration = Duration::from_secs(60);
+/// How often feeds are delivered to subscribers that failed to receive them.
+const REDELIVERY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Starts a task that makes scheduled posts public when their publication time comes.
+/// Links of the posts are queued for webmentions and posts caches are
+/// invalidated and feeds subscribers are notified when some posts are published.
+pub fn spawn_publisher<S: Storage + Send + 'static>(
+    page_context: Arc<PageContext<'static, S>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+        loop {
+            interval.tick().await;
+            publish_scheduled(&page_context).await;
+        }
+    })
+}
+
+async fn publish_scheduled<S: Storage + Send + 'static>(page_context: &PageContext<'_, S>) {
+    match page_context
+        .storage
+        .write(|s| {
+            let ids = s.publish_scheduled_posts(Utc::now())?;
+            for id in &ids {
+                webmention::queue_post_links_logged(s, *id);
+            }
+            Ok::<_, S::Err>(ids)
+        })
+        .await
+    {
+        Ok(ids) if ids.is_empty() => {}
+        Ok(ids) => {
+            page_context.posts_changed();
+            tracing::info!("scheduled posts published: {ids:?}");
+        }
+        Err(e) => tracing::error!("scheduled posts publishing error: {e}"),
+    }
+}
+
+/// Starts a task that fetches sources of queued webmentions and verifies them.
+pub fn spawn_webmention_receiver<S: Storage + Send + 'static>(
+    storage: Database<S>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(WEBMENTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = webmention::verify_pending(&storage).await {
+                tracing::error!("webmentions verification error: {e:#}");
+            }
+        }
+    })
+}
+
+/// Starts a task that sends due webmentions to pages linked from published posts.
+pub fn spawn_webmention_sender<S: Storage + Send + 'static>(
+    storage: Database<S>,
+) -> Result<JoinHandle<()>> {
+    let sender = webmention::Sender::new()?;
+    Ok(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SEND_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = webmention::send_due(&storage, &sender).await {
+                tracing::error!("webmentions sending error: {e:#}");
+            }
+        }
+    }))
+}
+
+/// Starts the only task that delivers feeds to subscribers. Feeds are delivered
+/// when the hub publishes them and periodically to subscribers that missed
+/// their last change.
+pub fn spawn_feed_distributor<S: Storage + Send + 'static>(
+    storage: Database<S>,
+    hub: Arc<Hub>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval_at(Instant::now() + REDELIVERY_INTERVAL, REDELIVERY_INTERVAL);
+        loop {
+            tokio::select! {
+                () = hub.changed() => {}
+                _ = interval.tick() => {}
+            }
+            websub::distribute_logged(&hub, &storage).await;
+        }
+    })
+}
+
+/// Starts a task that snapshots databases of `data_dir` into `backup_dir` every `interval`.
+/// The first snapshot is made one interval after start.
+pub fn spawn_backup(
+    data_dir: PathBuf,
+    backup_dir: PathBuf,
+    interval: Duration,
+    retention: Retention,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(Instant::now() + interval, interval);
+        loop {
+            interval.tick().await;
+            let data_dir = data_dir.clone();
+            let backup_dir = backup_dir.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                backup::backup(&data_dir, &backup_dir, Utc::now(), retention)
+            })
+            .await;
+            match result {
+                Ok(Ok(r)) => tracing::info!(
+                    "backup {} made, removed old: {:?}",
+                    r.snapshot.display(),
+                    r.removed
+                ),
+                Ok(Err(e)) => tracing::error!("backup error: {e:#}"),
+                Err(e) => tracing::error!("backup task error: {e}"),
+            }
+        }
+    })
+}
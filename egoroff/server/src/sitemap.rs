@@ -1,80 +1,476 @@
-use std::iter;
+//! [Sitemaps](https://www.sitemaps.org/protocol.html) of site sections, Apache
+//! documents, posts with their images and posts archives.
+//!
+//! Sitemap has at most [`MAX_URLS`] URLs so that larger sites get sitemap index
+//! which refers to numbered sitemap pages.
+//!
+//! Finding post images requires rendering the post, so images are kept in
+//! [`ImageCache`] until posts change.
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    iter,
+};
 
 use anyhow::Result;
-use kernel::xml::Builder;
+use chrono::{DateTime, Datelike, SecondsFormat, Utc};
+use kernel::{
+    cache::GenerationCache,
+    converter::{extract_images, post2html},
+    domain::{Archive, Post, PostsRequest},
+    xml::Builder,
+};
+use url::Url;
+
+use crate::domain::{Apache, blog_index_path};
 
 const SITE: &str = "https://www.egoroff.spb.ru/";
 const URLSET_ELT: &str = "urlset";
+const SITEMAP_INDEX_ELT: &str = "sitemapindex";
+const SITEMAP_ELT: &str = "sitemap";
 const URL_ELT: &str = "url";
 const LOC_ELT: &str = "loc";
+const LAST_MOD_ELT: &str = "lastmod";
 const CHANGE_FREQ_ELT: &str = "changefreq";
 const PRIORITY_ELT: &str = "priority";
+const IMAGE_ELT: &str = "image:image";
+const IMAGE_LOC_ELT: &str = "image:loc";
+const SITEMAP_NS: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+const IMAGE_NS: &str = "http://www.google.com/schemas/sitemap-image/1.1";
+
+/// The protocol limit of URLs in a sitemap.
+pub const MAX_URLS: usize = 50_000;
+/// Images of a page above this are not listed.
+const MAX_IMAGES: usize = 1_000;
 
-struct Url<'a> {
-    pub location: &'a str,
-    pub changefreq: &'a str,
-    pub priority: &'a str,
+pub struct SitemapUrl {
+    pub location: String,
+    pub lastmod: Option<DateTime<Utc>>,
+    pub changefreq: &'static str,
+    pub priority: &'static str,
+    /// Absolute URLs of images on the page.
+    pub images: Vec<String>,
+}
+
+impl SitemapUrl {
+    fn new(path: &str, changefreq: &'static str, priority: &'static str) -> Self {
+        Self {
+            location: format!("{SITE}{}", path.trim_start_matches('/')),
+            lastmod: None,
+            changefreq,
+            priority,
+            images: vec![],
+        }
+    }
 }
 
-pub fn make_site_map(
-    apache_docs: Vec<crate::domain::Apache>,
-    post_ids: Vec<i64>,
-) -> Result<String> {
+/// Images of public posts by post ID shared by all sitemap requests.
+/// Must be invalidated whenever any post changes.
+pub type ImageCache = GenerationCache<i64, Vec<String>>;
+
+/// Images of the post from the cache. Post is rendered if they aren't cached yet.
+fn cached_images(cache: &ImageCache, generation: u64, post: &Post, location: &str) -> Vec<String> {
+    if let Some(images) = cache.get(&post.id) {
+        return images;
+    }
+    let images = post_images(post, location);
+    cache.insert(generation, post.id, images.clone());
+    images
+}
+
+/// Makes URLs of site sections, Apache documents, public posts and archives.
+/// Sections are paths of the site graph like `/blog/`. Post images are taken
+/// from the cache, `generation` of which was read before reading posts.
+pub fn make_urls(
+    sections: Vec<String>,
+    apache_docs: Vec<Apache>,
+    posts: &[Post],
+    archive: &Archive,
+    images: &ImageCache,
+    generation: u64,
+) -> Vec<SitemapUrl> {
+    let blog_updated = posts.iter().map(updated).max();
+
+    let sections = sections.into_iter().map(|path| {
+        let (changefreq, priority) = if path == "/" {
+            ("weekly", "1.0")
+        } else {
+            ("weekly", "0.7")
+        };
+        SitemapUrl {
+            lastmod: blog_updated.filter(|_| path == "/" || path == "/blog/"),
+            ..SitemapUrl::new(&path, changefreq, priority)
+        }
+    });
+
+    let docs = apache_docs
+        .into_iter()
+        .map(|doc| SitemapUrl::new(&format!("portfolio/{}.html", doc.id), "yearly", "1.0"));
+
+    let posts_urls = posts.iter().map(|post| {
+        let url = SitemapUrl::new(&post.path(), "yearly", "1.0");
+        SitemapUrl {
+            lastmod: Some(updated(post)),
+            images: cached_images(images, generation, post, &url.location),
+            ..url
+        }
+    });
+
+    let updates = ArchiveUpdates::new(posts);
+    let archives = archive_requests(archive).map(|request| SitemapUrl {
+        lastmod: updates.get(&request),
+        ..SitemapUrl::new(&blog_index_path(&request), "weekly", "0.5")
+    });
+
+    sections
+        .chain(docs)
+        .chain(posts_urls)
+        .chain(archives)
+        .collect()
+}
+
+/// Renders sitemap or sitemap index if there are too many URLs for one sitemap.
+pub fn render(urls: &[SitemapUrl]) -> Result<String> {
+    render_limited(urls, MAX_URLS)
+}
+
+/// Renders the sitemap page of the index starting from 1 if there is such page.
+pub fn render_page(urls: &[SitemapUrl], page: usize) -> Result<Option<String>> {
+    render_page_limited(urls, page, MAX_URLS)
+}
+
+fn render_limited(urls: &[SitemapUrl], limit: usize) -> Result<String> {
+    if urls.len() > limit {
+        render_index(urls, limit)
+    } else {
+        render_urlset(urls)
+    }
+}
+
+fn render_page_limited(urls: &[SitemapUrl], page: usize, limit: usize) -> Result<Option<String>> {
+    let Some(chunk) = page
+        .checked_sub(1)
+        .and_then(|ix| urls.chunks(limit).nth(ix))
+    else {
+        return Ok(None);
+    };
+    render_urlset(chunk).map(Some)
+}
+
+fn render_index(urls: &[SitemapUrl], limit: usize) -> Result<String> {
+    let mut builder = Builder::new();
+
+    builder.write_attributed_start_tag(SITEMAP_INDEX_ELT, iter::once(("xmlns", SITEMAP_NS)))?;
+
+    for (ix, chunk) in urls.chunks(limit).enumerate() {
+        builder.write_start_tag(SITEMAP_ELT)?;
+        builder.write_element(LOC_ELT, &format!("{SITE}sitemap/{}.xml", ix + 1))?;
+        if let Some(lastmod) = chunk.iter().filter_map(|u| u.lastmod).max() {
+            builder.write_element(LAST_MOD_ELT, &w3c_datetime(lastmod))?;
+        }
+        builder.write_end_tag(SITEMAP_ELT)?;
+    }
+
+    builder.write_end_tag(SITEMAP_INDEX_ELT)?;
+    builder.to_string()
+}
+
+fn render_urlset(urls: &[SitemapUrl]) -> Result<String> {
     let mut builder = Builder::new();
 
     builder.write_attributed_start_tag(
         URLSET_ELT,
-        iter::once(("xmlns", "http://www.sitemaps.org/schemas/sitemap/0.9")),
+        [("xmlns", SITEMAP_NS), ("xmlns:image", IMAGE_NS)].into_iter(),
     )?;
 
-    let root = Url {
-        location: SITE,
-        changefreq: "weekly",
-        priority: "1.0",
-    };
-    write_url(&mut builder, &root)?;
+    for url in urls {
+        write_url(&mut builder, url)?;
+    }
 
-    let blog = Url {
-        location: &format!("{SITE}blog/"),
-        changefreq: "weekly",
-        priority: "0.7",
-    };
-    write_url(&mut builder, &blog)?;
+    builder.write_end_tag(URLSET_ELT)?;
+    builder.to_string()
+}
 
-    let portfolio = Url {
-        location: &format!("{SITE}portfolio/"),
-        changefreq: "weekly",
-        priority: "0.7",
-    };
-    write_url(&mut builder, &portfolio)?;
+/// Tag, year and month archive pages filters.
+fn archive_requests(archive: &Archive) -> impl Iterator<Item = PostsRequest> + '_ {
+    let tags = archive.tags.iter().map(|t| PostsRequest {
+        tag: Some(t.title.clone()),
+        ..Default::default()
+    });
+    let years = archive.years.iter().flat_map(|y| {
+        iter::once(PostsRequest {
+            year: Some(y.year),
+            ..Default::default()
+        })
+        .chain(y.months.iter().map(|m| PostsRequest {
+            year: Some(y.year),
+            month: Some(m.month),
+            ..Default::default()
+        }))
+    });
+    tags.chain(years)
+}
+
+/// The newest update of posts on tag, year and month archive pages.
+struct ArchiveUpdates<'a> {
+    tags: HashMap<&'a str, DateTime<Utc>>,
+    years: HashMap<i32, DateTime<Utc>>,
+    months: HashMap<(i32, u32), DateTime<Utc>>,
+}
 
-    for doc in apache_docs {
-        let d = Url {
-            location: &format!("{SITE}portfolio/{}.html", doc.id),
-            changefreq: "yearly",
-            priority: "1.0",
+impl<'a> ArchiveUpdates<'a> {
+    fn new(posts: &'a [Post]) -> Self {
+        let mut updates = Self {
+            tags: HashMap::new(),
+            years: HashMap::new(),
+            months: HashMap::new(),
         };
-        write_url(&mut builder, &d)?;
+        for post in posts {
+            let time = updated(post);
+            for tag in &post.tags {
+                newest(updates.tags.entry(tag.as_str()), time);
+            }
+            let year = post.created.year();
+            newest(updates.years.entry(year), time);
+            newest(updates.months.entry((year, post.created.month())), time);
+        }
+        updates
     }
 
-    for id in post_ids {
-        let d = Url {
-            location: &format!("{SITE}blog/{id}.html"),
-            changefreq: "yearly",
-            priority: "1.0",
-        };
-        write_url(&mut builder, &d)?;
+    fn get(&self, request: &PostsRequest) -> Option<DateTime<Utc>> {
+        match (&request.tag, request.year, request.month) {
+            (Some(tag), _, _) => self.tags.get(tag.as_str()).copied(),
+            (None, Some(year), Some(month)) => u32::try_from(month)
+                .ok()
+                .and_then(|m| self.months.get(&(year, m)).copied()),
+            (None, Some(year), None) => self.years.get(&year).copied(),
+            (None, None, _) => None,
+        }
     }
+}
 
-    builder.write_end_tag(URLSET_ELT)?;
-    builder.to_string()
+fn newest<K>(entry: Entry<'_, K, DateTime<Utc>>, time: DateTime<Utc>) {
+    let newest = entry.or_insert(time);
+    *newest = (*newest).max(time);
+}
+
+fn updated(post: &Post) -> DateTime<Utc> {
+    post.modified.max(post.created)
+}
+
+/// Absolute URLs of http images of the post. Relative sources are resolved against the post URL.
+fn post_images(post: &Post, location: &str) -> Vec<String> {
+    let images = match post2html(post).and_then(|html| extract_images(&html)) {
+        Ok(images) => images,
+        Err(e) => {
+            tracing::error!("Failed to get post {} images: {e:#}", post.id);
+            return vec![];
+        }
+    };
+    let Ok(base) = Url::parse(location) else {
+        return vec![];
+    };
+    images
+        .iter()
+        .filter_map(|src| base.join(src).ok())
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .map(String::from)
+        .take(MAX_IMAGES)
+        .collect()
 }
 
-fn write_url(builder: &mut Builder, root: &Url) -> Result<()> {
+fn w3c_datetime(dt: DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn write_url(builder: &mut Builder, url: &SitemapUrl) -> Result<()> {
     builder.write_start_tag(URL_ELT)?;
-    builder.write_element(LOC_ELT, root.location)?;
-    builder.write_element(CHANGE_FREQ_ELT, root.changefreq)?;
-    builder.write_element(PRIORITY_ELT, root.priority)?;
+    builder.write_element(LOC_ELT, &url.location)?;
+    if let Some(lastmod) = url.lastmod {
+        builder.write_element(LAST_MOD_ELT, &w3c_datetime(lastmod))?;
+    }
+    builder.write_element(CHANGE_FREQ_ELT, url.changefreq)?;
+    builder.write_element(PRIORITY_ELT, url.priority)?;
+    for image in &url.images {
+        builder.write_start_tag(IMAGE_ELT)?;
+        builder.write_element(IMAGE_LOC_ELT, image)?;
+        builder.write_end_tag(IMAGE_ELT)?;
+    }
     builder.write_end_tag(URL_ELT)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use kernel::domain::{Month, Tag, Year};
+    use rstest::{fixture, rstest};
+
+    #[rstest]
+    fn make_urls_test(posts: Vec<Post>) {
+        // arrange
+        let mut year = Year::new(2023);
+        year.append_month(Month {
+            month: 5,
+            ..Default::default()
+        });
+        let archive = Archive {
+            tags: vec![Tag {
+                title: "rust".to_owned(),
+                ..Default::default()
+            }],
+            years: vec![year],
+        };
+        let docs = vec![Apache {
+            id: "mod_rewrite".to_owned(),
+            ..Default::default()
+        }];
+        let sections = vec!["/".to_owned(), "/blog/".to_owned(), "/search/".to_owned()];
+
+        // act
+        let actual = make_urls(sections, docs, &posts, &archive, &ImageCache::new(), 0);
+
+        // assert
+        let locations: Vec<&str> = actual.iter().map(|u| u.location.as_str()).collect();
+        assert_eq!(
+            vec![
+                "https://www.egoroff.spb.ru/",
+                "https://www.egoroff.spb.ru/blog/",
+                "https://www.egoroff.spb.ru/search/",
+                "https://www.egoroff.spb.ru/portfolio/mod_rewrite.html",
+                "https://www.egoroff.spb.ru/blog/2023/first",
+                "https://www.egoroff.spb.ru/blog/2024/second",
+                "https://www.egoroff.spb.ru/blog/tag/rust/",
+                "https://www.egoroff.spb.ru/blog/2023/",
+                "https://www.egoroff.spb.ru/blog/2023/05/",
+            ],
+            locations
+        );
+        let lastmod = |ix: usize| actual[ix].lastmod.map(w3c_datetime);
+        assert_eq!(Some("2024-03-01T10:00:00Z".to_owned()), lastmod(1));
+        assert_eq!(None, lastmod(2));
+        assert_eq!(Some("2023-06-01T10:00:00Z".to_owned()), lastmod(4));
+        assert_eq!(Some("2024-03-01T10:00:00Z".to_owned()), lastmod(5));
+        assert_eq!(Some("2024-03-01T10:00:00Z".to_owned()), lastmod(6));
+        assert_eq!(Some("2023-06-01T10:00:00Z".to_owned()), lastmod(8));
+        assert_eq!(
+            vec![
+                "https://www.egoroff.spb.ru/p/1.png",
+                "https://img.example/2.jpg"
+            ],
+            actual[4].images
+        );
+        assert!(actual[5].images.is_empty());
+    }
+
+    #[rstest]
+    fn render_urlset_test(posts: Vec<Post>) {
+        // arrange
+        let urls = make_urls(
+            vec![],
+            vec![],
+            &posts,
+            &Archive::default(),
+            &ImageCache::new(),
+            0,
+        );
+
+        // act
+        let actual = render(&urls).unwrap();
+
+        // assert
+        assert!(actual.contains(r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">"#));
+        assert!(actual.contains(
+            "<url><loc>https://www.egoroff.spb.ru/blog/2023/first</loc><lastmod>2023-06-01T10:00:00Z</lastmod><changefreq>yearly</changefreq><priority>1.0</priority>\
+             <image:image><image:loc>https://www.egoroff.spb.ru/p/1.png</image:loc></image:image>"
+        ));
+    }
+
+    #[rstest]
+    fn image_cache_kept_until_invalidated(mut posts: Vec<Post>) {
+        // arrange
+        let cache = ImageCache::new();
+        let location = "https://www.egoroff.spb.ru/blog/2023/first";
+        let original = posts[0].clone();
+        let cached = cached_images(&cache, cache.generation(), &original, location);
+        posts[0].text = String::from("Без картинок");
+
+        // act
+        let before = cached_images(&cache, cache.generation(), &posts[0], location);
+        let stale_generation = cache.generation();
+        cache.invalidate();
+        let stale = cached_images(&cache, stale_generation, &original, location);
+        let after = cached_images(&cache, cache.generation(), &posts[0], location);
+
+        // assert
+        assert_eq!(2, cached.len());
+        assert_eq!(cached, before);
+        assert_eq!(cached, stale);
+        assert!(after.is_empty());
+    }
+
+    #[rstest]
+    fn render_index_when_over_limit(posts: Vec<Post>) {
+        // arrange
+        let urls = make_urls(
+            vec!["/".to_owned(), "/portfolio/".to_owned()],
+            vec![],
+            &posts,
+            &Archive::default(),
+            &ImageCache::new(),
+            0,
+        );
+
+        // act
+        let index = render_limited(&urls, 3).unwrap();
+        let second = render_page_limited(&urls, 2, 3).unwrap().unwrap();
+        let missing = render_page_limited(&urls, 3, 3).unwrap();
+        let zero = render_page_limited(&urls, 0, 3).unwrap();
+
+        // assert
+        assert!(
+            index.contains(r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#)
+        );
+        assert!(index.contains(
+            "<sitemap><loc>https://www.egoroff.spb.ru/sitemap/1.xml</loc><lastmod>2024-03-01T10:00:00Z</lastmod></sitemap>"
+        ));
+        assert!(index.contains(
+            "<sitemap><loc>https://www.egoroff.spb.ru/sitemap/2.xml</loc><lastmod>2024-03-01T10:00:00Z</lastmod></sitemap>"
+        ));
+        assert!(second.contains("blog/2024/second"));
+        assert!(!second.contains("portfolio"));
+        assert!(missing.is_none());
+        assert!(zero.is_none());
+    }
+
+    #[fixture]
+    fn posts() -> Vec<Post> {
+        vec![
+            Post {
+                id: 1,
+                title: "first".to_owned(),
+                text: "![a](/p/1.png) ![b](https://img.example/2.jpg) ![c](data:image/png;base64,AA==)"
+                    .to_owned(),
+                markdown: true,
+                slug: Some("first".to_owned()),
+                tags: vec!["rust".to_owned()],
+                created: "2023-05-10T10:00:00Z".parse().unwrap(),
+                modified: "2023-06-01T10:00:00Z".parse().unwrap(),
+                ..Default::default()
+            },
+            Post {
+                id: 3,
+                title: "second".to_owned(),
+                text: "no images".to_owned(),
+                markdown: true,
+                slug: Some("second".to_owned()),
+                tags: vec!["rust".to_owned()],
+                created: "2024-03-01T10:00:00Z".parse().unwrap(),
+                modified: "2024-01-01T10:00:00Z".parse().unwrap(),
+                ..Default::default()
+            },
+        ]
+    }
+}
@@ -0,0 +1,782 @@
+//! Receiving and sending of [webmentions](https://www.w3.org/TR/webmention/).
+//!
+//! Endpoint only validates and queues source and target pairs. Sources are
+//! fetched and verified later by background worker so that senders don't wait
+//! for another site and can't make the server fetch pages synchronously.
+//!
+//! Links of published posts are queued the same way and another worker
+//! discovers endpoints of linked pages and notifies them retrying failures.
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use kernel::{
+    converter::{extract_links, post2html},
+    domain::{
+        DeliveryStatus, OutgoingWebmention, Storage, Webmention, WebmentionKind, WebmentionStatus,
+    },
+    microformats::{HEntry, find_rel_link, parse_h_entry},
+};
+use reqwest::{Client, header};
+use serde::Deserialize;
+use thiserror::Error;
+use url::{Url, form_urlencoded};
+
+use crate::{
+    domain::Database,
+    indie::{self, FetchedPage, ME},
+};
+
+/// Webmentions verified by the worker at once.
+const VERIFY_BATCH: i32 = 10;
+/// Longer content of replies is truncated.
+const MAX_CONTENT_CHARS: usize = 500;
+/// Webmentions sent by the worker at once.
+const SEND_BATCH: i32 = 10;
+/// Delivery fails after this number of attempts.
+const MAX_ATTEMPTS: i32 = 6;
+/// Delay before the first retry. Every next delay is twice as long.
+const RETRY_DELAY: TimeDelta = TimeDelta::minutes(5);
+
+/// Webmention endpoint form.
+#[derive(Deserialize)]
+pub struct WebmentionRequest {
+    /// The URL of the page that mentions the post.
+    pub source: String,
+    /// The URL of the post mentioned.
+    pub target: String,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum WebmentionError {
+    #[error("source must be an http or https URL")]
+    InvalidSource,
+    #[error("target must be an http or https URL")]
+    InvalidTarget,
+    #[error("source and target must be different")]
+    SameSourceAndTarget,
+    #[error("target is not a post of this site")]
+    UnsupportedTarget,
+}
+
+impl WebmentionRequest {
+    /// Checks that source and target are different http URLs and target is on this site.
+    pub fn validate(&self) -> Result<(), WebmentionError> {
+        let source = parse_http_url(&self.source).ok_or(WebmentionError::InvalidSource)?;
+        let target = parse_http_url(&self.target).ok_or(WebmentionError::InvalidTarget)?;
+        if same_page(&source, &target) {
+            return Err(WebmentionError::SameSourceAndTarget);
+        }
+        if !target.as_str().starts_with(ME) {
+            return Err(WebmentionError::UnsupportedTarget);
+        }
+        Ok(())
+    }
+}
+
+/// Applies result of the source fetching to the webmention. Source must link
+/// to the target, its `h-entry` gives the author, the content and the kind of mention.
+#[must_use]
+pub fn verify(
+    mut mention: Webmention,
+    page: Result<FetchedPage>,
+    now: DateTime<Utc>,
+) -> Webmention {
+    mention.verified = Some(now);
+    let entry = page.and_then(|p| Ok((parse_h_entry(&p.body)?, p.url)));
+    let (entry, base) = match entry {
+        Ok(r) => r,
+        Err(e) => {
+            mention.status = WebmentionStatus::Invalid;
+            mention.error = Some(format!("{e:#}"));
+            return mention;
+        }
+    };
+    let Ok(target) = Url::parse(&mention.target) else {
+        mention.status = WebmentionStatus::Invalid;
+        mention.error = Some(WebmentionError::InvalidTarget.to_string());
+        return mention;
+    };
+
+    let refers_to_target = |urls: &[String]| {
+        urls.iter()
+            .filter_map(|u| base.join(u).ok())
+            .any(|u| same_page(&u, &target))
+    };
+    if !refers_to_target(&entry.links) {
+        mention.status = WebmentionStatus::Invalid;
+        mention.error = Some(String::from("source doesn't link to target"));
+        return mention;
+    }
+
+    mention.kind = kind(&entry, refers_to_target);
+    let resolve = |u: Option<String>| {
+        base.join(&u?)
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https"))
+            .map(String::from)
+    };
+    mention.author_url = resolve(entry.author_url);
+    mention.author_photo = resolve(entry.author_photo);
+    mention.author_name = entry.author_name;
+    mention.content = entry.content.map(|c| truncate(&c, MAX_CONTENT_CHARS));
+    mention.published = entry
+        .published
+        .and_then(|p| DateTime::parse_from_rfc3339(&p).ok())
+        .map(|p| p.with_timezone(&Utc));
+    mention.status = WebmentionStatus::Verified;
+    mention.error = None;
+    mention
+}
+
+/// Fetches sources of queued webmentions and saves verification results.
+pub async fn verify_pending<S: Storage + Send + 'static>(storage: &Database<S>) -> Result<usize> {
+    let pending = storage
+        .read(|s| s.get_pending_webmentions(VERIFY_BATCH))
+        .await?;
+    let verified = pending.len();
+    for mention in pending {
+        let page = indie::read_from_client(&mention.source).await;
+        let mention = verify(mention, page, Utc::now());
+        tracing::info!(
+            "webmention from {} verified: {}",
+            mention.source,
+            mention.status.as_str()
+        );
+        storage
+            .write(move |s| s.update_webmention(&mention))
+            .await?;
+    }
+    Ok(verified)
+}
+
+/// Queues webmentions to pages the public post links to. Targets notified before
+/// are queued too, so that sites learn about links removed by update.
+pub fn queue_post_links<S: Storage>(
+    storage: &mut S,
+    post_id: i64,
+    now: DateTime<Utc>,
+) -> Result<usize> {
+    let post = storage.get_post(post_id)?;
+    if !post.is_public {
+        return Ok(0);
+    }
+    let source = format!("{ME}{}", post.path());
+    let mut targets = outbound_links(&Url::parse(&source)?, &post2html(&post)?)?;
+    for sent in storage.get_outgoing_webmentions(post_id)? {
+        if !targets.contains(&sent.target) {
+            targets.push(sent.target);
+        }
+    }
+    Ok(storage.queue_outgoing_webmentions(post_id, &source, &targets, now)?)
+}
+
+/// Queues post links logging failures, because they must not fail the post change.
+pub fn queue_post_links_logged<S: Storage>(storage: &mut S, post_id: i64) {
+    match queue_post_links(storage, post_id, Utc::now()) {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("{n} webmentions of post {post_id} queued"),
+        Err(e) => tracing::error!("Failed to queue webmentions of post {post_id}: {e:#}"),
+    }
+}
+
+/// Sends due webmentions and saves delivery results.
+pub async fn send_due<S: Storage + Send + 'static>(
+    storage: &Database<S>,
+    sender: &Sender,
+) -> Result<usize> {
+    let now = Utc::now();
+    let due = storage
+        .read(move |s| s.get_due_outgoing_webmentions(now, SEND_BATCH))
+        .await?;
+    let sent = due.len();
+    for mention in due {
+        let mention = sender.deliver(mention, Utc::now()).await;
+        tracing::info!(
+            "webmention to {} delivery: {}",
+            mention.target,
+            mention.status.as_str()
+        );
+        storage
+            .write(move |s| s.update_outgoing_webmention(&mention))
+            .await?;
+    }
+    Ok(sent)
+}
+
+/// Discovers webmention endpoints of linked pages and notifies them.
+pub struct Sender {
+    client: Client,
+    /// Pages and endpoints must be on public hosts. Only tests send to local receivers.
+    public_only: bool,
+}
+
+impl Sender {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: indie::public_client()?,
+            public_only: true,
+        })
+    }
+
+    /// Tries to deliver the webmention and records the result. Failed delivery
+    /// is retried later with exponential backoff until attempts are exhausted.
+    pub async fn deliver(
+        &self,
+        mut mention: OutgoingWebmention,
+        now: DateTime<Utc>,
+    ) -> OutgoingWebmention {
+        mention.last_attempt = Some(now);
+        match self.try_deliver(&mention).await {
+            Ok(endpoint) => {
+                mention.status = if endpoint.is_some() {
+                    DeliveryStatus::Sent
+                } else {
+                    DeliveryStatus::Unsupported
+                };
+                mention.endpoint = endpoint.map(String::from);
+                mention.error = None;
+            }
+            Err(e) => {
+                mention.attempts += 1;
+                mention.error = Some(format!("{e:#}"));
+                if mention.attempts >= MAX_ATTEMPTS {
+                    mention.status = DeliveryStatus::Failed;
+                } else {
+                    mention.next_attempt = now + retry_delay(mention.attempts);
+                }
+            }
+        }
+        mention
+    }
+
+    /// Gives the endpoint notified or `None` if target doesn't support webmentions.
+    async fn try_deliver(&self, mention: &OutgoingWebmention) -> Result<Option<Url>> {
+        let target = Url::parse(&mention.target)?;
+        let page = self.fetch(&target).await?;
+        let Some(endpoint) = find_endpoint(&page)? else {
+            return Ok(None);
+        };
+        self.check(&endpoint)?;
+        let body = form_urlencoded::Serializer::new(String::new())
+            .append_pair("source", &mention.source)
+            .append_pair("target", &mention.target)
+            .finish();
+        self.client
+            .post(endpoint.as_str())
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(Some(endpoint))
+    }
+
+    async fn fetch(&self, url: &Url) -> Result<FetchedPage> {
+        self.check(url)?;
+        let response = self.client.get(url.as_str()).send().await?;
+        indie::read_page(response).await
+    }
+
+    fn check(&self, url: &Url) -> Result<()> {
+        if self.public_only {
+            indie::validate_uri(url.as_str())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Finds webmention endpoint of the page in `Link` headers first and then
+/// in the page itself. Relative endpoint is resolved against the page URL.
+fn find_endpoint(page: &FetchedPage) -> Result<Option<Url>> {
+    let from_header = page.links.iter().find_map(|l| find_header_endpoint(l));
+    let endpoint = match from_header {
+        Some(e) => Some(e.to_owned()),
+        None if page
+            .content_type
+            .as_deref()
+            .is_some_and(|c| c.contains("html")) =>
+        {
+            find_rel_link(&page.body, "webmention")?
+        }
+        None => None,
+    };
+    let Some(endpoint) = endpoint else {
+        return Ok(None);
+    };
+    let endpoint = page.url.join(&endpoint)?;
+    if !matches!(endpoint.scheme(), "http" | "https") {
+        bail!("unsupported endpoint {endpoint}");
+    }
+    Ok(Some(endpoint))
+}
+
+/// Finds `rel="webmention"` link of `Link` header value like `<url>; rel="webmention"`.
+fn find_header_endpoint(header: &str) -> Option<&str> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.trim().strip_prefix('<')?.split_once('>')?;
+        params
+            .split(';')
+            .filter_map(|p| p.trim().split_once('='))
+            .any(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("rel")
+                    && value
+                        .trim()
+                        .trim_matches('"')
+                        .split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("webmention"))
+            })
+            .then_some(url)
+    })
+}
+
+fn retry_delay(attempts: i32) -> TimeDelta {
+    RETRY_DELAY * 2_i32.pow(attempts.clamp(1, MAX_ATTEMPTS).unsigned_abs() - 1)
+}
+
+/// Absolute http links to other sites without fragments.
+fn outbound_links(source: &Url, html: &str) -> Result<Vec<String>> {
+    let mut links: Vec<String> = Vec::new();
+    for link in extract_links(html)? {
+        let Ok(mut url) = source.join(&link) else {
+            continue;
+        };
+        if !matches!(url.scheme(), "http" | "https") || url.host() == source.host() {
+            continue;
+        }
+        url.set_fragment(None);
+        let url = String::from(url);
+        if !links.contains(&url) {
+            links.push(url);
+        }
+    }
+    Ok(links)
+}
+
+fn kind(entry: &HEntry, refers_to_target: impl Fn(&[String]) -> bool) -> WebmentionKind {
+    if refers_to_target(&entry.in_reply_to) {
+        WebmentionKind::Reply
+    } else if refers_to_target(&entry.like_of) {
+        WebmentionKind::Like
+    } else if refers_to_target(&entry.repost_of) {
+        WebmentionKind::Repost
+    } else if refers_to_target(&entry.bookmark_of) {
+        WebmentionKind::Bookmark
+    } else {
+        WebmentionKind::Mention
+    }
+}
+
+fn parse_http_url(url: &str) -> Option<Url> {
+    Url::parse(url.trim())
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https") && u.host().is_some())
+}
+
+/// URLs point to the same page if they differ only by fragment or trailing slash.
+fn same_page(a: &Url, b: &Url) -> bool {
+    let page = |u: &Url| {
+        let mut u = u.clone();
+        u.set_fragment(None);
+        String::from(u).trim_end_matches('/').to_owned()
+    };
+    page(a) == page(b)
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((ix, _)) => format!("{}…", text[..ix].trim_end()),
+        None => text.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use anyhow::anyhow;
+    use axum::{Router, http::StatusCode, routing::get};
+    use kernel::{domain::Post, memory::Memory};
+    use rstest::rstest;
+    use std::sync::{Arc, Mutex};
+
+    const TARGET: &str = "https://www.egoroff.spb.ru/blog/2024/post";
+
+    #[rstest]
+    #[case("https://a.example/1", TARGET, Ok(()))]
+    #[case("ftp://a.example/1", TARGET, Err(WebmentionError::InvalidSource))]
+    #[case("not a url", TARGET, Err(WebmentionError::InvalidSource))]
+    #[case(
+        "https://a.example/1",
+        "/blog/1.html",
+        Err(WebmentionError::InvalidTarget)
+    )]
+    #[case(
+        TARGET,
+        "https://www.egoroff.spb.ru/blog/2024/post#c",
+        Err(WebmentionError::SameSourceAndTarget)
+    )]
+    #[case(
+        "https://a.example/1",
+        "https://a.example/2",
+        Err(WebmentionError::UnsupportedTarget)
+    )]
+    #[case(
+        "https://a.example/1",
+        "https://www.egoroff.spb.ru.evil/blog/1.html",
+        Err(WebmentionError::UnsupportedTarget)
+    )]
+    fn validate_tests(
+        #[case] source: &str,
+        #[case] target: &str,
+        #[case] expected: Result<(), WebmentionError>,
+    ) {
+        // arrange
+        let request = WebmentionRequest {
+            source: source.to_owned(),
+            target: target.to_owned(),
+        };
+
+        // act
+        let actual = request.validate();
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn verify_reply() {
+        // arrange
+        let html = r#"<div class="h-entry">
+            <a class="p-author h-card" href="/"><img class="u-photo" src="/me.jpg"> Alice</a>
+            <a class="u-in-reply-to" href="https://www.egoroff.spb.ru/blog/2024/post/">post</a>
+            <p class="e-content">Nice!</p>
+            <time class="dt-published" datetime="2024-03-01T10:00:00+03:00"></time>
+            </div>"#;
+
+        // act
+        let actual = verify(mention(), Ok(page(html)), Utc::now());
+
+        // assert
+        assert_eq!(WebmentionStatus::Verified, actual.status);
+        assert_eq!(WebmentionKind::Reply, actual.kind);
+        assert_eq!(Some("Alice".to_owned()), actual.author_name);
+        assert_eq!(Some("https://alice.example/".to_owned()), actual.author_url);
+        assert_eq!(
+            Some("https://alice.example/me.jpg".to_owned()),
+            actual.author_photo
+        );
+        assert_eq!(Some("Nice!".to_owned()), actual.content);
+        assert_eq!(
+            Some("2024-03-01T07:00:00Z".parse().unwrap()),
+            actual.published
+        );
+        assert!(actual.error.is_none());
+    }
+
+    #[rstest]
+    #[case(
+        r#"<a class="u-like-of" href="https://www.egoroff.spb.ru/blog/2024/post">like</a>"#,
+        WebmentionKind::Like
+    )]
+    #[case(
+        r#"<a class="u-repost-of" href="https://www.egoroff.spb.ru/blog/2024/post">repost</a>"#,
+        WebmentionKind::Repost
+    )]
+    #[case(
+        r#"<a href="https://www.egoroff.spb.ru/blog/2024/post#top">link</a>"#,
+        WebmentionKind::Mention
+    )]
+    fn verify_kind(#[case] html: &str, #[case] expected: WebmentionKind) {
+        // arrange
+        let html = format!(r#"<div class="h-entry">{html}</div>"#);
+
+        // act
+        let actual = verify(mention(), Ok(page(&html)), Utc::now());
+
+        // assert
+        assert_eq!(WebmentionStatus::Verified, actual.status);
+        assert_eq!(expected, actual.kind);
+    }
+
+    #[rstest]
+    #[case(Ok(page(r#"<a href="https://www.egoroff.spb.ru/blog/2024/other">x</a>"#)))]
+    #[case(Ok(page(r#"<a class="p-author" href="javascript:alert(1)">x</a>"#)))]
+    #[case(Err(anyhow!("404 Not Found")))]
+    fn verify_invalid(#[case] page: Result<FetchedPage>) {
+        // arrange
+
+        // act
+        let actual = verify(mention(), page, Utc::now());
+
+        // assert
+        assert_eq!(WebmentionStatus::Invalid, actual.status);
+        assert!(actual.error.is_some());
+        assert!(actual.verified.is_some());
+    }
+
+    #[rstest]
+    #[case("абв", 3, "абв")]
+    #[case("абв где", 4, "абв…")]
+    fn truncate_tests(#[case] text: &str, #[case] max: usize, #[case] expected: &str) {
+        // act
+        let actual = truncate(text, max);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case(
+        r#"<https://a.example/wm>; rel="webmention""#,
+        Some("https://a.example/wm")
+    )]
+    #[case(r#"</wm>; rel=webmention"#, Some("/wm"))]
+    #[case(r#"</a>; rel="next", </wm>; rel="other WebMention""#, Some("/wm"))]
+    #[case(r#"</a>; rel="next""#, None)]
+    #[case(r#"</a>; rel="webmention-like""#, None)]
+    fn find_header_endpoint_tests(#[case] header: &str, #[case] expected: Option<&str>) {
+        // act
+        let actual = find_header_endpoint(header);
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case(vec!["</h>; rel=\"webmention\""], "text/html", Some("https://alice.example/h"))]
+    #[case(vec![], "text/html; charset=utf-8", Some("https://alice.example/b?x=1"))]
+    #[case(vec![], "text/plain", None)]
+    #[case(vec!["</h>; rel=\"next\""], "text/html", Some("https://alice.example/b?x=1"))]
+    fn find_endpoint_tests(
+        #[case] links: Vec<&str>,
+        #[case] content_type: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        // arrange
+        let mut page = page(r#"<a rel="webmention" href="/b?x=1">wm</a>"#);
+        page.links = links.into_iter().map(str::to_owned).collect();
+        page.content_type = Some(content_type.to_owned());
+
+        // act
+        let actual = find_endpoint(&page).unwrap();
+
+        // assert
+        assert_eq!(expected, actual.as_ref().map(Url::as_str));
+    }
+
+    #[test]
+    fn outbound_links_test() {
+        // arrange
+        let source = Url::parse("https://www.egoroff.spb.ru/blog/2024/post").unwrap();
+        let html = r##"<a href="https://a.example/1#c">1</a> <a href="https://a.example/1">1</a>
+            <a href="/blog/1.html">own</a> <a href="#top">top</a> <a href="mailto:a@a.example">mail</a>
+            <a href="http://b.example">b</a>"##;
+
+        // act
+        let actual = outbound_links(&source, html).unwrap();
+
+        // assert
+        assert_eq!(vec!["https://a.example/1", "http://b.example/"], actual);
+    }
+
+    #[rstest]
+    #[case(1, 5)]
+    #[case(2, 10)]
+    #[case(5, 80)]
+    fn retry_delay_tests(#[case] attempts: i32, #[case] minutes: i64) {
+        // act
+        let actual = retry_delay(attempts);
+
+        // assert
+        assert_eq!(TimeDelta::minutes(minutes), actual);
+    }
+
+    #[test]
+    fn queue_post_links_includes_previous_targets() {
+        // arrange
+        let mut storage = Memory::new();
+        let post = Post {
+            id: 1,
+            title: "Пост".to_owned(),
+            text: "[a](https://a.example/) [own](/blog/2.html)".to_owned(),
+            markdown: true,
+            is_public: true,
+            ..Default::default()
+        };
+        storage.upsert_post(post).unwrap();
+        let now = Utc::now();
+        storage
+            .queue_outgoing_webmentions(
+                1,
+                "https://www.egoroff.spb.ru/blog/1.html",
+                &["https://removed.example/".to_owned()],
+                now,
+            )
+            .unwrap();
+
+        // act
+        let actual = queue_post_links(&mut storage, 1, now).unwrap();
+
+        // assert
+        assert_eq!(2, actual);
+        let targets: Vec<String> = storage
+            .get_outgoing_webmentions(1)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.target)
+            .collect();
+        assert_eq!(
+            vec!["https://a.example/", "https://removed.example/"],
+            targets
+        );
+    }
+
+    #[rstest]
+    #[case("/header", DeliveryStatus::Sent, Some("/endpoint?x=1"), 1)]
+    #[case("/html", DeliveryStatus::Sent, Some("/endpoint"), 1)]
+    #[case("/plain", DeliveryStatus::Unsupported, None, 0)]
+    #[tokio::test]
+    async fn deliver_to_local_receiver(
+        #[case] path: &str,
+        #[case] expected: DeliveryStatus,
+        #[case] endpoint: Option<&str>,
+        #[case] received: usize,
+    ) {
+        // arrange
+        let (base, requests) = receiver(StatusCode::ACCEPTED).await;
+        let sender = Sender {
+            client: Client::new(),
+            public_only: false,
+        };
+        let mention = outgoing(&base, path);
+
+        // act
+        let actual = sender.deliver(mention, Utc::now()).await;
+
+        // assert
+        assert_eq!(expected, actual.status);
+        assert_eq!(
+            endpoint.map(|e| base.join(e).unwrap().to_string()),
+            actual.endpoint
+        );
+        assert!(actual.error.is_none());
+        let requests = requests.lock().unwrap();
+        assert_eq!(received, requests.len());
+        if let Some(body) = requests.first() {
+            let target =
+                form_urlencoded::byte_serialize(actual.target.as_bytes()).collect::<String>();
+            assert!(body.contains("source=https%3A%2F%2Fwww.egoroff.spb.ru%2Fblog%2F1.html"));
+            assert!(body.contains(&format!("target={target}")));
+        }
+    }
+
+    #[tokio::test]
+    async fn deliver_failure_retried_later() {
+        // arrange
+        let (base, _) = receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let sender = Sender {
+            client: Client::new(),
+            public_only: false,
+        };
+        let mut mention = outgoing(&base, "/html");
+        mention.attempts = 2;
+        let now = Utc::now();
+
+        // act
+        let actual = sender.deliver(mention, now).await;
+
+        // assert
+        assert_eq!(DeliveryStatus::Pending, actual.status);
+        assert_eq!(3, actual.attempts);
+        assert_eq!(now + TimeDelta::minutes(20), actual.next_attempt);
+        assert_eq!(Some(now), actual.last_attempt);
+        assert!(actual.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn deliver_fails_after_last_attempt() {
+        // arrange
+        let (base, requests) = receiver(StatusCode::ACCEPTED).await;
+        let sender = Sender::new().unwrap();
+        let mut mention = outgoing(&base, "/html");
+        mention.attempts = MAX_ATTEMPTS - 1;
+
+        // act
+        let actual = sender.deliver(mention, Utc::now()).await;
+
+        // assert
+        assert_eq!(DeliveryStatus::Failed, actual.status);
+        assert_eq!(MAX_ATTEMPTS, actual.attempts);
+        assert!(actual.error.is_some());
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    /// Local stand-in of a linked site. Records bodies of webmentions received.
+    async fn receiver(status: StatusCode) -> (Url, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        let html = |body: &'static str| ([(header::CONTENT_TYPE, "text/html")], body);
+        let router = Router::new()
+            .route(
+                "/header",
+                get(|| async {
+                    (
+                        [(header::LINK, r#"</endpoint?x=1>; rel="webmention""#)],
+                        "no links",
+                    )
+                }),
+            )
+            .route(
+                "/html",
+                get(move || async move {
+                    html(r#"<html><head><link rel="webmention" href="/endpoint"></head></html>"#)
+                }),
+            )
+            .route(
+                "/plain",
+                get(move || async move { html("<p>no endpoint</p>") }),
+            )
+            .route(
+                "/endpoint",
+                axum::routing::post(move |body: String| async move {
+                    received.lock().unwrap().push(body);
+                    status
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (base, requests)
+    }
+
+    fn outgoing(base: &Url, path: &str) -> OutgoingWebmention {
+        OutgoingWebmention {
+            id: 1,
+            post_id: 1,
+            source: "https://www.egoroff.spb.ru/blog/1.html".to_owned(),
+            target: base.join(path).unwrap().to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn mention() -> Webmention {
+        Webmention {
+            id: 1,
+            source: "https://alice.example/reply".to_owned(),
+            target: TARGET.to_owned(),
+            post_id: 1,
+            ..Default::default()
+        }
+    }
+
+    fn page(body: &str) -> FetchedPage {
+        FetchedPage {
+            url: Url::parse("https://alice.example/reply").unwrap(),
+            content_type: Some("text/html".to_owned()),
+            links: vec![],
+            body: body.to_owned(),
+        }
+    }
+}
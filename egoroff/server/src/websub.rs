@@ -0,0 +1,898 @@
+//! Built-in [WebSub](https://www.w3.org/TR/websub/) hub of the site feeds.
+//!
+//! Subscription requests are validated and answered at once while intent is
+//! verified asynchronously by fetching subscriber callback with a challenge.
+//! Verified subscriptions are stored until their lease is over.
+//!
+//! When posts change feeds having subscribers are rendered again and delivered
+//! to subscribers if feed content differs from the one delivered to them last time.
+//! Digests of delivered content are stored with subscriptions, so subscribers
+//! that failed to receive the content get it on the next distribution.
+//! Distributions are made one at a time by the worker the hub wakes up.
+
+use std::fmt::Write;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::{StreamExt, stream};
+use hmac::{Hmac, Mac};
+use kernel::domain::{Storage, WebSubSubscription};
+use percent_encoding::percent_decode_str;
+use reqwest::{Client, header};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::Notify;
+use url::Url;
+
+use crate::{
+    domain::Database,
+    feed::{self, Feed, FeedFormat},
+    indie::{self, ME},
+};
+
+/// Lease given if subscriber doesn't ask for a particular one.
+const DEFAULT_LEASE: TimeDelta = TimeDelta::days(10);
+const MIN_LEASE: TimeDelta = TimeDelta::hours(1);
+const MAX_LEASE: TimeDelta = TimeDelta::days(30);
+/// Secrets longer than this must be rejected by the spec.
+const MAX_SECRET_BYTES: usize = 200;
+/// New subscribers of a topic having this many subscribers are refused.
+const MAX_TOPIC_SUBSCRIBERS: usize = 100;
+/// Number of subscribers notified at the same time.
+const DELIVERY_CONCURRENCY: usize = 8;
+
+/// The hub URL advertised by feeds.
+#[must_use]
+pub fn hub_url() -> String {
+    format!("{ME}websub")
+}
+
+/// Hub subscription form.
+#[derive(Deserialize, Default)]
+pub struct SubscriptionRequest {
+    #[serde(rename = "hub.mode")]
+    pub mode: String,
+    #[serde(rename = "hub.topic")]
+    pub topic: String,
+    #[serde(rename = "hub.callback")]
+    pub callback: String,
+    #[serde(rename = "hub.lease_seconds")]
+    pub lease_seconds: Option<i64>,
+    #[serde(rename = "hub.secret")]
+    pub secret: Option<String>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum WebSubError {
+    #[error("hub.mode must be subscribe or unsubscribe")]
+    UnsupportedMode,
+    #[error("hub.topic is not a feed of this site")]
+    UnsupportedTopic,
+    #[error("hub.callback must be an http or https URL")]
+    InvalidCallback,
+    #[error("hub.callback must be a public URL")]
+    PrivateCallback,
+    #[error("hub.secret must be shorter than 200 bytes")]
+    SecretTooLong,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Subscribe,
+    Unsubscribe,
+}
+
+impl Mode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Mode::Subscribe => "subscribe",
+            Mode::Unsubscribe => "unsubscribe",
+        }
+    }
+}
+
+/// Feed of the site that can be subscribed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topic {
+    pub format: FeedFormat,
+    /// Feeds with tag are Atom feeds of the tag posts.
+    pub tag: Option<String>,
+}
+
+impl Topic {
+    /// Recognizes feed URL. Only canonical URLs the feeds advertise are topics.
+    #[must_use]
+    pub fn parse(url: &str) -> Option<Self> {
+        let path = url.strip_prefix(ME)?;
+        let topic = match path {
+            "blog/recent.atom" => Self {
+                format: FeedFormat::Atom,
+                tag: None,
+            },
+            "blog/recent.rss" => Self {
+                format: FeedFormat::Rss,
+                tag: None,
+            },
+            "blog/feed.json" => Self {
+                format: FeedFormat::JsonFeed,
+                tag: None,
+            },
+            _ => {
+                let tag = path
+                    .strip_prefix("blog/tag/")?
+                    .strip_suffix("/recent.atom")?;
+                let tag = percent_decode_str(tag).decode_utf8().ok()?;
+                Self {
+                    format: FeedFormat::Atom,
+                    tag: Some(tag.into_owned()),
+                }
+            }
+        };
+        (topic.url() == url).then_some(topic)
+    }
+
+    #[must_use]
+    pub fn url(&self) -> String {
+        let feed = match &self.tag {
+            Some(tag) => Feed::tagged(self.format, tag, vec![]),
+            None => Feed::new(self.format, vec![]),
+        };
+        feed.feed_url
+    }
+
+    /// Reads the newest posts page of the feed with full text.
+    fn read<S: Storage>(&self, storage: &S) -> Result<Option<Feed>> {
+        feed::read_feed(storage, self.format, self.tag.as_deref(), 1, true)
+    }
+}
+
+/// Validated subscription request which intent is to be verified.
+#[derive(Debug, Clone)]
+pub struct Intent {
+    pub mode: Mode,
+    pub topic: String,
+    pub callback: Url,
+    pub lease: TimeDelta,
+    pub secret: Option<String>,
+}
+
+impl SubscriptionRequest {
+    pub fn validate(&self) -> Result<Intent, WebSubError> {
+        let mode = match self.mode.trim() {
+            "subscribe" => Mode::Subscribe,
+            "unsubscribe" => Mode::Unsubscribe,
+            _ => return Err(WebSubError::UnsupportedMode),
+        };
+        let topic = self.topic.trim();
+        if Topic::parse(topic).is_none() {
+            return Err(WebSubError::UnsupportedTopic);
+        }
+        let callback = Url::parse(self.callback.trim())
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https"))
+            .ok_or(WebSubError::InvalidCallback)?;
+        let secret = self.secret.as_ref().filter(|s| !s.is_empty());
+        if secret.is_some_and(|s| s.len() >= MAX_SECRET_BYTES) {
+            return Err(WebSubError::SecretTooLong);
+        }
+        let lease = self
+            .lease_seconds
+            .map_or(DEFAULT_LEASE, TimeDelta::seconds)
+            .clamp(MIN_LEASE, MAX_LEASE);
+        Ok(Intent {
+            mode,
+            topic: topic.to_owned(),
+            callback,
+            lease,
+            secret: secret.cloned(),
+        })
+    }
+}
+
+/// Verifies subscribers and delivers feeds to them.
+pub struct Hub {
+    client: Client,
+    /// Callbacks must be on public hosts. Only tests use local subscribers.
+    public_only: bool,
+    /// Wakes up the distribution worker when feeds may have changed.
+    changed: Notify,
+}
+
+impl Hub {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: indie::public_client()?,
+            public_only: true,
+            changed: Notify::new(),
+        })
+    }
+
+    /// Asks the distribution worker to deliver changed feeds so that post
+    /// changes don't wait for subscribers. Changes made while feeds are
+    /// being delivered are delivered by the next distribution.
+    pub fn publish(&self) {
+        self.changed.notify_one();
+    }
+
+    /// Waits until feeds are published.
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+
+    /// Checks that subscription callback can be used by the hub.
+    pub fn check(&self, intent: &Intent) -> Result<(), WebSubError> {
+        if self.public_only && indie::validate_uri(intent.callback.as_str()).is_err() {
+            return Err(WebSubError::PrivateCallback);
+        }
+        Ok(())
+    }
+
+    /// Asks subscriber to confirm the intent by echoing challenge and saves
+    /// subscription or removes it once confirmed.
+    pub async fn verify<S: Storage + Send + 'static>(
+        &self,
+        storage: &Database<S>,
+        intent: Intent,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let challenge = uuid::Uuid::new_v4().simple().to_string();
+        let mut url = intent.callback.clone();
+        url.query_pairs_mut()
+            .append_pair("hub.mode", intent.mode.as_str())
+            .append_pair("hub.topic", &intent.topic)
+            .append_pair("hub.challenge", &challenge);
+        if intent.mode == Mode::Subscribe {
+            url.query_pairs_mut()
+                .append_pair("hub.lease_seconds", &intent.lease.num_seconds().to_string());
+        }
+        let response = self.client.get(url.as_str()).send().await?;
+        let page = indie::read_page(response).await?;
+        if page.body.trim() != challenge {
+            bail!(
+                "{} didn't confirm {}",
+                intent.callback,
+                intent.mode.as_str()
+            );
+        }
+
+        let subscription = WebSubSubscription {
+            topic: intent.topic,
+            callback: intent.callback.to_string(),
+            secret: intent.secret,
+            created: now,
+            expires: now + intent.lease,
+            ..Default::default()
+        };
+        let mode = intent.mode;
+        storage
+            .write(move |s| match mode {
+                Mode::Subscribe => subscribe(s, &subscription, now),
+                Mode::Unsubscribe => {
+                    s.delete_subscription(&subscription.topic, &subscription.callback)?;
+                    Ok(())
+                }
+            })
+            .await
+    }
+
+    /// Delivers changed feeds to their subscribers. Returns the number of
+    /// notifications delivered. Failed deliveries are logged and retried next time.
+    /// Must not run concurrently, otherwise subscribers may get the same content twice.
+    pub async fn distribute<S: Storage + Send + 'static>(
+        &self,
+        storage: &Database<S>,
+        now: DateTime<Utc>,
+    ) -> Result<usize> {
+        let feeds = storage
+            .read(move |s| {
+                let mut feeds: Vec<(String, Vec<WebSubSubscription>, Option<Feed>)> = vec![];
+                for subscription in s.get_subscriptions(now)? {
+                    if let Some((topic, subscriptions, _)) = feeds.last_mut()
+                        && *topic == subscription.topic
+                    {
+                        subscriptions.push(subscription);
+                        continue;
+                    }
+                    let feed = match Topic::parse(&subscription.topic) {
+                        Some(topic) => topic.read(s)?,
+                        None => None,
+                    };
+                    feeds.push((subscription.topic.clone(), vec![subscription], feed));
+                }
+                anyhow::Ok(feeds)
+            })
+            .await?;
+
+        let mut delivered = vec![];
+        for (topic, subscriptions, feed) in feeds {
+            let Some(feed) = feed else {
+                continue;
+            };
+            let format = Topic::parse(&topic).map_or(FeedFormat::Atom, |t| t.format);
+            let content = format.render(&feed)?;
+            let digest = digest(&content);
+            let pending: Vec<WebSubSubscription> = subscriptions
+                .into_iter()
+                .filter(|s| s.delivered.as_deref() != Some(digest.as_str()))
+                .collect();
+            let content = content.as_str();
+            let mut deliveries = stream::iter(pending)
+                .map(|subscription| async move {
+                    let result = self.deliver(&subscription, format, content).await;
+                    (subscription, result)
+                })
+                .buffer_unordered(DELIVERY_CONCURRENCY);
+            while let Some((subscription, result)) = deliveries.next().await {
+                match result {
+                    Ok(()) => delivered.push((subscription.id, digest.clone())),
+                    Err(e) => tracing::warn!(
+                        "Failed to deliver {topic} to {}: {e:#}",
+                        subscription.callback
+                    ),
+                }
+            }
+        }
+
+        let count = delivered.len();
+        storage
+            .write(move |s| {
+                for (id, digest) in &delivered {
+                    s.set_subscription_delivered(*id, digest)?;
+                }
+                Ok::<_, S::Err>(())
+            })
+            .await?;
+        Ok(count)
+    }
+
+    async fn deliver(
+        &self,
+        subscription: &WebSubSubscription,
+        format: FeedFormat,
+        content: &str,
+    ) -> Result<()> {
+        if self.public_only {
+            indie::validate_uri(&subscription.callback)?;
+        }
+        let mut request = self
+            .client
+            .post(&subscription.callback)
+            .header(header::CONTENT_TYPE, format.content_type())
+            .header(header::LINK, link_header(&subscription.topic));
+        if let Some(secret) = &subscription.secret {
+            request = request.header("X-Hub-Signature", signature(secret, content)?);
+        }
+        request
+            .body(content.to_owned())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Saves the subscription unless the topic already has too many subscribers.
+/// Renewing existing subscription is always allowed.
+fn subscribe<S: Storage>(
+    storage: &mut S,
+    subscription: &WebSubSubscription,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let subscribers: Vec<WebSubSubscription> = storage
+        .get_subscriptions(now)?
+        .into_iter()
+        .filter(|s| s.topic == subscription.topic)
+        .collect();
+    if subscribers.len() >= MAX_TOPIC_SUBSCRIBERS
+        && !subscribers
+            .iter()
+            .any(|s| s.callback == subscription.callback)
+    {
+        bail!(
+            "{} already has {MAX_TOPIC_SUBSCRIBERS} subscribers",
+            subscription.topic
+        );
+    }
+    storage.upsert_subscription(subscription)?;
+    Ok(())
+}
+
+/// Digest of feed content that stays the same between restarts.
+fn digest(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// `Link` header value advertising the hub and the topic.
+#[must_use]
+pub fn link_header(topic: &str) -> String {
+    format!(r#"<{}>; rel="hub", <{topic}>; rel="self""#, hub_url())
+}
+
+/// Content signature in `X-Hub-Signature` header format.
+fn signature(secret: &str, content: &str) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).context("Invalid HMAC key")?;
+    mac.update(content.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    let mut signature = String::from("sha256=");
+    for b in digest {
+        write!(signature, "{b:02x}")?;
+    }
+    Ok(signature)
+}
+
+/// Delivers changed feeds logging the result.
+pub async fn distribute_logged<S: Storage + Send + 'static>(hub: &Hub, storage: &Database<S>) {
+    match hub.distribute(storage, Utc::now()).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("{n} feed notifications delivered"),
+        Err(e) => tracing::error!("Feeds distribution error: {e:#}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_in_result)]
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use axum::{
+        Router,
+        extract::Query,
+        http::{HeaderMap, StatusCode},
+        routing::get,
+    };
+    use kernel::{domain::Post, memory::Memory, pool::Pool};
+    use rstest::rstest;
+    use std::{
+        collections::HashMap,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::Duration,
+    };
+
+    const TOPIC: &str = "https://www.egoroff.spb.ru/blog/recent.atom";
+
+    #[rstest]
+    #[case(TOPIC, Some((FeedFormat::Atom, None)))]
+    #[case("https://www.egoroff.spb.ru/blog/recent.rss", Some((FeedFormat::Rss, None)))]
+    #[case("https://www.egoroff.spb.ru/blog/feed.json", Some((FeedFormat::JsonFeed, None)))]
+    #[case(
+        "https://www.egoroff.spb.ru/blog/tag/rust/recent.atom",
+        Some((FeedFormat::Atom, Some("rust")))
+    )]
+    #[case(
+        "https://www.egoroff.spb.ru/blog/tag/%D0%B2%D0%B5%D0%B1/recent.atom",
+        Some((FeedFormat::Atom, Some("веб")))
+    )]
+    #[case("https://www.egoroff.spb.ru/blog/tag/веб/recent.atom", None)]
+    #[case("https://www.egoroff.spb.ru/blog/tag/rust/recent.rss", None)]
+    #[case("https://www.egoroff.spb.ru/recent.atom", None)]
+    #[case("https://example.com/blog/recent.atom", None)]
+    fn topic_parse_tests(#[case] url: &str, #[case] expected: Option<(FeedFormat, Option<&str>)>) {
+        // act
+        let actual = Topic::parse(url);
+
+        // assert
+        assert_eq!(
+            expected.map(|(format, tag)| Topic {
+                format,
+                tag: tag.map(str::to_owned)
+            }),
+            actual
+        );
+    }
+
+    #[rstest]
+    #[case("subscribe", TOPIC, "https://reader.example/cb", None, Ok(()))]
+    #[case("unsubscribe", TOPIC, "https://reader.example/cb", None, Ok(()))]
+    #[case(
+        "publish",
+        TOPIC,
+        "https://reader.example/cb",
+        None,
+        Err(WebSubError::UnsupportedMode)
+    )]
+    #[case(
+        "subscribe",
+        "https://example.com/feed",
+        "https://reader.example/cb",
+        None,
+        Err(WebSubError::UnsupportedTopic)
+    )]
+    #[case(
+        "subscribe",
+        TOPIC,
+        "ftp://reader.example/cb",
+        None,
+        Err(WebSubError::InvalidCallback)
+    )]
+    #[case("subscribe", TOPIC, "/cb", None, Err(WebSubError::InvalidCallback))]
+    #[case(
+        "subscribe",
+        TOPIC,
+        "https://reader.example/cb",
+        Some(200),
+        Err(WebSubError::SecretTooLong)
+    )]
+    fn validate_tests(
+        #[case] mode: &str,
+        #[case] topic: &str,
+        #[case] callback: &str,
+        #[case] secret_len: Option<usize>,
+        #[case] expected: Result<(), WebSubError>,
+    ) {
+        // arrange
+        let request = SubscriptionRequest {
+            mode: mode.to_owned(),
+            topic: topic.to_owned(),
+            callback: callback.to_owned(),
+            secret: secret_len.map(|n| "s".repeat(n)),
+            ..Default::default()
+        };
+
+        // act
+        let actual = request.validate().map(|_| ());
+
+        // assert
+        assert_eq!(expected, actual);
+    }
+
+    #[rstest]
+    #[case(None, DEFAULT_LEASE)]
+    #[case(Some(60), MIN_LEASE)]
+    #[case(Some(86_400), TimeDelta::days(1))]
+    #[case(Some(100_000_000), MAX_LEASE)]
+    fn lease_tests(#[case] lease_seconds: Option<i64>, #[case] expected: TimeDelta) {
+        // arrange
+        let request = SubscriptionRequest {
+            mode: "subscribe".to_owned(),
+            topic: TOPIC.to_owned(),
+            callback: "https://reader.example/cb".to_owned(),
+            lease_seconds,
+            ..Default::default()
+        };
+
+        // act
+        let actual = request.validate().unwrap();
+
+        // assert
+        assert_eq!(expected, actual.lease);
+    }
+
+    #[test]
+    fn public_hub_rejects_local_callback() {
+        // arrange
+        let hub = Hub::new().unwrap();
+        let intent = intent(
+            Mode::Subscribe,
+            Url::parse("http://127.0.0.1:8080/cb").unwrap(),
+        );
+
+        // act
+        let actual = hub.check(&intent);
+
+        // assert
+        assert_eq!(Err(WebSubError::PrivateCallback), actual);
+    }
+
+    #[test]
+    fn signature_test() {
+        // act
+        let actual = signature("key", "The quick brown fox jumps over the lazy dog").unwrap();
+
+        // assert
+        assert_eq!(
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+            actual
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribe_unsubscribe_verified_by_local_subscriber() {
+        // arrange
+        let subscriber = Subscriber::start(true).await;
+        let storage = database();
+        let hub = local_hub();
+        let now = Utc::now();
+
+        // act
+        hub.verify(
+            &storage,
+            intent(Mode::Subscribe, subscriber.callback()),
+            now,
+        )
+        .await
+        .unwrap();
+        let subscribed = storage
+            .read(move |s| s.get_subscriptions(now))
+            .await
+            .unwrap();
+        hub.verify(
+            &storage,
+            intent(Mode::Unsubscribe, subscriber.callback()),
+            now,
+        )
+        .await
+        .unwrap();
+        let unsubscribed = storage
+            .read(move |s| s.get_subscriptions(now))
+            .await
+            .unwrap();
+
+        // assert
+        assert_eq!(1, subscribed.len());
+        assert_eq!(TOPIC, subscribed[0].topic);
+        assert_eq!(subscriber.callback().as_str(), subscribed[0].callback);
+        assert_eq!(now + DEFAULT_LEASE, subscribed[0].expires);
+        assert!(unsubscribed.is_empty());
+        let verifications = subscriber.verifications.lock().unwrap();
+        assert_eq!("subscribe", verifications[0]["hub.mode"]);
+        assert_eq!(TOPIC, verifications[0]["hub.topic"]);
+        assert_eq!("864000", verifications[0]["hub.lease_seconds"]);
+        assert_eq!("unsubscribe", verifications[1]["hub.mode"]);
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_subscription_not_saved() {
+        // arrange
+        let subscriber = Subscriber::start(false).await;
+        let storage = database();
+        let hub = local_hub();
+        let now = Utc::now();
+
+        // act
+        let actual = hub
+            .verify(
+                &storage,
+                intent(Mode::Subscribe, subscriber.callback()),
+                now,
+            )
+            .await;
+
+        // assert
+        assert!(actual.is_err());
+        let subscriptions = storage
+            .read(move |s| s.get_subscriptions(now))
+            .await
+            .unwrap();
+        assert!(subscriptions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn distribute_changed_feed_to_local_subscriber() {
+        // arrange
+        let subscriber = Subscriber::start(true).await;
+        let storage = database();
+        let hub = local_hub();
+        let now = Utc::now();
+        let mut subscribe = intent(Mode::Subscribe, subscriber.callback());
+        subscribe.secret = Some("s3cret".to_owned());
+        hub.verify(&storage, subscribe, now).await.unwrap();
+
+        // act
+        let first = hub.distribute(&storage, now).await.unwrap();
+        let unchanged = hub.distribute(&storage, now).await.unwrap();
+        storage
+            .write(|s| {
+                let mut post = s.get_post(1)?;
+                post.title = "Новый заголовок".to_owned();
+                s.upsert_post(post)
+            })
+            .await
+            .unwrap();
+        let changed = hub.distribute(&storage, now).await.unwrap();
+
+        // assert
+        assert_eq!(1, first);
+        assert_eq!(0, unchanged);
+        assert_eq!(1, changed);
+        let notifications = subscriber.notifications.lock().unwrap();
+        assert_eq!(2, notifications.len());
+        let (headers, body) = &notifications[1];
+        assert!(body.contains("Новый заголовок"));
+        assert_eq!(
+            FeedFormat::Atom.content_type(),
+            headers[header::CONTENT_TYPE].to_str().unwrap()
+        );
+        assert_eq!(link_header(TOPIC), headers[header::LINK].to_str().unwrap());
+        assert_eq!(
+            signature("s3cret", body).unwrap(),
+            headers["x-hub-signature"].to_str().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_delivery_retried() {
+        // arrange
+        let subscriber = Subscriber::start(true).await;
+        let storage = database();
+        let hub = local_hub();
+        let now = Utc::now();
+        hub.verify(
+            &storage,
+            intent(Mode::Subscribe, subscriber.callback()),
+            now,
+        )
+        .await
+        .unwrap();
+        subscriber.reject.store(true, Ordering::Relaxed);
+
+        // act
+        let failed = hub.distribute(&storage, now).await.unwrap();
+        subscriber.reject.store(false, Ordering::Relaxed);
+        let retried = hub.distribute(&storage, now).await.unwrap();
+        let unchanged = hub.distribute(&storage, now).await.unwrap();
+
+        // assert
+        assert_eq!(0, failed);
+        assert_eq!(1, retried);
+        assert_eq!(0, unchanged);
+        assert_eq!(1, subscriber.notifications.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn delivered_content_not_sent_again_after_restart() {
+        // arrange
+        let subscriber = Subscriber::start(true).await;
+        let storage = database();
+        let now = Utc::now();
+        let hub = local_hub();
+        hub.verify(
+            &storage,
+            intent(Mode::Subscribe, subscriber.callback()),
+            now,
+        )
+        .await
+        .unwrap();
+        let first = hub.distribute(&storage, now).await.unwrap();
+
+        // act
+        let restarted = local_hub().distribute(&storage, now).await.unwrap();
+
+        // assert
+        assert_eq!(1, first);
+        assert_eq!(0, restarted);
+        assert_eq!(1, subscriber.notifications.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn publish_wakes_up_worker_once() {
+        // arrange
+        let hub = local_hub();
+
+        // act
+        hub.publish();
+        hub.publish();
+        let woken = tokio::time::timeout(Duration::from_millis(100), hub.changed()).await;
+        let woken_again = tokio::time::timeout(Duration::from_millis(100), hub.changed()).await;
+
+        // assert
+        assert!(woken.is_ok());
+        assert!(woken_again.is_err());
+    }
+
+    #[test]
+    fn subscribe_over_limit_refused() {
+        // arrange
+        let mut storage = Memory::new();
+        let now = Utc::now();
+        let subscription = |n: usize| WebSubSubscription {
+            topic: TOPIC.to_owned(),
+            callback: format!("https://reader.example/{n}"),
+            created: now,
+            expires: now + DEFAULT_LEASE,
+            ..Default::default()
+        };
+        for n in 0..MAX_TOPIC_SUBSCRIBERS {
+            subscribe(&mut storage, &subscription(n), now).unwrap();
+        }
+
+        // act
+        let refused = subscribe(&mut storage, &subscription(MAX_TOPIC_SUBSCRIBERS), now);
+        let renewed = subscribe(&mut storage, &subscription(0), now);
+
+        // assert
+        assert!(refused.is_err());
+        assert!(renewed.is_ok());
+        assert_eq!(
+            MAX_TOPIC_SUBSCRIBERS,
+            storage.get_subscriptions(now).unwrap().len()
+        );
+    }
+
+    type Notifications = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    /// Local stand-in of a feed reader. Records verification requests and notifications.
+    struct Subscriber {
+        base: Url,
+        verifications: Arc<Mutex<Vec<HashMap<String, String>>>>,
+        notifications: Notifications,
+        /// Notifications are answered with server error while set.
+        reject: Arc<AtomicBool>,
+    }
+
+    impl Subscriber {
+        async fn start(confirm: bool) -> Self {
+            let verifications = Arc::new(Mutex::new(Vec::new()));
+            let notifications: Notifications = Arc::new(Mutex::new(Vec::new()));
+            let reject = Arc::new(AtomicBool::new(false));
+            let verified = verifications.clone();
+            let notified = notifications.clone();
+            let rejected = reject.clone();
+            let router = Router::new().route(
+                "/cb",
+                get(
+                    move |Query(query): Query<HashMap<String, String>>| async move {
+                        let challenge = query["hub.challenge"].clone();
+                        verified.lock().unwrap().push(query);
+                        if confirm {
+                            (StatusCode::OK, challenge)
+                        } else {
+                            (StatusCode::NOT_FOUND, String::new())
+                        }
+                    },
+                )
+                .post(move |headers: HeaderMap, body: String| async move {
+                    if rejected.load(Ordering::Relaxed) {
+                        return StatusCode::INTERNAL_SERVER_ERROR;
+                    }
+                    notified.lock().unwrap().push((headers, body));
+                    StatusCode::ACCEPTED
+                }),
+            );
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+            tokio::spawn(async move { axum::serve(listener, router).await });
+            Self {
+                base,
+                verifications,
+                notifications,
+                reject,
+            }
+        }
+
+        fn callback(&self) -> Url {
+            self.base.join("/cb").unwrap()
+        }
+    }
+
+    fn local_hub() -> Hub {
+        Hub {
+            client: Client::new(),
+            public_only: false,
+            changed: Notify::new(),
+        }
+    }
+
+    fn intent(mode: Mode, callback: Url) -> Intent {
+        Intent {
+            mode,
+            topic: TOPIC.to_owned(),
+            callback,
+            lease: DEFAULT_LEASE,
+            secret: None,
+        }
+    }
+
+    fn database() -> Database<Memory> {
+        let mut storage = Memory::new();
+        storage
+            .upsert_post(Post {
+                id: 1,
+                title: "Пост".to_owned(),
+                short_text: "Кратко".to_owned(),
+                text: "Текст".to_owned(),
+                markdown: true,
+                is_public: true,
+                created: "2024-01-10T10:00:00Z".parse().unwrap(),
+                ..Default::default()
+            })
+            .unwrap();
+        Arc::new(Pool::new(storage, vec![]))
+    }
+}
@@ -42,7 +42,6 @@ import { createAdminRouter } from "@/router";
 import DownloadsList from "@/components/DownloadsList.vue";
 import { remountBlogFilterFromHash } from "@/blogMount";
 
-import "highlight.js/lib/common";
 import CodeHighlighter from "@/components/CodeHighlighter.vue";
 
 library.add(
@@ -148,10 +147,13 @@ function mountHighlighting(prefix: string, el: Element): void {
   app.mount(el);
 }
 
-document.querySelectorAll("pre, code").forEach((el) => {
-  mountHighlighting("brush: ", el);
-  mountHighlighting("language-", el);
-});
+// Code of languages known to the server comes highlighted inside pre.highlight
+document
+  .querySelectorAll("pre:not(.highlight), pre:not(.highlight) code, :not(pre) > code")
+  .forEach((el) => {
+    mountHighlighting("brush: ", el);
+    mountHighlighting("language-", el);
+  });
 
 const admin = document.getElementById("admin");
 if (admin) {
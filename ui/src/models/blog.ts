@@ -17,6 +17,8 @@ export class Year {
 export class Tag {
   public title!: string
   public level!: number
+  public display_title?: string
+  public description?: string
 }
 
 export class Query {
@@ -34,6 +36,7 @@ export class Post {
   public id!: number
   public Title!: string
   public ShortText!: string
+  public Slug?: string
 }
 
 export class EditablePost {
@@ -46,4 +49,6 @@ export class EditablePost {
   public Tags!: Array<string>;
   public Text!: string;
   public ShortText!: string;
+  public Slug?: string;
+  public Status?: 'draft' | 'published' | 'scheduled';
 }